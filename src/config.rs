@@ -0,0 +1,213 @@
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use secp256k1::Secp256k1;
+
+use crate::nostr_client::bunker::Bunker;
+use crate::nostr_client::event::pubkey_from_secret;
+use crate::nostr_client::nostr_connect::{BunkerUri, ConnectUri, RemoteSigner};
+use crate::nostr_client::signer::Signer;
+use crate::nostr_client::wallet_connect::WalletConnectUri;
+
+/// Environment variable holding the hex-encoded secret key of the user
+const SECRET_KEY_VAR: &str = "NOSTR_SECRET_KEY";
+
+/// Environment variable holding the `bunker://` URI of a remote signer to
+/// sign with instead of a secret key, or `nostrconnect` to show a URI for
+/// one to connect through
+const BUNKER_VAR: &str = "NOSTR_BUNKER";
+
+/// Environment variable holding the hex-encoded secret key the client talks
+/// to remote signers with. A new one is made on each run when it's not set,
+/// so signers that remember the clients they trust will ask again.
+const CLIENT_KEY_VAR: &str = "NOSTR_CLIENT_KEY";
+
+/// Environment variable holding the path of the file the bunker keeps the
+/// apps it signs for in, so that they stay connected across runs
+const BUNKER_APPS_VAR: &str = "NOSTR_BUNKER_APPS";
+
+/// Environment variable holding the `nostr+walletconnect://` URI of the
+/// wallet zaps are paid with
+const WALLET_VAR: &str = "NOSTR_WALLET";
+
+/// Environment variable holding the address of the NIP-96 server files are
+/// uploaded to
+const MEDIA_SERVER_VAR: &str = "NOSTR_MEDIA_SERVER";
+
+const DEFAULT_MEDIA_SERVER: &str = "https://nostr.build";
+
+/// Environment variable holding a comma separated list of relays used to
+/// find everything else
+const RELAYS_VAR: &str = "NOSTR_RELAYS";
+
+const DEFAULT_RELAYS: [&str; 3] = [
+    "wss://relay.damus.io",
+    "wss://nos.lol",
+    "wss://purplepag.es",
+];
+
+/// Name the client introduces itself with to remote signers
+const CLIENT_NAME: &str = "gnc";
+
+/// What the client asks remote signers it shows a `nostrconnect://` URI to
+/// to be allowed to do
+const CLIENT_PERMISSIONS: [&str; 4] = [
+    "sign_event",
+    "nip44_encrypt",
+    "nip44_decrypt",
+    "nip04_decrypt",
+];
+
+/// How the events of the user get signed
+#[derive(Debug, Clone)]
+pub enum Key {
+    /// With their hex-encoded secret key
+    Secret(String),
+    /// By the remote signer behind a `bunker://` URI
+    Bunker(BunkerUri),
+    /// By a remote signer that connects through a `nostrconnect://` URI
+    /// shown on startup
+    NostrConnect,
+}
+
+/// Settings the client is started with
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub key: Key,
+    /// Hex-encoded secret key the client talks to remote signers with
+    pub client_key: String,
+    /// File the bunker keeps the apps it signs for in, if any
+    pub bunker_apps: Option<PathBuf>,
+    /// Wallet to pay with, if any
+    pub wallet: Option<WalletConnectUri>,
+    /// Address of the NIP-96 server files are uploaded to
+    pub media_server: String,
+    pub relays: Vec<String>,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Config> {
+        let secp = Secp256k1::new();
+
+        let key = match (env::var(BUNKER_VAR), env::var(SECRET_KEY_VAR)) {
+            (Ok(bunker), _) if bunker.trim() == "nostrconnect" => Key::NostrConnect,
+            (Ok(bunker), _) => Key::Bunker(
+                bunker
+                    .parse()
+                    .map_err(|err| anyhow!("{} is not a valid bunker URI: {}", BUNKER_VAR, err))?,
+            ),
+            (Err(_), Ok(secret_key)) => {
+                pubkey_from_secret(&secret_key, &secp)
+                    .map_err(|_| anyhow!("{} is not a valid secret key", SECRET_KEY_VAR))?;
+                Key::Secret(secret_key)
+            }
+            (Err(_), Err(_)) => {
+                return Err(anyhow!(
+                    "Set {} to your secret key, or {} to a remote signer",
+                    SECRET_KEY_VAR,
+                    BUNKER_VAR
+                ))
+            }
+        };
+
+        let client_key = match env::var(CLIENT_KEY_VAR) {
+            Ok(client_key) => {
+                pubkey_from_secret(&client_key, &secp)
+                    .map_err(|_| anyhow!("{} is not a valid secret key", CLIENT_KEY_VAR))?;
+                client_key
+            }
+            Err(_) => secp
+                .generate_keypair(&mut rand::thread_rng())
+                .0
+                .display_secret()
+                .to_string(),
+        };
+
+        let wallet = match env::var(WALLET_VAR) {
+            Ok(uri) => Some(
+                uri.parse()
+                    .map_err(|err| anyhow!("{} is not a valid wallet URI: {}", WALLET_VAR, err))?,
+            ),
+            Err(_) => None,
+        };
+
+        let relays = match env::var(RELAYS_VAR) {
+            Ok(relays) => relays
+                .split(',')
+                .map(str::trim)
+                .filter(|relay| !relay.is_empty())
+                .map(str::to_string)
+                .collect(),
+            Err(_) => DEFAULT_RELAYS
+                .iter()
+                .map(|relay| relay.to_string())
+                .collect(),
+        };
+
+        Ok(Config {
+            key,
+            client_key,
+            bunker_apps: env::var(BUNKER_APPS_VAR).ok().map(PathBuf::from),
+            wallet,
+            media_server: env::var(MEDIA_SERVER_VAR)
+                .unwrap_or_else(|_| DEFAULT_MEDIA_SERVER.to_string()),
+            relays,
+        })
+    }
+
+    /// Gets the signer of the user ready, connecting to the remote one
+    /// first. Runs before the interface starts, so progress goes to stderr.
+    pub async fn signer(&self) -> Result<Signer> {
+        match &self.key {
+            Key::Secret(secret_key) => Signer::local(secret_key),
+            Key::Bunker(uri) => {
+                let mut remote = self.remote_signer(uri.relays.clone()).await?;
+                eprintln!("Connecting to the remote signer…");
+                remote.connect(uri).await?;
+
+                Ok(Signer::Remote(Box::new(remote)))
+            }
+            Key::NostrConnect => {
+                let client_pubkey = pubkey_from_secret(&self.client_key, &Secp256k1::new())?;
+                let mut uri = ConnectUri::new(&client_pubkey, self.relays.clone());
+                uri.permissions = CLIENT_PERMISSIONS.map(str::to_string).to_vec();
+                uri.name = Some(CLIENT_NAME.to_string());
+
+                let mut remote = self.remote_signer(self.relays.clone()).await?;
+                eprintln!("Paste this in your remote signer:\n\n{}\n", uri);
+                eprintln!("Waiting for it to connect…");
+                remote.accept(&uri).await?;
+
+                Ok(Signer::Remote(Box::new(remote)))
+            }
+        }
+    }
+
+    /// The bunker signing for other apps with the secret key of the user,
+    /// along with the apps that connected on earlier runs
+    pub fn bunker(&self) -> Result<Bunker> {
+        let Key::Secret(secret_key) = &self.key else {
+            return Err(anyhow!(
+                "The bunker needs {} to be set, and {} not to be",
+                SECRET_KEY_VAR,
+                BUNKER_VAR
+            ));
+        };
+
+        let mut bunker = Bunker::new(secret_key)?;
+        if let Some(path) = &self.bunker_apps {
+            bunker
+                .load_apps(path)
+                .map_err(|err| anyhow!("Could not read {}: {}", path.display(), err))?;
+        }
+        Ok(bunker)
+    }
+
+    async fn remote_signer(&self, relays: Vec<String>) -> Result<RemoteSigner> {
+        let mut remote = RemoteSigner::new(&self.client_key, relays).await?;
+        remote.on_auth_url(|url| eprintln!("Approve the request at {}", url));
+
+        Ok(remote)
+    }
+}
@@ -1,29 +1,85 @@
+mod config;
 mod nostr_client;
 mod tui;
 
-// use nostr_client::{connection::Connection, event::Event as NostrEvent};
-// use secp256k1::Secp256k1;
-
+use config::Config;
+use nostr_client::entity::Entity;
+use nostr_client::event::unix_time;
+use nostr_client::http_auth;
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 use std::io;
-use tui::app::{App, AppResult};
+use tokio::sync::mpsc;
+use tui::app::{App, AppResult, View};
+use tui::bunker::{BunkerService, BunkerState};
 use tui::event::{Event, EventHandler};
 use tui::handler::handle_key_events;
+use tui::network::Network;
 use tui::tui::Tui;
 
-// const SECRET_KEY: &'static str = "";
-// const PUB_KEY: &'static str = "";
-
 #[tokio::main]
 async fn main() -> AppResult<()> {
-    // Create an application.
-    let mut app = App::new();
+    // `--http-auth` and `--verify-http-auth` sign and check NIP-98
+    // `Authorization` headers for other programs, then exit.
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    if arguments.first().map(String::as_str) == Some("--verify-http-auth") {
+        return verify_http_auth(&arguments[1..]);
+    }
+
+    // Read the keys and relays to use.
+    let config = Config::from_env()?;
+    if arguments.first().map(String::as_str) == Some("--http-auth") {
+        return print_http_auth(&config, &arguments[1..]).await;
+    }
+
+    // `--bunker` signs for other apps instead of being a client, otherwise
+    // a `nostr:` URI to open may be given as the first argument.
+    let argument = arguments.first().cloned();
+    let bunker_mode = argument.as_deref() == Some("--bunker");
+    let target = argument
+        .filter(|_| !bunker_mode)
+        .map(|uri| Entity::from_uri(&uri))
+        .transpose()?;
+
+    let (commands, command_receiver) = mpsc::unbounded_channel();
+    let mut app;
+    let events;
+    if bunker_mode {
+        let mut bunker = config.bunker()?;
+        let uri = bunker.uri(config.relays.clone());
+
+        // Create an application showing what the bunker does.
+        let (bunker_commands, bunker_receiver) = mpsc::unbounded_channel();
+        app = App::new(bunker.pubkey(), commands);
+        app.serve_bunker(BunkerState::new(uri.to_string(), bunker_commands));
+
+        // Answer other apps in the background.
+        events = EventHandler::new(250);
+        let service = BunkerService::new(
+            bunker,
+            config.relays.clone(),
+            config.bunker_apps.clone(),
+            events.sender(),
+            bunker_receiver,
+        );
+        tokio::spawn(service.run());
+    } else {
+        // Get the signer ready, and create an application.
+        let signer = config.signer().await?;
+        app = App::new(signer.pubkey(), commands);
+        if let Some(entity) = target {
+            app.open_entity(entity);
+        }
+
+        // Start talking to the relays in the background.
+        events = EventHandler::new(250);
+        tokio::spawn(Network::new(config, signer, events.sender(), command_receiver).run());
+    }
 
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(io::stderr());
     let terminal = Terminal::new(backend)?;
-    let events = EventHandler::new(250);
+
     let mut tui = Tui::new(terminal, events);
     tui.init()?;
 
@@ -37,6 +93,27 @@ async fn main() -> AppResult<()> {
             Event::Key(key_event) => handle_key_events(key_event, &mut app)?,
             Event::Mouse(_) => {}
             Event::Resize(_, _) => {}
+            Event::Nostr(event) => app.handle_nostr_event(event),
+            Event::Verified(pubkey, identifier) => app.set_verified(pubkey, identifier),
+            Event::UserFound(pubkey) => {
+                app.status = None;
+                app.push_view(View::Profile(pubkey));
+            }
+            Event::NoteFound { id, root_id } => app.open_found_note(&id, &root_id),
+            Event::List(list) => app.set_list(list),
+            Event::ListsFetched => app.lists_fetched = true,
+            Event::Uploaded { path, file } => app.attach_file(&path, file),
+            Event::Status(status) => app.status = Some(status),
+            Event::Bunker(event) => app.handle_bunker_event(event),
+            Event::Group { relay, event } => app.handle_group_event(&relay, event),
+            Event::ZapProvider { pubkey, provider } => {
+                app.zaps.set_provider(&pubkey, provider);
+            }
+            Event::Wallet {
+                lud16,
+                balance,
+                transactions,
+            } => app.set_wallet(lud16, balance, transactions),
         }
     }
 
@@ -44,3 +121,34 @@ async fn main() -> AppResult<()> {
     tui.exit()?;
     Ok(())
 }
+
+/// Prints the `Authorization` header of a request signed by the user, for
+/// other programs to authenticate as them with. Takes the method and url of
+/// the request, and the file holding its body if it has one.
+async fn print_http_auth(config: &Config, arguments: &[String]) -> AppResult<()> {
+    let [method, url, body @ ..] = arguments else {
+        return Err("Usage: --http-auth METHOD URL [BODY_FILE]".into());
+    };
+    let body = body.first().map(std::fs::read).transpose()?;
+
+    let mut signer = config.signer().await?;
+    let header = signer
+        .http_authorization(url, method, body.as_deref())
+        .await?;
+    println!("{}", header);
+    Ok(())
+}
+
+/// Prints the pubkey of who signed the `Authorization` header of a request,
+/// failing if it doesn't allow the request. Takes the method, url and header
+/// of the request, and the file holding its body if it has one.
+fn verify_http_auth(arguments: &[String]) -> AppResult<()> {
+    let [method, url, header, body @ ..] = arguments else {
+        return Err("Usage: --verify-http-auth METHOD URL HEADER [BODY_FILE]".into());
+    };
+    let body = body.first().map(std::fs::read).transpose()?;
+
+    let pubkey = http_auth::verify(header, url, method, body.as_deref(), unix_time())?;
+    println!("{}", pubkey);
+    Ok(())
+}
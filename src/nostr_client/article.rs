@@ -0,0 +1,278 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use super::event::Event as EventType;
+
+/// Addressable event kind of published long-form articles, described by
+/// NIP-23
+pub const ARTICLE_KIND: u64 = 30023;
+
+/// Addressable event kind of articles that are still drafts
+pub const DRAFT_KIND: u64 = 30024;
+
+/// A long-form article written in Markdown
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Article {
+    /// The `d` tag, which keeps the article addressable across edits
+    pub identifier: String,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub image: Option<String>,
+    /// When the article was first published, which stays the same across
+    /// edits, unlike `created_at`
+    pub published_at: Option<u64>,
+    pub hashtags: Vec<String>,
+    /// The body, in Markdown
+    pub content: String,
+    pub draft: bool,
+}
+
+impl Article {
+    pub fn from_event(event: &EventType) -> Result<Article> {
+        if !is_article(event) {
+            return Err(anyhow!("Event of kind {} is not an article", event.kind));
+        }
+
+        let first = |name: &'static str| {
+            event
+                .tag_values(name)
+                .next()
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        Ok(Article {
+            identifier: first("d").unwrap_or_default(),
+            title: first("title"),
+            summary: first("summary"),
+            image: first("image"),
+            published_at: first("published_at").and_then(|at| at.parse().ok()),
+            hashtags: event.tag_values("t").map(str::to_string).collect(),
+            content: event.content.clone(),
+            draft: event.kind == DRAFT_KIND,
+        })
+    }
+
+    /// Reads an article from a Markdown file. The file may start with a
+    /// front matter block between `---` lines, holding `title`, `summary`,
+    /// `image`, `tags` and `identifier` fields. Without a title, the first
+    /// top-level heading is used, and without an identifier, the name of
+    /// the file.
+    pub fn read_file(path: &Path, draft: bool) -> Result<Article> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| anyhow!("Can't read {}: {}", path.display(), err))?;
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+
+        let mut article = Article::from_markdown(&text, stem);
+        article.draft = draft;
+        Ok(article)
+    }
+
+    /// Parses the text of a Markdown file, as described by [`read_file`],
+    /// with `identifier` as the fallback identifier.
+    ///
+    /// [`read_file`]: Article::read_file
+    pub fn from_markdown(text: &str, identifier: &str) -> Article {
+        let mut article = Article {
+            identifier: identifier.to_string(),
+            ..Default::default()
+        };
+
+        let mut body = text;
+        if let Some(rest) = text.strip_prefix("---\n").or(text.strip_prefix("---\r\n")) {
+            if let Some(end) = rest.find("\n---") {
+                for line in rest[..end].lines() {
+                    if let Some((key, value)) = line.split_once(':') {
+                        article.set_field(key.trim(), unquote(value.trim()));
+                    }
+                }
+                let after = &rest[end + "\n---".len()..];
+                body = after.split_once('\n').map_or("", |(_, body)| body);
+            }
+        }
+        let body = body.trim_start_matches(['\r', '\n']);
+
+        // A leading `# Title` is taken as the title, so it isn't shown twice
+        match (&article.title, body.strip_prefix("# ")) {
+            (None, Some(heading)) => {
+                let (title, rest) = heading.split_once('\n').unwrap_or((heading, ""));
+                article.title = Some(title.trim().to_string());
+                article.content = rest.trim_start_matches(['\r', '\n']).to_string();
+            }
+            _ => article.content = body.to_string(),
+        }
+
+        article
+    }
+
+    fn set_field(&mut self, key: &str, value: &str) {
+        if value.is_empty() {
+            return;
+        }
+
+        match key {
+            "title" => self.title = Some(value.to_string()),
+            "summary" => self.summary = Some(value.to_string()),
+            "image" => self.image = Some(value.to_string()),
+            "identifier" | "d" | "slug" => self.identifier = value.to_string(),
+            "tags" | "hashtags" => {
+                self.hashtags = value
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .split(',')
+                    .map(|tag| unquote(tag.trim()).trim_start_matches('#'))
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_lowercase)
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn kind(&self) -> u64 {
+        match self.draft {
+            true => DRAFT_KIND,
+            false => ARTICLE_KIND,
+        }
+    }
+
+    /// The `kind:pubkey:identifier` coordinate of the article once `pubkey`
+    /// publishes it
+    pub fn address(&self, pubkey: &str) -> String {
+        format!("{}:{}:{}", self.kind(), pubkey, self.identifier)
+    }
+
+    /// Builds the unsigned event publishing this article
+    pub fn to_event(&self, pubkey: &str) -> EventType {
+        let mut event = EventType::new(pubkey, self.kind(), self.content.clone());
+
+        event.add_tag("d", &self.identifier, None);
+        if let Some(title) = &self.title {
+            event.add_tag("title", title, None);
+        }
+        if let Some(summary) = &self.summary {
+            event.add_tag("summary", summary, None);
+        }
+        if let Some(image) = &self.image {
+            event.add_tag("image", image, None);
+        }
+        if let Some(published_at) = self.published_at {
+            event.add_tag("published_at", &published_at.to_string(), None);
+        }
+        for hashtag in &self.hashtags {
+            event.add_tag("t", hashtag, None);
+        }
+
+        event
+    }
+}
+
+/// Whether `event` is an article, published or not
+pub fn is_article(event: &EventType) -> bool {
+    matches!(event.kind, ARTICLE_KIND | DRAFT_KIND)
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .or_else(|| {
+            value
+                .strip_prefix('\'')
+                .and_then(|value| value.strip_suffix('\''))
+        })
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed};
+
+    #[test]
+    fn reads_articles() {
+        let (secret_key, _) = keys();
+        let event = signed(
+            &secret_key,
+            ARTICLE_KIND,
+            "Body",
+            vec![
+                vec!["d", "my-article"],
+                vec!["title", "My article"],
+                vec!["summary", ""],
+                vec!["published_at", "1700000000"],
+                vec!["t", "nostr"],
+                vec!["t", "rust"],
+            ],
+        );
+
+        let article = Article::from_event(&event).unwrap();
+
+        assert_eq!(article.identifier, "my-article");
+        assert_eq!(article.title.as_deref(), Some("My article"));
+        assert_eq!(article.summary, None);
+        assert_eq!(article.published_at, Some(1700000000));
+        assert_eq!(article.hashtags, vec!["nostr", "rust"]);
+        assert!(!article.draft);
+
+        let draft = signed(
+            &secret_key,
+            DRAFT_KIND,
+            "",
+            vec![vec!["published_at", "soon"]],
+        );
+        let draft = Article::from_event(&draft).unwrap();
+        assert!(draft.draft);
+        assert_eq!(draft.identifier, "");
+        assert_eq!(draft.published_at, None);
+
+        assert!(Article::from_event(&signed(&secret_key, 1, "", vec![])).is_err());
+    }
+
+    #[test]
+    fn reads_back_what_it_publishes() {
+        let (secret_key, pubkey) = keys();
+        let article = Article {
+            identifier: "my-article".to_string(),
+            title: Some("My article".to_string()),
+            image: Some("https://example.com/cover.png".to_string()),
+            published_at: Some(1700000000),
+            hashtags: vec!["nostr".to_string()],
+            content: "Body".to_string(),
+            ..Default::default()
+        };
+
+        let mut event = article.to_event(&pubkey);
+        event
+            .setup(&secret_key, &secp256k1::Secp256k1::new())
+            .unwrap();
+
+        assert_eq!(Article::from_event(&event).unwrap(), article);
+        assert_eq!(
+            article.address(&pubkey),
+            format!("30023:{}:my-article", pubkey)
+        );
+    }
+
+    #[test]
+    fn reads_markdown() {
+        let text =
+            "---\ntitle: \"Front matter\"\ntags: [Nostr, '#rust']\nslug: front\n---\n\nBody\n";
+        let article = Article::from_markdown(text, "file");
+
+        assert_eq!(article.identifier, "front");
+        assert_eq!(article.title.as_deref(), Some("Front matter"));
+        assert_eq!(article.hashtags, vec!["nostr", "rust"]);
+        assert_eq!(article.content, "Body\n");
+
+        let article = Article::from_markdown("# Heading\n\nBody", "file");
+        assert_eq!(article.identifier, "file");
+        assert_eq!(article.title.as_deref(), Some("Heading"));
+        assert_eq!(article.content, "Body");
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use super::event::Event as EventType;
+
+/// Event of an issuer awarding one of their badges to users, described by
+/// NIP-58
+pub const BADGE_AWARD_KIND: u64 = 8;
+
+/// Addressable event listing the awarded badges a user shows on their
+/// profile, in order
+pub const PROFILE_BADGES_KIND: u64 = 30008;
+
+/// Addressable event of an issuer defining a badge they can award
+pub const BADGE_DEFINITION_KIND: u64 = 30009;
+
+/// The `d` tag of the profile badges event, of which each user has one
+pub const PROFILE_BADGES_IDENTIFIER: &str = "profile_badges";
+
+/// A badge, as defined by its issuer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadgeDefinition {
+    pub issuer: String,
+    pub identifier: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub thumb: Option<String>,
+}
+
+impl BadgeDefinition {
+    pub fn from_event(event: &EventType) -> Result<BadgeDefinition> {
+        if event.kind != BADGE_DEFINITION_KIND {
+            return Err(anyhow!(
+                "Event of kind {} is not a badge definition",
+                event.kind
+            ));
+        }
+        let first = |name: &'static str| {
+            event
+                .tag_values(name)
+                .next()
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        Ok(BadgeDefinition {
+            issuer: event.pubkey(),
+            identifier: first("d").ok_or(anyhow!("The badge has no identifier"))?,
+            name: first("name"),
+            description: first("description"),
+            image: first("image"),
+            thumb: first("thumb"),
+        })
+    }
+
+    /// The coordinate awards point to it by
+    pub fn address(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            BADGE_DEFINITION_KIND, self.issuer, self.identifier
+        )
+    }
+
+    /// The name of the badge, or its identifier for unnamed ones
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.identifier.clone())
+    }
+}
+
+/// A badge awarded to users
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadgeAward {
+    pub id: String,
+    pub issuer: String,
+    /// Coordinate of the definition of the badge
+    pub address: String,
+    pub recipients: Vec<String>,
+    pub created_at: u64,
+}
+
+impl BadgeAward {
+    /// Reads a kind 8 event, which has to be published by the issuer of the
+    /// badge it awards: anyone else could claim to award it otherwise.
+    pub fn from_event(event: &EventType) -> Result<BadgeAward> {
+        if event.kind != BADGE_AWARD_KIND {
+            return Err(anyhow!("Event of kind {} is not a badge award", event.kind));
+        }
+        let address = event
+            .tag_values("a")
+            .find(|address| address.starts_with(&format!("{}:", BADGE_DEFINITION_KIND)))
+            .ok_or(anyhow!("The award names no badge"))?;
+        let issuer = event.pubkey();
+        if address.split(':').nth(1) != Some(issuer.as_str()) {
+            return Err(anyhow!(
+                "The badge was awarded by someone else than its issuer"
+            ));
+        }
+
+        Ok(BadgeAward {
+            id: event
+                .id()
+                .ok_or(anyhow!("The award has no id"))?
+                .to_string(),
+            issuer,
+            address: address.to_string(),
+            recipients: event.tag_values("p").map(str::to_string).collect(),
+            created_at: event.created_at,
+        })
+    }
+
+    /// Whether `definition` is the one of the badge awarded, by the same
+    /// issuer
+    pub fn awards(&self, definition: &BadgeDefinition) -> bool {
+        definition.issuer == self.issuer && definition.address() == self.address
+    }
+}
+
+/// The awarded badges a user shows on their profile, as pairs of the
+/// coordinate of the badge and the id of its award
+pub fn profile_badges(event: &EventType) -> Vec<(String, String)> {
+    if event.kind != PROFILE_BADGES_KIND
+        || event.tag_values("d").next() != Some(PROFILE_BADGES_IDENTIFIER)
+    {
+        return Vec::new();
+    }
+
+    // Each badge is an `a` tag directly followed by the `e` tag of its award
+    let tags: Vec<&Vec<String>> = event
+        .tags
+        .iter()
+        .filter(|tag| matches!(tag.first().map(String::as_str), Some("a") | Some("e")))
+        .collect();
+    tags.windows(2)
+        .filter_map(|pair| match (pair[0].as_slice(), pair[1].as_slice()) {
+            ([a, address, ..], [e, id, ..]) if a == "a" && e == "e" => {
+                Some((address.clone(), id.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Builds the unsigned profile badges event of `pubkey`, showing the given
+/// badges in order
+pub fn set_profile_badges(pubkey: &str, badges: &[(String, String)]) -> EventType {
+    let mut event = EventType::new(pubkey, PROFILE_BADGES_KIND, String::new());
+    event.add_tag("d", PROFILE_BADGES_IDENTIFIER, None);
+    for (address, award_id) in badges {
+        event.add_tag("a", address, None);
+        event.add_tag("e", award_id, None);
+    }
+
+    event
+}
+
+/// Badge awards received so far, by id
+#[derive(Debug, Default)]
+pub struct BadgeAwards {
+    awards: HashMap<String, BadgeAward>,
+}
+
+impl BadgeAwards {
+    pub fn new() -> BadgeAwards {
+        BadgeAwards::default()
+    }
+
+    /// Stores the award made by `event`, unless it isn't a valid one
+    pub fn insert(&mut self, event: &EventType) -> bool {
+        let Ok(award) = BadgeAward::from_event(event) else {
+            return false;
+        };
+
+        self.awards.insert(award.id.clone(), award).is_none()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&BadgeAward> {
+        self.awards.get(id)
+    }
+
+    /// The badges awarded to `pubkey`, newest first
+    pub fn to(&self, pubkey: &str) -> Vec<&BadgeAward> {
+        let mut awards: Vec<&BadgeAward> = self
+            .awards
+            .values()
+            .filter(|award| award.recipients.iter().any(|recipient| recipient == pubkey))
+            .collect();
+        awards.sort_by_key(|award| std::cmp::Reverse(award.created_at));
+
+        awards
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed};
+
+    fn definition(secret_key: &str) -> BadgeDefinition {
+        let event = signed(
+            secret_key,
+            BADGE_DEFINITION_KIND,
+            "",
+            vec![
+                vec!["d", "bravery"],
+                vec!["name", "Medal of Bravery"],
+                vec!["image", "https://example.com/bravery.png"],
+            ],
+        );
+        BadgeDefinition::from_event(&event).unwrap()
+    }
+
+    #[test]
+    fn reads_definitions() {
+        let (issuer, issuer_pubkey) = keys();
+        let badge = definition(&issuer);
+
+        assert_eq!(badge.issuer, issuer_pubkey);
+        assert_eq!(badge.display_name(), "Medal of Bravery");
+        assert_eq!(badge.address(), format!("30009:{}:bravery", issuer_pubkey));
+
+        let unnamed = signed(&issuer, BADGE_DEFINITION_KIND, "", vec![vec!["d", "x"]]);
+        assert_eq!(
+            BadgeDefinition::from_event(&unnamed)
+                .unwrap()
+                .display_name(),
+            "x"
+        );
+        let without_d = signed(&issuer, BADGE_DEFINITION_KIND, "", vec![]);
+        assert!(BadgeDefinition::from_event(&without_d).is_err());
+    }
+
+    #[test]
+    fn validates_awards_against_their_definition() {
+        let (issuer, _) = keys();
+        let (impostor, _) = keys();
+        let (_, recipient) = keys();
+        let badge = definition(&issuer);
+        let award = |secret_key: &str, address: &str| {
+            signed(
+                secret_key,
+                BADGE_AWARD_KIND,
+                "",
+                vec![vec!["a", address], vec!["p", &recipient]],
+            )
+        };
+
+        let read = BadgeAward::from_event(&award(&issuer, &badge.address())).unwrap();
+        assert_eq!(read.recipients, vec![recipient.clone()]);
+        assert!(read.awards(&badge));
+        assert!(!read.awards(&definition(&impostor)));
+
+        let other_badge = BadgeDefinition {
+            identifier: "kindness".to_string(),
+            ..badge.clone()
+        };
+        assert!(!read.awards(&other_badge));
+
+        // Only the issuer of a badge can award it
+        assert!(BadgeAward::from_event(&award(&impostor, &badge.address())).is_err());
+        let note_address = format!("30023:{}:article", badge.issuer);
+        assert!(BadgeAward::from_event(&award(&issuer, &note_address)).is_err());
+
+        let mut awards = BadgeAwards::new();
+        assert!(awards.insert(&award(&issuer, &badge.address())));
+        assert!(!awards.insert(&award(&impostor, &badge.address())));
+        assert_eq!(awards.to(&recipient).len(), 1);
+    }
+
+    #[test]
+    fn reads_back_profile_badges() {
+        let (secret_key, pubkey) = keys();
+        let badges = vec![
+            ("30009:a:first".to_string(), "1".repeat(64)),
+            ("30009:b:second".to_string(), "2".repeat(64)),
+        ];
+
+        let mut event = set_profile_badges(&pubkey, &badges);
+        event
+            .setup(&secret_key, &secp256k1::Secp256k1::new())
+            .unwrap();
+        assert_eq!(profile_badges(&event), badges);
+
+        let unpaired = signed(
+            &secret_key,
+            PROFILE_BADGES_KIND,
+            "",
+            vec![
+                vec!["d", PROFILE_BADGES_IDENTIFIER],
+                vec!["a", "30009:a:first"],
+                vec!["a", "30009:b:second"],
+                vec!["e", "id"],
+            ],
+        );
+        assert_eq!(
+            profile_badges(&unpaired),
+            vec![("30009:b:second".to_string(), "id".to_string())]
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use bech32::primitives::decode::CheckedHrpstring;
+use bech32::{Bech32, Checksum, Fe32};
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, PublicKey, Secp256k1};
+use sha2::{Digest, Sha256};
+
+/// Millisatoshis in a bitcoin
+const MSATS_PER_BTC: u64 = 100_000_000_000;
+
+/// Length of the signature ending an invoice, in 5-bit words
+const SIGNATURE_WORDS: usize = 104;
+
+/// Length of the timestamp starting an invoice, in 5-bit words
+const TIMESTAMP_WORDS: usize = 7;
+
+/// The bech32 checksum, over the whole length invoices may have. BOLT 11
+/// doesn't keep to the 1023 characters bech32 guarantees error detection
+/// for, since route hints make invoices long.
+enum Bolt11Bech32 {}
+
+impl Checksum for Bolt11Bech32 {
+    type MidstateRepr = u32;
+    const CODE_LENGTH: usize = 7089;
+    const CHECKSUM_LENGTH: usize = Bech32::CHECKSUM_LENGTH;
+    const GENERATOR_SH: [u32; 5] = Bech32::GENERATOR_SH;
+    const TARGET_RESIDUE: u32 = Bech32::TARGET_RESIDUE;
+}
+
+/// What a BOLT 11 lightning invoice says, read without asking any node.
+/// Only invoices signed by the node they're paid to are read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invoice {
+    /// The whole invoice, as it's paid
+    pub invoice: String,
+    /// Network the invoice is for, like `bc` for bitcoin
+    pub currency: String,
+    /// How much it asks for, if it says
+    pub amount_msats: Option<u64>,
+    /// When it was made, in seconds since the epoch
+    pub timestamp: u64,
+    /// Hex-encoded hash of the preimage the payment reveals
+    pub payment_hash: Option<String>,
+    pub description: Option<String>,
+    /// Hex-encoded SHA-256 of a description too long to be included
+    pub description_hash: Option<String>,
+    /// How many seconds after its timestamp it can be paid for
+    pub expiry: Option<u64>,
+    /// Hex-encoded key of the node paid, which signed the invoice
+    pub payee: String,
+}
+
+impl Invoice {
+    /// Whether the invoice can't be paid anymore. Invoices without an
+    /// expiry last an hour.
+    pub fn is_expired(&self, now: u64) -> bool {
+        now > self.timestamp.saturating_add(self.expiry.unwrap_or(3600))
+    }
+}
+
+impl FromStr for Invoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invoice = s.trim().trim_start_matches("lightning:").to_lowercase();
+        let checked = CheckedHrpstring::new::<Bolt11Bech32>(&invoice)
+            .map_err(|err| anyhow!("Invalid invoice: {}", err))?;
+
+        let hrp = checked.hrp().to_lowercase();
+        let (currency, amount_msats) = read_hrp(&hrp)?;
+
+        let words: Vec<u8> = checked
+            .data_part_ascii_no_checksum()
+            .iter()
+            .map(|c| Fe32::from_char(char::from(*c)).map(Fe32::to_u8))
+            .collect::<Result<_, _>>()
+            .map_err(|err| anyhow!("Invalid invoice: {}", err))?;
+        if words.len() < TIMESTAMP_WORDS + SIGNATURE_WORDS {
+            return Err(anyhow!("The invoice is too short"));
+        }
+        let (data, signature) = words.split_at(words.len() - SIGNATURE_WORDS);
+        let fields = &data[TIMESTAMP_WORDS..];
+        let payee = recover_payee(&hrp, data, &to_bytes(signature))?;
+
+        let mut invoice = Invoice {
+            invoice,
+            currency,
+            amount_msats,
+            timestamp: to_int(&words[..TIMESTAMP_WORDS]),
+            payment_hash: None,
+            description: None,
+            description_hash: None,
+            expiry: None,
+            payee: payee.to_string(),
+        };
+
+        // Tagged fields are a type, a length over two words, then the data
+        let mut rest = fields;
+        while rest.len() >= 3 {
+            let length = to_int(&rest[1..3]) as usize;
+            let Some(data) = rest.get(3..3 + length) else {
+                return Err(anyhow!("Truncated field in the invoice"));
+            };
+            match Fe32::try_from(rest[0]).map(Fe32::to_char) {
+                Ok('p') if length == 52 => invoice.payment_hash = Some(hex::encode(to_bytes(data))),
+                Ok('d') => {
+                    invoice.description = Some(String::from_utf8(to_bytes(data))?);
+                }
+                Ok('h') if length == 52 => {
+                    invoice.description_hash = Some(hex::encode(to_bytes(data)))
+                }
+                Ok('x') => invoice.expiry = Some(to_int(data)),
+                // The payee may be given rather than recovered from the
+                // signature, and has to be the one who signed
+                Ok('n') if length == 53 && to_bytes(data) != payee.serialize() => {
+                    return Err(anyhow!("The invoice isn't signed by its payee"));
+                }
+                // Fields readers don't know about are skipped
+                _ => {}
+            }
+            rest = &rest[3 + length..];
+        }
+
+        Ok(invoice)
+    }
+}
+
+/// The key that made `signature`, which signs the human-readable part and
+/// the `data` of an invoice: its timestamp and fields, padded to bytes
+fn recover_payee(hrp: &str, data: &[u8], signature: &[u8]) -> Result<PublicKey> {
+    let mut signed = hrp.as_bytes().to_vec();
+    signed.extend(to_padded_bytes(data));
+    let message = Message::from_digest_slice(&Sha256::digest(&signed))?;
+
+    let invalid = |_| anyhow!("Invalid invoice signature");
+    let recovery_id = RecoveryId::from_i32(i32::from(signature[64])).map_err(invalid)?;
+    let signature =
+        RecoverableSignature::from_compact(&signature[..64], recovery_id).map_err(invalid)?;
+    Secp256k1::verification_only()
+        .recover_ecdsa(&message, &signature)
+        .map_err(invalid)
+}
+
+/// Reads the currency and the amount, in millisatoshis, of the
+/// human-readable part of an invoice, like `lnbc2500u`
+fn read_hrp(hrp: &str) -> Result<(String, Option<u64>)> {
+    let rest = hrp
+        .strip_prefix("ln")
+        .ok_or(anyhow!("Not a lightning invoice"))?;
+    let currency_end = rest
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let (currency, amount) = rest.split_at(currency_end);
+    if amount.is_empty() {
+        return Ok((currency.to_string(), None));
+    }
+
+    let (digits, multiplier) = match amount.char_indices().last() {
+        Some((index, c)) if c.is_ascii_alphabetic() => (&amount[..index], Some(c)),
+        _ => (amount, None),
+    };
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| anyhow!("Invalid amount {}", amount))?;
+
+    let msats = match multiplier {
+        None => value.checked_mul(MSATS_PER_BTC),
+        Some('m') => value.checked_mul(MSATS_PER_BTC / 1_000),
+        Some('u') => value.checked_mul(MSATS_PER_BTC / 1_000_000),
+        Some('n') => value.checked_mul(MSATS_PER_BTC / 1_000_000_000),
+        // A pico-bitcoin is a tenth of a millisatoshi
+        Some('p') if value.is_multiple_of(10) => Some(value / 10),
+        Some(_) => None,
+    };
+
+    match msats {
+        Some(msats) => Ok((currency.to_string(), Some(msats))),
+        None => Err(anyhow!("Invalid amount {}", amount)),
+    }
+}
+
+/// Reads big-endian 5-bit words as a number
+fn to_int(words: &[u8]) -> u64 {
+    words
+        .iter()
+        .fold(0, |value, word| (value << 5) | u64::from(*word))
+}
+
+/// Packs 5-bit words into bytes, dropping the padding bits at the end
+fn to_bytes(words: &[u8]) -> Vec<u8> {
+    pack(words).0
+}
+
+/// Packs 5-bit words into bytes, padding the last one with zeros
+fn to_padded_bytes(words: &[u8]) -> Vec<u8> {
+    let (mut bytes, buffer, bits) = pack(words);
+    if bits > 0 {
+        bytes.push((buffer << (8 - bits)) as u8);
+    }
+    bytes
+}
+
+/// Packs 5-bit words into bytes, returning them with the bits left over
+/// and how many there are
+fn pack(words: &[u8]) -> (Vec<u8>, u32, u32) {
+    let mut bytes = Vec::with_capacity(words.len() * 5 / 8 + 1);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for word in words {
+        buffer = (buffer << 5) | u32::from(*word);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    (bytes, buffer, bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{invoice, keys};
+
+    /// Key of the node signing the examples of the BOLT 11 specification
+    const PAYEE: &str = "03e7156ae33b0a208d0744199163177e909e80176e55d97a2f221ede0f934dd9ad";
+
+    #[test]
+    fn reads_the_specification_examples() {
+        let donation: Invoice = "lnbc1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdpl2pkx2ctnv5sxxmmwwd5kgetjypeh2ursdae8g6twvus8g6rfwvs8qun0dfjkxaq9qrsgq357wnc5r2ueh7ck6q93dj32dlqnls087fxdwk8qakdyafkq3yap9us6v52vjjsrvywa6rt52cm9r9zqt8r2t7mlcwspyetp5h2tztugp9lfyql".parse().unwrap();
+        assert_eq!(donation.payee, PAYEE);
+        assert_eq!(donation.amount_msats, None);
+        assert_eq!(
+            donation.description.as_deref(),
+            Some("Please consider supporting this project")
+        );
+
+        let hashed: Invoice = "lnbc20m1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqhp58yjmdan79s6qqdhdzgynm4zwqd5d7xmw5fk98klysy043l2ahrqs9qrsgq7ea976txfraylvgzuxs8kgcw23ezlrszfnh8r6qtfpr6cxga50aj6txm9rxrydzd06dfeawfk6swupvz4erwnyutnjq7x39ymw6j38gp7ynn44".parse().unwrap();
+        assert_eq!(hashed.payee, PAYEE);
+        assert_eq!(hashed.amount_msats, Some(2_000_000_000));
+        assert_eq!(
+            hashed.description_hash.as_deref(),
+            Some("3925b6f67e2c340036ed12093dd44e0368df1b6ea26c53dbe4811f58fd5db8c1")
+        );
+    }
+
+    #[test]
+    fn expires() {
+        let (node, _) = keys();
+        let fresh: Invoice = invoice(&node, 21_000, Vec::new()).parse().unwrap();
+
+        assert!(!fresh.is_expired(fresh.timestamp + 3600));
+        assert!(fresh.is_expired(fresh.timestamp + 3601));
+        let lasting = Invoice {
+            expiry: Some(u64::MAX),
+            ..fresh
+        };
+        assert!(!lasting.is_expired(u64::MAX));
+    }
+
+    #[test]
+    fn checks_who_signed() {
+        let (node, _) = keys();
+        let (_, other) = keys();
+        let hash = [7; 32];
+        let payee_field = |pubkey: &str| ('n', hex::decode(pubkey).unwrap());
+        let payee_key = |secret: &str| {
+            let secret: secp256k1::SecretKey = secret.parse().unwrap();
+            hex::encode(secret.public_key(&Secp256k1::new()).serialize())
+        };
+
+        let read: Invoice = invoice(&node, 21_000, vec![('h', hash.to_vec())])
+            .parse()
+            .unwrap();
+        assert_eq!(read.payee, payee_key(&node));
+        assert_eq!(read.amount_msats, Some(21_000));
+        assert_eq!(read.description_hash, Some(hex::encode(hash)));
+
+        let given = invoice(&node, 21_000, vec![payee_field(&payee_key(&node))]);
+        assert_eq!(given.parse::<Invoice>().unwrap().payee, payee_key(&node));
+
+        // Keys in nostr drop the prefix of the compressed form
+        let wrong = invoice(&node, 21_000, vec![payee_field(&format!("02{}", other))]);
+        assert!(wrong.parse::<Invoice>().is_err());
+    }
+}
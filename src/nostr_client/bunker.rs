@@ -0,0 +1,500 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use rand::distributions::{Alphanumeric, DistString};
+use secp256k1::{All, Secp256k1};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::encryption;
+use super::event::{pubkey_from_secret, Event as EventType};
+use super::nostr_connect::{message_event, read_message, BunkerUri, ConnectUri, Request, Response};
+use super::profile::short_pubkey;
+
+/// Length of the secrets of the `bunker://` URIs handed out
+const SECRET_LENGTH: usize = 16;
+
+/// An app that connected to the bunker, by the pubkey it talks with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BunkerApp {
+    pub pubkey: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// What it asked to be allowed to do when connecting, like
+    /// `sign_event:1`
+    #[serde(default)]
+    pub requested: Vec<String>,
+    /// What the user allowed it to do without asking them again
+    #[serde(default)]
+    pub permissions: BTreeSet<String>,
+}
+
+impl BunkerApp {
+    /// Whether the app may do what `permission` describes without asking.
+    /// A permission without parameters, like `sign_event`, covers them all.
+    pub fn allows(&self, permission: &str) -> bool {
+        self.permissions.contains(permission)
+            || permission
+                .split_once(':')
+                .is_some_and(|(method, _)| self.permissions.contains(method))
+    }
+
+    /// The name of the app, or a short form of its pubkey
+    pub fn display_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => short_pubkey(&self.pubkey),
+        }
+    }
+}
+
+/// What the user answered when asked about a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Go on with this request only
+    Once,
+    /// Go on, and with the same kind of request from the app from now on
+    Always,
+    Deny,
+}
+
+/// What the bunker does with a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Send this response right away
+    Answer(Response),
+    /// Ask the user first, see `Bunker::decide`
+    Ask { permission: String },
+}
+
+/// A NIP-46 remote signer holding the user's key, which other apps ask to
+/// sign and encrypt on their behalf. It talks to them with the user's key
+/// as well.
+pub struct Bunker {
+    secret_key: String,
+    pubkey: String,
+    secp: Secp256k1<All>,
+    /// Secrets of the URIs handed out that weren't used yet
+    secrets: HashSet<String>,
+    /// Apps that connected, by pubkey
+    pub apps: BTreeMap<String, BunkerApp>,
+}
+
+impl Bunker {
+    pub fn new(secret_key: &str) -> Result<Bunker> {
+        let secp = Secp256k1::new();
+        let pubkey = pubkey_from_secret(secret_key, &secp)?;
+
+        Ok(Bunker {
+            secret_key: secret_key.to_string(),
+            pubkey,
+            secp,
+            secrets: HashSet::new(),
+            apps: BTreeMap::new(),
+        })
+    }
+
+    pub fn pubkey(&self) -> &str {
+        &self.pubkey
+    }
+
+    /// A new URI for an app to connect through `relays`. Its secret can
+    /// only be used once.
+    pub fn uri(&mut self, relays: Vec<String>) -> BunkerUri {
+        let secret = Alphanumeric.sample_string(&mut rand::thread_rng(), SECRET_LENGTH);
+        self.secrets.insert(secret.clone());
+
+        BunkerUri {
+            signer_pubkey: self.pubkey.clone(),
+            relays,
+            secret: Some(secret),
+        }
+    }
+
+    /// Reads a request sent to the bunker
+    pub fn read(&self, event: &EventType) -> Result<Request> {
+        read_message(&self.secret_key, event)
+    }
+
+    /// Builds the signed event carrying `response` to `client`
+    pub fn message(&self, client: &str, response: &Response) -> Result<EventType> {
+        let mut event = message_event(&self.secret_key, &self.pubkey, client, response)?;
+        event.setup(&self.secret_key, &self.secp)?;
+
+        Ok(event)
+    }
+
+    /// Decides what to do with `request` from `client`: requests the app
+    /// was allowed to make are answered, others wait for the user.
+    pub fn handle(&mut self, client: &str, request: &Request) -> Outcome {
+        let answer = |result: Result<String>| {
+            Outcome::Answer(match result {
+                Ok(result) => Response::ok(&request.id, &result),
+                Err(err) => Response::error(&request.id, &err.to_string()),
+            })
+        };
+
+        if request.method == "connect" {
+            if request.params.first() != Some(&self.pubkey) {
+                return answer(Err(anyhow!("Wrong signer")));
+            }
+            // Apps connecting again are known already
+            if self.apps.contains_key(client) {
+                return answer(Ok("ack".to_string()));
+            }
+            return match request.params.get(1).filter(|secret| !secret.is_empty()) {
+                Some(secret) if self.secrets.remove(secret) => {
+                    self.add_app(client, request);
+                    answer(Ok("ack".to_string()))
+                }
+                Some(_) => answer(Err(anyhow!("Invalid secret"))),
+                None => Outcome::Ask {
+                    permission: match requested_permissions(request).join(",") {
+                        permissions if permissions.is_empty() => "connect".to_string(),
+                        permissions => permissions,
+                    },
+                },
+            };
+        }
+
+        let Some(app) = self.apps.get(client) else {
+            return answer(Err(anyhow!("Not connected")));
+        };
+        match request.method.as_str() {
+            "ping" => answer(Ok("pong".to_string())),
+            "get_public_key" => answer(Ok(self.pubkey.clone())),
+            _ => match required_permission(request) {
+                Some(permission) if app.allows(&permission) => answer(self.execute(request)),
+                Some(permission) => Outcome::Ask { permission },
+                None => answer(Err(anyhow!("Unsupported method {}", request.method))),
+            },
+        }
+    }
+
+    /// Answers `request` from `client` the way the user decided, after
+    /// they were asked about `permission`. Apps always allowed to connect
+    /// are allowed what they asked for as well.
+    pub fn decide(
+        &mut self,
+        client: &str,
+        request: &Request,
+        permission: &str,
+        decision: Decision,
+    ) -> Response {
+        if decision == Decision::Deny {
+            return Response::error(&request.id, "Denied by the user");
+        }
+
+        if request.method == "connect" {
+            self.add_app(client, request);
+            if decision == Decision::Always {
+                if let Some(app) = self.apps.get_mut(client) {
+                    app.permissions.extend(app.requested.iter().cloned());
+                }
+            }
+            return Response::ok(&request.id, "ack");
+        }
+        if decision == Decision::Always {
+            if let Some(app) = self.apps.get_mut(client) {
+                app.permissions.insert(permission.to_string());
+            }
+        }
+
+        match self.execute(request) {
+            Ok(result) => Response::ok(&request.id, &result),
+            Err(err) => Response::error(&request.id, &err.to_string()),
+        }
+    }
+
+    /// Connects to the app that showed `uri` to the user, allowing it what
+    /// it asked for if the user agreed to `grant` it. Returns the response
+    /// to send it, which carries the secret of the URI.
+    pub fn accept(&mut self, uri: &ConnectUri, grant: bool) -> Response {
+        let app = self
+            .apps
+            .entry(uri.client_pubkey.clone())
+            .or_insert_with(|| BunkerApp {
+                pubkey: uri.client_pubkey.clone(),
+                ..BunkerApp::default()
+            });
+        app.name = uri.name.clone().or(app.name.take());
+        app.requested = uri.permissions.clone();
+        if grant {
+            app.permissions.extend(uri.permissions.iter().cloned());
+        }
+
+        let id = Alphanumeric.sample_string(&mut rand::thread_rng(), SECRET_LENGTH);
+        Response::ok(&id, &uri.secret)
+    }
+
+    fn add_app(&mut self, client: &str, request: &Request) {
+        let requested = requested_permissions(request);
+        let app = self
+            .apps
+            .entry(client.to_string())
+            .or_insert_with(|| BunkerApp {
+                pubkey: client.to_string(),
+                ..BunkerApp::default()
+            });
+        app.requested = requested;
+    }
+
+    /// Signs or encrypts as `request` asks
+    fn execute(&self, request: &Request) -> Result<String> {
+        let param = |index: usize| {
+            request
+                .params
+                .get(index)
+                .map(String::as_str)
+                .ok_or(anyhow!("Missing parameter {}", index + 1))
+        };
+
+        match request.method.as_str() {
+            "sign_event" => {
+                let mut event = unsigned_event(&self.pubkey, param(0)?)?;
+                event.setup(&self.secret_key, &self.secp)?;
+                Ok(serde_json::to_string(&event)?)
+            }
+            "nip44_encrypt" => encryption::nip44_encrypt(&self.secret_key, param(0)?, param(1)?),
+            "nip44_decrypt" => encryption::nip44_decrypt(&self.secret_key, param(0)?, param(1)?),
+            "nip04_encrypt" => encryption::nip04_encrypt(&self.secret_key, param(0)?, param(1)?),
+            "nip04_decrypt" => encryption::nip04_decrypt(&self.secret_key, param(0)?, param(1)?),
+            method => Err(anyhow!("Unsupported method {}", method)),
+        }
+    }
+
+    /// Restores the apps saved at `path`, if any
+    pub fn load_apps(&mut self, path: &Path) -> Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let apps: Vec<BunkerApp> = serde_json::from_str(&fs::read_to_string(path)?)?;
+        self.apps = apps
+            .into_iter()
+            .map(|app| (app.pubkey.clone(), app))
+            .collect();
+        Ok(())
+    }
+
+    /// Saves the apps at `path`, so they stay connected on the next run
+    pub fn save_apps(&self, path: &Path) -> Result<()> {
+        let apps: Vec<&BunkerApp> = self.apps.values().collect();
+        fs::write(path, serde_json::to_string_pretty(&apps)?)?;
+
+        Ok(())
+    }
+}
+
+/// What the app has to be allowed to do for the bunker to answer
+/// `request` without asking the user, like `sign_event:1` to sign notes.
+/// Requests that don't need any have none.
+pub fn required_permission(request: &Request) -> Option<String> {
+    match request.method.as_str() {
+        "sign_event" => {
+            let kind = request
+                .params
+                .first()
+                .and_then(|json| serde_json::from_str::<Value>(json).ok())
+                .and_then(|event| event["kind"].as_u64());
+            Some(match kind {
+                Some(kind) => format!("sign_event:{}", kind),
+                None => "sign_event".to_string(),
+            })
+        }
+        method @ ("nip44_encrypt" | "nip44_decrypt" | "nip04_encrypt" | "nip04_decrypt") => {
+            Some(method.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// What the app sending the `connect` request asks to be allowed to do
+pub fn requested_permissions(request: &Request) -> Vec<String> {
+    request
+        .params
+        .get(2)
+        .map(|permissions| {
+            permissions
+                .split(',')
+                .filter(|permission| !permission.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Builds the event of `pubkey` described by `json`, which has its kind,
+/// content, tags and creation date
+fn unsigned_event(pubkey: &str, json: &str) -> Result<EventType> {
+    let value: Value = serde_json::from_str(json)?;
+    let kind = value["kind"]
+        .as_u64()
+        .ok_or(anyhow!("The event has no kind"))?;
+    let content = value["content"].as_str().unwrap_or_default().to_string();
+
+    let mut event = EventType::new(pubkey, kind, content);
+    if !value["tags"].is_null() {
+        event.tags = serde_json::from_value(value["tags"].clone())?;
+    }
+    if let Some(created_at) = value["created_at"].as_u64() {
+        event.created_at = created_at;
+    }
+
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::keys;
+
+    fn bunker() -> Bunker {
+        let (secret_key, _) = keys();
+        Bunker::new(&secret_key).unwrap()
+    }
+
+    fn sign_note() -> Request {
+        Request::new(
+            "sign_event",
+            vec![r#"{"kind":1,"content":"hello","tags":[]}"#.to_string()],
+        )
+    }
+
+    fn connect(bunker: &Bunker, secret: &str, permissions: &str) -> Request {
+        Request::new(
+            "connect",
+            vec![
+                bunker.pubkey().to_string(),
+                secret.to_string(),
+                permissions.to_string(),
+            ],
+        )
+    }
+
+    fn error(outcome: Outcome) -> Option<String> {
+        match outcome {
+            Outcome::Answer(response) => response.error,
+            Outcome::Ask { permission } => panic!("asked about {}", permission),
+        }
+    }
+
+    #[test]
+    fn grants_what_a_uri_asks_for_only_when_allowed() {
+        let (_, client) = keys();
+        let mut uri = ConnectUri::new(&client, vec!["wss://relay.example".to_string()]);
+        uri.permissions = vec!["sign_event:1".to_string(), "nip44_encrypt".to_string()];
+
+        let mut bunker = bunker();
+        let response = bunker.accept(&uri, false);
+        assert_eq!(response.result, uri.secret);
+        let app = &bunker.apps[&client];
+        assert_eq!(app.requested, uri.permissions);
+        assert!(app.permissions.is_empty());
+        assert_eq!(
+            bunker.handle(&client, &sign_note()),
+            Outcome::Ask {
+                permission: "sign_event:1".to_string()
+            }
+        );
+
+        bunker.accept(&uri, true);
+        assert!(bunker.apps[&client].allows("sign_event:1"));
+        assert!(bunker.apps[&client].allows("nip44_encrypt"));
+        assert_eq!(error(bunker.handle(&client, &sign_note())), None);
+    }
+
+    #[test]
+    fn connects_apps_with_a_secret_once() {
+        let (_, client) = keys();
+        let (_, other) = keys();
+        let mut bunker = bunker();
+        let secret = bunker.uri(Vec::new()).secret.unwrap();
+
+        let mut wrong_signer = connect(&bunker, &secret, "");
+        wrong_signer.params[0] = other.clone();
+        assert_eq!(
+            error(bunker.handle(&client, &wrong_signer)),
+            Some("Wrong signer".to_string())
+        );
+        assert_eq!(
+            error(bunker.handle(&client, &sign_note())),
+            Some("Not connected".to_string())
+        );
+
+        assert_eq!(
+            error(bunker.handle(&client, &connect(&bunker, &secret, ""))),
+            None
+        );
+        assert!(bunker.apps.contains_key(&client));
+        assert_eq!(
+            error(bunker.handle(&other, &connect(&bunker, &secret, ""))),
+            Some("Invalid secret".to_string())
+        );
+    }
+
+    #[test]
+    fn asks_before_connecting_apps_without_a_secret() {
+        let (_, client) = keys();
+        let mut bunker = bunker();
+        let request = connect(&bunker, "", "sign_event:1,nip44_encrypt");
+
+        assert_eq!(
+            bunker.handle(&client, &request),
+            Outcome::Ask {
+                permission: "sign_event:1,nip44_encrypt".to_string()
+            }
+        );
+        assert!(bunker.apps.is_empty());
+
+        let response = bunker.decide(&client, &request, "", Decision::Deny);
+        assert!(response.error.is_some());
+        assert!(bunker.apps.is_empty());
+
+        let response = bunker.decide(&client, &request, "", Decision::Once);
+        assert_eq!(response.result, "ack");
+        assert!(bunker.apps[&client].permissions.is_empty());
+
+        bunker.decide(&client, &request, "", Decision::Always);
+        assert!(bunker.apps[&client].allows("sign_event:1"));
+    }
+
+    #[test]
+    fn remembers_what_the_user_always_allows() {
+        let (_, client) = keys();
+        let mut bunker = bunker();
+        bunker.decide(
+            &client,
+            &connect(&bunker, "", ""),
+            "connect",
+            Decision::Once,
+        );
+
+        let request = sign_note();
+        let Outcome::Ask { permission } = bunker.handle(&client, &request) else {
+            panic!("signed without asking");
+        };
+        assert_eq!(permission, "sign_event:1");
+
+        let response = bunker.decide(&client, &request, &permission, Decision::Once);
+        let event: EventType = serde_json::from_str(&response.result).unwrap();
+        assert_eq!(event.pubkey(), bunker.pubkey());
+        assert!(matches!(
+            bunker.handle(&client, &sign_note()),
+            Outcome::Ask { .. }
+        ));
+
+        bunker.decide(&client, &request, &permission, Decision::Always);
+        assert_eq!(error(bunker.handle(&client, &sign_note())), None);
+        let reaction = Request::new(
+            "sign_event",
+            vec![r#"{"kind":7,"content":"+","tags":[]}"#.to_string()],
+        );
+        assert!(matches!(
+            bunker.handle(&client, &reaction),
+            Outcome::Ask { .. }
+        ));
+    }
+}
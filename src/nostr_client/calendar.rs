@@ -0,0 +1,488 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use super::event::Event as EventType;
+
+/// Calendar event lasting whole days, described by NIP-52
+pub const DATE_EVENT_KIND: u64 = 31922;
+
+/// Calendar event starting and ending at given times
+pub const TIME_EVENT_KIND: u64 = 31923;
+
+/// A calendar, pointing to the calendar events it holds
+pub const CALENDAR_KIND: u64 = 31924;
+
+/// Answer of a user to a calendar event, saying whether they will attend
+pub const RSVP_KIND: u64 = 31925;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// When a calendar event takes place
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// From the start date until the day before the end date, both written
+    /// `YYYY-MM-DD`. Events without an end date last one day.
+    Dates { start: String, end: Option<String> },
+    /// Between two unix timestamps, with the time zone the event takes place
+    /// in if it says
+    Times {
+        start: u64,
+        end: Option<u64>,
+        timezone: Option<String>,
+    },
+}
+
+/// A date-based or time-based calendar event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalendarEvent {
+    pub author: String,
+    pub identifier: String,
+    pub kind: u64,
+    pub title: String,
+    pub summary: Option<String>,
+    pub description: String,
+    pub schedule: Schedule,
+    pub locations: Vec<String>,
+    /// Pubkeys of the people taking part, like the speakers of a meetup
+    pub participants: Vec<String>,
+}
+
+impl CalendarEvent {
+    pub fn from_event(event: &EventType) -> Result<CalendarEvent> {
+        let first = |name: &'static str| {
+            event
+                .tag_values(name)
+                .next()
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        let start = first("start").ok_or(anyhow!("The calendar event has no start"))?;
+
+        let schedule = match event.kind {
+            DATE_EVENT_KIND => {
+                date_to_unix(&start).ok_or(anyhow!("Invalid start date {}", start))?;
+                Schedule::Dates {
+                    start,
+                    end: first("end").filter(|end| date_to_unix(end).is_some()),
+                }
+            }
+            TIME_EVENT_KIND => Schedule::Times {
+                start: start.parse()?,
+                end: first("end").and_then(|end| end.parse().ok()),
+                timezone: first("start_tzid"),
+            },
+            kind => return Err(anyhow!("Event of kind {} is not a calendar event", kind)),
+        };
+
+        Ok(CalendarEvent {
+            author: event.pubkey(),
+            identifier: first("d").ok_or(anyhow!("The calendar event has no identifier"))?,
+            kind: event.kind,
+            // Older events name themselves in a `name` tag
+            title: first("title").or(first("name")).unwrap_or_default(),
+            summary: first("summary"),
+            description: event.content.clone(),
+            schedule,
+            locations: event.tag_values("location").map(str::to_string).collect(),
+            participants: event.tag_values("p").map(str::to_string).collect(),
+        })
+    }
+
+    /// The coordinate RSVPs and calendars point to it by
+    pub fn address(&self) -> String {
+        format!("{}:{}:{}", self.kind, self.author, self.identifier)
+    }
+
+    /// When it starts, as a unix timestamp. Date-based events start at
+    /// midnight UTC.
+    pub fn starts_at(&self) -> u64 {
+        match &self.schedule {
+            Schedule::Dates { start, .. } => date_to_unix(start).unwrap_or_default(),
+            Schedule::Times { start, .. } => *start,
+        }
+    }
+
+    /// When it's over, as a unix timestamp
+    pub fn ends_at(&self) -> u64 {
+        match &self.schedule {
+            Schedule::Dates { end, .. } => end
+                .as_deref()
+                .and_then(date_to_unix)
+                .filter(|end| *end > self.starts_at())
+                .unwrap_or(self.starts_at() + SECONDS_PER_DAY),
+            Schedule::Times { start, end, .. } => end.filter(|end| end > start).unwrap_or(*start),
+        }
+    }
+
+    /// Whether it isn't over by `now`
+    pub fn is_upcoming(&self, now: u64) -> bool {
+        self.ends_at() > now
+    }
+
+    /// When it takes place, written for people. Times are given in UTC, with
+    /// the time zone of the event next to them.
+    pub fn when(&self) -> String {
+        match &self.schedule {
+            Schedule::Dates { .. } => {
+                let last_day = self.ends_at() - SECONDS_PER_DAY;
+                match last_day > self.starts_at() {
+                    true => format!(
+                        "{} → {}",
+                        format_date(self.starts_at()),
+                        format_date(last_day)
+                    ),
+                    false => format_date(self.starts_at()),
+                }
+            }
+            Schedule::Times { timezone, .. } => {
+                let mut when = format_time(self.starts_at());
+                if self.ends_at() > self.starts_at() {
+                    let end = match same_day(self.starts_at(), self.ends_at()) {
+                        true => format_time(self.ends_at())[15..].to_string(),
+                        false => format_time(self.ends_at()),
+                    };
+                    when.push_str(&format!(" → {}", end));
+                }
+                when.push_str(" UTC");
+                if let Some(timezone) = timezone {
+                    when.push_str(&format!(" ({})", timezone));
+                }
+                when
+            }
+        }
+    }
+}
+
+/// A calendar, as the coordinates of the calendar events it holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Calendar {
+    pub author: String,
+    pub identifier: String,
+    pub title: String,
+    pub events: Vec<String>,
+}
+
+impl Calendar {
+    pub fn from_event(event: &EventType) -> Result<Calendar> {
+        if event.kind != CALENDAR_KIND {
+            return Err(anyhow!("Event of kind {} is not a calendar", event.kind));
+        }
+        let identifier = event
+            .tag_values("d")
+            .next()
+            .ok_or(anyhow!("The calendar has no identifier"))?;
+
+        Ok(Calendar {
+            author: event.pubkey(),
+            identifier: identifier.to_string(),
+            title: event
+                .tag_values("title")
+                .next()
+                .unwrap_or(identifier)
+                .to_string(),
+            events: event
+                .tag_values("a")
+                .filter(|address| is_calendar_event_address(address))
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+}
+
+/// Whether `address` is the coordinate of a date-based or time-based
+/// calendar event
+pub fn is_calendar_event_address(address: &str) -> bool {
+    let kind = address.split(':').next().and_then(|kind| kind.parse().ok());
+    matches!(kind, Some(DATE_EVENT_KIND) | Some(TIME_EVENT_KIND))
+}
+
+/// Whether someone will attend a calendar event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RsvpStatus {
+    Accepted,
+    Tentative,
+    Declined,
+}
+
+impl RsvpStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RsvpStatus::Accepted => "accepted",
+            RsvpStatus::Tentative => "tentative",
+            RsvpStatus::Declined => "declined",
+        }
+    }
+}
+
+impl FromStr for RsvpStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<RsvpStatus> {
+        match s.trim().to_lowercase().as_str() {
+            "accepted" | "yes" => Ok(RsvpStatus::Accepted),
+            "tentative" | "maybe" => Ok(RsvpStatus::Tentative),
+            "declined" | "no" => Ok(RsvpStatus::Declined),
+            other => Err(anyhow!("Unknown RSVP status {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for RsvpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The answer of a user to a calendar event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rsvp {
+    pub author: String,
+    /// Coordinate of the calendar event
+    pub address: String,
+    pub status: RsvpStatus,
+    pub note: String,
+}
+
+impl Rsvp {
+    pub fn from_event(event: &EventType) -> Result<Rsvp> {
+        if event.kind != RSVP_KIND {
+            return Err(anyhow!("Event of kind {} is not an RSVP", event.kind));
+        }
+        let address = event
+            .tag_values("a")
+            .find(|address| is_calendar_event_address(address))
+            .ok_or(anyhow!("The RSVP names no calendar event"))?;
+        let status = event
+            .tag_values("status")
+            .next()
+            .ok_or(anyhow!("The RSVP has no status"))?;
+
+        Ok(Rsvp {
+            author: event.pubkey(),
+            address: address.to_string(),
+            status: status.parse()?,
+            note: event.content.trim().to_string(),
+        })
+    }
+}
+
+/// Builds the unsigned RSVP of `pubkey` to `calendar_event`. Its identifier
+/// is the coordinate of the event, so a new answer replaces the previous
+/// one.
+pub fn rsvp(
+    pubkey: &str,
+    calendar_event: &CalendarEvent,
+    status: RsvpStatus,
+    note: &str,
+) -> EventType {
+    let address = calendar_event.address();
+    let mut event = EventType::new(pubkey, RSVP_KIND, note.to_string());
+    event.add_tag("d", &address, None);
+    event.add_tag("a", &address, None);
+    event.add_tag("status", status.as_str(), None);
+    if status != RsvpStatus::Declined {
+        event.add_tag("fb", "busy", None);
+    }
+    event.add_tag("p", &calendar_event.author, None);
+
+    event
+}
+
+/// The unix timestamp of midnight UTC on `date`, written `YYYY-MM-DD`
+pub fn date_to_unix(date: &str) -> Option<u64> {
+    let mut parts = date.trim().splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if !(0..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    u64::try_from(days).ok().map(|days| days * SECONDS_PER_DAY)
+}
+
+/// The day of `timestamp` in UTC, like `Tue 2026-11-03`
+pub fn format_date(timestamp: u64) -> String {
+    let days = (timestamp / SECONDS_PER_DAY) as i64;
+    let (year, month, day) = civil_from_days(days);
+    // The first of January 1970 was a Thursday
+    let weekday = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"][days.rem_euclid(7) as usize];
+
+    format!("{} {:04}-{:02}-{:02}", weekday, year, month, day)
+}
+
+/// The day and time of `timestamp` in UTC, like `Tue 2026-11-03 18:30`
+pub fn format_time(timestamp: u64) -> String {
+    let seconds = timestamp % SECONDS_PER_DAY;
+    format!(
+        "{} {:02}:{:02}",
+        format_date(timestamp),
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
+fn same_day(a: u64, b: u64) -> bool {
+    a / SECONDS_PER_DAY == b / SECONDS_PER_DAY
+}
+
+/// Days between the first of January 1970 and the given date of the
+/// proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date of the proleptic Gregorian calendar `days` after the first of
+/// January 1970
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed};
+
+    /// 2026-11-03 at midnight UTC
+    const NOV_3: u64 = 1793664000;
+
+    #[test]
+    fn reads_date_events() {
+        let (secret_key, pubkey) = keys();
+        let event = signed(
+            &secret_key,
+            DATE_EVENT_KIND,
+            "Two days of talks",
+            vec![
+                vec!["d", "conf"],
+                vec!["title", "Nostr conference"],
+                vec!["start", "2026-11-03"],
+                vec!["end", "2026-11-05"],
+                vec!["location", "Lisbon"],
+            ],
+        );
+
+        let conference = CalendarEvent::from_event(&event).unwrap();
+
+        assert_eq!(conference.title, "Nostr conference");
+        assert_eq!(conference.locations, vec!["Lisbon"]);
+        assert_eq!(conference.address(), format!("31922:{}:conf", pubkey));
+        assert_eq!(conference.starts_at(), NOV_3);
+        assert_eq!(conference.ends_at(), NOV_3 + 2 * SECONDS_PER_DAY);
+        assert_eq!(conference.when(), "Tue 2026-11-03 → Wed 2026-11-04");
+        assert!(conference.is_upcoming(NOV_3 + 2 * SECONDS_PER_DAY - 1));
+        assert!(!conference.is_upcoming(NOV_3 + 2 * SECONDS_PER_DAY));
+
+        let one_day = signed(
+            &secret_key,
+            DATE_EVENT_KIND,
+            "",
+            vec![
+                vec!["d", "day"],
+                vec!["start", "2026-11-03"],
+                vec!["end", "soon"],
+            ],
+        );
+        let one_day = CalendarEvent::from_event(&one_day).unwrap();
+        assert_eq!(
+            one_day.schedule,
+            Schedule::Dates {
+                start: "2026-11-03".to_string(),
+                end: None
+            }
+        );
+        assert_eq!(one_day.ends_at(), NOV_3 + SECONDS_PER_DAY);
+        assert_eq!(one_day.when(), "Tue 2026-11-03");
+    }
+
+    #[test]
+    fn reads_time_events() {
+        let (secret_key, _) = keys();
+        let start = NOV_3 + 18 * 3600 + 30 * 60;
+        let event = signed(
+            &secret_key,
+            TIME_EVENT_KIND,
+            "",
+            vec![
+                vec!["d", "meetup"],
+                vec!["name", "Meetup"],
+                vec!["start", &start.to_string()],
+                vec!["end", &(start + 7200).to_string()],
+                vec!["start_tzid", "Europe/Lisbon"],
+            ],
+        );
+
+        let meetup = CalendarEvent::from_event(&event).unwrap();
+
+        assert_eq!(meetup.title, "Meetup");
+        assert_eq!(meetup.starts_at(), start);
+        assert_eq!(meetup.ends_at(), start + 7200);
+        assert_eq!(
+            meetup.when(),
+            "Tue 2026-11-03 18:30 → 20:30 UTC (Europe/Lisbon)"
+        );
+        assert!(meetup.is_upcoming(start + 7199));
+        assert!(!meetup.is_upcoming(start + 7200));
+    }
+
+    #[test]
+    fn refuses_invalid_events() {
+        let (secret_key, _) = keys();
+        let event = |kind: u64, start: &str| {
+            let tags = vec![vec!["d", "x"], vec!["start", start]];
+            CalendarEvent::from_event(&signed(&secret_key, kind, "", tags))
+        };
+
+        assert!(event(DATE_EVENT_KIND, "2026-13-01").is_err());
+        assert!(event(DATE_EVENT_KIND, "99999999999999-01-01").is_err());
+        assert!(event(DATE_EVENT_KIND, "1793664000").is_err());
+        assert!(event(TIME_EVENT_KIND, "2026-11-03").is_err());
+        assert!(event(1, "2026-11-03").is_err());
+    }
+
+    #[test]
+    fn answers_events() {
+        let (secret_key, pubkey) = keys();
+        let (organizer, _) = keys();
+        let tags = vec![vec!["d", "conf"], vec!["start", "2026-11-03"]];
+        let conference =
+            CalendarEvent::from_event(&signed(&organizer, DATE_EVENT_KIND, "", tags)).unwrap();
+
+        let mut event = rsvp(&pubkey, &conference, "maybe".parse().unwrap(), " if I can ");
+        event
+            .setup(&secret_key, &secp256k1::Secp256k1::new())
+            .unwrap();
+
+        let answer = Rsvp::from_event(&event).unwrap();
+        assert_eq!(answer.address, conference.address());
+        assert_eq!(answer.status, RsvpStatus::Tentative);
+        assert_eq!(answer.note, "if I can");
+        assert_eq!(event.tag_values("fb").next(), Some("busy"));
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::event::Event as EventType;
+
+/// Event creating a public chat channel, described by NIP-28. Its id is the
+/// id of the channel.
+pub const CHANNEL_CREATION_KIND: u64 = 40;
+
+/// Event of the creator of a channel changing its name, description or
+/// picture
+pub const CHANNEL_METADATA_KIND: u64 = 41;
+
+/// A message sent to a channel
+pub const CHANNEL_MESSAGE_KIND: u64 = 42;
+
+/// Event of a user hiding a message of a channel from themselves
+pub const CHANNEL_HIDE_MESSAGE_KIND: u64 = 43;
+
+/// Event of a user muting someone in the channels they read
+pub const CHANNEL_MUTE_USER_KIND: u64 = 44;
+
+/// What a channel is called and about, as found in the JSON content of its
+/// creation and metadata events
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelMetadata {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub about: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub picture: Option<String>,
+    /// Relays the messages of the channel are sent to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relays: Vec<String>,
+}
+
+impl ChannelMetadata {
+    /// Sets the field called `name` to `value`. The about and picture are
+    /// cleared by an empty value, written out so that readers merging the
+    /// metadata with older one drop theirs too.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "name" if value.is_empty() => return Err(anyhow!("Channels need a name")),
+            "name" => self.name = value.to_string(),
+            "about" => self.about = Some(value.to_string()),
+            "picture" => self.picture = Some(value.to_string()),
+            _ => return Err(anyhow!("Channels have no {} field", name)),
+        }
+
+        Ok(())
+    }
+}
+
+/// A public chat channel, along with its latest metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Channel {
+    /// The id of the kind 40 event creating it
+    pub id: String,
+    pub creator: String,
+    pub metadata: ChannelMetadata,
+    pub created_at: u64,
+    /// When `metadata` was last changed by the creator
+    pub updated_at: u64,
+}
+
+impl Channel {
+    pub fn from_creation(event: &EventType) -> Result<Channel> {
+        if event.kind != CHANNEL_CREATION_KIND {
+            return Err(anyhow!("Event of kind {} is not a channel", event.kind));
+        }
+        let id = event.id().ok_or(anyhow!("The channel has no id"))?;
+
+        Ok(Channel {
+            id: id.to_string(),
+            creator: event.pubkey(),
+            metadata: serde_json::from_str(&event.content)?,
+            created_at: event.created_at,
+            updated_at: event.created_at,
+        })
+    }
+
+    /// Applies the kind 41 `event` if it's a newer one of the creator for
+    /// this channel, returning whether it was. Fields it leaves out are
+    /// kept as they were.
+    pub fn update(&mut self, event: &EventType) -> bool {
+        let applies = event.kind == CHANNEL_METADATA_KIND
+            && event.pubkey() == self.creator
+            && event.created_at > self.updated_at
+            && channel_id(event).as_deref() == Some(self.id.as_str());
+        if !applies {
+            return false;
+        }
+        let Ok(metadata) = serde_json::from_str::<ChannelMetadata>(&event.content) else {
+            return false;
+        };
+
+        if !metadata.name.is_empty() {
+            self.metadata.name = metadata.name;
+        }
+        self.metadata.about = metadata.about.or(self.metadata.about.take());
+        self.metadata.picture = metadata.picture.or(self.metadata.picture.take());
+        if !metadata.relays.is_empty() {
+            self.metadata.relays = metadata.relays;
+        }
+        self.updated_at = event.created_at;
+        true
+    }
+
+    /// The name of the channel, or the start of its id for unnamed ones
+    pub fn display_name(&self) -> String {
+        match self.metadata.name.trim() {
+            "" => format!("#{}", &self.id[..8.min(self.id.len())]),
+            name => name.to_string(),
+        }
+    }
+
+    /// A relay the messages of the channel can be found at, if it says
+    pub fn relay(&self) -> Option<&str> {
+        self.metadata.relays.first().map(String::as_str)
+    }
+}
+
+/// The id of the channel a metadata event, a message, or a reply to one
+/// belongs to: the `e` tag marked `root`, or the first one for events that
+/// don't mark them
+pub fn channel_id(event: &EventType) -> Option<String> {
+    let root = event
+        .tags_named("e")
+        .find(|tag| tag.get(3).map(String::as_str) == Some("root"))
+        .or_else(|| event.tags_named("e").next())?;
+
+    root.get(1).cloned()
+}
+
+/// The id of the message `message` replies to, if it does
+pub fn reply_to(message: &EventType) -> Option<&str> {
+    message
+        .tags_named("e")
+        .find(|tag| tag.get(3).map(String::as_str) == Some("reply"))
+        .and_then(|tag| tag.get(1).map(String::as_str))
+}
+
+/// Builds the unsigned event of `pubkey` creating a channel
+pub fn create_channel(pubkey: &str, metadata: &ChannelMetadata) -> EventType {
+    let content = serde_json::to_string(metadata).unwrap_or_default();
+    EventType::new(pubkey, CHANNEL_CREATION_KIND, content)
+}
+
+/// Builds the unsigned event of `pubkey`, the creator of the channel with
+/// id `channel_id`, replacing its metadata. `relay` is where the channel
+/// can be found.
+pub fn set_metadata(
+    pubkey: &str,
+    channel_id: &str,
+    relay: Option<&str>,
+    metadata: &ChannelMetadata,
+) -> EventType {
+    let content = serde_json::to_string(metadata).unwrap_or_default();
+    let mut event = EventType::new(pubkey, CHANNEL_METADATA_KIND, content);
+    event.tags.push(root_tag(channel_id, relay));
+
+    event
+}
+
+/// Builds the unsigned message of `pubkey` to `channel`, replying to
+/// `reply_to` if it's given
+pub fn message(
+    pubkey: &str,
+    channel: &Channel,
+    content: String,
+    reply_to: Option<&EventType>,
+) -> EventType {
+    let mut event = EventType::new(pubkey, CHANNEL_MESSAGE_KIND, content);
+    event.tags.push(root_tag(&channel.id, channel.relay()));
+
+    if let Some(parent) = reply_to {
+        let relay = channel.relay().unwrap_or_default().to_string();
+        event.tags.push(vec![
+            "e".to_string(),
+            parent.id().unwrap_or_default().to_string(),
+            relay.clone(),
+            "reply".to_string(),
+        ]);
+        if parent.pubkey() != pubkey {
+            event
+                .tags
+                .push(vec!["p".to_string(), parent.pubkey(), relay]);
+        }
+    }
+
+    event
+}
+
+/// Builds the unsigned event of `pubkey` hiding the message with id
+/// `message_id` from themselves
+pub fn hide_message(pubkey: &str, message_id: &str, reason: &str) -> EventType {
+    let mut event = EventType::new(pubkey, CHANNEL_HIDE_MESSAGE_KIND, reason_content(reason));
+    event.add_tag("e", message_id, None);
+
+    event
+}
+
+/// Builds the unsigned event of `pubkey` muting `user` in the channels they
+/// read
+pub fn mute_user(pubkey: &str, user: &str, reason: &str) -> EventType {
+    let mut event = EventType::new(pubkey, CHANNEL_MUTE_USER_KIND, reason_content(reason));
+    event.add_tag("p", user, None);
+
+    event
+}
+
+fn root_tag(channel_id: &str, relay: Option<&str>) -> Vec<String> {
+    vec![
+        "e".to_string(),
+        channel_id.to_string(),
+        relay.unwrap_or_default().to_string(),
+        "root".to_string(),
+    ]
+}
+
+fn reason_content(reason: &str) -> String {
+    match reason.trim() {
+        "" => String::new(),
+        reason => json!({ "reason": reason }).to_string(),
+    }
+}
+
+/// Channels received so far with their messages, leaving out what the user
+/// hid or muted
+#[derive(Debug, Default)]
+pub struct Channels {
+    channels: HashMap<String, Channel>,
+    /// Messages of every channel, oldest first
+    messages: HashMap<String, Vec<EventType>>,
+    /// Metadata received before the channel it's for
+    pending: Vec<EventType>,
+    /// Messages the user hid
+    hidden: HashSet<String>,
+    /// Users the user muted
+    muted: HashSet<String>,
+}
+
+impl Channels {
+    pub fn new() -> Channels {
+        Channels::default()
+    }
+
+    /// Stores a channel, metadata event or message, returning the id of the
+    /// channel it's about if it was one
+    pub fn insert(&mut self, event: &EventType) -> Option<String> {
+        match event.kind {
+            CHANNEL_CREATION_KIND => {
+                let mut channel = Channel::from_creation(event).ok()?;
+                let id = channel.id.clone();
+                let pending = std::mem::take(&mut self.pending);
+                let (updates, pending) = pending
+                    .into_iter()
+                    .partition(|update| channel_id(update).as_deref() == Some(id.as_str()));
+                self.pending = pending;
+
+                let mut updates: Vec<EventType> = updates;
+                updates.sort_by_key(|update| update.created_at);
+                for update in &updates {
+                    channel.update(update);
+                }
+                self.channels.entry(id.clone()).or_insert(channel);
+                Some(id)
+            }
+            CHANNEL_METADATA_KIND => {
+                let id = channel_id(event)?;
+                match self.channels.get_mut(&id) {
+                    Some(channel) => channel.update(event).then_some(id),
+                    None => {
+                        self.pending.push(event.clone());
+                        None
+                    }
+                }
+            }
+            CHANNEL_MESSAGE_KIND => {
+                let id = channel_id(event)?;
+                let messages = self.messages.entry(id.clone()).or_default();
+                if messages.iter().any(|known| known.id() == event.id()) {
+                    return None;
+                }
+                let position =
+                    messages.partition_point(|known| known.created_at <= event.created_at);
+                messages.insert(position, event.clone());
+                Some(id)
+            }
+            _ => None,
+        }
+    }
+
+    /// Records a kind 43 or 44 event of the user
+    pub fn moderate(&mut self, event: &EventType) {
+        match event.kind {
+            CHANNEL_HIDE_MESSAGE_KIND => self
+                .hidden
+                .extend(event.tag_values("e").map(str::to_string)),
+            CHANNEL_MUTE_USER_KIND => self.muted.extend(event.tag_values("p").map(str::to_string)),
+            _ => {}
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Channel> {
+        self.channels.get(id)
+    }
+
+    /// The known channels, the ones with the latest messages first
+    pub fn list(&self) -> Vec<&Channel> {
+        let mut channels: Vec<&Channel> = self.channels.values().collect();
+        channels.sort_by_key(|channel| std::cmp::Reverse(self.last_active(&channel.id)));
+
+        channels
+    }
+
+    /// When something last happened in the channel with id `id`
+    pub fn last_active(&self, id: &str) -> u64 {
+        let created_at = self
+            .channels
+            .get(id)
+            .map_or(0, |channel| channel.created_at);
+        self.messages(id)
+            .last()
+            .map_or(created_at, |message| message.created_at.max(created_at))
+    }
+
+    /// The messages of the channel with id `id` the user didn't hide,
+    /// oldest first
+    pub fn messages(&self, id: &str) -> Vec<&EventType> {
+        self.messages
+            .get(id)
+            .into_iter()
+            .flatten()
+            .filter(|message| {
+                !message.id().is_some_and(|id| self.hidden.contains(id))
+                    && !self.muted.contains(&message.pubkey())
+            })
+            .collect()
+    }
+
+    /// Drops the messages `remove` returns true for, like deleted ones
+    pub fn remove_messages(&mut self, remove: impl Fn(&EventType) -> bool) {
+        for messages in self.messages.values_mut() {
+            messages.retain(|message| !remove(message));
+        }
+    }
+
+    /// Looks a message up by id, in any channel
+    pub fn find_message(&self, id: &str) -> Option<&EventType> {
+        self.messages
+            .values()
+            .flatten()
+            .find(|message| message.id() == Some(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::keys;
+
+    fn sign(secret_key: &str, mut event: EventType, created_at: u64) -> EventType {
+        event.created_at = created_at;
+        event
+            .setup(secret_key, &secp256k1::Secp256k1::new())
+            .unwrap();
+        event
+    }
+
+    #[test]
+    fn lets_the_creator_change_the_metadata() {
+        let (creator, creator_pubkey) = keys();
+        let (other, other_pubkey) = keys();
+        let metadata = ChannelMetadata {
+            name: "rust".to_string(),
+            about: Some("Talking about Rust".to_string()),
+            relays: vec!["wss://relay.example.com".to_string()],
+            ..ChannelMetadata::default()
+        };
+        let creation = sign(&creator, create_channel(&creator_pubkey, &metadata), 100);
+        let mut channel = Channel::from_creation(&creation).unwrap();
+
+        let mut changed = channel.metadata.clone();
+        changed.set("name", "rustaceans").unwrap();
+        changed.set("about", "").unwrap();
+        let update = set_metadata(&creator_pubkey, &channel.id, channel.relay(), &changed);
+        assert_eq!(update.kind, CHANNEL_METADATA_KIND);
+        assert_eq!(
+            update.tags,
+            vec![vec!["e", &channel.id, "wss://relay.example.com", "root"]]
+        );
+
+        let stranger = set_metadata(
+            &other_pubkey,
+            &channel.id,
+            None,
+            &ChannelMetadata::default(),
+        );
+        assert!(!channel.update(&sign(&other, stranger, 300)));
+        assert!(!channel.update(&sign(&creator, update.clone(), 100)));
+        assert!(channel.update(&sign(&creator, update, 200)));
+        assert_eq!(channel.display_name(), "rustaceans");
+        assert_eq!(channel.metadata.about.as_deref(), Some(""));
+        assert_eq!(channel.metadata.relays, metadata.relays);
+        assert_eq!(channel.updated_at, 200);
+    }
+
+    #[test]
+    fn sets_metadata_fields() {
+        let mut metadata = ChannelMetadata::default();
+
+        metadata
+            .set("picture", "https://example.com/rust.png")
+            .unwrap();
+        assert_eq!(
+            metadata.picture.as_deref(),
+            Some("https://example.com/rust.png")
+        );
+        assert!(metadata.set("name", "").is_err());
+        assert!(metadata.set("relays", "wss://relay.example.com").is_err());
+    }
+}
@@ -1,92 +1,451 @@
 #![allow(unused, dead_code)]
 
-use std::str::FromStr;
+use std::{collections::VecDeque, fmt, str::FromStr, time::Duration};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use futures_util::{SinkExt, StreamExt};
 use rand::distributions::{Alphanumeric, DistString};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use tokio::net::TcpStream;
+use secp256k1::{Secp256k1, VerifyOnly};
+use tokio::{net::TcpStream, time::timeout};
 use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
 
 use super::event::Event as EventType;
+use super::relay_info::{Limitation, RelayInformation};
 use super::request::{Filter, Request};
 use super::response::Response;
 
+const DEFAULT_SUBSCRIPTION_ID_LENGTH: usize = 64;
+
+/// How long `fetch` waits for a relay to finish sending stored events
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long `publish_event` waits for a relay to accept or refuse an event
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Error of a relay that only serves clients authenticating with NIP-42,
+/// which isn't supported
+#[derive(Debug)]
+pub struct AuthRequired(pub String);
+
+impl fmt::Display for AuthRequired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "relay {} requires authentication, which isn't supported",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for AuthRequired {}
+
+/// Error of a connection whose socket failed or was closed, which a new
+/// connection to the relay may get past
+#[derive(Debug)]
+pub struct Disconnected(pub String);
+
+impl fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "lost the connection to relay {}", self.0)
+    }
+}
+
+impl std::error::Error for Disconnected {}
+
 pub struct Connection {
-    subscription_id: String,
+    /// Subscriptions that were requested and not yet closed
+    subscriptions: Vec<String>,
+    /// Messages that arrived while waiting for something else, handed out
+    /// by `next_response` before reading from the socket again
+    pending: VecDeque<Response>,
+    secp: Secp256k1<VerifyOnly>,
 
     relay_url: String,
+    relay_info: Option<RelayInformation>,
     conn: WebSocketStream<MaybeTlsStream<TcpStream>>,
 }
 
 impl Connection {
     pub async fn new(relay_url: &str) -> Result<Connection> {
+        // Relays are not required to serve an information document, in which
+        // case we simply don't know their limits up front.
+        let relay_info = RelayInformation::fetch(relay_url).await.ok();
         let (mut conn, _) = connect_async(relay_url).await?;
 
         Ok(Connection {
-            subscription_id: generate_subscription_id(64),
+            subscriptions: Vec::new(),
+            pending: VecDeque::new(),
+            secp: Secp256k1::verification_only(),
             relay_url: relay_url.to_string(),
+            relay_info,
             conn,
         })
     }
 
+    pub fn relay_url(&self) -> &str {
+        &self.relay_url
+    }
+
+    /// The NIP-11 document fetched when the connection was opened, if any
+    pub fn relay_info(&self) -> Option<&RelayInformation> {
+        self.relay_info.as_ref()
+    }
+
+    /// Whether the relay says it only serves authenticated clients, so that
+    /// nothing can be read from or published to it
+    pub fn requires_auth(&self) -> bool {
+        self.limitation()
+            .is_some_and(|limitation| limitation.auth_required)
+    }
+
+    /// Sends `event` to the relay and waits for the `OK` message telling
+    /// whether it was accepted. A relay that doesn't answer in time fails.
     pub async fn publish_event(&mut self, event: EventType) -> Result<Response> {
+        self.check_event(&event)?;
+
+        let event_id = event
+            .id()
+            .ok_or(anyhow!("cannot publish an event without an id"))?
+            .to_string();
         let event_json = Request::Event { event };
-        let event_str = serde_json::to_string(&event_json).unwrap();
+        self.send(&event_json).await?;
 
-        self.conn.send(Message::Text(event_str)).await?;
+        let answer = async {
+            loop {
+                match self.read_response().await? {
+                    Some(response @ Response::Ok { .. }) if is_ok_for(&response, &event_id) => {
+                        return Ok(response)
+                    }
+                    Some(response) => self.pending.push_back(response),
+                    None => return Err(Disconnected(self.relay_url.clone()).into()),
+                }
+            }
+        };
 
-        let response = self.conn.next().await.unwrap().unwrap().to_string();
-        let response = Response::from_str(&response).unwrap();
+        timeout(PUBLISH_TIMEOUT, answer)
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("relay {} didn't answer in time", self.relay_url)))
+    }
 
-        println!("Got response: {:?}", response);
+    /// Sends a `REQ` to the relay and returns the id of the new subscription.
+    /// Matching events are read through `next_response`.
+    pub async fn subscribe(&mut self, filters: Vec<Filter>) -> Result<String> {
+        let subscription_id = generate_subscription_id(self.subscription_id_length());
+        self.resubscribe(&subscription_id, filters).await?;
 
-        Ok(response)
+        Ok(subscription_id)
     }
 
-    pub async fn subscribe(&mut self, filters: Vec<Filter>) -> Result<()> {
+    /// Sends a `REQ` for a subscription that keeps the given id, such as one
+    /// that was open on an earlier connection to the same relay
+    pub async fn resubscribe(
+        &mut self,
+        subscription_id: &str,
+        mut filters: Vec<Filter>,
+    ) -> Result<()> {
+        self.check_subscription(&mut filters)?;
+
         let request = Request::Req {
-            subscription_id: self.subscription_id.clone(),
+            subscription_id: subscription_id.to_string(),
             filters,
         };
-        let request_str = serde_json::to_string(&request).unwrap();
+        self.send(&request).await?;
 
-        self.conn.send(Message::Text(request_str)).await?;
+        self.subscriptions.push(subscription_id.to_string());
 
+        Ok(())
+    }
+
+    /// Requests the stored events matching `filters`, and closes the
+    /// subscription once the relay signals there are no more of them.
+    pub async fn fetch(&mut self, filters: Vec<Filter>) -> Result<Vec<EventType>> {
+        let subscription_id = self.subscribe(filters).await?;
+        let mut events = Vec::new();
+
+        let collect = async {
+            loop {
+                match self.read_response().await? {
+                    Some(Response::Event {
+                        subscription_id: id,
+                        event,
+                    }) if id == subscription_id => events.push(event),
+                    Some(Response::Eose {
+                        subscription_id: id,
+                    }) if id == subscription_id => return Ok(true),
+                    Some(Response::Closed {
+                        subscription_id: id,
+                        ..
+                    }) if id == subscription_id => return Ok(false),
+                    Some(response) => self.pending.push_back(response),
+                    None => return Err(anyhow!("relay {} closed the connection", self.relay_url)),
+                }
+            }
+        };
+
+        // A relay that never sends `EOSE` still gets its subscription closed
+        let still_open = timeout(FETCH_TIMEOUT, collect).await.unwrap_or(Ok(true))?;
+        if still_open {
+            self.close(&subscription_id).await?;
+        } else {
+            self.subscriptions.retain(|id| *id != subscription_id);
+        }
+
+        Ok(events)
+    }
+
+    /// Waits for the next message sent by the relay. Returns `None` once the
+    /// relay closes the connection.
+    pub async fn next_response(&mut self) -> Result<Option<Response>> {
+        let response = match self.pending.pop_front() {
+            Some(response) => Some(response),
+            None => self.read_response().await?,
+        };
+        if let Some(Response::Closed {
+            subscription_id, ..
+        }) = &response
+        {
+            self.subscriptions.retain(|id| id != subscription_id);
+        }
+
+        Ok(response)
+    }
+
+    /// Ends the subscription identified by `subscription_id`
+    pub async fn close(&mut self, subscription_id: &str) -> Result<()> {
+        let request = Request::Close {
+            subscription_id: subscription_id.to_string(),
+        };
+        self.send(&request).await?;
+
+        self.subscriptions.retain(|id| id != subscription_id);
+
+        Ok(())
+    }
+
+    /// Reads the next message from the socket, skipping the ones we can't use
+    async fn read_response(&mut self) -> Result<Option<Response>> {
         loop {
-            let response = match self.conn.next().await {
-                Some(Ok(data)) => data.to_string(),
-                Some(_) => {
-                    println!("Ignoring error variant...");
-                    continue;
+            let data = match self.conn.next().await {
+                Some(Ok(Message::Text(data))) => data,
+                // Pings, pongs and binary frames carry nothing for us
+                Some(Ok(_)) => continue,
+                Some(Err(err)) => {
+                    log::debug!("failed to read from {}: {}", self.relay_url, err);
+                    return Err(Disconnected(self.relay_url.clone()).into());
                 }
-                None => break,
+                None => return Ok(None),
+            };
+
+            // Relays may speak newer parts of the protocol than we do, which
+            // shouldn't cut short what we're waiting for
+            let Ok(response) = Response::from_str(&data) else {
+                log::debug!("skipping message from {}: {}", self.relay_url, data);
+                continue;
             };
 
-            let response = Response::from_str(&response).unwrap();
-            println!("Got: {:?}", response);
+            match &response {
+                // Events with an invalid signature are dropped right away
+                Response::Event { event, .. } if event.verify(&self.secp).is_err() => continue,
+                Response::Closed {
+                    subscription_id,
+                    message,
+                } => log::debug!(
+                    "{} closed subscription {}: {}",
+                    self.relay_url,
+                    subscription_id,
+                    message
+                ),
+                Response::Notice { message } => {
+                    log::debug!("notice from {}: {}", self.relay_url, message)
+                }
+                Response::Auth { challenge } => {
+                    log::debug!("{} asks to authenticate: {}", self.relay_url, challenge)
+                }
+                _ => {}
+            }
+
+            return Ok(Some(response));
+        }
+    }
+
+    async fn send(&mut self, request: &Request) -> Result<()> {
+        let request_str = serde_json::to_string(request)?;
+
+        if let Some(max) = self.limitation().and_then(|l| l.max_message_length) {
+            if request_str.len() > max {
+                return Err(anyhow!(
+                    "message of {} bytes exceeds the relay limit of {} bytes",
+                    request_str.len(),
+                    max
+                ));
+            }
+        }
+
+        if let Err(err) = self.conn.send(Message::Text(request_str)).await {
+            log::debug!("failed to send to {}: {}", self.relay_url, err);
+            return Err(Disconnected(self.relay_url.clone()).into());
         }
 
         Ok(())
     }
 
-    pub async fn close(&mut self, event: EventType) -> Result<Response> {
-        let event_json = Request::Event { event };
-        let event_str = serde_json::to_string(&event_json).unwrap();
-        println!("Sending event: {:?}", event_str);
+    fn limitation(&self) -> Option<&Limitation> {
+        self.relay_info.as_ref()?.limitation.as_ref()
+    }
 
-        self.conn.send(Message::Text(event_str)).await?;
+    fn subscription_id_length(&self) -> usize {
+        self.limitation()
+            .and_then(|l| l.max_subid_length)
+            .map_or(DEFAULT_SUBSCRIPTION_ID_LENGTH, |max| {
+                max.min(DEFAULT_SUBSCRIPTION_ID_LENGTH)
+            })
+    }
 
-        let response = self.conn.next().await.unwrap().unwrap().to_string();
-        let response = Response::from_str(&response).unwrap();
+    /// Makes sure the relay is willing to accept `event` before sending it
+    fn check_event(&self, event: &EventType) -> Result<()> {
+        let Some(limitation) = self.limitation() else {
+            return Ok(());
+        };
 
-        Ok(response)
+        if limitation.auth_required {
+            return Err(AuthRequired(self.relay_url.clone()).into());
+        }
+        if limitation.payment_required {
+            return Err(anyhow!("relay {} requires payment", self.relay_url));
+        }
+        if let Some(max) = limitation.max_event_tags {
+            if event.tags.len() > max {
+                return Err(anyhow!("event has more than {} tags", max));
+            }
+        }
+        if let Some(max) = limitation.max_content_length {
+            if event.content.chars().count() > max {
+                return Err(anyhow!("event content is longer than {} characters", max));
+            }
+        }
+
+        Ok(())
     }
+
+    /// Makes sure the relay is willing to accept a new subscription with
+    /// `filters`, lowering their limits to what the relay allows
+    fn check_subscription(&self, filters: &mut [Filter]) -> Result<()> {
+        let Some(limitation) = self.limitation() else {
+            return Ok(());
+        };
+
+        if limitation.auth_required {
+            return Err(AuthRequired(self.relay_url.clone()).into());
+        }
+        if let Some(max) = limitation.max_subscriptions {
+            if self.subscriptions.len() >= max {
+                return Err(anyhow!("relay allows at most {} subscriptions", max));
+            }
+        }
+        if let Some(max) = limitation.max_filters {
+            if filters.len() > max {
+                return Err(anyhow!("relay allows at most {} filters", max));
+            }
+        }
+        if let Some(max) = limitation.max_limit {
+            for filter in filters.iter_mut() {
+                filter.clamp_limit(max);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn is_ok_for(response: &Response, event_id: &str) -> bool {
+    matches!(response, Response::Ok { event_id: id, .. } if id == event_id)
 }
 
 fn generate_subscription_id(len: usize) -> String {
     Alphanumeric.sample_string(&mut rand::thread_rng(), len)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed, TestRelay};
+
+    #[tokio::test]
+    async fn skips_messages_it_cant_use() {
+        let relay = TestRelay::start().await;
+        let mut conn = Connection::new(&relay.url).await.unwrap();
+        // Once a fetch went through, the relay is listening for what to send
+        conn.fetch(vec![Filter::new().kinds(vec![1])])
+            .await
+            .unwrap();
+
+        let (secret, _) = keys();
+        let note = signed(&secret, 1, "hello", vec![]);
+        let mut forged = note.clone();
+        forged.content = "goodbye".to_string();
+
+        relay.send_raw("not json");
+        relay.send_raw(r#"["COUNT","sub",{"count":3}]"#);
+        relay.send_raw(r#"["EVENT","sub",{"content":"no signature"}]"#);
+        relay.send_raw(&json!(["EVENT", "sub", forged]).to_string());
+        relay.send_raw(r#"["NOTICE","slow down"]"#);
+        relay.send_raw(&json!(["EVENT", "sub", note]).to_string());
+
+        match conn.next_response().await.unwrap() {
+            Some(Response::Notice { message }) => assert_eq!(message, "slow down"),
+            other => panic!("expected the notice, got {:?}", other),
+        }
+        match conn.next_response().await.unwrap() {
+            Some(Response::Event { event, .. }) => assert_eq!(event.id(), note.id()),
+            other => panic!("expected the note, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn forgets_subscriptions_closed_while_fetching() {
+        let relay = TestRelay::start().await;
+        let mut conn = Connection::new(&relay.url).await.unwrap();
+        conn.fetch(vec![Filter::new().kinds(vec![1])])
+            .await
+            .unwrap();
+
+        let subscription_id = conn
+            .subscribe(vec![Filter::new().kinds(vec![1])])
+            .await
+            .unwrap();
+        relay.send_raw(&json!(["CLOSED", subscription_id, "error: too many"]).to_string());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The relay's answers are kept aside by the fetch, then handed out
+        conn.fetch(vec![Filter::new().kinds(vec![1])])
+            .await
+            .unwrap();
+        assert_eq!(conn.pending.len(), 2);
+        assert_eq!(conn.subscriptions, vec![subscription_id]);
+        conn.next_response().await.unwrap();
+        conn.next_response().await.unwrap();
+        assert!(conn.subscriptions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reads_the_relay_information() {
+        let info = json!({
+            "name": "test relay",
+            "pubkey": "ab".repeat(32),
+            "limitation": { "max_message_length": 64 },
+        });
+        let relay = TestRelay::start_with_info(Some(info)).await;
+        let mut conn = Connection::new(&relay.url).await.unwrap();
+
+        let relay_info = conn.relay_info().unwrap();
+        assert_eq!(relay_info.name.as_deref(), Some("test relay"));
+        assert_eq!(relay_info.pubkey, Some("ab".repeat(32)));
+
+        // Requests longer than the relay takes aren't sent
+        let filter = Filter::new().authors(vec!["ab".repeat(32)]);
+        assert!(conn.subscribe(vec![filter]).await.is_err());
+    }
+}
@@ -0,0 +1,306 @@
+use anyhow::{anyhow, Result};
+
+use super::event::{unix_time, Event as EventType, TEXT_NOTE_KIND};
+use super::file_metadata::FILE_METADATA_KIND;
+use super::pool::{any_accepted, RelayPool};
+use super::repost::{GENERIC_REPOST_KIND, REPOST_KIND};
+use super::request::Filter;
+use super::signer::Signer;
+
+/// Replaceable event kind holding the list of users someone follows, as
+/// described by NIP-02
+pub const CONTACT_LIST_KIND: u64 = 3;
+
+/// How many notes the home feed asks each relay for when it's opened
+const HOME_FEED_LIMIT: u64 = 100;
+
+/// A followed user, with an optional relay where their notes can be found
+/// and a local name given to them by the follower
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contact {
+    pub pubkey: String,
+    pub relay_url: Option<String>,
+    pub petname: Option<String>,
+}
+
+impl Contact {
+    pub fn new(pubkey: &str) -> Contact {
+        Contact {
+            pubkey: pubkey.to_string(),
+            relay_url: None,
+            petname: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ContactList {
+    pub contacts: Vec<Contact>,
+    pub created_at: u64,
+
+    /// Tags other than `p` and the content, which some clients use for a
+    /// legacy relay list. They are kept as is, so republishing the list
+    /// doesn't drop data written by other clients.
+    other_tags: Vec<Vec<String>>,
+    content: String,
+}
+
+impl ContactList {
+    pub fn from_event(event: &EventType) -> Result<ContactList> {
+        if event.kind != CONTACT_LIST_KIND {
+            return Err(anyhow!("expected a kind {} event", CONTACT_LIST_KIND));
+        }
+
+        let mut list = ContactList {
+            created_at: event.created_at,
+            content: event.content.clone(),
+            ..Default::default()
+        };
+
+        for tag in &event.tags {
+            if tag.first().map(String::as_str) != Some("p") {
+                list.other_tags.push(tag.clone());
+                continue;
+            }
+
+            let Some(pubkey) = tag.get(1) else {
+                continue;
+            };
+            let non_empty = |value: Option<&String>| value.filter(|v| !v.is_empty()).cloned();
+
+            list.add(Contact {
+                pubkey: pubkey.clone(),
+                relay_url: non_empty(tag.get(2)),
+                petname: non_empty(tag.get(3)),
+            });
+        }
+
+        Ok(list)
+    }
+
+    /// Builds the unsigned kind 3 event publishing this list
+    pub fn to_event(&self, pubkey: &str) -> EventType {
+        let mut event = EventType::new(pubkey, CONTACT_LIST_KIND, self.content.clone());
+
+        for contact in &self.contacts {
+            let mut tag = vec!["p".to_string(), contact.pubkey.clone()];
+            if contact.relay_url.is_some() || contact.petname.is_some() {
+                tag.push(contact.relay_url.clone().unwrap_or_default());
+            }
+            if let Some(petname) = &contact.petname {
+                tag.push(petname.clone());
+            }
+            event.tags.push(tag);
+        }
+        event.tags.extend(self.other_tags.iter().cloned());
+
+        event
+    }
+
+    pub fn pubkeys(&self) -> Vec<String> {
+        self.contacts
+            .iter()
+            .map(|contact| contact.pubkey.clone())
+            .collect()
+    }
+
+    pub fn contact(&self, pubkey: &str) -> Option<&Contact> {
+        self.contacts
+            .iter()
+            .find(|contact| contact.pubkey == pubkey)
+    }
+
+    pub fn follows(&self, pubkey: &str) -> bool {
+        self.contact(pubkey).is_some()
+    }
+
+    /// Adds `contact` to the list, replacing the entry of the same user
+    pub fn add(&mut self, contact: Contact) {
+        match self
+            .contacts
+            .iter_mut()
+            .find(|c| c.pubkey == contact.pubkey)
+        {
+            Some(known) => *known = contact,
+            None => self.contacts.push(contact),
+        }
+    }
+
+    /// Removes `pubkey` from the list, returning whether it was there
+    pub fn remove(&mut self, pubkey: &str) -> bool {
+        let len = self.contacts.len();
+        self.contacts.retain(|contact| contact.pubkey != pubkey);
+
+        self.contacts.len() != len
+    }
+
+    /// The filter for the home feed, notes and reposts, and the authors it
+    /// should be sent for: every followed user, plus the owner of the list
+    pub fn home_feed(&self, own_pubkey: &str) -> (Filter, Vec<String>) {
+        let mut authors = self.pubkeys();
+        if !authors.iter().any(|author| author == own_pubkey) {
+            authors.push(own_pubkey.to_string());
+        }
+
+        let filter = Filter::new()
+            .kinds(vec![
+                TEXT_NOTE_KIND,
+                REPOST_KIND,
+                GENERIC_REPOST_KIND,
+                FILE_METADATA_KIND,
+            ])
+            .limit(HOME_FEED_LIMIT);
+
+        (filter, authors)
+    }
+
+    /// Fetches the newest contact list published by `pubkey`
+    pub async fn fetch(pool: &mut RelayPool, pubkey: &str) -> Option<ContactList> {
+        let event = ContactList::fetch_event(pool, pubkey).await?;
+
+        ContactList::from_event(&event).ok()
+    }
+
+    /// Fetches the newest kind 3 event published by `pubkey`
+    pub async fn fetch_event(pool: &mut RelayPool, pubkey: &str) -> Option<EventType> {
+        let filter = Filter::new().kinds(vec![CONTACT_LIST_KIND]);
+
+        pool.fetch_from_authors(filter, &[pubkey.to_string()])
+            .await
+            .into_iter()
+            .filter(|event| event.pubkey() == pubkey)
+            .max_by_key(|event| event.created_at)
+    }
+}
+
+/// Follows `contact` and republishes the contact list, see `update`
+pub async fn follow(
+    pool: &mut RelayPool,
+    known: Option<&ContactList>,
+    contact: Contact,
+    signer: &mut Signer,
+) -> Result<EventType> {
+    update(pool, known, signer, |list| list.add(contact)).await
+}
+
+/// Unfollows `pubkey` and republishes the contact list, see `update`
+pub async fn unfollow(
+    pool: &mut RelayPool,
+    known: Option<&ContactList>,
+    pubkey: &str,
+    signer: &mut Signer,
+) -> Result<EventType> {
+    update(pool, known, signer, |list| {
+        list.remove(pubkey);
+    })
+    .await
+}
+
+/// Applies `change` to the user's contact list and publishes the result,
+/// which is returned.
+///
+/// The list may have been changed from another device since `known` was
+/// loaded, so the newest version is fetched again and used as the base when
+/// it's newer. The published list is always dated after the one it replaces.
+///
+/// `known` is the list fetched earlier, or an empty one when the user
+/// starts a new list. Without either, nothing is published: a list that
+/// couldn't be fetched this time would be replaced by one with only the
+/// change.
+pub async fn update(
+    pool: &mut RelayPool,
+    known: Option<&ContactList>,
+    signer: &mut Signer,
+    change: impl FnOnce(&mut ContactList),
+) -> Result<EventType> {
+    let pubkey = signer.pubkey().to_string();
+
+    let mut list = match (ContactList::fetch(pool, &pubkey).await, known) {
+        (Some(latest), Some(known)) if known.created_at >= latest.created_at => known.clone(),
+        (Some(latest), _) => latest,
+        (None, Some(known)) => known.clone(),
+        (None, None) => return Err(anyhow!("could not fetch the contact list")),
+    };
+    change(&mut list);
+
+    let mut event = list.to_event(&pubkey);
+    event.created_at = unix_time().max(list.created_at + 1);
+    signer.sign(&mut event).await?;
+
+    if !any_accepted(&pool.publish(&event).await) {
+        return Err(anyhow!("no relay accepted the contact list"));
+    }
+
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed, TestRelay};
+
+    #[tokio::test]
+    async fn refuses_to_replace_a_list_that_was_not_fetched() {
+        let relay = TestRelay::start().await;
+        let mut pool = RelayPool::new(vec![relay.url.clone()]);
+        let (secret_key, _) = keys();
+        let mut signer = Signer::local(&secret_key).unwrap();
+        let (_, followed) = keys();
+
+        let published = follow(&mut pool, None, Contact::new(&followed), &mut signer).await;
+
+        assert!(published.is_err());
+        assert!(relay.events().is_empty());
+    }
+
+    #[tokio::test]
+    async fn starts_a_new_list_when_asked() {
+        let relay = TestRelay::start().await;
+        let mut pool = RelayPool::new(vec![relay.url.clone()]);
+        let (secret_key, _) = keys();
+        let mut signer = Signer::local(&secret_key).unwrap();
+        let (_, followed) = keys();
+
+        let new_list = ContactList::default();
+        let event = follow(
+            &mut pool,
+            Some(&new_list),
+            Contact::new(&followed),
+            &mut signer,
+        )
+        .await
+        .unwrap();
+
+        let list = ContactList::from_event(&event).unwrap();
+        assert_eq!(list.pubkeys(), vec![followed]);
+    }
+
+    #[tokio::test]
+    async fn updates_the_newest_list() {
+        let relay = TestRelay::start().await;
+        let mut pool = RelayPool::new(vec![relay.url.clone()]);
+        let (secret_key, _) = keys();
+        let mut signer = Signer::local(&secret_key).unwrap();
+        let (_, followed) = keys();
+        let (_, unfollowed) = keys();
+        let (_, new) = keys();
+        relay.publish(signed(
+            &secret_key,
+            CONTACT_LIST_KIND,
+            "",
+            vec![vec!["p", &followed], vec!["p", &unfollowed]],
+        ));
+
+        let event = unfollow(&mut pool, None, &unfollowed, &mut signer)
+            .await
+            .unwrap();
+        let list = ContactList::from_event(&event).unwrap();
+        assert_eq!(list.pubkeys(), vec![followed.clone()]);
+
+        let event = follow(&mut pool, Some(&list), Contact::new(&new), &mut signer)
+            .await
+            .unwrap();
+        let list = ContactList::from_event(&event).unwrap();
+        assert_eq!(list.pubkeys(), vec![followed, new]);
+    }
+}
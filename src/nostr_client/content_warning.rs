@@ -0,0 +1,11 @@
+use super::event::Event as EventType;
+
+/// The reason readers are warned about the content of `event` for, from
+/// its `content-warning` tag as described by NIP-36. The reason may be
+/// empty.
+pub fn content_warning(event: &EventType) -> Option<&str> {
+    event
+        .tags_named("content-warning")
+        .next()
+        .map(|tag| tag.get(1).map(String::as_str).unwrap_or_default().trim())
+}
@@ -0,0 +1,173 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+
+use super::event::Event as EventType;
+
+pub const DELETION_KIND: u64 = 5;
+
+/// A kind 5 event, asking for events of its author to be deleted as
+/// described by NIP-09
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deletion {
+    pub author: String,
+    /// Ids of the events to delete
+    pub event_ids: Vec<String>,
+    /// Coordinates of the replaceable or addressable events to delete, in
+    /// every version up to `created_at`
+    pub addresses: Vec<String>,
+    /// Kinds of the events to delete
+    pub kinds: Vec<u64>,
+    pub reason: String,
+    pub created_at: u64,
+}
+
+impl Deletion {
+    pub fn from_event(event: &EventType) -> Result<Deletion> {
+        if event.kind != DELETION_KIND {
+            return Err(anyhow!("Event of kind {} is not a deletion", event.kind));
+        }
+
+        Ok(Deletion {
+            author: event.pubkey(),
+            event_ids: event.tag_values("e").map(str::to_string).collect(),
+            addresses: event.tag_values("a").map(str::to_string).collect(),
+            kinds: event
+                .tag_values("k")
+                .filter_map(|kind| kind.parse().ok())
+                .collect(),
+            reason: event.content.clone(),
+            created_at: event.created_at,
+        })
+    }
+}
+
+/// Builds the unsigned request of `pubkey` to delete `events`, which should
+/// all be theirs
+pub fn delete(events: &[&EventType], pubkey: &str, reason: &str) -> EventType {
+    let mut event = EventType::new(pubkey, DELETION_KIND, reason.to_string());
+
+    let mut kinds = BTreeSet::new();
+    for target in events {
+        match target.address() {
+            Some(address) => event.add_tag("a", &address, None),
+            None => event.add_tag("e", target.id().unwrap_or_default(), None),
+        }
+        kinds.insert(target.kind);
+    }
+    for kind in kinds {
+        event.add_tag("k", &kind.to_string(), None);
+    }
+
+    event
+}
+
+/// Deletion requests received so far. A request only applies to events of
+/// its own author, since anyone can publish one.
+#[derive(Debug, Default)]
+pub struct Deletions {
+    /// Authors of the deletion requests naming each event id. Anyone can
+    /// name any id, so all of them are kept and only the event's own author
+    /// counts.
+    events: HashMap<String, HashSet<String>>,
+    /// Newest deletion of each coordinate, covering the versions created
+    /// until then
+    addresses: HashMap<String, u64>,
+}
+
+impl Deletions {
+    pub fn new() -> Deletions {
+        Deletions::default()
+    }
+
+    /// Records the deletion request `event`, returning whether it was one
+    pub fn insert(&mut self, event: &EventType) -> bool {
+        let Ok(deletion) = Deletion::from_event(event) else {
+            return false;
+        };
+
+        for id in deletion.event_ids {
+            self.events
+                .entry(id)
+                .or_default()
+                .insert(deletion.author.clone());
+        }
+        for address in deletion.addresses {
+            // Coordinates of someone else's events are ignored
+            if address.split(':').nth(1) != Some(deletion.author.as_str()) {
+                continue;
+            }
+            let deleted_until = self.addresses.entry(address).or_default();
+            *deleted_until = (*deleted_until).max(deletion.created_at);
+        }
+        true
+    }
+
+    /// Whether the author of `event` asked for it to be deleted
+    pub fn is_deleted(&self, event: &EventType) -> bool {
+        let by_id = event
+            .id()
+            .and_then(|id| self.events.get(id))
+            .is_some_and(|authors| authors.contains(&event.pubkey()));
+        let by_address = event
+            .address()
+            .and_then(|address| self.addresses.get(&address))
+            .is_some_and(|deleted_until| event.created_at <= *deleted_until);
+
+        by_id || by_address
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::Secp256k1;
+
+    use super::*;
+    use crate::nostr_client::event::{pubkey_from_secret, unix_time};
+    use crate::nostr_client::testing::{keys, signed};
+
+    /// The deletion of `target` requested by the owner of `secret_key`
+    fn deletion(secret_key: &str, target: &EventType) -> EventType {
+        let secp = Secp256k1::new();
+        let pubkey = pubkey_from_secret(secret_key, &secp).unwrap();
+        let mut event = delete(&[target], &pubkey, "");
+        event.setup(secret_key, &secp).unwrap();
+        event
+    }
+
+    #[test]
+    fn only_the_author_deletes_their_events() {
+        let (author, _) = keys();
+        let (other, _) = keys();
+        let note = signed(&author, 1, "hello", vec![]);
+        let mut deletions = Deletions::new();
+
+        deletions.insert(&deletion(&other, &note));
+        assert!(!deletions.is_deleted(&note));
+
+        // Requests of others naming the note too don't hide the author's own
+        deletions.insert(&deletion(&author, &note));
+        deletions.insert(&deletion(&other, &note));
+        assert!(deletions.is_deleted(&note));
+    }
+
+    #[test]
+    fn deletes_addresses_up_to_the_request() {
+        let (author, _) = keys();
+        let (other, _) = keys();
+        let mut article = signed(&author, 30023, "draft", vec![vec!["d", "post"]]);
+        article.created_at = 100;
+        let mut deletions = Deletions::new();
+
+        deletions.insert(&deletion(&other, &article));
+        assert!(!deletions.is_deleted(&article));
+
+        deletions.insert(&deletion(&author, &article));
+        assert!(deletions.is_deleted(&article));
+
+        // Versions published after the request stay
+        let mut edited = signed(&author, 30023, "edited", vec![vec!["d", "post"]]);
+        edited.created_at = unix_time() + 60;
+        assert!(!deletions.is_deleted(&edited));
+    }
+}
@@ -0,0 +1,432 @@
+use std::str::FromStr;
+
+use aes::cipher::{
+    block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit, StreamCipher,
+};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20::ChaCha20;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use secp256k1::{ecdh, Parity, PublicKey, SecretKey, XOnlyPublicKey};
+use sha2::Sha256;
+
+/// Version byte of the NIP-44 payloads this module reads and writes
+const NIP44_VERSION: u8 = 2;
+
+/// Salt the NIP-44 conversation key is derived with
+const NIP44_SALT: &[u8] = b"nip44-v2";
+
+/// Separates the ciphertext from the IV in NIP-04 payloads
+const NIP04_IV_SEPARATOR: &str = "?iv=";
+
+/// The x coordinate of the point shared by `secret_key` and `pubkey`,
+/// which both NIP-04 and NIP-44 derive their keys from
+fn shared_x(secret_key: &str, pubkey: &str) -> Result<[u8; 32]> {
+    let secret_key = SecretKey::from_str(secret_key)?;
+    let pubkey = XOnlyPublicKey::from_str(pubkey)?;
+    let point = ecdh::shared_secret_point(
+        &PublicKey::from_x_only_public_key(pubkey, Parity::Even),
+        &secret_key,
+    );
+
+    let mut x = [0; 32];
+    x.copy_from_slice(&point[..32]);
+    Ok(x)
+}
+
+/// The key two users share to talk to each other with NIP-44, which is
+/// the same from both sides
+pub fn conversation_key(secret_key: &str, pubkey: &str) -> Result<[u8; 32]> {
+    let (key, _) = Hkdf::<Sha256>::extract(Some(NIP44_SALT), &shared_x(secret_key, pubkey)?);
+
+    Ok(key.into())
+}
+
+/// Encrypts `plaintext` from the owner of `secret_key` to `pubkey`, as
+/// described by NIP-44
+pub fn nip44_encrypt(secret_key: &str, pubkey: &str, plaintext: &str) -> Result<String> {
+    let mut nonce = [0; 32];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    nip44_encrypt_with_nonce(&conversation_key(secret_key, pubkey)?, &nonce, plaintext)
+}
+
+fn nip44_encrypt_with_nonce(
+    conversation_key: &[u8; 32],
+    nonce: &[u8; 32],
+    plaintext: &str,
+) -> Result<String> {
+    let (chacha_key, chacha_nonce, hmac_key) = message_keys(conversation_key, nonce)?;
+
+    let mut ciphertext = pad(plaintext)?;
+    ChaCha20::new(&chacha_key.into(), &chacha_nonce.into()).apply_keystream(&mut ciphertext);
+    let mac = mac(&hmac_key, nonce, &ciphertext)?;
+
+    let mut payload = vec![NIP44_VERSION];
+    payload.extend_from_slice(nonce);
+    payload.extend_from_slice(&ciphertext);
+    payload.extend_from_slice(&mac);
+    Ok(BASE64.encode(payload))
+}
+
+/// Decrypts a NIP-44 `payload` sent between the owner of `secret_key` and
+/// `pubkey`, in either direction
+pub fn nip44_decrypt(secret_key: &str, pubkey: &str, payload: &str) -> Result<String> {
+    nip44_decrypt_with_key(&conversation_key(secret_key, pubkey)?, payload)
+}
+
+fn nip44_decrypt_with_key(conversation_key: &[u8; 32], payload: &str) -> Result<String> {
+    if payload.starts_with('#') {
+        return Err(anyhow!("Unsupported encryption version"));
+    }
+    if !(132..=87472).contains(&payload.len()) {
+        return Err(anyhow!("Invalid payload length {}", payload.len()));
+    }
+
+    let data = BASE64.decode(payload)?;
+    if !(99..=65603).contains(&data.len()) {
+        return Err(anyhow!("Invalid payload length {}", data.len()));
+    }
+    if data[0] != NIP44_VERSION {
+        return Err(anyhow!("Unsupported encryption version {}", data[0]));
+    }
+
+    let nonce: [u8; 32] = data[1..33].try_into()?;
+    let (ciphertext, received_mac) = data[33..].split_at(data.len() - 33 - 32);
+    let (chacha_key, chacha_nonce, hmac_key) = message_keys(conversation_key, &nonce)?;
+
+    let mut verifier = Hmac::<Sha256>::new_from_slice(&hmac_key)?;
+    verifier.update(&nonce);
+    verifier.update(ciphertext);
+    verifier
+        .verify_slice(received_mac)
+        .map_err(|_| anyhow!("Invalid MAC"))?;
+
+    let mut padded = ciphertext.to_vec();
+    ChaCha20::new(&chacha_key.into(), &chacha_nonce.into()).apply_keystream(&mut padded);
+    unpad(&padded)
+}
+
+/// The ChaCha20 key and nonce, and the HMAC key, of a single message
+fn message_keys(
+    conversation_key: &[u8; 32],
+    nonce: &[u8; 32],
+) -> Result<([u8; 32], [u8; 12], [u8; 32])> {
+    let hkdf = Hkdf::<Sha256>::from_prk(conversation_key)
+        .map_err(|_| anyhow!("Invalid conversation key"))?;
+    let mut keys = [0; 76];
+    hkdf.expand(nonce, &mut keys)
+        .map_err(|_| anyhow!("Could not derive the message keys"))?;
+
+    Ok((
+        keys[..32].try_into()?,
+        keys[32..44].try_into()?,
+        keys[44..].try_into()?,
+    ))
+}
+
+fn mac(hmac_key: &[u8; 32], nonce: &[u8; 32], ciphertext: &[u8]) -> Result<[u8; 32]> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_key)?;
+    mac.update(nonce);
+    mac.update(ciphertext);
+
+    Ok(mac.finalize().into_bytes().into())
+}
+
+/// The length messages of `length` bytes are padded to, so that their
+/// exact length doesn't leak
+fn padded_length(length: usize) -> usize {
+    if length <= 32 {
+        return 32;
+    }
+
+    let next_power = 1 << (usize::BITS - (length - 1).leading_zeros());
+    let chunk = if next_power <= 256 {
+        32
+    } else {
+        next_power / 8
+    };
+    chunk * ((length - 1) / chunk + 1)
+}
+
+fn pad(plaintext: &str) -> Result<Vec<u8>> {
+    let length = plaintext.len();
+    if !(1..=u16::MAX as usize).contains(&length) {
+        return Err(anyhow!("Messages must be between 1 and 65535 bytes long"));
+    }
+
+    let mut padded = (length as u16).to_be_bytes().to_vec();
+    padded.extend_from_slice(plaintext.as_bytes());
+    padded.resize(2 + padded_length(length), 0);
+    Ok(padded)
+}
+
+fn unpad(padded: &[u8]) -> Result<String> {
+    let [high, low, rest @ ..] = padded else {
+        return Err(anyhow!("Invalid padding"));
+    };
+    let length = u16::from_be_bytes([*high, *low]) as usize;
+    if length == 0 || rest.len() != padded_length(length) {
+        return Err(anyhow!("Invalid padding"));
+    }
+
+    Ok(String::from_utf8(rest[..length].to_vec())?)
+}
+
+/// Encrypts `plaintext` from the owner of `secret_key` to `pubkey` the way
+/// NIP-04 did, for peers that don't support NIP-44 yet
+pub fn nip04_encrypt(secret_key: &str, pubkey: &str, plaintext: &str) -> Result<String> {
+    let key = shared_x(secret_key, pubkey)?;
+    let mut iv = [0; 16];
+    rand::thread_rng().fill_bytes(&mut iv);
+
+    let ciphertext = cbc::Encryptor::<aes::Aes256>::new(&key.into(), &iv.into())
+        .encrypt_padded_vec_mut::<Pkcs7>(plaintext.as_bytes());
+    Ok(format!(
+        "{}{}{}",
+        BASE64.encode(ciphertext),
+        NIP04_IV_SEPARATOR,
+        BASE64.encode(iv)
+    ))
+}
+
+/// Decrypts a NIP-04 `payload` sent between the owner of `secret_key` and
+/// `pubkey`, in either direction
+pub fn nip04_decrypt(secret_key: &str, pubkey: &str, payload: &str) -> Result<String> {
+    let (ciphertext, iv) = payload
+        .split_once(NIP04_IV_SEPARATOR)
+        .ok_or(anyhow!("Missing the IV"))?;
+    let iv: [u8; 16] = BASE64
+        .decode(iv)?
+        .try_into()
+        .map_err(|_| anyhow!("The IV must be 16 bytes long"))?;
+    let key = shared_x(secret_key, pubkey)?;
+
+    let plaintext = cbc::Decryptor::<aes::Aes256>::new(&key.into(), &iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(&BASE64.decode(ciphertext)?)
+        .map_err(|_| anyhow!("Invalid padding"))?;
+    Ok(String::from_utf8(plaintext)?)
+}
+
+/// Whether `payload` was encrypted with NIP-04, telling it apart from
+/// NIP-44 payloads by the IV only NIP-04 ones carry
+pub fn is_nip04(payload: &str) -> bool {
+    payload.contains(NIP04_IV_SEPARATOR)
+}
+
+/// Decrypts `payload` whichever of NIP-04 and NIP-44 it was encrypted with
+pub fn decrypt(secret_key: &str, pubkey: &str, payload: &str) -> Result<String> {
+    match is_nip04(payload) {
+        true => nip04_decrypt(secret_key, pubkey, payload),
+        false => nip44_decrypt(secret_key, pubkey, payload),
+    }
+}
+
+/// Vectors from the NIP-44 specification, see
+/// https://github.com/paulmillr/nip44/blob/main/nip44.vectors.json
+#[cfg(test)]
+mod tests {
+    use secp256k1::Secp256k1;
+
+    use super::*;
+    use crate::nostr_client::event::pubkey_from_secret;
+
+    fn key(hex: &str) -> [u8; 32] {
+        hex::decode(hex).unwrap().try_into().unwrap()
+    }
+
+    fn pubkey(secret_key: &str) -> String {
+        pubkey_from_secret(secret_key, &Secp256k1::new()).unwrap()
+    }
+
+    #[test]
+    fn derives_conversation_keys() {
+        let vectors = [
+            (
+                "315e59ff51cb9209768cf7da80791ddcaae56ac9775eb25b6dee1234bc5d2268",
+                "c2f9d9948dc8c7c38321e4b85c8558872eafa0641cd269db76848a6073e69133",
+                "3dfef0ce2a4d80a25e7a328accf73448ef67096f65f79588e358d9a0eb9013f1",
+            ),
+            (
+                "a1e37752c9fdc1273be53f68c5f74be7c8905728e8de75800b94262f9497c86e",
+                "03bb7947065dde12ba991ea045132581d0954f042c84e06d8c00066e23c1a800",
+                "4d14f36e81b8452128da64fe6f1eae873baae2f444b02c950b90e43553f2178b",
+            ),
+        ];
+
+        for (secret_key, pubkey, expected) in vectors {
+            assert_eq!(conversation_key(secret_key, pubkey).unwrap(), key(expected));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        let vectors = [
+            // The secret key is out of the curve order
+            (
+                "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                "c2f9d9948dc8c7c38321e4b85c8558872eafa0641cd269db76848a6073e69133",
+            ),
+            (
+                "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141",
+                "c2f9d9948dc8c7c38321e4b85c8558872eafa0641cd269db76848a6073e69133",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "c2f9d9948dc8c7c38321e4b85c8558872eafa0641cd269db76848a6073e69133",
+            ),
+            // The public key isn't on the curve
+            (
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            ),
+        ];
+
+        for (secret_key, pubkey) in vectors {
+            assert!(conversation_key(secret_key, pubkey).is_err());
+        }
+    }
+
+    #[test]
+    fn derives_message_keys() {
+        let conversation_key =
+            key("a1a3d60f3470a8612633924e91febf96dc5366ce130f658b1f0fc652c20b3b54");
+        let nonce = key("e1e6f880560d6d149ed83dcc7e5861ee62a5ee051f7fde9975fe5d25d2a02d72");
+
+        let (chacha_key, chacha_nonce, hmac_key) = message_keys(&conversation_key, &nonce).unwrap();
+
+        assert_eq!(
+            hex::encode(chacha_key),
+            "f145f3bed47cb70dbeaac07f3a3fe683e822b3715edb7c4fe310829014ce7d76"
+        );
+        assert_eq!(hex::encode(chacha_nonce), "c4ad129bb01180c0933a160c");
+        assert_eq!(
+            hex::encode(hmac_key),
+            "027c1db445f05e2eee864a0975b0ddef5b7110583c8c192de3732571ca5838c4"
+        );
+    }
+
+    #[test]
+    fn computes_padded_lengths() {
+        let vectors = [
+            (1, 32),
+            (16, 32),
+            (32, 32),
+            (33, 64),
+            (37, 64),
+            (45, 64),
+            (49, 64),
+            (64, 64),
+            (65, 96),
+            (100, 128),
+            (111, 128),
+            (200, 224),
+            (250, 256),
+            (320, 320),
+            (383, 384),
+            (384, 384),
+            (400, 448),
+            (500, 512),
+            (512, 512),
+            (515, 640),
+            (700, 768),
+            (800, 896),
+            (900, 1024),
+            (1020, 1024),
+            (65536, 65536),
+        ];
+
+        for (length, padded) in vectors {
+            assert_eq!(padded_length(length), padded, "length {}", length);
+        }
+    }
+
+    #[test]
+    fn encrypts_and_decrypts() {
+        let vectors = [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                "c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "a",
+                "AgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABee0G5VSK0/9YypIObAtDKfYEAjD35uVkHyB0F4DwrcNaCXlCWZKaArsGrY6M9wnuTMxWfp1RTN9Xga8no+kF5Vsb",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d",
+                "f00000000000000000000000000000f00000000000000000000000000000000f",
+                "🍕🫃",
+                "AvAAAAAAAAAAAAAAAAAAAPAAAAAAAAAAAAAAAAAAAAAPSKSK6is9ngkX2+cSq85Th16oRTISAOfhStnixqZziKMDvB0QQzgFZdjLTPicCJaV8nDITO+QfaQ61+KbWQIOO2Yj",
+            ),
+        ];
+
+        for (secret_key, other_secret_key, expected_key, nonce, plaintext, payload) in vectors {
+            let other = pubkey(other_secret_key);
+            let shared = conversation_key(secret_key, &other).unwrap();
+            assert_eq!(shared, key(expected_key));
+            // Both sides share the same key
+            assert_eq!(
+                conversation_key(other_secret_key, &pubkey(secret_key)).unwrap(),
+                shared
+            );
+
+            let encrypted = nip44_encrypt_with_nonce(&shared, &key(nonce), plaintext).unwrap();
+            assert_eq!(encrypted, payload);
+            assert_eq!(
+                nip44_decrypt(other_secret_key, &pubkey(secret_key), payload).unwrap(),
+                plaintext
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_payloads() {
+        let shared = key("c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d");
+        let valid = "AgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABee0G5VSK0/9YypIObAtDKfYEAjD35uVkHyB0F4DwrcNaCXlCWZKaArsGrY6M9wnuTMxWfp1RTN9Xga8no+kF5Vsb";
+        let data = BASE64.decode(valid).unwrap();
+
+        let mut unknown_version = data.clone();
+        unknown_version[0] = 1;
+        let mut tampered_mac = data.clone();
+        *tampered_mac.last_mut().unwrap() ^= 1;
+        let mut tampered_ciphertext = data.clone();
+        tampered_ciphertext[40] ^= 1;
+
+        // Authentic, but padded to another length than the one it announces
+        let nonce = [1; 32];
+        let (chacha_key, chacha_nonce, hmac_key) = message_keys(&shared, &nonce).unwrap();
+        let mut ciphertext = pad("a").unwrap();
+        ciphertext[1] = 33;
+        ChaCha20::new(&chacha_key.into(), &chacha_nonce.into()).apply_keystream(&mut ciphertext);
+        let mut bad_padding = vec![NIP44_VERSION];
+        bad_padding.extend_from_slice(&nonce);
+        bad_padding.extend_from_slice(&ciphertext);
+        bad_padding.extend_from_slice(&mac(&hmac_key, &nonce, &ciphertext).unwrap());
+
+        let payloads = [
+            "#Atqupco0WyaOW2IGDKcshwxI9xO8HgD/P8Ddt46CbxDbrhdG8VmJZE0UICD06CUvEvdnr1cp1fiMtlM/GrE92xAc1EwsVCQEgWEu2gsHUVf4JAa3TpgkmFc3TWsax0v6n/Wq".to_string(),
+            String::new(),
+            valid[..100].to_string(),
+            valid.replace('/', "!"),
+            BASE64.encode(unknown_version),
+            BASE64.encode(tampered_mac),
+            BASE64.encode(tampered_ciphertext),
+            BASE64.encode(bad_padding),
+        ];
+        for payload in payloads {
+            assert!(
+                nip44_decrypt_with_key(&shared, &payload).is_err(),
+                "{}",
+                payload
+            );
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use bech32::{Bech32, Hrp};
+
+use super::reference::URI_PREFIX;
+
+/// TLV types used by the shareable identifiers
+const TLV_SPECIAL: u8 = 0;
+const TLV_RELAY: u8 = 1;
+const TLV_AUTHOR: u8 = 2;
+const TLV_KIND: u8 = 3;
+
+/// A key, event or address in the bech32 form described by NIP-19, the way
+/// they are shared with users
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entity {
+    /// `npub`, a hex-encoded public key
+    Pubkey(String),
+    /// `note`, a hex-encoded event id
+    Note(String),
+    /// `nprofile`, a public key with relays where its events can be found
+    Profile { pubkey: String, relays: Vec<String> },
+    /// `nevent`, an event id with relays where it can be found, and
+    /// optionally its author and kind
+    Event {
+        id: String,
+        relays: Vec<String>,
+        author: Option<String>,
+        kind: Option<u64>,
+    },
+    /// `naddr`, the coordinate of an addressable event with relays where it
+    /// can be found
+    Address {
+        identifier: String,
+        pubkey: String,
+        kind: u64,
+        relays: Vec<String>,
+    },
+}
+
+impl Entity {
+    /// Reads a NIP-21 `nostr:` URI. The scheme may be left out, since users
+    /// often paste bare identifiers. Secret keys are refused like any other
+    /// unsupported entity.
+    pub fn from_uri(uri: &str) -> Result<Entity> {
+        let uri = uri.trim();
+        let entity = match uri.get(..URI_PREFIX.len()) {
+            Some(scheme) if scheme.eq_ignore_ascii_case(URI_PREFIX) => &uri[URI_PREFIX.len()..],
+            _ => uri,
+        };
+
+        entity.parse()
+    }
+
+    fn prefix(&self) -> &'static str {
+        match self {
+            Entity::Pubkey(_) => "npub",
+            Entity::Note(_) => "note",
+            Entity::Profile { .. } => "nprofile",
+            Entity::Event { .. } => "nevent",
+            Entity::Address { .. } => "naddr",
+        }
+    }
+
+    /// The public key this entity points to, if any
+    pub fn pubkey(&self) -> Option<&str> {
+        match self {
+            Entity::Pubkey(pubkey)
+            | Entity::Profile { pubkey, .. }
+            | Entity::Address { pubkey, .. } => Some(pubkey),
+            Entity::Event { author, .. } => author.as_deref(),
+            Entity::Note(_) => None,
+        }
+    }
+
+    /// The event id this entity points to, if any
+    pub fn event_id(&self) -> Option<&str> {
+        match self {
+            Entity::Note(id) | Entity::Event { id, .. } => Some(id),
+            _ => None,
+        }
+    }
+
+    /// The `kind:pubkey:identifier` coordinate of an `naddr`
+    pub fn address(&self) -> Option<String> {
+        match self {
+            Entity::Address {
+                identifier,
+                pubkey,
+                kind,
+                ..
+            } => Some(format!("{}:{}:{}", kind, pubkey, identifier)),
+            _ => None,
+        }
+    }
+
+    /// Relays where what this entity points to can be found
+    pub fn relays(&self) -> &[String] {
+        match self {
+            Entity::Profile { relays, .. }
+            | Entity::Event { relays, .. }
+            | Entity::Address { relays, .. } => relays,
+            _ => &[],
+        }
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        match self {
+            Entity::Pubkey(hex) | Entity::Note(hex) => bytes = decode_hex32(hex)?,
+            Entity::Profile { pubkey, relays } => {
+                push_tlv(&mut bytes, TLV_SPECIAL, &decode_hex32(pubkey)?);
+                push_relays(&mut bytes, relays);
+            }
+            Entity::Event {
+                id,
+                relays,
+                author,
+                kind,
+            } => {
+                push_tlv(&mut bytes, TLV_SPECIAL, &decode_hex32(id)?);
+                push_relays(&mut bytes, relays);
+                if let Some(author) = author {
+                    push_tlv(&mut bytes, TLV_AUTHOR, &decode_hex32(author)?);
+                }
+                if let Some(kind) = kind {
+                    push_tlv(&mut bytes, TLV_KIND, &(*kind as u32).to_be_bytes());
+                }
+            }
+            Entity::Address {
+                identifier,
+                pubkey,
+                kind,
+                relays,
+            } => {
+                push_tlv(&mut bytes, TLV_SPECIAL, identifier.as_bytes());
+                push_relays(&mut bytes, relays);
+                push_tlv(&mut bytes, TLV_AUTHOR, &decode_hex32(pubkey)?);
+                push_tlv(&mut bytes, TLV_KIND, &(*kind as u32).to_be_bytes());
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl FromStr for Entity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Entity> {
+        let (hrp, data) = bech32::decode(s).map_err(|err| anyhow!("Invalid bech32: {}", err))?;
+
+        match hrp.as_str() {
+            "npub" => Ok(Entity::Pubkey(encode_hex32(&data)?)),
+            "note" => Ok(Entity::Note(encode_hex32(&data)?)),
+            "nprofile" => {
+                let tlv = Tlv::parse(&data)?;
+                Ok(Entity::Profile {
+                    pubkey: encode_hex32(tlv.special()?)?,
+                    relays: tlv.relays(),
+                })
+            }
+            "nevent" => {
+                let tlv = Tlv::parse(&data)?;
+                Ok(Entity::Event {
+                    id: encode_hex32(tlv.special()?)?,
+                    relays: tlv.relays(),
+                    author: tlv.author()?,
+                    kind: tlv.kind()?,
+                })
+            }
+            "naddr" => {
+                let tlv = Tlv::parse(&data)?;
+                Ok(Entity::Address {
+                    identifier: String::from_utf8(tlv.special()?.to_vec())?,
+                    pubkey: tlv.author()?.ok_or(anyhow!("naddr without an author"))?,
+                    kind: tlv.kind()?.ok_or(anyhow!("naddr without a kind"))?,
+                    relays: tlv.relays(),
+                })
+            }
+            prefix => Err(anyhow!("Unsupported prefix `{}`", prefix)),
+        }
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let data = self.to_bytes().map_err(|_| fmt::Error)?;
+        let hrp = Hrp::parse(self.prefix()).map_err(|_| fmt::Error)?;
+
+        f.write_str(&bech32::encode::<Bech32>(hrp, &data).map_err(|_| fmt::Error)?)
+    }
+}
+
+/// The type-length-value entries of `nprofile`, `nevent` and `naddr`
+struct Tlv<'a> {
+    entries: Vec<(u8, &'a [u8])>,
+}
+
+impl<'a> Tlv<'a> {
+    fn parse(mut data: &'a [u8]) -> Result<Tlv<'a>> {
+        let mut entries = Vec::new();
+        while let [kind, length, rest @ ..] = data {
+            let length = *length as usize;
+            if rest.len() < length {
+                return Err(anyhow!("Truncated TLV entry"));
+            }
+            entries.push((*kind, &rest[..length]));
+            data = &rest[length..];
+        }
+
+        Ok(Tlv { entries })
+    }
+
+    fn first(&self, kind: u8) -> Option<&'a [u8]> {
+        self.entries
+            .iter()
+            .find(|(entry_kind, _)| *entry_kind == kind)
+            .map(|(_, value)| *value)
+    }
+
+    fn special(&self) -> Result<&'a [u8]> {
+        self.first(TLV_SPECIAL)
+            .ok_or(anyhow!("Missing the main TLV entry"))
+    }
+
+    fn relays(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|(kind, _)| *kind == TLV_RELAY)
+            .filter_map(|(_, value)| String::from_utf8(value.to_vec()).ok())
+            .collect()
+    }
+
+    fn author(&self) -> Result<Option<String>> {
+        self.first(TLV_AUTHOR).map(encode_hex32).transpose()
+    }
+
+    fn kind(&self) -> Result<Option<u64>> {
+        self.first(TLV_KIND)
+            .map(|value| {
+                let bytes: [u8; 4] = value
+                    .try_into()
+                    .map_err(|_| anyhow!("Kinds take 4 bytes"))?;
+                Ok(u32::from_be_bytes(bytes) as u64)
+            })
+            .transpose()
+    }
+}
+
+fn push_tlv(bytes: &mut Vec<u8>, kind: u8, value: &[u8]) {
+    // Values longer than a TLV entry can hold are cut
+    let value = &value[..value.len().min(u8::MAX as usize)];
+    bytes.push(kind);
+    bytes.push(value.len() as u8);
+    bytes.extend_from_slice(value);
+}
+
+fn push_relays(bytes: &mut Vec<u8>, relays: &[String]) {
+    for relay in relays {
+        push_tlv(bytes, TLV_RELAY, relay.as_bytes());
+    }
+}
+
+fn decode_hex32(hex: &str) -> Result<Vec<u8>> {
+    let bytes = hex::decode(hex)?;
+    if bytes.len() != 32 {
+        return Err(anyhow!("Expected 32 bytes, got {}", bytes.len()));
+    }
+
+    Ok(bytes)
+}
+
+fn encode_hex32(bytes: &[u8]) -> Result<String> {
+    if bytes.len() != 32 {
+        return Err(anyhow!("Expected 32 bytes, got {}", bytes.len()));
+    }
+
+    Ok(hex::encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBKEY: &str = "3bf0c63fcb93463407af97a5e5ee64fa883d107ef9e558472c4eb9aaaefa459d";
+    const ID: &str = "7e7e9c42a91bfef19fa929e5fda1b72e0ebc1a4c1141673e2794234d86addf4e";
+
+    fn round_trip(entity: Entity) {
+        let encoded = entity.to_string();
+        assert!(encoded.starts_with(entity.prefix()));
+        assert_eq!(encoded.parse::<Entity>().unwrap(), entity);
+    }
+
+    #[test]
+    fn reads_the_nip19_examples() {
+        assert_eq!(
+            "npub10elfcs4fr0l0r8af98jlmgdh9c8tcxjvz9qkw038js35mp4dma8qzvjptg"
+                .parse::<Entity>()
+                .unwrap(),
+            Entity::Pubkey(ID.to_string())
+        );
+        assert_eq!(
+            "nprofile1qqsrhuxx8l9ex335q7he0f09aej04zpazpl0ne2cgukyawd24mayt8gpp4mhxue69uhhytnc9e3k7mgpz4mhxue69uhkg6nzv9ejuumpv34kytnrdaksjlyr9p"
+                .parse::<Entity>()
+                .unwrap(),
+            Entity::Profile {
+                pubkey: PUBKEY.to_string(),
+                relays: vec![
+                    "wss://r.x.com".to_string(),
+                    "wss://djbas.sadkb.com".to_string()
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let relays = vec!["wss://relay.example.com".to_string()];
+
+        round_trip(Entity::Pubkey(PUBKEY.to_string()));
+        round_trip(Entity::Note(ID.to_string()));
+        round_trip(Entity::Profile {
+            pubkey: PUBKEY.to_string(),
+            relays: relays.clone(),
+        });
+        round_trip(Entity::Event {
+            id: ID.to_string(),
+            relays: relays.clone(),
+            author: Some(PUBKEY.to_string()),
+            kind: Some(30023),
+        });
+        round_trip(Entity::Event {
+            id: ID.to_string(),
+            relays: vec![],
+            author: None,
+            kind: None,
+        });
+        round_trip(Entity::Address {
+            identifier: "my-article".to_string(),
+            pubkey: PUBKEY.to_string(),
+            kind: 30023,
+            relays,
+        });
+    }
+
+    #[test]
+    fn reads_uris() {
+        let npub = Entity::Pubkey(PUBKEY.to_string()).to_string();
+
+        for uri in [format!("nostr:{}", npub), format!("NOSTR:{}", npub), npub] {
+            assert_eq!(
+                Entity::from_uri(&uri).unwrap(),
+                Entity::Pubkey(PUBKEY.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_malformed_input() {
+        let npub = Entity::Pubkey(PUBKEY.to_string()).to_string();
+        let mut corrupted = npub.clone();
+        corrupted.replace_range(10..11, if &npub[10..11] == "q" { "p" } else { "q" });
+        let nsec = bech32::encode::<Bech32>(Hrp::parse("nsec").unwrap(), &[1; 32]).unwrap();
+        let short = bech32::encode::<Bech32>(Hrp::parse("npub").unwrap(), &[1; 31]).unwrap();
+        let truncated =
+            bech32::encode::<Bech32>(Hrp::parse("nprofile").unwrap(), &[TLV_SPECIAL, 32, 1, 2, 3])
+                .unwrap();
+        let no_author = bech32::encode::<Bech32>(
+            Hrp::parse("naddr").unwrap(),
+            &[TLV_SPECIAL, 1, b'a', TLV_KIND, 4, 0, 0, 0, 1],
+        )
+        .unwrap();
+
+        for input in [
+            "",
+            "npub",
+            "not bech32",
+            &corrupted,
+            &nsec,
+            &short,
+            &truncated,
+            &no_author,
+        ] {
+            assert!(input.parse::<Entity>().is_err(), "{} was accepted", input);
+        }
+        assert!(Entity::Pubkey("abcd".to_string()).to_bytes().is_err());
+    }
+}
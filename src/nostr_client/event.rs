@@ -11,13 +11,16 @@ use serde_json::json;
 
 use secp256k1::{
     hashes::{hex::DisplayHex, sha256},
-    All, Message, Parity, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey,
+    schnorr::Signature,
+    All, Message, Parity, PublicKey, Secp256k1, SecretKey, Verification, XOnlyPublicKey,
 };
 
-#[derive(Debug)]
+/// Kind of the short text notes making up the feed
+pub const TEXT_NOTE_KIND: u64 = 1;
+
+#[derive(Debug, Clone)]
 pub struct PubKeyWrapper {
     key: XOnlyPublicKey,
-    parity: Parity,
 }
 
 impl serde::Serialize for PubKeyWrapper {
@@ -29,7 +32,19 @@ impl serde::Serialize for PubKeyWrapper {
     }
 }
 
-#[derive(Debug, Serialize)]
+impl<'de> serde::Deserialize<'de> for PubKeyWrapper {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let key = String::deserialize(deserializer)?;
+        let key = XOnlyPublicKey::from_str(&key).map_err(serde::de::Error::custom)?;
+
+        Ok(PubKeyWrapper { key })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event {
     id: Option<String>,
     sig: Option<String>,
@@ -43,21 +58,22 @@ pub struct Event {
 }
 
 impl Event {
+    /// Creates an unsigned event. `pubkey` may be given either as the 32-byte
+    /// x-only key used by the protocol or as a 33-byte compressed key.
     pub fn new(pubkey: &str, kind: u64, content: String) -> Event {
-        let created_at = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Failed to obtain unix time")
-            .as_secs();
         let tags = Vec::new();
-        let (key, parity) = PublicKey::from_str(pubkey).unwrap().x_only_public_key();
+        let key = match XOnlyPublicKey::from_str(pubkey) {
+            Ok(key) => key,
+            Err(_) => PublicKey::from_str(pubkey).unwrap().x_only_public_key().0,
+        };
 
         Event {
             id: None,
             sig: None,
-            pubkey: PubKeyWrapper { key, parity },
+            pubkey: PubKeyWrapper { key },
             kind,
             content,
-            created_at,
+            created_at: unix_time(),
             tags,
         }
     }
@@ -65,7 +81,7 @@ impl Event {
     /// Generates a new id for the event, and then signs it with the secret key
     pub fn setup(&mut self, secret_key: &str, secp: &Secp256k1<All>) -> Result<()> {
         self.generate_id()?;
-        self.sign(secret_key, &secp)?;
+        self.sign(secret_key, secp)?;
 
         Ok(())
     }
@@ -81,7 +97,62 @@ impl Event {
         self.tags.push(vec![tag_key.into(), tag_value.into()]);
     }
 
-    /// Generates a 32-byte hex-encoded sha256 of the serialized event data, 
+    /// The hex-encoded id of the event, available once it has been set up
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// The hex-encoded x-only public key of the author
+    pub fn pubkey(&self) -> String {
+        hex::encode(self.pubkey.key.serialize())
+    }
+
+    /// Returns the tags whose name is `tag_key`
+    pub fn tags_named<'a>(&'a self, tag_key: &'a str) -> impl Iterator<Item = &'a Vec<String>> {
+        self.tags
+            .iter()
+            .filter(move |tag| tag.first().map(String::as_str) == Some(tag_key))
+    }
+
+    /// Returns the values (the second element) of the tags named `tag_key`
+    pub fn tag_values<'a>(&'a self, tag_key: &'a str) -> impl Iterator<Item = &'a str> {
+        self.tags_named(tag_key)
+            .filter_map(|tag| tag.get(1).map(String::as_str))
+    }
+
+    /// The `kind:pubkey:d-tag` coordinate other events point to replaceable
+    /// and addressable events by, through `a` tags. Regular events have
+    /// none.
+    pub fn address(&self) -> Option<String> {
+        let identifier = match self.kind {
+            0 | 3 | 10000..=19999 => "",
+            30000..=39999 => self.tag_values("d").next().unwrap_or_default(),
+            _ => return None,
+        };
+
+        Some(format!("{}:{}:{}", self.kind, self.pubkey(), identifier))
+    }
+
+    /// Checks that the id matches the event data and that the signature was
+    /// produced by the author over that id
+    pub fn verify<C: Verification>(&self, secp: &Secp256k1<C>) -> Result<()> {
+        let (Some(id), Some(sig)) = (&self.id, &self.sig) else {
+            return Err(anyhow!("cannot verify an event without id and signature"));
+        };
+
+        let expected =
+            Message::from_hashed_data::<sha256::Hash>(self.serialize_for_id().as_bytes());
+        if expected.to_string() != *id {
+            return Err(anyhow!("event id {} does not match its content", id));
+        }
+
+        let sig = Signature::from_str(sig)?;
+        secp.verify_schnorr(&sig, &expected, &self.pubkey.key)?;
+
+        Ok(())
+    }
+
+    /// Generates a 32-byte hex-encoded sha256 of the serialized event data,
     /// then updates the event id
     fn generate_id(&mut self) -> Result<()> {
         let id = Message::from_hashed_data::<sha256::Hash>(self.serialize_for_id().as_bytes());
@@ -90,7 +161,7 @@ impl Event {
         Ok(())
     }
 
-    // Uses the secret key alongside the secp engine to correctly sign the 
+    // Uses the secret key alongside the secp engine to correctly sign the
     // event data, that is represented by the `id` field.
     fn sign(&mut self, secret_key: &str, secp: &Secp256k1<All>) -> Result<()> {
         if self.id.is_none() {
@@ -101,7 +172,7 @@ impl Event {
         let id_as_msg = Message::from_digest_slice(&hex::decode(id).unwrap())?;
 
         let secret_key = SecretKey::from_str(secret_key)?;
-        let keypair = secret_key.keypair(&secp);
+        let keypair = secret_key.keypair(secp);
         let sig = secp.sign_schnorr(&id_as_msg, &keypair);
 
         self.sig = Some(sig.to_string());
@@ -109,7 +180,7 @@ impl Event {
         Ok(())
     }
 
-    /// The protocol specifies this format for serializing the `Event` type 
+    /// The protocol specifies this format for serializing the `Event` type
     /// for id generation
     fn serialize_for_id(&self) -> String {
         json!([
@@ -123,3 +194,19 @@ impl Event {
         .to_string()
     }
 }
+
+/// Current unix time, in seconds
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Failed to obtain unix time")
+        .as_secs()
+}
+
+/// The hex-encoded x-only public key matching `secret_key`
+pub fn pubkey_from_secret(secret_key: &str, secp: &Secp256k1<All>) -> Result<String> {
+    let secret_key = SecretKey::from_str(secret_key)?;
+    let (pubkey, _) = secret_key.x_only_public_key(secp);
+
+    Ok(hex::encode(pubkey.serialize()))
+}
@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Result};
+
+use super::event::{unix_time, Event as EventType};
+
+/// The longest notes can be made to last, a century
+pub const MAX_DURATION: u64 = 100 * 365 * 24 * 60 * 60;
+
+/// The unix time after which `event` should be dropped, from its
+/// `expiration` tag as described by NIP-40
+pub fn expiration(event: &EventType) -> Option<u64> {
+    event
+        .tag_values("expiration")
+        .next()
+        .and_then(|at| at.parse().ok())
+}
+
+/// Whether `event` expired by `now`
+pub fn is_expired(event: &EventType, now: u64) -> bool {
+    expiration(event).is_some_and(|at| at <= now)
+}
+
+/// Makes `event` expire `after` seconds from now. It has to be set before
+/// the event is signed.
+pub fn expire_after(event: &mut EventType, after: u64) -> Result<()> {
+    let at = unix_time()
+        .checked_add(after)
+        .ok_or(anyhow!("Notes can't expire that far in the future"))?;
+
+    event
+        .tags
+        .retain(|tag| tag.first().map(String::as_str) != Some("expiration"));
+    event.add_tag("expiration", &at.to_string(), None);
+    Ok(())
+}
+
+/// Reads a duration like `90s`, `30m`, `12h`, `7d` or `2w` into seconds.
+/// Plain numbers are taken as seconds. Durations longer than
+/// [`MAX_DURATION`] are refused.
+pub fn parse_duration(text: &str) -> Result<u64> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(position) => text.split_at(position),
+        None => (text, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow!("`{}` is not a duration, try 30m, 12h or 7d", text))?;
+
+    let seconds = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(anyhow!("Unknown unit `{}`, try s, m, h, d or w", unit)),
+    };
+
+    number
+        .checked_mul(seconds)
+        .filter(|duration| *duration > 0)
+        .filter(|duration| *duration <= MAX_DURATION)
+        .ok_or(anyhow!("`{}` is not a usable duration", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed};
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90").unwrap(), 90);
+        assert_eq!(parse_duration("30m").unwrap(), 30 * 60);
+        assert_eq!(parse_duration(" 7d ").unwrap(), 7 * 24 * 60 * 60);
+        assert_eq!(parse_duration("2w").unwrap(), 14 * 24 * 60 * 60);
+
+        assert_eq!(parse_duration("5200w").unwrap(), 5200 * 7 * 24 * 60 * 60);
+
+        for text in [
+            "",
+            "0",
+            "0h",
+            "-1d",
+            "soon",
+            "3y",
+            "5300w",
+            "99999999999999d",
+        ] {
+            assert!(parse_duration(text).is_err(), "{} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn expires_events() {
+        let (secret_key, pubkey) = keys();
+        let mut event = EventType::new(&pubkey, 1, "hello".to_string());
+        event.add_tag("expiration", "1", None);
+
+        expire_after(&mut event, 60).unwrap();
+
+        let at = expiration(&event).unwrap();
+        assert_eq!(event.tag_values("expiration").count(), 1);
+        assert!(at >= unix_time() + 59 && at <= unix_time() + 60);
+        assert!(!is_expired(&event, at - 1));
+        assert!(is_expired(&event, at));
+
+        assert!(expire_after(&mut event, u64::MAX).is_err());
+        assert_eq!(expiration(&event), Some(at));
+        assert!(!is_expired(&signed(&secret_key, 1, "", vec![]), u64::MAX));
+    }
+}
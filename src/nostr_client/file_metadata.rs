@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Result};
+
+use super::event::Event as EventType;
+
+/// Event describing a file shared by a user, described by NIP-94
+pub const FILE_METADATA_KIND: u64 = 1063;
+
+/// What is known of a file hosted somewhere, either from a kind 1063 event
+/// or from the `imeta` tag of a note linking to it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub url: String,
+    /// MIME type, like `image/jpeg`
+    pub mime_type: Option<String>,
+    /// Hex-encoded sha256 of the file
+    pub hash: Option<String>,
+    /// Hex-encoded sha256 of the file before the server transformed it
+    pub original_hash: Option<String>,
+    /// Size in bytes
+    pub size: Option<u64>,
+    /// Width and height of images and videos, like `800x600`
+    pub dimensions: Option<String>,
+    pub blurhash: Option<String>,
+    pub thumb: Option<String>,
+    pub summary: Option<String>,
+    /// Description of the file for those who can't see it
+    pub alt: Option<String>,
+}
+
+impl FileMetadata {
+    /// Reads the `[name, value]` pairs describing a file, as found in the
+    /// tags of kind 1063 events and in the answers of NIP-96 servers
+    pub fn from_tags(tags: &[Vec<String>]) -> Result<FileMetadata> {
+        let mut file = FileMetadata::default();
+        for tag in tags {
+            if let [name, value, ..] = tag.as_slice() {
+                file.set(name, value);
+            }
+        }
+
+        file.checked()
+    }
+
+    pub fn from_event(event: &EventType) -> Result<FileMetadata> {
+        if event.kind != FILE_METADATA_KIND {
+            return Err(anyhow!(
+                "Event of kind {} is not a file metadata event",
+                event.kind
+            ));
+        }
+
+        FileMetadata::from_tags(&event.tags)
+    }
+
+    /// Reads an `imeta` tag, of which each value is a name followed by a
+    /// space and its value, described by NIP-92
+    pub fn from_imeta(tag: &[String]) -> Result<FileMetadata> {
+        let Some((_, entries)) = tag.split_first().filter(|(name, _)| *name == "imeta") else {
+            return Err(anyhow!("Not an imeta tag"));
+        };
+
+        let mut file = FileMetadata::default();
+        for (name, value) in entries.iter().filter_map(|entry| entry.split_once(' ')) {
+            file.set(name, value);
+        }
+
+        file.checked()
+    }
+
+    fn set(&mut self, name: &str, value: &str) {
+        let value = Some(value.trim().to_string()).filter(|value| !value.is_empty());
+        match name {
+            "url" => self.url = value.unwrap_or_default(),
+            "m" => self.mime_type = value,
+            "x" => self.hash = value,
+            "ox" => self.original_hash = value,
+            "size" => self.size = value.and_then(|size| size.parse().ok()),
+            "dim" => self.dimensions = value,
+            "blurhash" => self.blurhash = value,
+            "thumb" => self.thumb = value,
+            "summary" => self.summary = value,
+            "alt" => self.alt = value,
+            _ => {}
+        }
+    }
+
+    fn checked(self) -> Result<FileMetadata> {
+        match self.url.is_empty() {
+            true => Err(anyhow!("The file has no url")),
+            false => Ok(self),
+        }
+    }
+
+    /// The names and values describing the file, in the order of NIP-94
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("url", self.url.clone())];
+        let optional = [
+            ("m", self.mime_type.clone()),
+            ("x", self.hash.clone()),
+            ("ox", self.original_hash.clone()),
+            ("size", self.size.map(|size| size.to_string())),
+            ("dim", self.dimensions.clone()),
+            ("blurhash", self.blurhash.clone()),
+            ("thumb", self.thumb.clone()),
+            ("summary", self.summary.clone()),
+            ("alt", self.alt.clone()),
+        ];
+        for (name, value) in optional {
+            if let Some(value) = value {
+                fields.push((name, value));
+            }
+        }
+
+        fields
+    }
+
+    /// The `imeta` tag describing the file, for notes linking to it
+    pub fn imeta(&self) -> Vec<String> {
+        let mut tag = vec!["imeta".to_string()];
+        tag.extend(
+            self.fields()
+                .into_iter()
+                .map(|(name, value)| format!("{} {}", name, value)),
+        );
+
+        tag
+    }
+
+    /// Short description of the file for people, like
+    /// `A cat · image/png · 1.2 MB · 800x600`
+    pub fn describe(&self) -> String {
+        let parts: Vec<String> = [
+            self.alt.clone().or(self.summary.clone()),
+            self.mime_type.clone(),
+            self.size.map(format_size),
+            self.dimensions.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        match parts.is_empty() {
+            true => "File".to_string(),
+            false => parts.join(" · "),
+        }
+    }
+}
+
+/// The files `event` is about: the one a kind 1063 event describes, or the
+/// ones the `imeta` tags of a note describe
+pub fn files(event: &EventType) -> Vec<FileMetadata> {
+    if event.kind == FILE_METADATA_KIND {
+        return FileMetadata::from_event(event).into_iter().collect();
+    }
+
+    event
+        .tags_named("imeta")
+        .filter_map(|tag| FileMetadata::from_imeta(tag).ok())
+        .collect()
+}
+
+/// Adds the `imeta` tag describing `file` to `event`
+pub fn attach(event: &mut EventType, file: &FileMetadata) {
+    event.tags.push(file.imeta());
+}
+
+/// `bytes` written for people, like `1.2 MB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["kB", "MB", "GB", "TB"];
+
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit + 1 < UNITS.len() {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
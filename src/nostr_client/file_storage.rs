@@ -0,0 +1,458 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde::Deserialize;
+use url::Url;
+
+use super::file_metadata::FileMetadata;
+use super::signer::Signer;
+
+/// Where NIP-96 servers describe their upload API
+const WELL_KNOWN_PATH: &str = "/.well-known/nostr/nip96.json";
+
+/// How long a request to a file server may take, uploads included
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How long to wait between two questions to a server still processing an
+/// upload, and how many times to ask before giving up
+const PROCESSING_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_PROCESSING_POLLS: usize = 30;
+
+/// How a NIP-96 file server takes uploads
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ServerInfo {
+    /// Where files are uploaded to
+    pub api_url: String,
+    /// Where files are downloaded from, when it's not the API
+    pub download_url: Option<String>,
+    /// Another server handling the uploads, in which case this one has no
+    /// API of its own
+    pub delegated_to_url: Option<String>,
+    /// MIME types the server takes, any when empty
+    pub content_types: Vec<String>,
+}
+
+impl ServerInfo {
+    /// Fetches how the server at `server` takes uploads, following its
+    /// delegation to another server if it has one
+    pub async fn fetch(client: &reqwest::Client, server: &str) -> Result<ServerInfo> {
+        let mut server = server.trim_end_matches('/').to_string();
+        for _ in 0..2 {
+            let info_url = format!("{}{}", server, WELL_KNOWN_PATH);
+            let mut info: ServerInfo = client
+                .get(&info_url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            if !info.api_url.is_empty() {
+                // Some servers give the API relative to themselves
+                info.api_url = Url::parse(&info_url)?.join(&info.api_url)?.to_string();
+                return Ok(info);
+            }
+            match info.delegated_to_url {
+                Some(delegate) => server = delegate.trim_end_matches('/').to_string(),
+                None => break,
+            }
+        }
+
+        Err(anyhow!("{} doesn't take uploads", server))
+    }
+}
+
+/// What a server answers an upload with, or a question about its processing
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct UploadResponse {
+    status: String,
+    message: Option<String>,
+    processing_url: Option<String>,
+    nip94_event: Option<Nip94Event>,
+}
+
+/// The kind 1063 event a server describes an uploaded file with, which it
+/// leaves to the client to publish
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Nip94Event {
+    tags: Vec<Vec<String>>,
+    content: String,
+}
+
+/// Uploads the file at `path` to the NIP-96 server at `server`, signing the
+/// request with `signer`, and waits for the server to be done processing it.
+pub async fn upload(signer: &mut Signer, server: &str, path: &Path) -> Result<FileMetadata> {
+    let data = tokio::fs::read(path)
+        .await
+        .map_err(|err| anyhow!("Can't read {}: {}", path.display(), err))?;
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("file")
+        .to_string();
+    let mime_type = mime_type(&file_name);
+
+    let client = http_client();
+    let info = ServerInfo::fetch(&client, server).await?;
+    if !info.content_types.is_empty() && !accepts(&info.content_types, mime_type) {
+        return Err(anyhow!("The server doesn't take {} files", mime_type));
+    }
+
+    let boundary = format!("gnc{}", hex::encode(rand::random::<[u8; 12]>()));
+    let body = form_data(&boundary, &file_name, mime_type, &data);
+    let authorization = signer
+        .http_authorization(&info.api_url, "POST", Some(&body))
+        .await?;
+
+    let response = client
+        .post(&info.api_url)
+        .header(AUTHORIZATION, authorization)
+        .header(
+            CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", boundary),
+        )
+        .body(body)
+        .send()
+        .await?;
+    let mut upload = read_response(response).await?;
+
+    // Servers may take a while to transform what they're sent
+    if upload.nip94_event.is_none() {
+        let processing_url = upload
+            .processing_url
+            .ok_or(anyhow!("The server didn't say where the file is"))?;
+        let processing_url = Url::parse(&info.api_url)?.join(&processing_url)?;
+        for _ in 0..MAX_PROCESSING_POLLS {
+            tokio::time::sleep(PROCESSING_POLL_INTERVAL).await;
+            upload = read_response(client.get(processing_url.clone()).send().await?).await?;
+            if upload.nip94_event.is_some() {
+                break;
+            }
+        }
+    }
+
+    let event = upload
+        .nip94_event
+        .ok_or(anyhow!("The server took too long to process the file"))?;
+    let mut file = FileMetadata::from_tags(&event.tags)?;
+    if file.mime_type.is_none() {
+        file.mime_type = Some(mime_type.to_string());
+    }
+    if file.summary.is_none() && !event.content.trim().is_empty() {
+        file.summary = Some(event.content.trim().to_string());
+    }
+
+    Ok(file)
+}
+
+async fn read_response(response: reqwest::Response) -> Result<UploadResponse> {
+    let code = response.status();
+    let upload: UploadResponse = response
+        .json()
+        .await
+        .map_err(|_| anyhow!("The server answered {}", code))?;
+
+    if !code.is_success() || upload.status == "error" {
+        return Err(anyhow!(
+            "The server refused the file: {}",
+            upload.message.unwrap_or(code.to_string())
+        ));
+    }
+    Ok(upload)
+}
+
+/// The `multipart/form-data` body sending `data` as the `file` field, along
+/// with its size and type
+fn form_data(boundary: &str, file_name: &str, mime_type: &str, data: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    let fields = [
+        ("size", data.len().to_string()),
+        ("content_type", mime_type.to_string()),
+    ];
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            )
+            .as_bytes(),
+        );
+    }
+
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+            boundary,
+            file_name.replace(['"', '\r', '\n'], ""),
+            mime_type
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(data);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+    body
+}
+
+/// Whether `mime_type` is one of `content_types`, which may end with a
+/// wildcard like `image/*`
+fn accepts(content_types: &[String], mime_type: &str) -> bool {
+    content_types
+        .iter()
+        .any(|accepted| match accepted.strip_suffix('*') {
+            Some(prefix) => mime_type.starts_with(prefix),
+            None => accepted == mime_type,
+        })
+}
+
+/// The MIME type of a file, guessed from the extension of its name
+pub fn mime_type(file_name: &str) -> &'static str {
+    let extension = file_name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("Failed to build the http client")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::nostr_client::event::unix_time;
+    use crate::nostr_client::http_auth;
+    use crate::nostr_client::testing::{http_server, keys};
+
+    /// The path and value of a header of an HTTP request, and its body
+    fn read_request(request: &str) -> (&str, Option<&str>, &str) {
+        let (head, body) = request.split_once("\r\n\r\n").unwrap_or((request, ""));
+        let path = head.split_whitespace().nth(1).unwrap_or_default();
+        let authorization = head.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("authorization")
+                .then_some(value.trim())
+        });
+        (path, authorization, body)
+    }
+
+    /// Writes a file named `name` holding `content` in a new directory
+    fn file(name: &str, content: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "gnc-upload-{}",
+            hex::encode(rand::random::<[u8; 8]>())
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn signer() -> Signer {
+        Signer::local(&keys().0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn follows_delegation_to_a_relative_api() {
+        let delegate = http_server(|request| match read_request(request).0 {
+            WELL_KNOWN_PATH => (200, json!({ "api_url": "/api/v2/media" }).to_string()),
+            _ => (404, String::new()),
+        })
+        .await;
+        let delegated = json!({ "api_url": "", "delegated_to_url": delegate.clone() });
+        let server = http_server(move |_| (200, delegated.to_string())).await;
+
+        let info = ServerInfo::fetch(&http_client(), &server).await.unwrap();
+        assert_eq!(info.api_url, format!("{}/api/v2/media", delegate));
+    }
+
+    #[tokio::test]
+    async fn fails_on_servers_without_an_api() {
+        let empty = http_server(|_| (200, json!({}).to_string())).await;
+        assert!(ServerInfo::fetch(&http_client(), &empty).await.is_err());
+
+        let missing = http_server(|_| (404, String::new())).await;
+        assert!(ServerInfo::fetch(&http_client(), &missing).await.is_err());
+
+        // Delegation only goes one server further
+        let delegate = http_server(|_| {
+            let delegated = json!({ "delegated_to_url": "http://127.0.0.1:9" });
+            (200, delegated.to_string())
+        })
+        .await;
+        let chained = http_server(move |_| {
+            (
+                200,
+                json!({ "delegated_to_url": delegate.clone() }).to_string(),
+            )
+        })
+        .await;
+        assert!(ServerInfo::fetch(&http_client(), &chained).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn uploads_and_waits_for_processing() {
+        let authorizations = Arc::new(Mutex::new(Vec::new()));
+        let seen = authorizations.clone();
+        let server = http_server(move |request| {
+            let (path, authorization, body) = read_request(request);
+            match path {
+                WELL_KNOWN_PATH => (
+                    200,
+                    json!({ "api_url": "/upload", "content_types": ["image/*"] }).to_string(),
+                ),
+                "/upload" => {
+                    seen.lock().unwrap().push((
+                        authorization.unwrap_or_default().to_string(),
+                        body.to_string(),
+                    ));
+                    (
+                        202,
+                        json!({ "status": "processing", "processing_url": "/upload/status/1" })
+                            .to_string(),
+                    )
+                }
+                "/upload/status/1" => (
+                    200,
+                    json!({
+                        "status": "success",
+                        "nip94_event": {
+                            "tags": [["url", "https://files.example/cat.png"], ["x", "abcd"]],
+                            "content": "a cat ",
+                        },
+                    })
+                    .to_string(),
+                ),
+                _ => (404, String::new()),
+            }
+        })
+        .await;
+
+        let path = file("cat.png", "not quite a png");
+        let file = upload(&mut signer(), &server, &path).await.unwrap();
+        assert_eq!(file.url, "https://files.example/cat.png");
+        assert_eq!(file.hash.as_deref(), Some("abcd"));
+        assert_eq!(file.mime_type.as_deref(), Some("image/png"));
+        assert_eq!(file.summary.as_deref(), Some("a cat"));
+
+        // The upload is signed for its url, method and body
+        let (authorization, body) = authorizations.lock().unwrap()[0].clone();
+        assert!(body.contains("filename=\"cat.png\""));
+        assert!(body.contains("not quite a png"));
+        let url = format!("{}/upload", server);
+        http_auth::verify(
+            &authorization,
+            &url,
+            "POST",
+            Some(body.as_bytes()),
+            unix_time(),
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_refusals() {
+        let server = http_server(|request| match read_request(request).0 {
+            WELL_KNOWN_PATH => (200, json!({ "api_url": "/upload" }).to_string()),
+            _ => (
+                413,
+                json!({ "status": "error", "message": "The file is too big" }).to_string(),
+            ),
+        })
+        .await;
+        let err = upload(&mut signer(), &server, &file("cat.png", "meow"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The server refused the file: The file is too big"
+        );
+
+        let broken = http_server(|request| match read_request(request).0 {
+            WELL_KNOWN_PATH => (200, json!({ "api_url": "/upload" }).to_string()),
+            _ => (500, "oops".to_string()),
+        })
+        .await;
+        let err = upload(&mut signer(), &broken, &file("cat.png", "meow"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("The server answered 500"));
+
+        let images_only = http_server(|_| {
+            (
+                200,
+                json!({ "api_url": "/upload", "content_types": ["image/*"] }).to_string(),
+            )
+        })
+        .await;
+        let err = upload(&mut signer(), &images_only, &file("notes.pdf", "%PDF"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The server doesn't take application/pdf files"
+        );
+    }
+
+    #[test]
+    fn builds_form_data() {
+        let body = form_data("b0undary", "my \"cat\".png", "image/png", b"data");
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "--b0undary\r\nContent-Disposition: form-data; name=\"size\"\r\n\r\n4\r\n\
+             --b0undary\r\nContent-Disposition: form-data; name=\"content_type\"\r\n\r\nimage/png\r\n\
+             --b0undary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"my cat.png\"\r\nContent-Type: image/png\r\n\r\n\
+             data\r\n--b0undary--\r\n"
+        );
+    }
+
+    #[test]
+    fn matches_content_types() {
+        let types = vec!["image/*".to_string(), "video/mp4".to_string()];
+        assert!(accepts(&types, "image/png"));
+        assert!(accepts(&types, "video/mp4"));
+        assert!(!accepts(&types, "video/webm"));
+        assert!(!accepts(&[], "image/png"));
+    }
+
+    #[test]
+    fn guesses_mime_types() {
+        assert_eq!(mime_type("cat.JPG"), "image/jpeg");
+        assert_eq!(mime_type("archive.tar.flac"), "audio/flac");
+        assert_eq!(mime_type("README"), "application/octet-stream");
+        assert_eq!(mime_type("program.exe"), "application/octet-stream");
+    }
+}
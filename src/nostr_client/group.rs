@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use super::event::Event as EventType;
+use super::relay_list::normalize_url;
+
+/// A message sent to a group, described by NIP-29. Every event of a group
+/// names it in an `h` tag, and lives on the relay hosting the group.
+pub const GROUP_CHAT_KIND: u64 = 9;
+
+/// Event of an admin adding a user to a group, or changing their roles
+pub const GROUP_PUT_USER_KIND: u64 = 9000;
+
+/// Event of an admin removing a user from a group
+pub const GROUP_REMOVE_USER_KIND: u64 = 9001;
+
+/// Event of an admin changing the name, picture or settings of a group
+pub const GROUP_EDIT_METADATA_KIND: u64 = 9002;
+
+/// Event of an admin deleting an event of a group
+pub const GROUP_DELETE_EVENT_KIND: u64 = 9005;
+
+/// Event of a user asking to join a group
+pub const GROUP_JOIN_REQUEST_KIND: u64 = 9021;
+
+/// Event of a user asking to be removed from a group
+pub const GROUP_LEAVE_REQUEST_KIND: u64 = 9022;
+
+/// Addressable event of the relay describing a group
+pub const GROUP_METADATA_KIND: u64 = 39000;
+
+/// Addressable event of the relay listing the admins of a group and their
+/// roles
+pub const GROUP_ADMINS_KIND: u64 = 39001;
+
+/// Addressable event of the relay listing the members of a group
+pub const GROUP_MEMBERS_KIND: u64 = 39002;
+
+/// Addressable event of the relay listing the roles admins may have
+pub const GROUP_ROLES_KIND: u64 = 39003;
+
+/// Whether `kind` is one the relay signs to describe a group
+pub fn is_relay_kind(kind: u64) -> bool {
+    (GROUP_METADATA_KIND..=GROUP_ROLES_KIND).contains(&kind)
+}
+
+/// Whether `event` can be trusted to come from the relay hosting its group,
+/// whose NIP-11 document gives `relay_pubkey`. The events describing the
+/// group have to be signed by the relay, so none can be trusted from relays
+/// that don't give their key.
+pub fn is_trusted(event: &EventType, relay_pubkey: Option<&str>) -> bool {
+    !is_relay_kind(event.kind) || relay_pubkey == Some(event.pubkey().as_str())
+}
+
+/// Whether `event` belongs to a group: written by its users with an `h`
+/// tag, or by the relay to describe it
+pub fn is_group_event(event: &EventType) -> bool {
+    is_relay_kind(event.kind) || event.tag_values("h").next().is_some()
+}
+
+/// A group, by the relay hosting it and its id there, written
+/// `host'id` as described by NIP-29
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GroupId {
+    /// The normalized url of the relay
+    pub relay: String,
+    pub id: String,
+}
+
+impl GroupId {
+    pub fn new(relay: &str, id: &str) -> Result<GroupId> {
+        let relay = match relay.contains("://") {
+            true => relay.to_string(),
+            false => format!("wss://{}", relay),
+        };
+        let relay = normalize_url(&relay).ok_or(anyhow!("Invalid relay {}", relay))?;
+
+        let valid = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !valid {
+            return Err(anyhow!("Invalid group id {}", id));
+        }
+
+        Ok(GroupId {
+            relay,
+            id: id.to_string(),
+        })
+    }
+}
+
+impl FromStr for GroupId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<GroupId> {
+        let (relay, id) = s
+            .trim()
+            .split_once('\'')
+            .ok_or(anyhow!("Groups are written host'id"))?;
+        GroupId::new(relay, id)
+    }
+}
+
+impl fmt::Display for GroupId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let host = self.relay.strip_prefix("wss://").unwrap_or(&self.relay);
+        write!(f, "{}'{}", host, self.id)
+    }
+}
+
+/// What the relay hosting a group says about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub id: GroupId,
+    pub name: Option<String>,
+    pub about: Option<String>,
+    pub picture: Option<String>,
+    /// Whether only members can read it
+    pub private: bool,
+    /// Whether the relay ignores join requests, leaving it to admins to add
+    /// users
+    pub closed: bool,
+    /// Admins and their roles
+    pub admins: Vec<(String, Vec<String>)>,
+    pub members: Vec<String>,
+    /// Roles admins may have, with what they're for
+    pub roles: Vec<(String, String)>,
+    /// When each of the events of the relay describing it was made, so
+    /// older versions are ignored
+    updated_at: HashMap<u64, u64>,
+}
+
+impl Group {
+    pub fn new(id: GroupId) -> Group {
+        Group {
+            id,
+            name: None,
+            about: None,
+            picture: None,
+            private: false,
+            closed: false,
+            admins: Vec::new(),
+            members: Vec::new(),
+            roles: Vec::new(),
+            updated_at: HashMap::new(),
+        }
+    }
+
+    /// Applies one of the events of the relay describing the group, unless
+    /// a newer one was applied already. Returns whether it was.
+    ///
+    /// Only the relay may publish those, so they have to be checked to be
+    /// signed by the pubkey in its NIP-11 document before.
+    pub fn apply(&mut self, event: &EventType) -> bool {
+        let applies = is_relay_kind(event.kind)
+            && event.tag_values("d").next() == Some(self.id.id.as_str())
+            && self
+                .updated_at
+                .get(&event.kind)
+                .is_none_or(|updated_at| *updated_at < event.created_at);
+        if !applies {
+            return false;
+        }
+
+        let first = |name: &'static str| {
+            event
+                .tag_values(name)
+                .next()
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        match event.kind {
+            GROUP_METADATA_KIND => {
+                self.name = first("name");
+                self.about = first("about");
+                self.picture = first("picture");
+                self.private = event.tags_named("private").next().is_some();
+                self.closed = event.tags_named("closed").next().is_some();
+            }
+            GROUP_ADMINS_KIND => {
+                self.admins = event
+                    .tags_named("p")
+                    .filter_map(|tag| Some((tag.get(1)?.clone(), tag[2..].to_vec())))
+                    .collect();
+            }
+            GROUP_MEMBERS_KIND => {
+                self.members = event.tag_values("p").map(str::to_string).collect();
+            }
+            GROUP_ROLES_KIND => {
+                self.roles = event
+                    .tags_named("role")
+                    .filter_map(|tag| {
+                        let description = tag.get(2).cloned().unwrap_or_default();
+                        Some((tag.get(1)?.clone(), description))
+                    })
+                    .collect();
+            }
+            _ => {}
+        }
+        self.updated_at.insert(event.kind, event.created_at);
+        true
+    }
+
+    pub fn is_admin(&self, pubkey: &str) -> bool {
+        self.admins.iter().any(|(admin, _)| admin == pubkey)
+    }
+
+    /// Whether `pubkey` is in the group, as far as the relay told
+    pub fn is_member(&self, pubkey: &str) -> bool {
+        self.is_admin(pubkey) || self.members.iter().any(|member| member == pubkey)
+    }
+
+    /// The name of the group, or its id for unnamed ones
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.id.id.clone())
+    }
+}
+
+/// Builds the unsigned message of `pubkey` to `group`, replying to
+/// `reply_to` if it's given
+pub fn message(
+    pubkey: &str,
+    group: &GroupId,
+    content: String,
+    reply_to: Option<&EventType>,
+) -> EventType {
+    let mut event = group_event(pubkey, GROUP_CHAT_KIND, group, content);
+    if let Some(parent) = reply_to {
+        event.tags.push(vec![
+            "q".to_string(),
+            parent.id().unwrap_or_default().to_string(),
+            group.relay.clone(),
+            parent.pubkey(),
+        ]);
+    }
+
+    event
+}
+
+/// Builds the unsigned request of `pubkey` to join `group`, with the
+/// invite code they were given if any
+pub fn join_request(pubkey: &str, group: &GroupId, reason: &str, code: Option<&str>) -> EventType {
+    let mut event = group_event(pubkey, GROUP_JOIN_REQUEST_KIND, group, reason.to_string());
+    if let Some(code) = code {
+        event.add_tag("code", code, None);
+    }
+
+    event
+}
+
+/// Builds the unsigned request of `pubkey` to leave `group`
+pub fn leave_request(pubkey: &str, group: &GroupId, reason: &str) -> EventType {
+    group_event(pubkey, GROUP_LEAVE_REQUEST_KIND, group, reason.to_string())
+}
+
+/// Builds the unsigned event of the admin `pubkey` adding `user` to
+/// `group` with the given roles
+pub fn put_user(pubkey: &str, group: &GroupId, user: &str, roles: &[String]) -> EventType {
+    let mut event = group_event(pubkey, GROUP_PUT_USER_KIND, group, String::new());
+    let mut tag = vec!["p".to_string(), user.to_string()];
+    tag.extend(roles.iter().cloned());
+    event.tags.push(tag);
+
+    event
+}
+
+/// Builds the unsigned event of the admin `pubkey` removing `user` from
+/// `group`
+pub fn remove_user(pubkey: &str, group: &GroupId, user: &str, reason: &str) -> EventType {
+    let mut event = group_event(pubkey, GROUP_REMOVE_USER_KIND, group, reason.to_string());
+    event.add_tag("p", user, None);
+
+    event
+}
+
+/// Builds the unsigned event of the admin `pubkey` deleting the event with
+/// id `event_id` from `group`
+pub fn delete_event(pubkey: &str, group: &GroupId, event_id: &str, reason: &str) -> EventType {
+    let mut event = group_event(pubkey, GROUP_DELETE_EVENT_KIND, group, reason.to_string());
+    event.add_tag("e", event_id, None);
+
+    event
+}
+
+fn group_event(pubkey: &str, kind: u64, group: &GroupId, content: String) -> EventType {
+    let mut event = EventType::new(pubkey, kind, content);
+    event.add_tag("h", &group.id, None);
+
+    event
+}
+
+/// Groups and their events received so far, per relay
+#[derive(Debug, Default)]
+pub struct Groups {
+    groups: HashMap<GroupId, Group>,
+    /// Messages, requests and moderation events of every group, oldest
+    /// first
+    events: HashMap<GroupId, Vec<EventType>>,
+}
+
+impl Groups {
+    pub fn new() -> Groups {
+        Groups::default()
+    }
+
+    /// Stores `event`, received from `relay`, returning the group it's
+    /// about if it was one of a group
+    pub fn insert(&mut self, relay: &str, event: &EventType) -> Option<GroupId> {
+        let id = match is_relay_kind(event.kind) {
+            true => event.tag_values("d").next()?,
+            false => event.tag_values("h").next()?,
+        };
+        let id = GroupId::new(relay, id).ok()?;
+
+        if is_relay_kind(event.kind) {
+            let group = self
+                .groups
+                .entry(id.clone())
+                .or_insert_with(|| Group::new(id.clone()));
+            return group.apply(event).then_some(id);
+        }
+
+        if event.kind == GROUP_DELETE_EVENT_KIND && self.is_admin(&id, &event.pubkey()) {
+            let deleted: Vec<&str> = event.tag_values("e").collect();
+            if let Some(events) = self.events.get_mut(&id) {
+                events.retain(|known| !known.id().is_some_and(|id| deleted.contains(&id)));
+            }
+        }
+
+        let events = self.events.entry(id.clone()).or_default();
+        if events.iter().any(|known| known.id() == event.id()) {
+            return None;
+        }
+        let position = events.partition_point(|known| known.created_at <= event.created_at);
+        events.insert(position, event.clone());
+        Some(id)
+    }
+
+    pub fn get(&self, id: &GroupId) -> Option<&Group> {
+        self.groups.get(id)
+    }
+
+    fn is_admin(&self, id: &GroupId, pubkey: &str) -> bool {
+        self.groups
+            .get(id)
+            .is_some_and(|group| group.is_admin(pubkey))
+    }
+
+    /// The groups heard of, by name
+    pub fn list(&self) -> Vec<&Group> {
+        let mut groups: Vec<&Group> = self.groups.values().collect();
+        groups.sort_by_key(|group| group.display_name().to_lowercase());
+
+        groups
+    }
+
+    /// The messages of `id`, along with the requests and moderation events
+    /// of its users, oldest first. Deletions are left out, since what they
+    /// delete is.
+    pub fn events(&self, id: &GroupId) -> Vec<&EventType> {
+        self.events
+            .get(id)
+            .into_iter()
+            .flatten()
+            .filter(|event| event.kind != GROUP_DELETE_EVENT_KIND)
+            .collect()
+    }
+
+    /// Drops the events `remove` returns true for, like deleted ones
+    pub fn remove_events(&mut self, remove: impl Fn(&EventType) -> bool) {
+        for events in self.events.values_mut() {
+            events.retain(|event| !remove(event));
+        }
+    }
+
+    /// Looks a message up by id, in any group
+    pub fn find_message(&self, id: &str) -> Option<&EventType> {
+        self.events
+            .values()
+            .flatten()
+            .find(|event| event.id() == Some(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed};
+
+    #[test]
+    fn trusts_only_what_the_relay_signs_about_groups() {
+        let (relay_secret_key, relay_pubkey) = keys();
+        let (user_secret_key, _) = keys();
+        let metadata = |secret_key: &str| {
+            signed(
+                secret_key,
+                GROUP_METADATA_KIND,
+                "",
+                vec![vec!["d", "pizza"], vec!["name", "Pizza lovers"]],
+            )
+        };
+        let message = signed(
+            &user_secret_key,
+            GROUP_CHAT_KIND,
+            "hello",
+            vec![vec!["h", "pizza"]],
+        );
+
+        assert!(is_trusted(
+            &metadata(&relay_secret_key),
+            Some(&relay_pubkey)
+        ));
+        assert!(!is_trusted(
+            &metadata(&user_secret_key),
+            Some(&relay_pubkey)
+        ));
+        assert!(!is_trusted(&metadata(&relay_secret_key), None));
+        assert!(is_trusted(&message, Some(&relay_pubkey)));
+        assert!(is_trusted(&message, None));
+    }
+
+    #[test]
+    fn reads_what_the_relay_says_about_groups() {
+        let (relay_secret_key, _) = keys();
+        let (user_secret_key, user_pubkey) = keys();
+        let relay = "wss://groups.example";
+        let id = GroupId::new(relay, "pizza").unwrap();
+        let mut groups = Groups::new();
+
+        let metadata = signed(
+            &relay_secret_key,
+            GROUP_METADATA_KIND,
+            "",
+            vec![vec!["d", "pizza"], vec!["name", "Pizza lovers"]],
+        );
+        assert_eq!(groups.insert(relay, &metadata), Some(id.clone()));
+        let members = signed(
+            &relay_secret_key,
+            GROUP_MEMBERS_KIND,
+            "",
+            vec![vec!["d", "pizza"], vec!["p", &user_pubkey]],
+        );
+        groups.insert(relay, &members);
+        let message = signed(
+            &user_secret_key,
+            GROUP_CHAT_KIND,
+            "hello",
+            vec![vec!["h", "pizza"]],
+        );
+        groups.insert(relay, &message);
+
+        let group = groups.get(&id).unwrap();
+        assert_eq!(group.display_name(), "Pizza lovers");
+        assert!(group.is_member(&user_pubkey));
+        let events: Vec<_> = groups.events(&id).iter().map(|event| event.id()).collect();
+        assert_eq!(events, vec![message.id()]);
+    }
+
+    #[test]
+    fn keeps_moderation_events_of_groups() {
+        let (admin, _) = keys();
+        let relay = "wss://groups.example";
+        let id = GroupId::new(relay, "pizza").unwrap();
+        let mut groups = Groups::new();
+        let edit = signed(
+            &admin,
+            GROUP_EDIT_METADATA_KIND,
+            "",
+            vec![vec!["h", "pizza"], vec!["name", "Pizza fans"]],
+        );
+        let stray = signed(&admin, GROUP_EDIT_METADATA_KIND, "", vec![]);
+
+        assert!(is_group_event(&edit));
+        assert!(!is_group_event(&stray));
+        assert_eq!(groups.insert(relay, &edit), Some(id.clone()));
+        assert_eq!(groups.insert(relay, &stray), None);
+        let events: Vec<_> = groups.events(&id).iter().map(|event| event.id()).collect();
+        assert_eq!(events, vec![edit.id()]);
+    }
+}
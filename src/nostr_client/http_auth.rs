@@ -0,0 +1,184 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use secp256k1::Secp256k1;
+use sha2::{Digest, Sha256};
+
+use super::event::Event as EventType;
+
+/// Event proving who makes an HTTP request, described by NIP-98. It's sent
+/// in the `Authorization` header of the request rather than to relays.
+pub const HTTP_AUTH_KIND: u64 = 27235;
+
+/// Scheme of the `Authorization` headers carrying an HTTP auth event
+pub const AUTH_SCHEME: &str = "Nostr";
+
+/// How many seconds the time of an HTTP auth event may be off from the
+/// time the request is checked, since it can be replayed in the meantime
+pub const MAX_TIME_DIFFERENCE: u64 = 60;
+
+/// Builds the unsigned HTTP auth event of `pubkey` for a `method` request
+/// to `url`, bound to the body of the request if it has one.
+pub fn auth_event(pubkey: &str, url: &str, method: &str, body: Option<&[u8]>) -> EventType {
+    let mut event = EventType::new(pubkey, HTTP_AUTH_KIND, String::new());
+    event.add_tag("u", url, None);
+    event.add_tag("method", &method.to_uppercase(), None);
+    if let Some(body) = body {
+        event.add_tag("payload", &payload_hash(body), None);
+    }
+
+    event
+}
+
+/// The value of the `Authorization` header carrying the signed `event`
+pub fn header(event: &EventType) -> Result<String> {
+    let json = serde_json::to_string(event)?;
+    Ok(format!("{} {}", AUTH_SCHEME, BASE64.encode(json)))
+}
+
+/// Reads the HTTP auth event carried by the value of an `Authorization`
+/// header, without checking it
+pub fn from_header(header: &str) -> Result<EventType> {
+    let encoded = header
+        .trim()
+        .strip_prefix(AUTH_SCHEME)
+        .filter(|rest| rest.starts_with(' '))
+        .ok_or(anyhow!(
+            "The authorization is not of the {} scheme",
+            AUTH_SCHEME
+        ))?;
+    let json = BASE64.decode(encoded.trim())?;
+
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// Checks that the value of an `Authorization` header allows a `method`
+/// request to `url` made at `now`, and that it was signed for `body`:
+/// requests with a body have to name it. Returns the pubkey of who made the
+/// request.
+pub fn verify(
+    header: &str,
+    url: &str,
+    method: &str,
+    body: Option<&[u8]>,
+    now: u64,
+) -> Result<String> {
+    let event = from_header(header)?;
+    if event.kind != HTTP_AUTH_KIND {
+        return Err(anyhow!(
+            "Event of kind {} is not an HTTP authorization",
+            event.kind
+        ));
+    }
+    event.verify(&Secp256k1::verification_only())?;
+
+    if event.created_at.abs_diff(now) > MAX_TIME_DIFFERENCE {
+        return Err(anyhow!("The authorization has expired"));
+    }
+    if event.tag_values("u").next() != Some(url) {
+        return Err(anyhow!("The authorization is for another url"));
+    }
+    let signed_method = event.tag_values("method").next().unwrap_or_default();
+    if !signed_method.eq_ignore_ascii_case(method) {
+        return Err(anyhow!("The authorization is for another method"));
+    }
+    match (event.tag_values("payload").next(), body) {
+        (Some(payload), body) => {
+            if !payload.eq_ignore_ascii_case(&payload_hash(body.unwrap_or_default())) {
+                return Err(anyhow!("The authorization is for another body"));
+            }
+        }
+        (None, Some(body)) if !body.is_empty() => {
+            return Err(anyhow!("The authorization isn't for a body"));
+        }
+        (None, _) => {}
+    }
+
+    Ok(event.pubkey())
+}
+
+/// The hex-encoded sha256 of `body`, as found in the `payload` tag
+pub fn payload_hash(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::event::{pubkey_from_secret, unix_time};
+    use crate::nostr_client::testing::{keys, signed};
+
+    const URL: &str = "https://files.example/api/upload";
+
+    /// The header authorizing a request of `secret_key`
+    fn authorization(secret_key: &str, method: &str, body: Option<&[u8]>) -> String {
+        let secp = Secp256k1::new();
+        let pubkey = pubkey_from_secret(secret_key, &secp).unwrap();
+        let mut event = auth_event(&pubkey, URL, method, body);
+        event.setup(secret_key, &secp).unwrap();
+        header(&event).unwrap()
+    }
+
+    #[test]
+    fn verifies_what_it_builds() {
+        let (secret_key, pubkey) = keys();
+        let now = unix_time();
+
+        let get = authorization(&secret_key, "get", None);
+        assert_eq!(verify(&get, URL, "GET", None, now).unwrap(), pubkey);
+        assert_eq!(verify(&get, URL, "GET", Some(b""), now).unwrap(), pubkey);
+
+        let post = authorization(&secret_key, "POST", Some(b"file"));
+        assert_eq!(from_header(&post).unwrap().kind, HTTP_AUTH_KIND);
+        assert_eq!(
+            verify(&post, URL, "post", Some(b"file"), now + 30).unwrap(),
+            pubkey
+        );
+    }
+
+    #[test]
+    fn rejects_other_requests() {
+        let (secret_key, _) = keys();
+        let now = unix_time();
+        let post = authorization(&secret_key, "POST", Some(b"file"));
+
+        let other_url = "https://files.example/api/delete";
+        assert!(verify(&post, other_url, "POST", Some(b"file"), now).is_err());
+        assert!(verify(&post, URL, "PUT", Some(b"file"), now).is_err());
+        assert!(verify(&post, URL, "POST", Some(b"other file"), now).is_err());
+        assert!(verify(&post, URL, "POST", Some(b"file"), now + 120).is_err());
+        assert!(verify(&post, URL, "POST", Some(b"file"), now - 120).is_err());
+
+        // Requests with a body have to be signed for it
+        let unbound = authorization(&secret_key, "POST", None);
+        assert!(verify(&unbound, URL, "POST", Some(b"file"), now).is_err());
+    }
+
+    #[test]
+    fn rejects_other_events() {
+        let (secret_key, _) = keys();
+        let now = unix_time();
+
+        let note = signed(
+            &secret_key,
+            1,
+            "",
+            vec![vec!["u", URL], vec!["method", "GET"]],
+        );
+        assert!(verify(&header(&note).unwrap(), URL, "GET", None, now).is_err());
+
+        let mut forged = from_header(&authorization(&secret_key, "GET", None)).unwrap();
+        forged.tags[0][1] = "https://files.example/api/delete".to_string();
+        let forged = header(&forged).unwrap();
+        assert!(verify(
+            &forged,
+            "https://files.example/api/delete",
+            "GET",
+            None,
+            now
+        )
+        .is_err());
+
+        assert!(verify("Bearer abc", URL, "GET", None, now).is_err());
+        assert!(verify("Nostr not-base64!", URL, "GET", None, now).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use reqwest::redirect::Policy;
+use serde::Deserialize;
+
+/// How long a successful lookup is trusted before asking the domain again
+const VERIFIED_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How long a failed lookup is remembered, so a broken domain isn't asked
+/// again for every note of the same author
+const FAILED_TTL: Duration = Duration::from_secs(5 * 60);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An internet identifier of the form `name@domain`, as described by NIP-05
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Identifier {
+    pub name: String,
+    pub domain: String,
+}
+
+impl FromStr for Identifier {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        // A bare domain stands for the root identifier of that domain
+        let (name, domain) = s.rsplit_once('@').unwrap_or(("_", s));
+
+        let valid_name = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+        if name.is_empty() || !valid_name || domain.is_empty() || domain.contains('/') {
+            return Err(anyhow!("invalid identifier {}", s));
+        }
+
+        Ok(Identifier {
+            name: name.to_lowercase(),
+            domain: domain.to_lowercase(),
+        })
+    }
+}
+
+/// Shows the identifier the way clients usually do, with the root
+/// identifier `_@domain` shown as just the domain
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name == "_" {
+            return write!(f, "{}", self.domain);
+        }
+
+        write!(f, "{}@{}", self.name, self.domain)
+    }
+}
+
+/// What a domain's `nostr.json` says about an identifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentifierEntry {
+    pub pubkey: String,
+    /// Relays where the user can be found, if the domain suggests any
+    pub relays: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WellKnown {
+    #[serde(default)]
+    names: HashMap<String, String>,
+    #[serde(default)]
+    relays: HashMap<String, Vec<String>>,
+}
+
+struct CachedLookup {
+    entry: Option<IdentifierEntry>,
+    fetched_at: Instant,
+}
+
+/// Resolves and verifies identifiers, caching what the domains answer.
+///
+/// It only needs a shared reference, so a single verifier can serve many
+/// tasks at once.
+pub struct IdentifierVerifier {
+    client: reqwest::Client,
+    /// Where requests go instead of `https://<domain>`, for talking to a
+    /// local stand-in server
+    base_url: Option<String>,
+    cache: Mutex<HashMap<Identifier, CachedLookup>>,
+}
+
+impl Default for IdentifierVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdentifierVerifier {
+    pub fn new() -> IdentifierVerifier {
+        // NIP-05 forbids following redirects, since they would let any
+        // server answer for the domain
+        let client = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("Failed to build the http client");
+
+        IdentifierVerifier {
+            client,
+            base_url: None,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sends every request to `base_url` instead of the identifier's domain
+    #[cfg(test)]
+    pub fn with_base_url(base_url: &str) -> IdentifierVerifier {
+        IdentifierVerifier {
+            base_url: Some(base_url.trim_end_matches('/').to_string()),
+            ..Self::new()
+        }
+    }
+
+    /// Finds the pubkey and relays `identifier` points to
+    pub async fn lookup(&self, identifier: &Identifier) -> Result<IdentifierEntry> {
+        if let Some(cached) = self.cached(identifier) {
+            return cached.ok_or(anyhow!("{} could not be found", identifier));
+        }
+
+        let entry = self.fetch(identifier).await;
+        self.cache.lock().unwrap().insert(
+            identifier.clone(),
+            CachedLookup {
+                entry: entry.as_ref().ok().cloned(),
+                fetched_at: Instant::now(),
+            },
+        );
+
+        entry
+    }
+
+    /// Checks that `identifier` points to `pubkey`
+    pub async fn verify(&self, identifier: &Identifier, pubkey: &str) -> bool {
+        self.lookup(identifier)
+            .await
+            .is_ok_and(|entry| entry.pubkey == pubkey)
+    }
+
+    /// The cached result of a lookup that hasn't expired yet
+    fn cached(&self, identifier: &Identifier) -> Option<Option<IdentifierEntry>> {
+        let cache = self.cache.lock().unwrap();
+        let cached = cache.get(identifier)?;
+
+        let ttl = match cached.entry {
+            Some(_) => VERIFIED_TTL,
+            None => FAILED_TTL,
+        };
+        if cached.fetched_at.elapsed() > ttl {
+            return None;
+        }
+
+        Some(cached.entry.clone())
+    }
+
+    async fn fetch(&self, identifier: &Identifier) -> Result<IdentifierEntry> {
+        let base_url = match &self.base_url {
+            Some(base_url) => base_url.clone(),
+            None => format!("https://{}", identifier.domain),
+        };
+
+        let well_known: WellKnown = self
+            .client
+            .get(format!("{}/.well-known/nostr.json", base_url))
+            .query(&[("name", &identifier.name)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let pubkey = well_known
+            .names
+            .iter()
+            .find(|(name, _)| name.to_lowercase() == identifier.name)
+            .map(|(_, pubkey)| pubkey.to_lowercase())
+            .ok_or(anyhow!("{} is not listed by its domain", identifier))?;
+        let relays = well_known.relays.get(&pubkey).cloned().unwrap_or_default();
+
+        Ok(IdentifierEntry { pubkey, relays })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::nostr_client::testing::{http_server, keys};
+
+    /// A verifier talking to a server whose `nostr.json` lists `names` and
+    /// `relays`
+    async fn verifier(names: serde_json::Value, relays: serde_json::Value) -> IdentifierVerifier {
+        let url = http_server(move |request| {
+            let (head, _) = request.split_once("\r\n").unwrap_or_default();
+            if !head.starts_with("GET /.well-known/nostr.json?name=") {
+                return (404, String::new());
+            }
+            (200, json!({ "names": names, "relays": relays }).to_string())
+        })
+        .await;
+
+        IdentifierVerifier::with_base_url(&url)
+    }
+
+    #[test]
+    fn parses_identifiers() {
+        let identifier: Identifier = " Bob@Example.com ".parse().unwrap();
+        assert_eq!(identifier.name, "bob");
+        assert_eq!(identifier.domain, "example.com");
+        assert_eq!(identifier.to_string(), "bob@example.com");
+
+        let root: Identifier = "example.com".parse().unwrap();
+        assert_eq!(root.name, "_");
+        assert_eq!(root.to_string(), "example.com");
+
+        assert!("bob smith@example.com".parse::<Identifier>().is_err());
+        assert!("bob@".parse::<Identifier>().is_err());
+        assert!("bob@example.com/path".parse::<Identifier>().is_err());
+    }
+
+    #[tokio::test]
+    async fn resolves_names_whatever_their_case() {
+        let (_, pubkey) = keys();
+        let verifier = verifier(
+            json!({ "Bob": pubkey.to_uppercase() }),
+            json!({ &pubkey: ["wss://relay.example.com"] }),
+        )
+        .await;
+
+        let entry = verifier
+            .lookup(&"BOB@example.com".parse().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(entry.pubkey, pubkey);
+        assert_eq!(entry.relays, vec!["wss://relay.example.com"]);
+    }
+
+    #[tokio::test]
+    async fn resolves_the_root_identifier() {
+        let (_, pubkey) = keys();
+        let (_, other) = keys();
+        let verifier = verifier(json!({ "_": pubkey, "bob": other }), json!({})).await;
+
+        let root = "example.com".parse().unwrap();
+
+        assert_eq!(verifier.lookup(&root).await.unwrap().pubkey, pubkey);
+        assert!(verifier.verify(&root, &pubkey).await);
+    }
+
+    #[tokio::test]
+    async fn rejects_other_pubkeys_and_unlisted_names() {
+        let (_, pubkey) = keys();
+        let (_, other) = keys();
+        let verifier = verifier(json!({ "bob": pubkey }), json!({})).await;
+
+        assert!(
+            !verifier
+                .verify(&"bob@example.com".parse().unwrap(), &other)
+                .await
+        );
+        assert!(verifier
+            .lookup(&"alice@example.com".parse().unwrap())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn fails_on_error_statuses() {
+        let url = http_server(|_| (404, String::new())).await;
+        let verifier = IdentifierVerifier::with_base_url(&url);
+
+        assert!(verifier
+            .lookup(&"bob@example.com".parse().unwrap())
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::event::Event as EventType;
+
+/// Event labeling notes or users, described by NIP-32. Other events may
+/// label themselves with the same tags.
+pub const LABEL_KIND: u64 = 1985;
+
+/// Namespace of the labels that don't say which one they belong to
+pub const UGC_NAMESPACE: &str = "ugc";
+
+/// A value from a namespace, like `en` from `ISO-639-1`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Label {
+    pub namespace: String,
+    pub value: String,
+}
+
+impl Label {
+    pub fn new(namespace: &str, value: &str) -> Label {
+        Label {
+            namespace: namespace.to_string(),
+            value: value.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.namespace.as_str() {
+            UGC_NAMESPACE => write!(f, "{}", self.value),
+            namespace => write!(f, "{}/{}", namespace, self.value),
+        }
+    }
+}
+
+/// The labels in the `l` tags of `event`, each in the namespace its tag is
+/// marked with
+pub fn labels(event: &EventType) -> Vec<Label> {
+    event
+        .tags_named("l")
+        .filter_map(|tag| {
+            let value = tag.get(1).filter(|value| !value.is_empty())?;
+            let namespace = tag.get(2).map_or(UGC_NAMESPACE, String::as_str);
+            Some(Label::new(namespace, value))
+        })
+        .collect()
+}
+
+/// The ids of the notes a kind 1985 `event` labels, or the pubkeys of the
+/// users it labels if it labels no note
+pub fn targets(event: &EventType) -> Vec<&str> {
+    match event.tags_named("e").next() {
+        Some(_) => event.tag_values("e").collect(),
+        None => event.tag_values("p").collect(),
+    }
+}
+
+/// Builds the unsigned event of `pubkey` labeling `note` with `label`
+pub fn label_note(pubkey: &str, note: &EventType, label: &Label) -> EventType {
+    let mut event = label_event(pubkey, label);
+    event.add_tag("e", note.id().unwrap_or_default(), None);
+    event.add_tag("p", &note.pubkey(), None);
+
+    event
+}
+
+/// Builds the unsigned event of `pubkey` labeling the user `user` with
+/// `label`
+pub fn label_user(pubkey: &str, user: &str, label: &Label) -> EventType {
+    let mut event = label_event(pubkey, label);
+    event.add_tag("p", user, None);
+
+    event
+}
+
+fn label_event(pubkey: &str, label: &Label) -> EventType {
+    let mut event = EventType::new(pubkey, LABEL_KIND, String::new());
+    if label.namespace != UGC_NAMESPACE {
+        event.add_tag("L", &label.namespace, None);
+    }
+    event.tags.push(vec![
+        "l".to_string(),
+        label.value.clone(),
+        label.namespace.clone(),
+    ]);
+
+    event
+}
+
+/// Labels received so far, by the id of the note or the pubkey of the user
+/// they're about, along with who put them
+#[derive(Debug, Default)]
+pub struct Labels {
+    labels: HashMap<String, Vec<(String, Label)>>,
+}
+
+impl Labels {
+    pub fn new() -> Labels {
+        Labels::default()
+    }
+
+    /// Stores the labels of a kind 1985 `event`, returning whether there
+    /// were new ones
+    pub fn insert(&mut self, event: &EventType) -> bool {
+        if event.kind != LABEL_KIND {
+            return false;
+        }
+
+        let labeler = event.pubkey();
+        let new_labels = labels(event);
+        let mut inserted = false;
+        for target in targets(event) {
+            let known = self.labels.entry(target.to_string()).or_default();
+            for label in &new_labels {
+                let entry = (labeler.clone(), label.clone());
+                if !known.contains(&entry) {
+                    known.push(entry);
+                    inserted = true;
+                }
+            }
+        }
+
+        inserted
+    }
+
+    /// The labels of the note with id `target`, or the user with pubkey
+    /// `target`, with who put them
+    pub fn of(&self, target: &str) -> &[(String, Label)] {
+        self.labels.get(target).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed};
+
+    fn sign(secret_key: &str, mut event: EventType) -> EventType {
+        event
+            .setup(secret_key, &secp256k1::Secp256k1::new())
+            .unwrap();
+        event
+    }
+
+    #[test]
+    fn reads_label_namespaces() {
+        let (secret_key, _) = keys();
+        let event = signed(
+            &secret_key,
+            1,
+            "hola",
+            vec![
+                vec!["L", "ISO-639-1"],
+                vec!["l", "es", "ISO-639-1"],
+                vec!["l", "greeting"],
+                vec!["l", ""],
+            ],
+        );
+
+        let read = labels(&event);
+
+        assert_eq!(
+            read,
+            vec![
+                Label::new("ISO-639-1", "es"),
+                Label::new(UGC_NAMESPACE, "greeting")
+            ]
+        );
+        assert_eq!(read[0].to_string(), "ISO-639-1/es");
+        assert_eq!(read[1].to_string(), "greeting");
+    }
+
+    #[test]
+    fn labels_notes_and_users() {
+        let (secret_key, pubkey) = keys();
+        let (author, author_pubkey) = keys();
+        let note = signed(&author, 1, "hello", vec![]);
+        let language = Label::new("ISO-639-1", "en");
+        let funny = Label::new(UGC_NAMESPACE, "funny");
+
+        let on_note = sign(&secret_key, label_note(&pubkey, &note, &language));
+        assert_eq!(on_note.kind, LABEL_KIND);
+        assert_eq!(
+            on_note.tag_values("L").collect::<Vec<_>>(),
+            vec!["ISO-639-1"]
+        );
+        assert_eq!(labels(&on_note), vec![language.clone()]);
+        assert_eq!(targets(&on_note), vec![note.id().unwrap()]);
+
+        let on_user = sign(&secret_key, label_user(&pubkey, &author_pubkey, &funny));
+        assert!(on_user.tag_values("L").next().is_none());
+        assert_eq!(labels(&on_user), vec![funny.clone()]);
+        assert_eq!(targets(&on_user), vec![author_pubkey.as_str()]);
+
+        let mut known = Labels::new();
+        assert!(known.insert(&on_note));
+        assert!(!known.insert(&on_note));
+        assert!(known.insert(&on_user));
+        assert!(!known.insert(&note));
+        assert_eq!(known.of(note.id().unwrap()), &[(pubkey.clone(), language)]);
+        assert_eq!(known.of(&author_pubkey), &[(pubkey, funny)]);
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+
+use super::event::Event as EventType;
+use super::thread::ThreadRefs;
+
+/// Users, words, hashtags and threads the author doesn't want to see
+pub const MUTE_LIST_KIND: u64 = 10000;
+
+/// Notes the author wants shown on their profile
+pub const PIN_LIST_KIND: u64 = 10001;
+
+/// Notes and articles the author saved for later
+pub const BOOKMARKS_KIND: u64 = 10003;
+
+/// NIP-29 groups the author is in, as `group` items with the id of the
+/// group and the relay hosting it
+pub const GROUPS_KIND: u64 = 10009;
+
+/// Named groups of users
+pub const FOLLOW_SET_KIND: u64 = 30000;
+
+/// Named groups of bookmarks
+pub const BOOKMARK_SET_KIND: u64 = 30003;
+
+/// Named selections of notes and articles
+pub const CURATION_SET_KIND: u64 = 30004;
+
+/// Tags of sets that describe the set rather than being items of it
+const METADATA_TAGS: [&str; 5] = ["d", "title", "name", "description", "image"];
+
+/// A list or set of items described by NIP-51. Items are tags, like `p`
+/// for users and `e` for notes. The private ones are kept encrypted to the
+/// author in the content, so only they can read them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct List {
+    pub kind: u64,
+    pub author: String,
+    /// The `d` tag of sets, which lists don't have
+    pub identifier: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub public: Vec<Vec<String>>,
+    pub private: Vec<Vec<String>>,
+    /// The content as published, when the private items it holds couldn't
+    /// be decrypted. It's published back as is, so they aren't lost.
+    pub unreadable_content: Option<String>,
+    pub created_at: u64,
+}
+
+impl List {
+    /// A new empty list of `kind` by `author`
+    pub fn new(kind: u64, author: &str) -> List {
+        List {
+            kind,
+            author: author.to_string(),
+            identifier: is_set(kind).then(String::new),
+            title: None,
+            description: None,
+            image: None,
+            public: Vec::new(),
+            private: Vec::new(),
+            unreadable_content: None,
+            created_at: 0,
+        }
+    }
+
+    /// Reads the public items of a list. The private ones are encrypted in
+    /// the content, see `read_private`.
+    pub fn from_event(event: &EventType) -> Result<List> {
+        if !is_list(event) {
+            return Err(anyhow!("Event of kind {} is not a list", event.kind));
+        }
+
+        let first = |name: &'static str| {
+            event
+                .tag_values(name)
+                .next()
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        let public = event
+            .tags
+            .iter()
+            .filter(|tag| {
+                tag.first()
+                    .is_some_and(|name| !METADATA_TAGS.contains(&name.as_str()))
+            })
+            .cloned()
+            .collect();
+
+        Ok(List {
+            kind: event.kind,
+            author: event.pubkey(),
+            identifier: is_set(event.kind).then(|| first("d").unwrap_or_default()),
+            title: first("title").or(first("name")),
+            description: first("description"),
+            image: first("image"),
+            public,
+            private: Vec::new(),
+            unreadable_content: None,
+            created_at: event.created_at,
+        })
+    }
+
+    /// Sets the private items from `json`, the decrypted content of the
+    /// list
+    pub fn read_private(&mut self, json: &str) -> Result<()> {
+        self.private =
+            serde_json::from_str(json).map_err(|err| anyhow!("Invalid private items: {}", err))?;
+
+        Ok(())
+    }
+
+    /// The private items as JSON, to be encrypted to the author with NIP-44
+    /// and published as the content of the list
+    pub fn private_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&self.private)?)
+    }
+
+    /// Builds the unsigned event publishing this list, with `content`
+    /// holding the encrypted private items, see `private_json`
+    pub fn to_event(&self, content: String) -> EventType {
+        let mut event = EventType::new(&self.author, self.kind, content);
+
+        if let Some(identifier) = &self.identifier {
+            event.add_tag("d", identifier, None);
+        }
+        for (name, value) in [
+            ("title", &self.title),
+            ("description", &self.description),
+            ("image", &self.image),
+        ] {
+            if let Some(value) = value {
+                event.add_tag(name, value, None);
+            }
+        }
+        event.tags.extend(self.public.iter().cloned());
+
+        event
+    }
+
+    /// The `kind:pubkey:identifier` coordinate of the list
+    pub fn address(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            self.kind,
+            self.author,
+            self.identifier.as_deref().unwrap_or_default()
+        )
+    }
+
+    /// Every item, public ones first
+    pub fn items(&self) -> impl Iterator<Item = &Vec<String>> {
+        self.public.iter().chain(self.private.iter())
+    }
+
+    /// The values of the items named `name`, like the ids of the `e` items
+    pub fn values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.items()
+            .filter(move |item| item.first().map(String::as_str) == Some(name))
+            .filter_map(|item| item.get(1).map(String::as_str))
+    }
+
+    pub fn contains(&self, name: &str, value: &str) -> bool {
+        self.values(name).any(|known| known == value)
+    }
+
+    /// Adds the item `name` `value`, privately or not, unless it's there
+    /// already
+    pub fn insert(&mut self, name: &str, value: &str, private: bool) {
+        if self.contains(name, value) {
+            return;
+        }
+
+        let item = vec![name.to_string(), value.to_string()];
+        match private {
+            true => self.private.push(item),
+            false => self.public.push(item),
+        }
+    }
+
+    /// Removes the item `name` `value`, returning whether it was there
+    pub fn remove(&mut self, name: &str, value: &str) -> bool {
+        let matches = |item: &Vec<String>| {
+            item.first().map(String::as_str) == Some(name)
+                && item.get(1).map(String::as_str) == Some(value)
+        };
+        let count = self.public.len() + self.private.len();
+        self.public.retain(|item| !matches(item));
+        self.private.retain(|item| !matches(item));
+
+        self.public.len() + self.private.len() != count
+    }
+
+    /// Adds the item if it isn't there, or removes it, returning whether
+    /// it's there now
+    pub fn toggle(&mut self, name: &str, value: &str, private: bool) -> bool {
+        if self.remove(name, value) {
+            return false;
+        }
+
+        self.insert(name, value, private);
+        true
+    }
+}
+
+/// Whether `event` is one of the lists or sets this module reads
+pub fn is_list(event: &EventType) -> bool {
+    is_list_kind(event.kind)
+}
+
+/// Whether events of `kind` are lists or sets this module reads
+pub fn is_list_kind(kind: u64) -> bool {
+    matches!(
+        kind,
+        MUTE_LIST_KIND | PIN_LIST_KIND | BOOKMARKS_KIND | GROUPS_KIND
+    ) || is_set(kind)
+}
+
+/// Whether lists of `kind` are sets, of which a user may have many
+pub fn is_set(kind: u64) -> bool {
+    matches!(
+        kind,
+        FOLLOW_SET_KIND | BOOKMARK_SET_KIND | CURATION_SET_KIND
+    )
+}
+
+/// What the mute list of the user hides from them
+#[derive(Debug, Clone, Default)]
+pub struct Mutes {
+    pubkeys: HashSet<String>,
+    /// Lowercase words hidden wherever they appear
+    words: Vec<String>,
+    /// Lowercase hashtags, without the `#`
+    hashtags: HashSet<String>,
+    /// Ids of the notes whose whole conversation is hidden
+    threads: HashSet<String>,
+}
+
+impl Mutes {
+    pub fn from_list(list: &List) -> Mutes {
+        Mutes {
+            pubkeys: list.values("p").map(str::to_string).collect(),
+            words: list
+                .values("word")
+                .filter(|word| !word.is_empty())
+                .map(str::to_lowercase)
+                .collect(),
+            hashtags: list.values("t").map(str::to_lowercase).collect(),
+            threads: list.values("e").map(str::to_string).collect(),
+        }
+    }
+
+    /// Whether `event` should be hidden: its author, one of its words or
+    /// hashtags, or the conversation it belongs to was muted
+    pub fn mutes(&self, event: &EventType) -> bool {
+        if self.pubkeys.contains(&event.pubkey()) {
+            return true;
+        }
+
+        let in_muted_thread = event
+            .id()
+            .into_iter()
+            .map(str::to_string)
+            .chain(ThreadRefs::root_id(event))
+            .any(|id| self.threads.contains(&id));
+        if in_muted_thread {
+            return true;
+        }
+
+        let content = event.content.to_lowercase();
+        let tagged = event
+            .tag_values("t")
+            .any(|hashtag| self.hashtags.contains(&hashtag.to_lowercase()));
+        let written = content.split_whitespace().any(|word| {
+            word.strip_prefix('#').is_some_and(|hashtag| {
+                self.hashtags
+                    .contains(hashtag.trim_end_matches(|c: char| !c.is_alphanumeric()))
+            })
+        });
+
+        tagged || written || self.words.iter().any(|word| content.contains(word))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::encryption::{nip44_decrypt, nip44_encrypt};
+    use crate::nostr_client::testing::{keys, signed};
+
+    #[test]
+    fn keeps_private_items_encrypted_to_the_author() {
+        let (secret_key, pubkey) = keys();
+        let (_, spammer) = keys();
+        let (_, troll) = keys();
+        let mut list = List::new(MUTE_LIST_KIND, &pubkey);
+        list.insert("p", &spammer, false);
+        list.insert("p", &troll, true);
+        list.insert("word", "Airdrop", true);
+        list.insert("p", &troll, false);
+
+        let content = nip44_encrypt(&secret_key, &pubkey, &list.private_json().unwrap()).unwrap();
+        let mut event = list.to_event(content);
+        event
+            .setup(&secret_key, &secp256k1::Secp256k1::new())
+            .unwrap();
+
+        assert_eq!(event.tags, vec![vec!["p", spammer.as_str()]]);
+        assert!(!event.content.contains(&troll));
+
+        let mut read = List::from_event(&event).unwrap();
+        assert_eq!(read.identifier, None);
+        assert_eq!(read.public, list.public);
+        assert!(read.private.is_empty());
+        read.read_private(&nip44_decrypt(&secret_key, &pubkey, &event.content).unwrap())
+            .unwrap();
+        assert_eq!(read.private, list.private);
+        assert!(read.contains("p", &troll));
+        assert!(read.read_private("not json").is_err());
+    }
+
+    #[test]
+    fn reads_sets() {
+        let (secret_key, pubkey) = keys();
+        let (_, friend) = keys();
+        let event = signed(
+            &secret_key,
+            FOLLOW_SET_KIND,
+            "",
+            vec![
+                vec!["d", "friends"],
+                vec!["name", "Friends"],
+                vec!["description", "People I know"],
+                vec!["p", &friend],
+            ],
+        );
+
+        let list = List::from_event(&event).unwrap();
+
+        assert_eq!(list.identifier.as_deref(), Some("friends"));
+        assert_eq!(list.title.as_deref(), Some("Friends"));
+        assert_eq!(list.description.as_deref(), Some("People I know"));
+        assert_eq!(list.public, vec![vec!["p", friend.as_str()]]);
+        assert_eq!(list.address(), format!("30000:{}:friends", pubkey));
+        assert!(List::from_event(&signed(&secret_key, 1, "", vec![])).is_err());
+    }
+
+    #[test]
+    fn toggles_items() {
+        let (_, pubkey) = keys();
+        let mut list = List::new(BOOKMARKS_KIND, &pubkey);
+
+        assert!(list.toggle("e", "id", true));
+        assert_eq!(list.private, vec![vec!["e", "id"]]);
+        assert!(!list.toggle("e", "id", false));
+        assert!(list.items().next().is_none());
+        assert!(!list.remove("e", "id"));
+    }
+
+    #[test]
+    fn mutes_public_and_private_items() {
+        let (secret_key, pubkey) = keys();
+        let (muted, muted_pubkey) = keys();
+        let (other, _) = keys();
+        let mut list = List::new(MUTE_LIST_KIND, &pubkey);
+        list.insert("p", &muted_pubkey, false);
+        list.insert("word", "Airdrop", true);
+        list.insert("t", "Spam", true);
+        let thread = signed(&other, 1, "root", vec![]);
+        list.insert("e", thread.id().unwrap(), false);
+        let mutes = Mutes::from_list(&list);
+
+        let note = |secret_key: &str, content: &str, tags: Vec<Vec<&str>>| {
+            mutes.mutes(&signed(secret_key, 1, content, tags))
+        };
+        assert!(note(&muted, "hello", vec![]));
+        assert!(note(&other, "Free AIRDROP now", vec![]));
+        assert!(note(&other, "so much #spam!", vec![]));
+        assert!(note(&other, "tagged", vec![vec!["t", "SPAM"]]));
+        assert!(mutes.mutes(&thread));
+        assert!(note(
+            &other,
+            "a reply",
+            vec![vec!["e", thread.id().unwrap(), "", "root"]]
+        ));
+        assert!(!note(&secret_key, "hello #nostr", vec![]));
+    }
+}
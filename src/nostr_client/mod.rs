@@ -1,4 +1,38 @@
+pub mod article;
+pub mod badge;
+pub mod bolt11;
+pub mod bunker;
+pub mod calendar;
+pub mod channel;
 pub mod connection;
+pub mod contact_list;
+pub mod content_warning;
+pub mod deletion;
+pub mod encryption;
+pub mod entity;
 pub mod event;
+pub mod expiration;
+pub mod file_metadata;
+pub mod file_storage;
+pub mod group;
+pub mod http_auth;
+pub mod identifier;
+pub mod label;
+pub mod list;
+pub mod nostr_connect;
+pub mod pool;
+pub mod profile;
+pub mod reaction;
+pub mod reference;
+pub mod relay_info;
+pub mod relay_list;
+pub mod report;
+pub mod repost;
 pub mod request;
 pub mod response;
+pub mod signer;
+#[cfg(test)]
+pub mod testing;
+pub mod thread;
+pub mod wallet_connect;
+pub mod zap;
@@ -0,0 +1,603 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Result};
+use rand::distributions::{Alphanumeric, DistString};
+use secp256k1::{All, Secp256k1, XOnlyPublicKey};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use tokio::time::timeout;
+use url::{form_urlencoded, Url};
+
+use super::encryption;
+use super::event::{pubkey_from_secret, unix_time, Event as EventType};
+use super::pool::{any_accepted, RelayPool};
+use super::request::Filter;
+use super::response::Response as RelayResponse;
+
+/// Kind of the encrypted requests and responses exchanged with a remote
+/// signer, as described by NIP-46
+pub const NOSTR_CONNECT_KIND: u64 = 24133;
+
+/// How long a request waits for its response. Signers may ask their user
+/// to approve it first.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long the client waits for a signer to connect through a
+/// `nostrconnect://` URI, which the user has to paste in it first
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Length of the random ids of requests, and of the secrets of
+/// `nostrconnect://` URIs
+const RANDOM_LENGTH: usize = 16;
+
+/// A request to a remote signer. Its params are all strings, events and
+/// other objects included, which are given as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Request {
+    pub id: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: Vec<String>,
+}
+
+impl Request {
+    /// A request to call `method` with a new random id
+    pub fn new(method: &str, params: Vec<String>) -> Request {
+        Request {
+            id: random_string(),
+            method: method.to_string(),
+            params,
+        }
+    }
+}
+
+/// The answer of a remote signer to the request with the same id
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Response {
+    pub id: String,
+    #[serde(default)]
+    pub result: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    pub fn ok(id: &str, result: &str) -> Response {
+        Response {
+            id: id.to_string(),
+            result: result.to_string(),
+            error: None,
+        }
+    }
+
+    pub fn error(id: &str, error: &str) -> Response {
+        Response {
+            id: id.to_string(),
+            result: String::new(),
+            error: Some(error.to_string()),
+        }
+    }
+
+    /// The page the user has to visit for the signer to go on with the
+    /// request, if it needs them to. The actual response comes afterwards.
+    pub fn auth_url(&self) -> Option<&str> {
+        match self.result.as_str() {
+            "auth_url" => self.error.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Builds the unsigned event carrying `message` from `author`, the owner of
+/// `secret_key`, to `recipient`, encrypted with NIP-44
+pub fn message_event(
+    secret_key: &str,
+    author: &str,
+    recipient: &str,
+    message: &impl Serialize,
+) -> Result<EventType> {
+    let content =
+        encryption::nip44_encrypt(secret_key, recipient, &serde_json::to_string(message)?)?;
+    let mut event = EventType::new(author, NOSTR_CONNECT_KIND, content);
+    event.add_tag("p", recipient, None);
+
+    Ok(event)
+}
+
+/// Reads the message `event` carries to the owner of `secret_key`. Some
+/// signers still encrypt them with NIP-04.
+pub fn read_message<T: DeserializeOwned>(secret_key: &str, event: &EventType) -> Result<T> {
+    if event.kind != NOSTR_CONNECT_KIND {
+        return Err(anyhow!("Event of kind {} is not a message", event.kind));
+    }
+
+    let json = encryption::decrypt(secret_key, &event.pubkey(), &event.content)?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// A `bunker://` URI, which a remote signer hands out for clients to
+/// connect to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BunkerUri {
+    pub signer_pubkey: String,
+    pub relays: Vec<String>,
+    /// Single use secret proving the client was given the URI
+    pub secret: Option<String>,
+}
+
+impl FromStr for BunkerUri {
+    type Err = anyhow::Error;
+
+    fn from_str(uri: &str) -> Result<BunkerUri> {
+        let (signer_pubkey, query) = parse_uri(uri, "bunker")?;
+
+        Ok(BunkerUri {
+            signer_pubkey,
+            relays: query.values("relay"),
+            secret: query.value("secret"),
+        })
+    }
+}
+
+impl fmt::Display for BunkerUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        for relay in &self.relays {
+            query.append_pair("relay", relay);
+        }
+        if let Some(secret) = &self.secret {
+            query.append_pair("secret", secret);
+        }
+
+        write!(f, "bunker://{}?{}", self.signer_pubkey, query.finish())
+    }
+}
+
+/// A `nostrconnect://` URI, which a client shows for the user to paste in
+/// their remote signer, so that the signer connects to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectUri {
+    pub client_pubkey: String,
+    pub relays: Vec<String>,
+    /// Secret the signer answers the connection with, so the client knows
+    /// it's the one it was shown to
+    pub secret: String,
+    /// What the client would like to be allowed to do, like `sign_event:1`
+    pub permissions: Vec<String>,
+    /// Name of the client, shown by the signer
+    pub name: Option<String>,
+}
+
+impl ConnectUri {
+    /// A URI for the client with key `client_pubkey` to be reached at
+    /// `relays`, with a new random secret
+    pub fn new(client_pubkey: &str, relays: Vec<String>) -> ConnectUri {
+        ConnectUri {
+            client_pubkey: client_pubkey.to_string(),
+            relays,
+            secret: random_string(),
+            permissions: Vec::new(),
+            name: None,
+        }
+    }
+}
+
+impl FromStr for ConnectUri {
+    type Err = anyhow::Error;
+
+    fn from_str(uri: &str) -> Result<ConnectUri> {
+        let (client_pubkey, query) = parse_uri(uri, "nostrconnect")?;
+
+        Ok(ConnectUri {
+            client_pubkey,
+            relays: query.values("relay"),
+            secret: query
+                .value("secret")
+                .ok_or(anyhow!("The URI has no secret"))?,
+            permissions: query
+                .value("perms")
+                .map(|permissions| {
+                    permissions
+                        .split(',')
+                        .filter(|permission| !permission.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            name: query.value("name"),
+        })
+    }
+}
+
+impl fmt::Display for ConnectUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        for relay in &self.relays {
+            query.append_pair("relay", relay);
+        }
+        query.append_pair("secret", &self.secret);
+        if !self.permissions.is_empty() {
+            query.append_pair("perms", &self.permissions.join(","));
+        }
+        if let Some(name) = &self.name {
+            query.append_pair("name", name);
+        }
+
+        write!(
+            f,
+            "nostrconnect://{}?{}",
+            self.client_pubkey,
+            query.finish()
+        )
+    }
+}
+
+/// The query parameters of a URI
+pub struct Query(Vec<(String, String)>);
+
+impl Query {
+    pub fn values(&self, name: &str) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(key, value)| key == name && !value.is_empty())
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    pub fn value(&self, name: &str) -> Option<String> {
+        self.values(name).into_iter().next()
+    }
+}
+
+/// Splits a URI of `scheme` into the pubkey it starts with and its query,
+/// making sure it names at least one relay
+pub fn parse_uri(uri: &str, scheme: &str) -> Result<(String, Query)> {
+    let url = Url::parse(uri.trim())?;
+    if url.scheme() != scheme {
+        return Err(anyhow!("Expected a {}:// URI", scheme));
+    }
+
+    // Some apps leave the slashes out, which makes the pubkey the path
+    let pubkey = url.host_str().unwrap_or(url.path()).to_lowercase();
+    XOnlyPublicKey::from_str(&pubkey).map_err(|_| anyhow!("Invalid pubkey {}", pubkey))?;
+
+    let query = Query(url.query_pairs().into_owned().collect());
+    if query.values("relay").is_empty() {
+        return Err(anyhow!("The URI names no relay"));
+    }
+
+    Ok((pubkey, query))
+}
+
+fn random_string() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), RANDOM_LENGTH)
+}
+
+/// Called with the pages the user has to visit to approve requests
+type AuthUrlCallback = Box<dyn Fn(&str) + Send>;
+
+/// A connection to a NIP-46 remote signer, which holds the user's key and
+/// signs or encrypts on their behalf when asked to over relays. The client
+/// talks to it with a key of its own, which is not the user's.
+pub struct RemoteSigner {
+    client_secret_key: String,
+    client_pubkey: String,
+    signer_pubkey: String,
+    /// The user the signer signs for, whose key may differ from the one
+    /// the signer talks with
+    user_pubkey: String,
+    relays: Vec<String>,
+    pool: RelayPool,
+    secp: Secp256k1<All>,
+    /// Told about the pages the user has to visit to approve requests
+    on_auth_url: Option<AuthUrlCallback>,
+}
+
+impl RemoteSigner {
+    /// Starts listening at `relays` for messages to the client, which talks
+    /// with `client_secret_key`. The signer is then reached through
+    /// `connect` or `accept`.
+    pub async fn new(client_secret_key: &str, relays: Vec<String>) -> Result<RemoteSigner> {
+        let secp = Secp256k1::new();
+        let client_pubkey = pubkey_from_secret(client_secret_key, &secp)?;
+
+        let mut pool = RelayPool::new(relays.clone());
+        let filter = Filter::new()
+            .kinds(vec![NOSTR_CONNECT_KIND])
+            .tag('p', vec![client_pubkey.clone()])
+            .since(unix_time());
+        if pool.subscribe_to(&relays, vec![filter]).await.is_empty() {
+            return Err(anyhow!("Could not reach the relays of the signer"));
+        }
+
+        Ok(RemoteSigner {
+            client_secret_key: client_secret_key.to_string(),
+            client_pubkey,
+            signer_pubkey: String::new(),
+            user_pubkey: String::new(),
+            relays,
+            pool,
+            secp,
+            on_auth_url: None,
+        })
+    }
+
+    /// Connects to the signer behind `uri`, which should name the relays
+    /// the client listens at
+    pub async fn connect(&mut self, uri: &BunkerUri) -> Result<()> {
+        self.signer_pubkey = uri.signer_pubkey.clone();
+
+        let mut params = vec![uri.signer_pubkey.clone()];
+        params.extend(uri.secret.clone());
+        let result = self.call("connect", params).await?;
+        if result != "ack" && Some(&result) != uri.secret.as_ref() {
+            return Err(anyhow!("Unexpected answer to connect: {}", result));
+        }
+
+        self.load_user_pubkey().await
+    }
+
+    /// Waits for a signer to connect through `uri`, which the user pasted
+    /// in it
+    pub async fn accept(&mut self, uri: &ConnectUri) -> Result<()> {
+        let wait = async {
+            loop {
+                let (event, response) = self.next_message().await;
+                if response.result == uri.secret {
+                    return event.pubkey();
+                }
+            }
+        };
+        self.signer_pubkey = timeout(ACCEPT_TIMEOUT, wait)
+            .await
+            .map_err(|_| anyhow!("No signer connected"))?;
+
+        self.load_user_pubkey().await
+    }
+
+    async fn load_user_pubkey(&mut self) -> Result<()> {
+        let pubkey = self.call("get_public_key", Vec::new()).await?;
+        XOnlyPublicKey::from_str(&pubkey)
+            .map_err(|_| anyhow!("The signer answered with an invalid pubkey"))?;
+
+        self.user_pubkey = pubkey;
+        Ok(())
+    }
+
+    /// The pubkey of the user the signer signs for
+    pub fn pubkey(&self) -> &str {
+        &self.user_pubkey
+    }
+
+    /// Calls `callback` with the pages the user has to visit for the
+    /// signer to go on with a request.
+    pub fn on_auth_url(&mut self, callback: impl Fn(&str) + Send + 'static) {
+        self.on_auth_url = Some(Box::new(callback));
+    }
+
+    /// Asks the signer to sign `event`, making sure it signed that very
+    /// event for the user.
+    pub async fn sign_event(&mut self, event: &EventType) -> Result<EventType> {
+        let unsigned = json!({
+            "kind": event.kind,
+            "content": event.content,
+            "tags": event.tags,
+            "created_at": event.created_at,
+        });
+        let result = self.call("sign_event", vec![unsigned.to_string()]).await?;
+
+        let signed: EventType = serde_json::from_str(&result)?;
+        signed.verify(&self.secp)?;
+        let same = signed.pubkey() == self.user_pubkey
+            && signed.kind == event.kind
+            && signed.content == event.content
+            && signed.tags == event.tags
+            && signed.created_at == event.created_at;
+        if !same {
+            return Err(anyhow!("The signer signed a different event"));
+        }
+
+        Ok(signed)
+    }
+
+    pub async fn nip44_encrypt(&mut self, pubkey: &str, plaintext: &str) -> Result<String> {
+        let params = vec![pubkey.to_string(), plaintext.to_string()];
+        self.call("nip44_encrypt", params).await
+    }
+
+    pub async fn nip44_decrypt(&mut self, pubkey: &str, payload: &str) -> Result<String> {
+        let params = vec![pubkey.to_string(), payload.to_string()];
+        self.call("nip44_decrypt", params).await
+    }
+
+    pub async fn nip04_decrypt(&mut self, pubkey: &str, payload: &str) -> Result<String> {
+        let params = vec![pubkey.to_string(), payload.to_string()];
+        self.call("nip04_decrypt", params).await
+    }
+
+    /// Sends a request to the signer and waits for its result
+    async fn call(&mut self, method: &str, params: Vec<String>) -> Result<String> {
+        let request = Request::new(method, params);
+        let mut event = message_event(
+            &self.client_secret_key,
+            &self.client_pubkey,
+            &self.signer_pubkey,
+            &request,
+        )?;
+        event.setup(&self.client_secret_key, &self.secp)?;
+
+        let relays = self.relays.clone();
+        if !any_accepted(&self.pool.publish_to(&relays, &event).await) {
+            return Err(anyhow!("No relay accepted the request to the signer"));
+        }
+
+        let wait = async {
+            loop {
+                let (event, response) = self.next_message().await;
+                if response.id != request.id || event.pubkey() != self.signer_pubkey {
+                    continue;
+                }
+                if let Some(url) = response.auth_url() {
+                    if let Some(callback) = &self.on_auth_url {
+                        callback(url);
+                    }
+                    continue;
+                }
+
+                return match response.error {
+                    Some(error) if !error.is_empty() => {
+                        Err(anyhow!("The signer refused to {}: {}", method, error))
+                    }
+                    _ => Ok(response.result),
+                };
+            }
+        };
+        timeout(REQUEST_TIMEOUT, wait)
+            .await
+            .map_err(|_| anyhow!("The signer didn't answer"))?
+    }
+
+    /// Waits for the next response sent to the client, skipping whatever
+    /// can't be read
+    async fn next_message(&mut self) -> (EventType, Response) {
+        loop {
+            let (_, RelayResponse::Event { event, .. }) = self.pool.next_response().await else {
+                continue;
+            };
+            if let Ok(response) = read_message(&self.client_secret_key, &event) {
+                return (event, response);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use tokio::task::JoinHandle;
+
+    use super::*;
+    use crate::nostr_client::bunker::{Bunker, Decision, Outcome};
+    use crate::nostr_client::testing::{keys, TestRelay};
+
+    /// Answers the requests sent to `bunker` at `relay`, approving each one
+    /// the app wasn't allowed to make as if the user did
+    fn serve(mut bunker: Bunker, relay: String) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut pool = RelayPool::new(vec![relay.clone()]);
+            let filter = Filter::new()
+                .kinds(vec![NOSTR_CONNECT_KIND])
+                .tag('p', vec![bunker.pubkey().to_string()]);
+            pool.subscribe_to(std::slice::from_ref(&relay), vec![filter])
+                .await;
+
+            let mut seen = HashSet::new();
+            loop {
+                let (_, RelayResponse::Event { event, .. }) = pool.next_response().await else {
+                    continue;
+                };
+                let Ok(request) = bunker.read(&event) else {
+                    continue;
+                };
+                // The relay sends stored requests again after reconnecting
+                if !seen.insert(request.id.clone()) {
+                    continue;
+                }
+
+                let client = event.pubkey();
+                let response = match bunker.handle(&client, &request) {
+                    Outcome::Answer(response) => response,
+                    Outcome::Ask { permission } => {
+                        bunker.decide(&client, &request, &permission, Decision::Once)
+                    }
+                };
+                let answer = bunker.message(&client, &response).unwrap();
+                pool.publish_to(std::slice::from_ref(&relay), &answer).await;
+            }
+        })
+    }
+
+    /// A remote signer connected to a bunker holding the key of a new user,
+    /// whose pubkey is returned too
+    async fn connected(relay: &TestRelay) -> (RemoteSigner, String, JoinHandle<()>) {
+        let (user_secret_key, user_pubkey) = keys();
+        let mut bunker = Bunker::new(&user_secret_key).unwrap();
+        let uri = bunker.uri(vec![relay.url.clone()]);
+        let service = serve(bunker, relay.url.clone());
+
+        let (client_secret_key, _) = keys();
+        let mut signer = RemoteSigner::new(&client_secret_key, vec![relay.url.clone()])
+            .await
+            .unwrap();
+        signer.connect(&uri).await.unwrap();
+
+        (signer, user_pubkey, service)
+    }
+
+    #[test]
+    fn reads_uris() {
+        let (_, pubkey) = keys();
+        let uri: ConnectUri = format!(
+            "nostrconnect://{}?relay=wss%3A%2F%2Frelay.example.com&secret=abc&perms=sign_event%3A1,nip44_encrypt&name=App",
+            pubkey
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(uri.relays, vec!["wss://relay.example.com"]);
+        assert_eq!(uri.permissions, vec!["sign_event:1", "nip44_encrypt"]);
+        assert_eq!(uri.to_string().parse::<ConnectUri>().unwrap(), uri);
+
+        let uri: BunkerUri = format!("bunker://{}?relay=wss://relay.example.com", pubkey)
+            .parse()
+            .unwrap();
+        assert_eq!(uri.secret, None);
+        assert!(format!("bunker://{}", pubkey).parse::<BunkerUri>().is_err());
+        assert!("bunker://nope?relay=wss://relay.example.com"
+            .parse::<BunkerUri>()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn signs_and_encrypts_through_a_bunker() {
+        let relay = TestRelay::start().await;
+        let (mut signer, user_pubkey, _service) = connected(&relay).await;
+        assert_eq!(signer.pubkey(), user_pubkey);
+
+        let note = EventType::new(&user_pubkey, 1, "hello".to_string());
+        let signed = signer.sign_event(&note).await.unwrap();
+        assert_eq!(signed.content, "hello");
+        assert_eq!(signed.pubkey(), user_pubkey);
+
+        let (_, other) = keys();
+        let encrypted = signer.nip44_encrypt(&other, "secret").await.unwrap();
+        assert_ne!(encrypted, "secret");
+        let self_encrypted = signer
+            .nip44_encrypt(&user_pubkey, "note to self")
+            .await
+            .unwrap();
+        assert_eq!(
+            signer
+                .nip44_decrypt(&user_pubkey, &self_encrypted)
+                .await
+                .unwrap(),
+            "note to self"
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_listening_after_the_relay_drops() {
+        let mut relay = TestRelay::start().await;
+        let (mut signer, user_pubkey, _service) = connected(&relay).await;
+
+        relay.stop();
+        relay.restart().await;
+
+        let note = EventType::new(&user_pubkey, 1, "still there".to_string());
+        let signed = timeout(Duration::from_secs(20), signer.sign_event(&note))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(signed.content, "still there");
+    }
+}
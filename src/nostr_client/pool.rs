@@ -0,0 +1,656 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use tokio::time::{sleep_until, Instant};
+
+use anyhow::{anyhow, Result};
+use futures::future::{join_all, pending, select_all};
+use tokio::time::timeout;
+
+use super::connection::{Connection, Disconnected};
+use super::event::Event as EventType;
+use super::relay_list::{normalize_url, RelayList, RELAY_LIST_KIND};
+use super::request::Filter;
+use super::response::Response;
+
+/// How long we wait for a relay to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long we first wait before reconnecting to a relay that dropped us,
+/// doubled after each failed attempt up to `MAX_RECONNECT_DELAY`
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(300);
+
+/// How many of a user's relays are used when reading from or writing to
+/// them. Users often list many relays that all carry the same notes.
+const MAX_RELAYS_PER_USER: usize = 3;
+
+/// A set of relay connections that are opened on demand, routing requests
+/// with the outbox model: notes are read from their authors' write relays,
+/// and delivered to the read relays of the users they mention.
+pub struct RelayPool {
+    connections: HashMap<String, Connection>,
+    /// Filters of the live subscriptions on each relay, by subscription id,
+    /// sent again when the relay is reconnected to
+    subscriptions: HashMap<String, HashMap<String, Vec<Filter>>>,
+    /// Relays with live subscriptions that dropped us, by when we next try
+    /// to reconnect to them
+    reconnects: HashMap<String, Reconnect>,
+    /// Newest known relay list of each pubkey
+    relay_lists: HashMap<String, RelayList>,
+    /// Relays used to discover relay lists, and as a fallback for users who
+    /// haven't published one
+    bootstrap_relays: Vec<String>,
+}
+
+impl RelayPool {
+    pub fn new(bootstrap_relays: Vec<String>) -> RelayPool {
+        RelayPool {
+            connections: HashMap::new(),
+            subscriptions: HashMap::new(),
+            reconnects: HashMap::new(),
+            relay_lists: HashMap::new(),
+            bootstrap_relays: bootstrap_relays
+                .iter()
+                .filter_map(|url| normalize_url(url))
+                .collect(),
+        }
+    }
+
+    pub fn bootstrap_relays(&self) -> &[String] {
+        &self.bootstrap_relays
+    }
+
+    /// The connected relays that only serve authenticated clients, which
+    /// nothing can be read from or published to
+    pub fn auth_required_relays(&self) -> Vec<String> {
+        let mut relays: Vec<String> = self
+            .connections
+            .iter()
+            .filter(|(_, conn)| conn.requires_auth())
+            .map(|(url, _)| url.clone())
+            .collect();
+        relays.sort();
+
+        relays
+    }
+
+    /// Whether a connection to `relay_url` is open. Relays that fail a
+    /// request are dropped, so this tells whether the last one went through.
+    pub fn is_connected(&self, relay_url: &str) -> bool {
+        normalize_url(relay_url).is_some_and(|url| self.connections.contains_key(&url))
+    }
+
+    /// Returns the connection to `relay_url`, connecting to it first if needed
+    pub async fn connection(&mut self, relay_url: &str) -> Result<&mut Connection> {
+        let url = normalize_url(relay_url).ok_or(anyhow!("invalid relay url {}", relay_url))?;
+        self.connect_all(std::slice::from_ref(&url)).await;
+
+        self.connections
+            .get_mut(&url)
+            .ok_or(anyhow!("could not connect to {}", url))
+    }
+
+    /// The cached relay list of `pubkey`, see `load_relay_lists`
+    pub fn relay_list(&self, pubkey: &str) -> Option<&RelayList> {
+        self.relay_lists.get(pubkey)
+    }
+
+    /// Caches `list` as the relay list of `pubkey`, unless a newer one is
+    /// already known
+    pub fn set_relay_list(&mut self, pubkey: &str, list: RelayList) {
+        match self.relay_lists.get(pubkey) {
+            Some(known) if known.created_at >= list.created_at => {}
+            _ => {
+                self.relay_lists.insert(pubkey.to_string(), list);
+            }
+        }
+    }
+
+    /// Fetches the relay lists of the given pubkeys that aren't cached yet
+    /// from the bootstrap relays
+    pub async fn load_relay_lists(&mut self, pubkeys: &[String]) {
+        let missing: Vec<String> = pubkeys
+            .iter()
+            .filter(|pubkey| !self.relay_lists.contains_key(*pubkey))
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        let filter = Filter::new().authors(missing).kinds(vec![RELAY_LIST_KIND]);
+        let relays = self.bootstrap_relays.clone();
+        for event in self.fetch_from(&relays, vec![filter]).await {
+            if let Ok(list) = RelayList::from_event(&event) {
+                self.set_relay_list(&event.pubkey(), list);
+            }
+        }
+    }
+
+    /// Fetches the events matching `filters` from each of `relays`. Relays
+    /// that fail are skipped, and duplicated events are only returned once.
+    pub async fn fetch_from(&mut self, relays: &[String], filters: Vec<Filter>) -> Vec<EventType> {
+        let requests = relays
+            .iter()
+            .filter_map(|url| normalize_url(url))
+            .map(|url| (url, filters.clone()))
+            .collect();
+
+        self.fetch_each(requests).await
+    }
+
+    /// Fetches the events matching `filter` written by `authors`, asking
+    /// each author's write relays for their own notes only
+    pub async fn fetch_from_authors(
+        &mut self,
+        filter: Filter,
+        authors: &[String],
+    ) -> Vec<EventType> {
+        let requests = self.requests_by_author(filter, authors).await;
+
+        self.fetch_each(requests).await
+    }
+
+    /// Opens a live subscription for the events matching `filter` written by
+    /// `authors` on their write relays. Returns the relay and id of each
+    /// subscription, the events themselves come through `next_response`.
+    pub async fn subscribe_authors(
+        &mut self,
+        filter: Filter,
+        authors: &[String],
+    ) -> Vec<(String, String)> {
+        let requests = self.requests_by_author(filter, authors).await;
+
+        self.subscribe_each(requests).await
+    }
+
+    /// Opens a live subscription for `filters` on each of `relays`
+    pub async fn subscribe_to(
+        &mut self,
+        relays: &[String],
+        filters: Vec<Filter>,
+    ) -> Vec<(String, String)> {
+        let requests = relays
+            .iter()
+            .filter_map(|url| normalize_url(url))
+            .map(|url| (url, filters.clone()))
+            .collect();
+
+        self.subscribe_each(requests).await
+    }
+
+    /// Closes subscriptions returned by `subscribe_authors` or `subscribe_to`
+    pub async fn close_subscriptions(&mut self, subscriptions: &[(String, String)]) {
+        for (url, subscription_id) in subscriptions {
+            self.forget_subscription(url, subscription_id);
+            if let Some(conn) = self.connections.get_mut(url) {
+                // A relay that fails here will be dropped on its next read
+                let _ = conn.close(subscription_id).await;
+            }
+        }
+    }
+
+    /// Waits for the next message sent by any of the connected relays, and
+    /// returns it along with the url of the relay. Connections that end are
+    /// dropped from the pool, and reconnected to in the meantime when they
+    /// had live subscriptions.
+    pub async fn next_response(&mut self) -> (String, Response) {
+        loop {
+            let next_reconnect = self.reconnects.values().map(|reconnect| reconnect.at).min();
+            let reconnect = async {
+                match next_reconnect {
+                    Some(at) => sleep_until(at).await,
+                    None => pending().await,
+                }
+            };
+            let read = async {
+                if self.connections.is_empty() {
+                    return pending().await;
+                }
+
+                let reads = self.connections.iter_mut().map(|(url, conn)| {
+                    Box::pin(async move { (url.clone(), conn.next_response().await) })
+                });
+                select_all(reads).await.0
+            };
+
+            let read = tokio::select! {
+                read = read => Some(read),
+                _ = reconnect => None,
+            };
+            match read {
+                Some((url, Ok(Some(response)))) => {
+                    // The relay ended this subscription itself, so it isn't
+                    // restored later
+                    if let Response::Closed {
+                        subscription_id, ..
+                    } = &response
+                    {
+                        self.forget_subscription(&url, subscription_id);
+                    }
+                    return (url, response);
+                }
+                Some((url, _)) => self.drop_connection(&url),
+                None => self.reconnect_due().await,
+            }
+        }
+    }
+
+    /// Fetches the events matching `filter` that mention `pubkey`, from the
+    /// relays where they expect to receive them
+    pub async fn fetch_mentions(&mut self, filter: Filter, pubkey: &str) -> Vec<EventType> {
+        self.load_relay_lists(&[pubkey.to_string()]).await;
+
+        let relays = self.inbox_relays(pubkey);
+        let filter = filter.tag('p', vec![pubkey.to_string()]);
+
+        self.fetch_from(&relays, vec![filter]).await
+    }
+
+    /// Sends `event` to each of `relays`, returning the outcome per relay
+    pub async fn publish_to(
+        &mut self,
+        relays: &[String],
+        event: &EventType,
+    ) -> Vec<(String, Result<Response>)> {
+        let relays: HashSet<String> = relays.iter().filter_map(|url| normalize_url(url)).collect();
+        self.connect_all(&relays.iter().cloned().collect::<Vec<_>>())
+            .await;
+
+        let publishes = self
+            .connections
+            .iter_mut()
+            .filter(|(url, _)| relays.contains(*url))
+            .map(
+                |(url, conn)| async move { (url.clone(), conn.publish_event(event.clone()).await) },
+            );
+        let mut results = join_all(publishes).await;
+
+        // A connection may have ended since it was last read from, which
+        // only shows once it's used: those relays are connected to again,
+        // restoring their subscriptions, and the event sent once more.
+        // Events the relay refused, or that it would refuse by its limits,
+        // aren't sent again.
+        let failed: Vec<String> = results
+            .iter()
+            .filter(|(_, result)| is_disconnected(result))
+            .map(|(url, _)| url.clone())
+            .collect();
+        for url in &failed {
+            self.drop_connection(url);
+        }
+        self.connect_all(&failed).await;
+        for (url, result) in results.iter_mut() {
+            if !failed.contains(url) {
+                continue;
+            }
+            if let Some(conn) = self.connections.get_mut(url) {
+                *result = conn.publish_event(event.clone()).await;
+            }
+        }
+
+        for (url, result) in &results {
+            if is_disconnected(result) {
+                self.drop_connection(url);
+            }
+        }
+        for url in relays {
+            if !results.iter().any(|(published, _)| *published == url) {
+                results.push((url.clone(), Err(anyhow!("could not connect to {}", url))));
+            }
+        }
+
+        results
+    }
+
+    /// Sends `event` to its author's write relays, and to the read relays of
+    /// every user it mentions through a `p` tag. Relay lists themselves also
+    /// go to the bootstrap relays, where other clients look for them.
+    pub async fn publish(&mut self, event: &EventType) -> Vec<(String, Result<Response>)> {
+        let author = event.pubkey();
+        let mentioned: Vec<String> = event.tag_values("p").map(str::to_string).collect();
+
+        let mut users = mentioned.clone();
+        users.push(author.clone());
+        self.load_relay_lists(&users).await;
+
+        if event.kind == RELAY_LIST_KIND {
+            if let Ok(list) = RelayList::from_event(event) {
+                self.set_relay_list(&author, list);
+            }
+        }
+
+        let mut relays = self.outbox_relays(&author);
+        for pubkey in &mentioned {
+            relays.extend(self.inbox_relays(pubkey));
+        }
+        if event.kind == RELAY_LIST_KIND {
+            relays.extend(self.bootstrap_relays.iter().cloned());
+        }
+
+        self.publish_to(&relays, event).await
+    }
+
+    /// The relays `pubkey` publishes their notes to
+    pub fn outbox_relays(&self, pubkey: &str) -> Vec<String> {
+        let relays = self
+            .relay_lists
+            .get(pubkey)
+            .map(|list| list.write_relays().map(str::to_string).collect())
+            .unwrap_or_default();
+
+        self.pick_relays(relays)
+    }
+
+    /// The relays `pubkey` reads notes mentioning them from
+    pub fn inbox_relays(&self, pubkey: &str) -> Vec<String> {
+        let relays = self
+            .relay_lists
+            .get(pubkey)
+            .map(|list| list.read_relays().map(str::to_string).collect())
+            .unwrap_or_default();
+
+        self.pick_relays(relays)
+    }
+
+    /// Limits `relays` to a few of them, preferring the ones we are already
+    /// connected to. Falls back to the bootstrap relays when empty.
+    fn pick_relays(&self, mut relays: Vec<String>) -> Vec<String> {
+        if relays.is_empty() {
+            return self.bootstrap_relays.clone();
+        }
+
+        relays.sort_by_key(|url| !self.connections.contains_key(url));
+        relays.truncate(MAX_RELAYS_PER_USER);
+
+        relays
+    }
+
+    /// Closes the connection to `relay_url`, planning to reconnect to it if
+    /// it had live subscriptions
+    fn drop_connection(&mut self, relay_url: &str) {
+        self.connections.remove(relay_url);
+
+        let has_subscriptions = self
+            .subscriptions
+            .get(relay_url)
+            .is_some_and(|subscriptions| !subscriptions.is_empty());
+        if has_subscriptions && !self.reconnects.contains_key(relay_url) {
+            self.reconnects.insert(
+                relay_url.to_string(),
+                Reconnect {
+                    at: Instant::now() + MIN_RECONNECT_DELAY,
+                    delay: MIN_RECONNECT_DELAY,
+                },
+            );
+        }
+    }
+
+    /// Tries to reconnect to the relays whose time has come, backing off
+    /// further from the ones that still fail
+    async fn reconnect_due(&mut self) {
+        let now = Instant::now();
+        let due: Vec<String> = self
+            .reconnects
+            .iter()
+            .filter(|(_, reconnect)| reconnect.at <= now)
+            .map(|(url, _)| url.clone())
+            .collect();
+
+        self.connect_all(&due).await;
+
+        for url in due {
+            if let Some(reconnect) = self.reconnects.get_mut(&url) {
+                reconnect.delay = (reconnect.delay * 2).min(MAX_RECONNECT_DELAY);
+                reconnect.at = Instant::now() + reconnect.delay;
+            }
+        }
+    }
+
+    /// Stops tracking a subscription, once it's closed by us or the relay
+    fn forget_subscription(&mut self, relay_url: &str, subscription_id: &str) {
+        if let Some(subscriptions) = self.subscriptions.get_mut(relay_url) {
+            subscriptions.remove(subscription_id);
+            if subscriptions.is_empty() {
+                self.subscriptions.remove(relay_url);
+                self.reconnects.remove(relay_url);
+            }
+        }
+    }
+
+    /// Opens a connection to each of `relays` that we aren't connected to.
+    /// Subscriptions that were live on an earlier connection to the same
+    /// relay are sent again, under the same id.
+    async fn connect_all(&mut self, relays: &[String]) {
+        let missing: HashSet<&String> = relays
+            .iter()
+            .filter(|url| !self.connections.contains_key(*url))
+            .collect();
+
+        let connects = missing.into_iter().map(|url| async move {
+            (
+                url.clone(),
+                timeout(CONNECT_TIMEOUT, Connection::new(url)).await,
+            )
+        });
+
+        for (url, result) in join_all(connects).await {
+            let Ok(Ok(mut conn)) = result else {
+                continue;
+            };
+
+            self.reconnects.remove(&url);
+            if let Some(subscriptions) = self.subscriptions.get_mut(&url) {
+                let mut restored = HashMap::new();
+                for (subscription_id, filters) in subscriptions.drain() {
+                    match conn.resubscribe(&subscription_id, filters.clone()).await {
+                        Ok(()) => {
+                            restored.insert(subscription_id, filters);
+                        }
+                        Err(err) => log::warn!("could not restore subscription: {}", err),
+                    }
+                }
+                *subscriptions = restored;
+            }
+            self.connections.insert(url, conn);
+        }
+    }
+
+    /// Groups `authors` by their write relays, making one filter per relay
+    /// that only asks for the authors publishing there
+    async fn requests_by_author(
+        &mut self,
+        filter: Filter,
+        authors: &[String],
+    ) -> HashMap<String, Vec<Filter>> {
+        self.load_relay_lists(authors).await;
+
+        let mut authors_by_relay: HashMap<String, Vec<String>> = HashMap::new();
+        for author in authors {
+            for relay in self.outbox_relays(author) {
+                authors_by_relay
+                    .entry(relay)
+                    .or_default()
+                    .push(author.clone());
+            }
+        }
+
+        authors_by_relay
+            .into_iter()
+            .map(|(relay, authors)| (relay, vec![filter.clone().authors(authors)]))
+            .collect()
+    }
+
+    /// Opens one subscription per relay, each with its own filters
+    async fn subscribe_each(
+        &mut self,
+        requests: HashMap<String, Vec<Filter>>,
+    ) -> Vec<(String, String)> {
+        self.connect_all(&requests.keys().cloned().collect::<Vec<_>>())
+            .await;
+
+        let mut subscriptions = Vec::new();
+        for (url, filters) in requests {
+            let Some(conn) = self.connections.get_mut(&url) else {
+                continue;
+            };
+            if let Ok(subscription_id) = conn.subscribe(filters.clone()).await {
+                self.subscriptions
+                    .entry(url.clone())
+                    .or_default()
+                    .insert(subscription_id.clone(), filters);
+                subscriptions.push((url, subscription_id));
+            }
+        }
+
+        subscriptions
+    }
+
+    /// Runs one fetch per relay, each with its own filters, and merges the
+    /// results. Connections that fail are dropped, to be reopened later.
+    async fn fetch_each(&mut self, requests: HashMap<String, Vec<Filter>>) -> Vec<EventType> {
+        self.connect_all(&requests.keys().cloned().collect::<Vec<_>>())
+            .await;
+
+        let fetches =
+            self.connections
+                .iter_mut()
+                .filter_map(|(url, conn)| requests.get(url).map(|filters| (url, conn, filters)))
+                .map(|(url, conn, filters)| async move {
+                    (url.clone(), conn.fetch(filters.clone()).await)
+                });
+
+        let mut seen = HashSet::new();
+        let mut events = Vec::new();
+        for (url, result) in join_all(fetches).await {
+            let Ok(fetched) = result else {
+                self.drop_connection(&url);
+                continue;
+            };
+
+            for event in fetched {
+                if seen.insert(event.id().map(str::to_string)) {
+                    events.push(event);
+                }
+            }
+        }
+
+        events
+    }
+}
+
+/// When to next try reconnecting to a relay, and how long we waited before
+/// that attempt
+struct Reconnect {
+    at: Instant,
+    delay: Duration,
+}
+
+/// Whether at least one relay accepted the event sent by a `publish` call
+pub fn any_accepted(results: &[(String, Result<Response>)]) -> bool {
+    results
+        .iter()
+        .any(|(_, result)| matches!(result, Ok(Response::Ok { accepted: true, .. })))
+}
+
+/// Whether a publish failed because the connection was lost, rather than
+/// because of the event
+fn is_disconnected(result: &Result<Response>) -> bool {
+    result
+        .as_ref()
+        .is_err_and(|err| err.downcast_ref::<Disconnected>().is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed, TestRelay};
+
+    #[tokio::test]
+    async fn restores_subscriptions_after_reconnecting() {
+        let mut relay = TestRelay::start().await;
+        let mut pool = RelayPool::new(vec![relay.url.clone()]);
+        let filter = Filter::new().kinds(vec![1]);
+        let subscriptions = pool.subscribe_to(&[relay.url.clone()], vec![filter]).await;
+        assert_eq!(subscriptions.len(), 1);
+        let (_, subscription_id) = &subscriptions[0];
+
+        let (_, response) = pool.next_response().await;
+        assert!(matches!(response, Response::Eose { .. }));
+
+        relay.stop();
+        relay.restart().await;
+        let (secret_key, _) = keys();
+        let note = signed(&secret_key, 1, "hello again", vec![]);
+        relay.publish(note.clone());
+
+        let (_, response) = timeout(Duration::from_secs(10), pool.next_response())
+            .await
+            .unwrap();
+        let Response::Event {
+            subscription_id: id,
+            event,
+        } = response
+        else {
+            panic!("expected an event, got {:?}", response);
+        };
+        assert_eq!(id, *subscription_id);
+        assert_eq!(event.id(), note.id());
+    }
+
+    #[tokio::test]
+    async fn does_not_restore_closed_subscriptions() {
+        let mut relay = TestRelay::start().await;
+        let mut pool = RelayPool::new(vec![relay.url.clone()]);
+        let filter = Filter::new().kinds(vec![1]);
+        let subscriptions = pool.subscribe_to(&[relay.url.clone()], vec![filter]).await;
+        pool.close_subscriptions(&subscriptions).await;
+
+        relay.stop();
+        let _ = timeout(Duration::from_secs(1), pool.next_response()).await;
+
+        assert!(pool.reconnects.is_empty());
+    }
+
+    #[tokio::test]
+    async fn publishes_again_on_a_new_connection() {
+        let mut relay = TestRelay::start().await;
+        let mut pool = RelayPool::new(vec![relay.url.clone()]);
+        pool.connect_all(&[relay.url.clone()]).await;
+        let (secret_key, _) = keys();
+        let note = signed(&secret_key, 1, "hello", vec![]);
+
+        relay.stop();
+        relay.restart().await;
+        let results = pool.publish_to(&[relay.url.clone()], &note).await;
+
+        assert!(any_accepted(&results));
+        assert_eq!(relay.events().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn returns_limit_errors_without_sending_again() {
+        let relay = TestRelay::start_with_info(Some(serde_json::json!({
+            "limitation": { "max_content_length": 5 }
+        })))
+        .await;
+        let mut pool = RelayPool::new(vec![relay.url.clone()]);
+        let (secret_key, _) = keys();
+        let note = signed(&secret_key, 1, "too long for this relay", vec![]);
+
+        let results = pool
+            .publish_to(std::slice::from_ref(&relay.url), &note)
+            .await;
+
+        assert_eq!(results.len(), 1);
+        let err = results[0].1.as_ref().unwrap_err();
+        assert!(err.to_string().contains("longer than 5"), "{}", err);
+        assert_eq!(pool.connections.len(), 1);
+        assert!(relay.events().is_empty());
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+use super::event::{unix_time, Event as EventType};
+use super::pool::{any_accepted, RelayPool};
+use super::request::Filter;
+use super::signer::Signer;
+
+/// Replaceable event kind holding a user's profile, described by NIP-01
+pub const METADATA_KIND: u64 = 0;
+
+/// A user's profile, parsed from the JSON content of a kind 0 event. Besides
+/// the basic fields of NIP-01, it includes the extra ones of NIP-24.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub name: Option<String>,
+    pub display_name: Option<String>,
+    pub about: Option<String>,
+    pub picture: Option<String>,
+    pub banner: Option<String>,
+    pub website: Option<String>,
+    pub nip05: Option<String>,
+    pub lud06: Option<String>,
+    pub lud16: Option<String>,
+    pub bot: Option<bool>,
+
+    /// Fields we don't know about, kept so that editing a profile doesn't
+    /// drop what other clients wrote into it
+    pub extra: Map<String, Value>,
+}
+
+impl Profile {
+    pub fn from_event(event: &EventType) -> Result<Profile> {
+        if event.kind != METADATA_KIND {
+            return Err(anyhow!("expected a kind {} event", METADATA_KIND));
+        }
+
+        let mut fields: Map<String, Value> = serde_json::from_str(&event.content)?;
+        let mut take = |key: &str| match fields.remove(key) {
+            Some(Value::String(value)) if !value.is_empty() => Some(value),
+            _ => None,
+        };
+
+        let mut profile = Profile {
+            name: take("name"),
+            display_name: take("display_name"),
+            about: take("about"),
+            picture: take("picture"),
+            banner: take("banner"),
+            website: take("website"),
+            nip05: take("nip05"),
+            lud06: take("lud06"),
+            lud16: take("lud16"),
+            ..Default::default()
+        };
+
+        // NIP-24 deprecates these spellings, but plenty of profiles still
+        // only carry them
+        let display_name = take("displayName");
+        let username = take("username");
+        profile.display_name = profile.display_name.or(display_name);
+        profile.name = profile.name.or(username);
+
+        profile.bot = match fields.remove("bot") {
+            Some(Value::Bool(bot)) => Some(bot),
+            Some(Value::String(bot)) => Some(bot == "true"),
+            _ => None,
+        };
+        profile.extra = fields;
+
+        Ok(profile)
+    }
+
+    /// Builds the unsigned kind 0 event publishing this profile
+    pub fn to_event(&self, pubkey: &str) -> EventType {
+        let mut fields = self.extra.clone();
+        let fields_to_set = [
+            ("name", &self.name),
+            ("display_name", &self.display_name),
+            ("about", &self.about),
+            ("picture", &self.picture),
+            ("banner", &self.banner),
+            ("website", &self.website),
+            ("nip05", &self.nip05),
+            ("lud06", &self.lud06),
+            ("lud16", &self.lud16),
+        ];
+        for (key, value) in fields_to_set {
+            if let Some(value) = value {
+                fields.insert(key.to_string(), Value::String(value.clone()));
+            }
+        }
+        if let Some(bot) = self.bot {
+            fields.insert("bot".to_string(), Value::Bool(bot));
+        }
+
+        EventType::new(pubkey, METADATA_KIND, Value::Object(fields).to_string())
+    }
+
+    /// Sets the field called `name` to `value`, or removes it when `value`
+    /// is empty
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let value = Some(value.to_string()).filter(|value| !value.is_empty());
+        let field = match name {
+            "name" => &mut self.name,
+            "display_name" => &mut self.display_name,
+            "about" => &mut self.about,
+            "picture" => &mut self.picture,
+            "banner" => &mut self.banner,
+            "website" => &mut self.website,
+            "nip05" => &mut self.nip05,
+            "lud06" => &mut self.lud06,
+            "lud16" => &mut self.lud16,
+            "bot" => {
+                self.bot = match value.as_deref() {
+                    None => None,
+                    Some("true") => Some(true),
+                    Some("false") => Some(false),
+                    Some(_) => return Err(anyhow!("bot is either true or false")),
+                };
+                return Ok(());
+            }
+            _ => return Err(anyhow!("profiles have no {} field", name)),
+        };
+        *field = value;
+
+        Ok(())
+    }
+
+    /// The name to show for this user, if they set one
+    pub fn best_name(&self) -> Option<&str> {
+        self.display_name.as_deref().or(self.name.as_deref())
+    }
+
+    /// The name this user can be mentioned by with `@`, which can't hold
+    /// whitespace
+    pub fn handle(&self) -> Option<String> {
+        let name = self.name.as_deref().or(self.display_name.as_deref())?;
+        let handle: String = name.split_whitespace().collect();
+
+        (!handle.is_empty()).then_some(handle)
+    }
+
+    /// Fetches the profile events of `pubkeys` from their write relays, to
+    /// be stored in a `ProfileCache`
+    pub async fn fetch_events(pool: &mut RelayPool, pubkeys: &[String]) -> Vec<EventType> {
+        let filter = Filter::new().kinds(vec![METADATA_KIND]);
+
+        pool.fetch_from_authors(filter, pubkeys).await
+    }
+}
+
+/// The newest known profile of each user
+#[derive(Debug, Default)]
+pub struct ProfileCache {
+    profiles: HashMap<String, (u64, Profile)>,
+}
+
+impl ProfileCache {
+    pub fn new() -> ProfileCache {
+        Self::default()
+    }
+
+    /// Stores the profile carried by `event`, unless a newer one is already
+    /// known. Returns whether the cache changed.
+    pub fn insert(&mut self, event: &EventType) -> bool {
+        let Ok(profile) = Profile::from_event(event) else {
+            return false;
+        };
+
+        let pubkey = event.pubkey();
+        if let Some((created_at, _)) = self.profiles.get(&pubkey) {
+            if *created_at >= event.created_at {
+                return false;
+            }
+        }
+
+        self.profiles.insert(pubkey, (event.created_at, profile));
+
+        true
+    }
+
+    pub fn get(&self, pubkey: &str) -> Option<&Profile> {
+        self.profiles.get(pubkey).map(|(_, profile)| profile)
+    }
+
+    /// Every known profile, by pubkey
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Profile)> {
+        self.profiles
+            .iter()
+            .map(|(pubkey, (_, profile))| (pubkey, profile))
+    }
+
+    /// The name to show for `pubkey`: the one they picked if their profile
+    /// is known, or a shortened version of the key otherwise
+    pub fn display_name(&self, pubkey: &str) -> String {
+        match self.get(pubkey).and_then(Profile::best_name) {
+            Some(name) => name.to_string(),
+            None => short_pubkey(pubkey),
+        }
+    }
+}
+
+/// Shortens a hex pubkey to something that fits in a line of the feed
+pub fn short_pubkey(pubkey: &str) -> String {
+    if pubkey.len() <= 12 {
+        return pubkey.to_string();
+    }
+
+    format!("{}…{}", &pubkey[..8], &pubkey[pubkey.len() - 4..])
+}
+
+/// Applies `change` to the user's profile and publishes the result, which
+/// is returned. As with the contact list, the newest version is fetched
+/// first so that edits made from another device aren't lost, and nothing is
+/// published if none of the user's relays answered: a profile that couldn't
+/// be fetched would be replaced by one with only the change.
+pub async fn update(
+    pool: &mut RelayPool,
+    signer: &mut Signer,
+    change: impl FnOnce(&mut Profile) -> Result<()>,
+) -> Result<EventType> {
+    let pubkey = signer.pubkey().to_string();
+
+    let mut cache = ProfileCache::new();
+    for event in Profile::fetch_events(pool, std::slice::from_ref(&pubkey)).await {
+        cache.insert(&event);
+    }
+    let (created_at, mut profile) = match cache.profiles.remove(&pubkey) {
+        Some(latest) => latest,
+        None if pool
+            .outbox_relays(&pubkey)
+            .iter()
+            .any(|url| pool.is_connected(url)) =>
+        {
+            Default::default()
+        }
+        None => return Err(anyhow!("could not fetch the profile")),
+    };
+    change(&mut profile)?;
+
+    let mut event = profile.to_event(&pubkey);
+    event.created_at = unix_time().max(created_at + 1);
+    signer.sign(&mut event).await?;
+
+    if !any_accepted(&pool.publish(&event).await) {
+        return Err(anyhow!("no relay accepted the profile"));
+    }
+
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed, TestRelay};
+
+    #[test]
+    fn keeps_unknown_fields() {
+        let (secret_key, pubkey) = keys();
+        let content = r#"{"name":"alice","displayName":"Alice","bot":"true","pronouns":"she/her","status":{"emoji":"🌱"}}"#;
+        let event = signed(&secret_key, METADATA_KIND, content, vec![]);
+
+        let profile = Profile::from_event(&event).unwrap();
+        assert_eq!(profile.name.as_deref(), Some("alice"));
+        assert_eq!(profile.display_name.as_deref(), Some("Alice"));
+        assert_eq!(profile.bot, Some(true));
+
+        let published = profile.to_event(&pubkey);
+        let fields: Value = serde_json::from_str(&published.content).unwrap();
+        assert_eq!(fields["pronouns"], "she/her");
+        assert_eq!(fields["status"]["emoji"], "🌱");
+        // Deprecated spellings are written back the NIP-24 way
+        assert_eq!(fields["display_name"], "Alice");
+        assert!(fields.get("displayName").is_none());
+        assert_eq!(Profile::from_event(&published).unwrap(), profile);
+    }
+
+    #[test]
+    fn sets_fields() {
+        let mut profile = Profile::default();
+        profile.set("about", "Hello there").unwrap();
+        profile.set("bot", "false").unwrap();
+        assert_eq!(profile.about.as_deref(), Some("Hello there"));
+        assert_eq!(profile.bot, Some(false));
+
+        profile.set("about", "").unwrap();
+        assert_eq!(profile.about, None);
+        assert!(profile.set("bot", "maybe").is_err());
+        assert!(profile.set("age", "30").is_err());
+    }
+
+    #[tokio::test]
+    async fn updates_the_newest_profile() {
+        let relay = TestRelay::start().await;
+        let (secret_key, pubkey) = keys();
+        let mut signer = Signer::local(&secret_key).unwrap();
+        let mut older = signed(&secret_key, METADATA_KIND, r#"{"name":"old"}"#, vec![]);
+        older.created_at -= 10;
+        older
+            .setup(&secret_key, &secp256k1::Secp256k1::new())
+            .unwrap();
+        relay.publish(older);
+        relay.publish(signed(
+            &secret_key,
+            METADATA_KIND,
+            r#"{"name":"alice","pronouns":"she/her"}"#,
+            vec![],
+        ));
+
+        let mut pool = RelayPool::new(vec![relay.url.clone()]);
+        let event = update(&mut pool, &mut signer, |profile| {
+            profile.set("about", "Hello")
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(event.pubkey(), pubkey);
+        let profile = Profile::from_event(&event).unwrap();
+        assert_eq!(profile.name.as_deref(), Some("alice"));
+        assert_eq!(profile.about.as_deref(), Some("Hello"));
+        assert_eq!(profile.extra["pronouns"], "she/her");
+        assert!(relay
+            .events()
+            .iter()
+            .any(|stored| stored.id() == event.id()));
+    }
+
+    #[tokio::test]
+    async fn refuses_to_replace_a_profile_that_was_not_fetched() {
+        let (secret_key, _) = keys();
+        let mut signer = Signer::local(&secret_key).unwrap();
+        let mut pool = RelayPool::new(vec!["ws://127.0.0.1:1".to_string()]);
+
+        let updated = update(&mut pool, &mut signer, |profile| profile.set("name", "bob")).await;
+
+        assert!(updated.is_err());
+    }
+}
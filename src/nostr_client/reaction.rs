@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use super::event::Event as EventType;
+
+pub const REACTION_KIND: u64 = 7;
+
+/// What a reaction expresses, as described by NIP-25
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ReactionContent {
+    /// A `+`, or an empty content
+    Like,
+    /// A `-`
+    Dislike,
+    /// A plain emoji, or any other text
+    Emoji(String),
+    /// A NIP-30 custom emoji, shown as `:shortcode:` and drawn from `url`
+    Custom { shortcode: String, url: String },
+}
+
+impl ReactionContent {
+    /// Reads the content of a reaction, looking custom emoji up in the
+    /// `emoji` tags of `event`
+    pub fn from_event(event: &EventType) -> ReactionContent {
+        match event.content.as_str() {
+            "" | "+" => ReactionContent::Like,
+            "-" => ReactionContent::Dislike,
+            content => {
+                let custom = content
+                    .strip_prefix(':')
+                    .and_then(|content| content.strip_suffix(':'))
+                    .and_then(|shortcode| {
+                        event
+                            .tags_named("emoji")
+                            .find(|tag| tag.get(1).map(String::as_str) == Some(shortcode))
+                            .and_then(|tag| tag.get(2))
+                            .map(|url| (shortcode.to_string(), url.clone()))
+                    });
+
+                match custom {
+                    Some((shortcode, url)) => ReactionContent::Custom { shortcode, url },
+                    None => ReactionContent::Emoji(content.to_string()),
+                }
+            }
+        }
+    }
+
+    /// The content of a reaction event
+    pub fn content(&self) -> String {
+        match self {
+            ReactionContent::Like => "+".to_string(),
+            ReactionContent::Dislike => "-".to_string(),
+            ReactionContent::Emoji(emoji) => emoji.clone(),
+            ReactionContent::Custom { shortcode, .. } => format!(":{}:", shortcode),
+        }
+    }
+
+    /// How the reaction is shown to the user
+    pub fn label(&self) -> String {
+        match self {
+            ReactionContent::Like => "♥".to_string(),
+            ReactionContent::Dislike => "👎".to_string(),
+            other => other.content(),
+        }
+    }
+}
+
+/// A kind 7 event, reacting to another event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reaction {
+    /// The event reacted to
+    pub target_id: String,
+    /// The author of the event reacted to
+    pub target_author: Option<String>,
+    /// The kind of the event reacted to
+    pub target_kind: Option<u64>,
+    pub content: ReactionContent,
+}
+
+impl Reaction {
+    pub fn from_event(event: &EventType) -> Result<Reaction> {
+        if event.kind != REACTION_KIND {
+            return Err(anyhow!("Event of kind {} is not a reaction", event.kind));
+        }
+
+        // The last `e` and `p` tags point to the event reacted to
+        let target_id = event
+            .tag_values("e")
+            .last()
+            .ok_or(anyhow!("Reaction without an `e` tag"))?
+            .to_string();
+
+        Ok(Reaction {
+            target_id,
+            target_author: event.tag_values("p").last().map(str::to_string),
+            target_kind: event
+                .tag_values("k")
+                .next()
+                .and_then(|kind| kind.parse().ok()),
+            content: ReactionContent::from_event(event),
+        })
+    }
+}
+
+/// Builds the unsigned reaction of `pubkey` to `target`
+pub fn react(target: &EventType, pubkey: &str, content: ReactionContent) -> EventType {
+    let mut event = EventType::new(pubkey, REACTION_KIND, content.content());
+
+    event.add_tag("e", target.id().unwrap_or_default(), None);
+    event.add_tag("p", &target.pubkey(), None);
+    event.add_tag("k", &target.kind.to_string(), None);
+    if let ReactionContent::Custom { shortcode, url } = &content {
+        event
+            .tags
+            .push(vec!["emoji".to_string(), shortcode.clone(), url.clone()]);
+    }
+
+    event
+}
+
+/// A reaction seen by `ReactionCounts`, by its author
+#[derive(Debug)]
+struct SeenReaction {
+    author: String,
+    created_at: u64,
+    reaction: Reaction,
+}
+
+/// Reactions received so far, counted per event reacted to. Only the
+/// latest reaction of each user to an event counts, since reacting again
+/// replaces what they said before.
+#[derive(Debug, Default)]
+pub struct ReactionCounts {
+    /// How many of each reaction every event got
+    counts: HashMap<String, HashMap<ReactionContent, usize>>,
+    /// Reaction events already seen, since relays send them again
+    seen: HashMap<String, SeenReaction>,
+    /// The id of the reaction counted for each user and event they reacted to
+    latest: HashMap<(String, String), String>,
+}
+
+impl ReactionCounts {
+    pub fn new() -> ReactionCounts {
+        ReactionCounts::default()
+    }
+
+    /// Takes in `event` if it's a reaction that wasn't seen yet, returning
+    /// whether it's now counted: it isn't when its author reacted to the
+    /// same event later.
+    pub fn insert(&mut self, event: &EventType) -> bool {
+        let Some(id) = event.id() else {
+            return false;
+        };
+        let Ok(reaction) = Reaction::from_event(event) else {
+            return false;
+        };
+        if self.seen.contains_key(id) {
+            return false;
+        }
+
+        let key = (event.pubkey(), reaction.target_id.clone());
+        self.seen.insert(
+            id.to_string(),
+            SeenReaction {
+                author: event.pubkey(),
+                created_at: event.created_at,
+                reaction,
+            },
+        );
+
+        let newest = match self.latest.get(&key) {
+            Some(counted) => self.newer(id, counted) == id,
+            None => true,
+        };
+        if newest {
+            self.count(key, Some(id.to_string()));
+        }
+        newest
+    }
+
+    /// Stops counting the reaction with id `id` as long as `author` wrote it,
+    /// returning whether it was counted. The previous reaction of the author
+    /// to the same event counts again, if any.
+    pub fn remove(&mut self, id: &str, author: &str) -> bool {
+        let Some(removed) = self.seen.get(id).filter(|seen| seen.author == author) else {
+            return false;
+        };
+        let key = (removed.author.clone(), removed.reaction.target_id.clone());
+
+        let counted = self.latest.get(&key).map(String::as_str) == Some(id);
+        if counted {
+            let previous = self
+                .seen
+                .iter()
+                .filter(|(other, seen)| {
+                    *other != id && seen.author == key.0 && seen.reaction.target_id == key.1
+                })
+                .map(|(other, _)| other.as_str())
+                .reduce(|a, b| self.newer(a, b))
+                .map(str::to_string);
+            self.count(key, previous);
+        }
+
+        self.seen.remove(id);
+        counted
+    }
+
+    /// Of the two seen reactions `a` and `b`, the one made last
+    fn newer<'a>(&self, a: &'a str, b: &'a str) -> &'a str {
+        let order = |id: &'a str| (self.seen.get(id).map(|seen| seen.created_at), id);
+
+        order(a).max(order(b)).1
+    }
+
+    /// Counts the seen reaction `id` for the user and event of `key`,
+    /// instead of the one counted until now
+    fn count(&mut self, key: (String, String), id: Option<String>) {
+        let content = |id: &str| self.seen.get(id).map(|seen| seen.reaction.content.clone());
+        let added = id.as_deref().and_then(content);
+        let removed = self.latest.get(&key).and_then(|id| content(id));
+
+        let counts = self.counts.entry(key.1.clone()).or_default();
+        if let Some(content) = removed {
+            if let Some(count) = counts.get_mut(&content) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(&content);
+                }
+            }
+        }
+        if let Some(content) = added {
+            *counts.entry(content).or_default() += 1;
+        }
+
+        match id {
+            Some(id) => self.latest.insert(key, id),
+            None => self.latest.remove(&key),
+        };
+    }
+
+    /// The reactions to `event_id` with their counts, most frequent first
+    pub fn summary(&self, event_id: &str) -> Vec<(&ReactionContent, usize)> {
+        let Some(counts) = self.counts.get(event_id) else {
+            return Vec::new();
+        };
+
+        let mut summary: Vec<(&ReactionContent, usize)> = counts
+            .iter()
+            .map(|(content, count)| (content, *count))
+            .collect();
+        summary.sort_by(|(a, a_count), (b, b_count)| {
+            b_count
+                .cmp(a_count)
+                .then_with(|| a.content().cmp(&b.content()))
+        });
+        summary
+    }
+
+    /// How `pubkey` reacted to `event_id`, if they did
+    pub fn reaction_of(&self, pubkey: &str, event_id: &str) -> Option<&ReactionContent> {
+        let id = self
+            .latest
+            .get(&(pubkey.to_string(), event_id.to_string()))?;
+
+        self.seen.get(id).map(|seen| &seen.reaction.content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::deletion::{self, Deletion, Deletions};
+    use crate::nostr_client::testing::{keys, signed};
+
+    fn reaction(secret_key: &str, target: &EventType, content: &str, created_at: u64) -> EventType {
+        let mut event = signed(secret_key, REACTION_KIND, content, vec![]);
+        event.add_tag("e", target.id().unwrap(), None);
+        event.created_at = created_at;
+        event
+            .setup(secret_key, &secp256k1::Secp256k1::new())
+            .unwrap();
+        event
+    }
+
+    #[test]
+    fn reads_reaction_content() {
+        let (secret_key, _) = keys();
+        let custom = signed(
+            &secret_key,
+            REACTION_KIND,
+            ":soapbox:",
+            vec![vec!["emoji", "soapbox", "https://example.com/soapbox.png"]],
+        );
+
+        assert_eq!(
+            ReactionContent::from_event(&signed(&secret_key, REACTION_KIND, "", vec![])),
+            ReactionContent::Like
+        );
+        assert_eq!(
+            ReactionContent::from_event(&signed(&secret_key, REACTION_KIND, "-", vec![])),
+            ReactionContent::Dislike
+        );
+        assert_eq!(
+            ReactionContent::from_event(&custom),
+            ReactionContent::Custom {
+                shortcode: "soapbox".to_string(),
+                url: "https://example.com/soapbox.png".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn counts_the_latest_reaction_of_each_user() {
+        let (author, _) = keys();
+        let (alice, alice_pubkey) = keys();
+        let (bob, _) = keys();
+        let note = signed(&author, 1, "hello", vec![]);
+        let id = note.id().unwrap();
+        let mut counts = ReactionCounts::new();
+
+        assert!(counts.insert(&reaction(&alice, &note, "+", 100)));
+        assert!(counts.insert(&reaction(&alice, &note, "🤙", 200)));
+        // Relays may send an older reaction after a newer one
+        assert!(!counts.insert(&reaction(&alice, &note, "-", 150)));
+        assert!(counts.insert(&reaction(&bob, &note, "🤙", 100)));
+
+        assert_eq!(
+            counts.summary(id),
+            vec![(&ReactionContent::Emoji("🤙".to_string()), 2)]
+        );
+        assert_eq!(
+            counts.reaction_of(&alice_pubkey, id),
+            Some(&ReactionContent::Emoji("🤙".to_string()))
+        );
+    }
+
+    #[test]
+    fn counts_the_previous_reaction_once_the_latest_is_deleted() {
+        let (author, _) = keys();
+        let (alice, alice_pubkey) = keys();
+        let note = signed(&author, 1, "hello", vec![]);
+        let id = note.id().unwrap();
+        let like = reaction(&alice, &note, "+", 100);
+        let emoji = reaction(&alice, &note, "🤙", 200);
+        let mut counts = ReactionCounts::new();
+        counts.insert(&like);
+        counts.insert(&emoji);
+
+        // Only the author of a reaction can delete it
+        assert!(!counts.remove(emoji.id().unwrap(), &note.pubkey()));
+        assert!(counts.remove(emoji.id().unwrap(), &alice_pubkey));
+        assert_eq!(counts.summary(id), vec![(&ReactionContent::Like, 1)]);
+
+        assert!(counts.remove(like.id().unwrap(), &alice_pubkey));
+        assert!(counts.summary(id).is_empty());
+        assert_eq!(counts.reaction_of(&alice_pubkey, id), None);
+    }
+
+    #[test]
+    fn stops_counting_deleted_reactions() {
+        let (author, _) = keys();
+        let (alice, alice_pubkey) = keys();
+        let note = signed(&author, 1, "hello", vec![]);
+        let id = note.id().unwrap();
+        let like = reaction(&alice, &note, "+", 100);
+        let mut counts = ReactionCounts::new();
+        counts.insert(&like);
+        assert_eq!(counts.summary(id), vec![(&ReactionContent::Like, 1)]);
+
+        let mut request = deletion::delete(&[&like], &alice_pubkey, "");
+        request.setup(&alice, &secp256k1::Secp256k1::new()).unwrap();
+        let deletion = Deletion::from_event(&request).unwrap();
+        for deleted in &deletion.event_ids {
+            counts.remove(deleted, &deletion.author);
+        }
+        assert!(counts.summary(id).is_empty());
+
+        // Relays sending the reaction again don't bring it back
+        let mut deletions = Deletions::new();
+        deletions.insert(&request);
+        assert!(deletions.is_deleted(&like));
+    }
+}
@@ -0,0 +1,180 @@
+use super::entity::Entity;
+use super::event::Event as EventType;
+
+/// Scheme references to other users and events start with inside content
+pub const URI_PREFIX: &str = "nostr:";
+
+/// A reference to a user or event inside the content of an event, as
+/// described by NIP-27
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    /// Byte range of the whole `nostr:…` reference in the content
+    pub start: usize,
+    pub end: usize,
+    pub entity: Entity,
+}
+
+/// Finds the references inside `content`, skipping the ones that don't
+/// decode
+pub fn references(content: &str) -> Vec<Reference> {
+    let mut references = Vec::new();
+    let mut offset = 0;
+
+    while let Some(found) = content[offset..].find(URI_PREFIX) {
+        let start = offset + found;
+        let entity_start = start + URI_PREFIX.len();
+        let length = content[entity_start..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(content.len() - entity_start);
+        let end = entity_start + length;
+
+        if let Ok(entity) = content[entity_start..end].parse::<Entity>() {
+            references.push(Reference { start, end, entity });
+        }
+        offset = end.max(start + 1);
+    }
+
+    references
+}
+
+/// Adds the tags clients expect next to the references in the content of
+/// `event`: a `p` tag per mentioned user, and a `q` tag per quoted event
+pub fn tag_references(event: &mut EventType) {
+    for reference in references(&event.content) {
+        let tag = match &reference.entity {
+            Entity::Pubkey(pubkey) | Entity::Profile { pubkey, .. } => {
+                vec!["p".to_string(), pubkey.clone()]
+            }
+            Entity::Note(id) => vec!["q".to_string(), id.clone()],
+            Entity::Event {
+                id, relays, author, ..
+            } => vec![
+                "q".to_string(),
+                id.clone(),
+                relays.first().cloned().unwrap_or_default(),
+                author.clone().unwrap_or_default(),
+            ],
+            Entity::Address { relays, .. } => vec![
+                "q".to_string(),
+                reference.entity.address().unwrap_or_default(),
+                relays.first().cloned().unwrap_or_default(),
+            ],
+        };
+
+        let tagged = event
+            .tags_named(&tag[0])
+            .any(|known| known.get(1) == tag.get(1));
+        if !tagged {
+            event.tags.push(tag);
+        }
+    }
+}
+
+/// Turns the `@name` mentions in `text` into `nostr:npub…` references,
+/// using `resolve` to find the pubkey behind each name. Names that don't
+/// resolve are left as they are.
+pub fn link_mentions(text: &str, resolve: impl Fn(&str) -> Option<String>) -> String {
+    let mut linked = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(at) = rest.find('@') {
+        let name_length = rest[at + 1..]
+            .find(|c: char| c.is_whitespace() || ",.!?;:()".contains(c))
+            .unwrap_or(rest.len() - at - 1);
+        let name = &rest[at + 1..at + 1 + name_length];
+
+        linked.push_str(&rest[..at]);
+        let starts_word = linked.chars().next_back().is_none_or(char::is_whitespace);
+        match resolve(name).filter(|_| starts_word && !name.is_empty()) {
+            Some(pubkey) => {
+                linked.push_str(URI_PREFIX);
+                linked.push_str(&Entity::Pubkey(pubkey).to_string());
+            }
+            None => linked.push_str(&rest[at..at + 1 + name_length]),
+        }
+        rest = &rest[at + 1 + name_length..];
+    }
+    linked.push_str(rest);
+
+    linked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed};
+
+    const PUBKEY: &str = "3bf0c63fcb93463407af97a5e5ee64fa883d107ef9e558472c4eb9aaaefa459d";
+    const ID: &str = "7e7e9c42a91bfef19fa929e5fda1b72e0ebc1a4c1141673e2794234d86addf4e";
+
+    #[test]
+    fn finds_references() {
+        let npub = Entity::Pubkey(PUBKEY.to_string());
+        let note = Entity::Note(ID.to_string());
+        let content = format!(
+            "gm nostr:{}, see nostr:{}. nostr:nothing nostr:",
+            npub, note
+        );
+
+        let found = references(&content);
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].entity, npub);
+        assert_eq!(
+            &content[found[0].start..found[0].end],
+            format!("nostr:{}", npub)
+        );
+        assert_eq!(found[1].entity, note);
+        assert_eq!(
+            &content[found[1].start..found[1].end],
+            format!("nostr:{}", note)
+        );
+        assert!(references("no references here").is_empty());
+    }
+
+    #[test]
+    fn tags_references_once() {
+        let (secret_key, _) = keys();
+        let event_reference = Entity::Event {
+            id: ID.to_string(),
+            relays: vec!["wss://relay.example.com".to_string()],
+            author: Some(PUBKEY.to_string()),
+            kind: None,
+        };
+        let address = Entity::Address {
+            identifier: "my-article".to_string(),
+            pubkey: PUBKEY.to_string(),
+            kind: 30023,
+            relays: vec![],
+        };
+        let npub = Entity::Pubkey(PUBKEY.to_string());
+        let content = format!(
+            "nostr:{} nostr:{} nostr:{} nostr:{}",
+            npub, npub, event_reference, address
+        );
+        let mut event = signed(&secret_key, 1, &content, vec![]);
+
+        tag_references(&mut event);
+
+        assert_eq!(
+            event.tags,
+            vec![
+                vec!["p", PUBKEY],
+                vec!["q", ID, "wss://relay.example.com", PUBKEY],
+                vec!["q", &format!("30023:{}:my-article", PUBKEY), ""],
+            ]
+        );
+    }
+
+    #[test]
+    fn links_mentions() {
+        let resolve = |name: &str| (name == "alice").then(|| PUBKEY.to_string());
+        let npub = Entity::Pubkey(PUBKEY.to_string());
+
+        assert_eq!(
+            link_mentions("hi @alice, meet @bob and mail me@alice", resolve),
+            format!("hi nostr:{}, meet @bob and mail me@alice", npub)
+        );
+        assert_eq!(link_mentions("@ alone", resolve), "@ alone");
+    }
+}
@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use reqwest::header::ACCEPT;
+use serde::{Deserialize, Serialize};
+
+/// How long a relay may take to serve its information document
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The relay information document described by NIP-11. Every field is
+/// optional on the wire, so missing values fall back to their defaults.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RelayInformation {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub banner: Option<String>,
+    pub icon: Option<String>,
+    pub pubkey: Option<String>,
+    pub contact: Option<String>,
+    pub supported_nips: Vec<u64>,
+    pub software: Option<String>,
+    pub version: Option<String>,
+
+    pub limitation: Option<Limitation>,
+
+    pub relay_countries: Vec<String>,
+    pub language_tags: Vec<String>,
+    pub tags: Vec<String>,
+    pub posting_policy: Option<String>,
+    pub payments_url: Option<String>,
+}
+
+/// The `limitation` block of the relay information document, which tells
+/// clients what the relay is willing to accept.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Limitation {
+    pub max_message_length: Option<usize>,
+    pub max_subscriptions: Option<usize>,
+    pub max_filters: Option<usize>,
+    pub max_limit: Option<u64>,
+    pub max_subid_length: Option<usize>,
+    pub max_event_tags: Option<usize>,
+    pub max_content_length: Option<usize>,
+    pub min_pow_difficulty: Option<u64>,
+    pub auth_required: bool,
+    pub payment_required: bool,
+    pub restricted_writes: bool,
+    pub created_at_lower_limit: Option<u64>,
+    pub created_at_upper_limit: Option<u64>,
+}
+
+impl RelayInformation {
+    /// Fetches the information document of the relay listening on
+    /// `relay_url`. The document is served over HTTP on the same URI as
+    /// the websocket, so the scheme is swapped before making the request.
+    pub async fn fetch(relay_url: &str) -> Result<RelayInformation> {
+        let response = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?
+            .get(http_url(relay_url)?)
+            .header(ACCEPT, "application/nostr+json")
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response.json::<RelayInformation>().await?)
+    }
+}
+
+/// Maps a `ws://` or `wss://` relay url to its `http://` or `https://`
+/// counterpart.
+fn http_url(relay_url: &str) -> Result<String> {
+    if let Some(rest) = relay_url.strip_prefix("wss://") {
+        return Ok(format!("https://{}", rest));
+    }
+    if let Some(rest) = relay_url.strip_prefix("ws://") {
+        return Ok(format!("http://{}", rest));
+    }
+
+    Err(anyhow!("invalid relay url {}", relay_url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_documents() {
+        let info: RelayInformation = serde_json::from_str(
+            r#"{
+                "name": "relay",
+                "supported_nips": [1, 11, 29],
+                "software": "git+https://example.com/relay.git",
+                "limitation": {
+                    "max_message_length": 16384,
+                    "max_limit": 500,
+                    "auth_required": true
+                },
+                "fees": { "admission": [{ "amount": 1000, "unit": "msats" }] }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(info.name.as_deref(), Some("relay"));
+        assert_eq!(info.supported_nips, vec![1, 11, 29]);
+        assert_eq!(info.pubkey, None);
+        let limitation = info.limitation.unwrap();
+        assert_eq!(limitation.max_message_length, Some(16384));
+        assert_eq!(limitation.max_limit, Some(500));
+        assert_eq!(limitation.max_subscriptions, None);
+        assert!(limitation.auth_required);
+        assert!(!limitation.payment_required);
+
+        // Every field may be missing
+        let empty: RelayInformation = serde_json::from_str("{}").unwrap();
+        assert!(empty.limitation.is_none());
+        assert!(empty.supported_nips.is_empty());
+    }
+
+    #[test]
+    fn swaps_the_scheme() {
+        assert_eq!(
+            http_url("wss://relay.example.com").unwrap(),
+            "https://relay.example.com"
+        );
+        assert_eq!(
+            http_url("ws://127.0.0.1:7777").unwrap(),
+            "http://127.0.0.1:7777"
+        );
+        assert!(http_url("https://relay.example.com").is_err());
+    }
+}
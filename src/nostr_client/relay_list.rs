@@ -0,0 +1,267 @@
+use anyhow::{anyhow, Result};
+
+use super::event::{unix_time, Event as EventType};
+use super::pool::{any_accepted, RelayPool};
+use super::request::Filter;
+use super::signer::Signer;
+
+/// Replaceable event kind holding a user's relay list, described by NIP-65
+pub const RELAY_LIST_KIND: u64 = 10002;
+
+/// A single relay of a relay list. A relay with no marker is used for both
+/// reading and writing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayMetadata {
+    pub url: String,
+    pub read: bool,
+    pub write: bool,
+}
+
+/// The relays where a user publishes their notes (write relays) and where
+/// they expect to receive notes mentioning them (read relays)
+#[derive(Debug, Clone, Default)]
+pub struct RelayList {
+    pub relays: Vec<RelayMetadata>,
+    pub created_at: u64,
+}
+
+impl RelayList {
+    /// Parses the `r` tags of a kind 10002 event. Tags with an invalid url
+    /// or an unknown marker are skipped.
+    pub fn from_event(event: &EventType) -> Result<RelayList> {
+        if event.kind != RELAY_LIST_KIND {
+            return Err(anyhow!("expected a kind {} event", RELAY_LIST_KIND));
+        }
+
+        let mut list = RelayList {
+            relays: Vec::new(),
+            created_at: event.created_at,
+        };
+
+        for tag in event.tags_named("r") {
+            let Some(url) = tag.get(1).and_then(|url| normalize_url(url)) else {
+                continue;
+            };
+            let (read, write) = match tag.get(2).map(String::as_str) {
+                None | Some("") => (true, true),
+                Some("read") => (true, false),
+                Some("write") => (false, true),
+                Some(_) => continue,
+            };
+
+            list.set(&url, read, write);
+        }
+
+        Ok(list)
+    }
+
+    /// Builds the unsigned kind 10002 event publishing this list
+    pub fn to_event(&self, pubkey: &str) -> EventType {
+        let mut event = EventType::new(pubkey, RELAY_LIST_KIND, String::new());
+
+        for relay in &self.relays {
+            let mut tag = vec!["r".to_string(), relay.url.clone()];
+            match (relay.read, relay.write) {
+                (true, false) => tag.push("read".to_string()),
+                (false, true) => tag.push("write".to_string()),
+                _ => {}
+            }
+            event.tags.push(tag);
+        }
+
+        event
+    }
+
+    /// Adds `url` to the list, or updates its markers if it's already there.
+    /// A relay that is neither read nor written to is removed.
+    pub fn set(&mut self, url: &str, read: bool, write: bool) {
+        let Some(url) = normalize_url(url) else {
+            return;
+        };
+
+        self.relays.retain(|relay| relay.url != url);
+        if read || write {
+            self.relays.push(RelayMetadata { url, read, write });
+        }
+    }
+
+    pub fn read_relays(&self) -> impl Iterator<Item = &str> {
+        self.relays
+            .iter()
+            .filter(|relay| relay.read)
+            .map(|relay| relay.url.as_str())
+    }
+
+    pub fn write_relays(&self) -> impl Iterator<Item = &str> {
+        self.relays
+            .iter()
+            .filter(|relay| relay.write)
+            .map(|relay| relay.url.as_str())
+    }
+}
+
+/// Applies `change` to the relay list of the user and publishes the result,
+/// which is returned.
+///
+/// As with contact lists, the newest version is fetched from the bootstrap
+/// relays first and used as the base when it's newer than the cached one.
+/// Without either, a new list is only started if a bootstrap relay
+/// answered: a list that couldn't be fetched would otherwise be replaced by
+/// one with only the change.
+pub async fn update(
+    pool: &mut RelayPool,
+    signer: &mut Signer,
+    change: impl FnOnce(&mut RelayList),
+) -> Result<RelayList> {
+    let pubkey = signer.pubkey().to_string();
+
+    let relays = pool.bootstrap_relays().to_vec();
+    let filter = Filter::new()
+        .kinds(vec![RELAY_LIST_KIND])
+        .authors(vec![pubkey.clone()]);
+    for event in pool.fetch_from(&relays, vec![filter]).await {
+        if let Ok(list) = RelayList::from_event(&event) {
+            if event.pubkey() == pubkey {
+                pool.set_relay_list(&pubkey, list);
+            }
+        }
+    }
+    let mut list = match pool.relay_list(&pubkey) {
+        Some(list) => list.clone(),
+        None if relays.iter().any(|url| pool.is_connected(url)) => RelayList::default(),
+        None => return Err(anyhow!("could not fetch the relay list")),
+    };
+    change(&mut list);
+
+    let mut event = list.to_event(&pubkey);
+    event.created_at = unix_time().max(list.created_at + 1);
+    signer.sign(&mut event).await?;
+
+    if !any_accepted(&pool.publish(&event).await) {
+        return Err(anyhow!("no relay accepted the relay list"));
+    }
+    list.created_at = event.created_at;
+
+    Ok(list)
+}
+
+/// Brings a relay url to a canonical form, so the same relay isn't connected
+/// to twice. Returns `None` for anything that isn't a websocket url.
+pub fn normalize_url(url: &str) -> Option<String> {
+    let url = url.trim().trim_end_matches('/');
+    let (scheme, rest) = url.split_once("://")?;
+    let scheme = scheme.to_lowercase();
+
+    if (scheme != "ws" && scheme != "wss") || rest.is_empty() {
+        return None;
+    }
+
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    if path.is_empty() {
+        return Some(format!("{}://{}", scheme, host.to_lowercase()));
+    }
+
+    Some(format!("{}://{}/{}", scheme, host.to_lowercase(), path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed, TestRelay};
+
+    #[test]
+    fn reads_back_what_it_publishes() {
+        let (secret_key, pubkey) = keys();
+        let event = signed(
+            &secret_key,
+            RELAY_LIST_KIND,
+            "",
+            vec![
+                vec!["r", "wss://Both.example.com/"],
+                vec!["r", "wss://read.example.com", "read"],
+                vec!["r", "wss://write.example.com", "write"],
+                vec!["r", "wss://unknown.example.com", "sometimes"],
+                vec!["r", "https://not-a-relay.example.com"],
+            ],
+        );
+
+        let list = RelayList::from_event(&event).unwrap();
+        assert_eq!(
+            list.read_relays().collect::<Vec<_>>(),
+            vec!["wss://both.example.com", "wss://read.example.com"]
+        );
+        assert_eq!(
+            list.write_relays().collect::<Vec<_>>(),
+            vec!["wss://both.example.com", "wss://write.example.com"]
+        );
+
+        let published = list.to_event(&pubkey);
+        assert_eq!(
+            published.tags,
+            vec![
+                vec!["r", "wss://both.example.com"],
+                vec!["r", "wss://read.example.com", "read"],
+                vec!["r", "wss://write.example.com", "write"],
+            ]
+        );
+        assert_eq!(
+            RelayList::from_event(&published).unwrap().relays,
+            list.relays
+        );
+    }
+
+    #[tokio::test]
+    async fn updates_the_newest_list() {
+        let relay = TestRelay::start().await;
+        let (secret_key, _) = keys();
+        let mut signer = Signer::local(&secret_key).unwrap();
+        relay.publish(signed(
+            &secret_key,
+            RELAY_LIST_KIND,
+            "",
+            vec![
+                vec!["r", &relay.url, "write"],
+                vec!["r", "ws://127.0.0.1:1", "read"],
+            ],
+        ));
+
+        let mut pool = RelayPool::new(vec![relay.url.clone()]);
+        let list = update(&mut pool, &mut signer, |list| {
+            list.set("ws://127.0.0.1:2", true, false);
+            list.set("ws://127.0.0.1:1", false, false);
+        })
+        .await
+        .unwrap();
+
+        let published = relay
+            .events()
+            .into_iter()
+            .max_by_key(|event| event.created_at)
+            .unwrap();
+        assert_eq!(
+            RelayList::from_event(&published).unwrap().relays,
+            list.relays
+        );
+        assert_eq!(
+            published.tags,
+            vec![
+                vec!["r", relay.url.as_str(), "write"],
+                vec!["r", "ws://127.0.0.1:2", "read"],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_to_replace_a_list_that_was_not_fetched() {
+        let (secret_key, _) = keys();
+        let mut signer = Signer::local(&secret_key).unwrap();
+        let mut pool = RelayPool::new(vec!["ws://127.0.0.1:1".to_string()]);
+
+        let updated = update(&mut pool, &mut signer, |list| {
+            list.set("wss://relay.example.com", true, true)
+        })
+        .await;
+
+        assert!(updated.is_err());
+    }
+}
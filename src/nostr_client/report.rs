@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use super::event::Event as EventType;
+
+/// Event reporting a note or a user as objectionable, described by NIP-56
+pub const REPORT_KIND: u64 = 1984;
+
+/// What a note or a user is reported for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReportType {
+    Nudity,
+    Malware,
+    Profanity,
+    Illegal,
+    Spam,
+    Impersonation,
+    Other,
+}
+
+impl ReportType {
+    pub const ALL: [ReportType; 7] = [
+        ReportType::Nudity,
+        ReportType::Malware,
+        ReportType::Profanity,
+        ReportType::Illegal,
+        ReportType::Spam,
+        ReportType::Impersonation,
+        ReportType::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportType::Nudity => "nudity",
+            ReportType::Malware => "malware",
+            ReportType::Profanity => "profanity",
+            ReportType::Illegal => "illegal",
+            ReportType::Spam => "spam",
+            ReportType::Impersonation => "impersonation",
+            ReportType::Other => "other",
+        }
+    }
+
+    /// Whether what's reported for it is left out altogether, rather than
+    /// shown behind a warning
+    pub fn hides(&self) -> bool {
+        matches!(
+            self,
+            ReportType::Malware
+                | ReportType::Illegal
+                | ReportType::Spam
+                | ReportType::Impersonation
+        )
+    }
+}
+
+impl FromStr for ReportType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ReportType> {
+        ReportType::ALL
+            .into_iter()
+            .find(|report_type| report_type.as_str() == s.trim().to_lowercase())
+            .ok_or(anyhow!("Unknown report type {}", s.trim()))
+    }
+}
+
+impl fmt::Display for ReportType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A report of a note or user, by who made it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub reporter: String,
+    pub report_type: ReportType,
+    pub reason: String,
+    pub created_at: u64,
+}
+
+/// The ids of the notes `event` reports with what for, or the pubkeys of
+/// the users it reports if it reports no note. Notes are reported along with
+/// their author, who isn't reported themselves then.
+pub fn targets(event: &EventType) -> Vec<(&str, ReportType)> {
+    let typed = |name: &'static str| {
+        event
+            .tags_named(name)
+            .filter_map(|tag| Some((tag.get(1)?.as_str(), tag.get(2)?.parse().ok()?)))
+            .collect::<Vec<(&str, ReportType)>>()
+    };
+
+    match event.tags_named("e").next() {
+        Some(_) => typed("e"),
+        None => typed("p"),
+    }
+}
+
+/// Builds the unsigned report of `pubkey` about `note`
+pub fn report_note(
+    pubkey: &str,
+    note: &EventType,
+    report_type: ReportType,
+    reason: &str,
+) -> EventType {
+    let mut event = EventType::new(pubkey, REPORT_KIND, reason.to_string());
+    event.tags.push(vec![
+        "e".to_string(),
+        note.id().unwrap_or_default().to_string(),
+        report_type.to_string(),
+    ]);
+    event.add_tag("p", &note.pubkey(), None);
+
+    event
+}
+
+/// Builds the unsigned report of `pubkey` about the user `user`
+pub fn report_user(pubkey: &str, user: &str, report_type: ReportType, reason: &str) -> EventType {
+    let mut event = EventType::new(pubkey, REPORT_KIND, reason.to_string());
+    event.tags.push(vec![
+        "p".to_string(),
+        user.to_string(),
+        report_type.to_string(),
+    ]);
+
+    event
+}
+
+/// Reports received so far, by the id of the note or the pubkey of the user
+/// they're about
+#[derive(Debug, Default)]
+pub struct Reports {
+    reports: HashMap<String, Vec<Report>>,
+}
+
+impl Reports {
+    pub fn new() -> Reports {
+        Reports::default()
+    }
+
+    /// Stores the reports made by `event`, returning whether there were new
+    /// ones
+    pub fn insert(&mut self, event: &EventType) -> bool {
+        if event.kind != REPORT_KIND {
+            return false;
+        }
+
+        let reporter = event.pubkey();
+        let mut inserted = false;
+        for (target, report_type) in targets(event) {
+            let reports = self.reports.entry(target.to_string()).or_default();
+            let known = reports
+                .iter()
+                .any(|report| report.reporter == reporter && report.report_type == report_type);
+            if known {
+                continue;
+            }
+
+            reports.push(Report {
+                reporter: reporter.clone(),
+                report_type,
+                reason: event.content.trim().to_string(),
+                created_at: event.created_at,
+            });
+            inserted = true;
+        }
+
+        inserted
+    }
+
+    /// The reports about the note with id `target`, or the user with pubkey
+    /// `target`
+    pub fn of(&self, target: &str) -> &[Report] {
+        self.reports.get(target).map_or(&[], Vec::as_slice)
+    }
+}
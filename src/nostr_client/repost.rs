@@ -0,0 +1,202 @@
+use anyhow::{anyhow, Result};
+use secp256k1::{Secp256k1, Verification};
+
+use super::entity::Entity;
+use super::event::{Event as EventType, TEXT_NOTE_KIND};
+
+/// Repost of a text note
+pub const REPOST_KIND: u64 = 6;
+
+/// Repost of any other kind of event
+pub const GENERIC_REPOST_KIND: u64 = 16;
+
+/// A kind 6 or 16 event, sharing someone else's event as described by
+/// NIP-18
+#[derive(Debug, Clone)]
+pub struct Repost {
+    /// The event reposted
+    pub target_id: String,
+    /// Relay the reposted event can be found at
+    pub relay: Option<String>,
+    /// The author of the event reposted
+    pub target_author: Option<String>,
+    /// The kind of the event reposted
+    pub target_kind: Option<u64>,
+    /// The reposted event, when it was embedded with a valid signature
+    pub embedded: Option<EventType>,
+}
+
+impl Repost {
+    /// Reads a repost. The embedded event is only kept when its signature
+    /// holds and it is the one the tags point to.
+    pub fn from_event<C: Verification>(event: &EventType, secp: &Secp256k1<C>) -> Result<Repost> {
+        if !is_repost(event) {
+            return Err(anyhow!("Event of kind {} is not a repost", event.kind));
+        }
+
+        let tag = event
+            .tags_named("e")
+            .next()
+            .ok_or(anyhow!("Repost without an `e` tag"))?;
+        let target_id = tag.get(1).cloned().unwrap_or_default();
+        let relay = tag.get(2).filter(|relay| !relay.is_empty()).cloned();
+
+        let embedded = serde_json::from_str::<EventType>(&event.content)
+            .ok()
+            .filter(|embedded| embedded.id() == Some(target_id.as_str()))
+            .filter(|embedded| embedded.verify(secp).is_ok());
+
+        let target_kind = match event.kind {
+            REPOST_KIND => Some(TEXT_NOTE_KIND),
+            _ => event
+                .tag_values("k")
+                .next()
+                .and_then(|kind| kind.parse().ok()),
+        };
+
+        Ok(Repost {
+            target_id,
+            relay,
+            target_author: event.tag_values("p").next().map(str::to_string),
+            target_kind,
+            embedded,
+        })
+    }
+}
+
+pub fn is_repost(event: &EventType) -> bool {
+    event.kind == REPOST_KIND || event.kind == GENERIC_REPOST_KIND
+}
+
+/// Builds the unsigned repost of `target` by `pubkey`, embedding it.
+/// `relay` is where others can find `target`.
+pub fn repost(target: &EventType, pubkey: &str, relay: Option<&str>) -> EventType {
+    let kind = match target.kind {
+        TEXT_NOTE_KIND => REPOST_KIND,
+        _ => GENERIC_REPOST_KIND,
+    };
+    let content = serde_json::to_string(target).unwrap_or_default();
+    let mut event = EventType::new(pubkey, kind, content);
+
+    event.tags.push(vec![
+        "e".to_string(),
+        target.id().unwrap_or_default().to_string(),
+        relay.unwrap_or_default().to_string(),
+    ]);
+    event.add_tag("p", &target.pubkey(), None);
+    if kind == GENERIC_REPOST_KIND {
+        event.add_tag("k", &target.kind.to_string(), None);
+    }
+
+    event
+}
+
+/// Builds the unsigned note of `pubkey` quoting `target`: `content` is
+/// followed by a `nostr:` reference to it, and a `q` tag points to it
+pub fn quote(target: &EventType, pubkey: &str, content: &str) -> EventType {
+    let target_id = target.id().unwrap_or_default().to_string();
+    let reference = Entity::Note(target_id.clone());
+    let content = format!("{}\n\nnostr:{}", content.trim_end(), reference);
+    let mut event = EventType::new(pubkey, TEXT_NOTE_KIND, content);
+
+    event.tags.push(vec![
+        "q".to_string(),
+        target_id,
+        String::new(),
+        target.pubkey(),
+    ]);
+    event.add_tag("p", &target.pubkey(), None);
+
+    event
+}
+
+/// Ids of the events quoted by `event` through `q` tags
+pub fn quoted_ids(event: &EventType) -> impl Iterator<Item = &str> {
+    event.tag_values("q")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed};
+
+    fn signed_by(secret_key: &str, mut event: EventType) -> EventType {
+        event.setup(secret_key, &Secp256k1::new()).unwrap();
+        event
+    }
+
+    #[test]
+    fn reposts_notes_and_other_kinds() {
+        let (author, author_pubkey) = keys();
+        let (reposter, reposter_pubkey) = keys();
+        let note = signed(&author, TEXT_NOTE_KIND, "hello", vec![]);
+        let article = signed(&author, 30023, "# Title", vec![vec!["d", "title"]]);
+        let secp = Secp256k1::new();
+
+        let event = signed_by(
+            &reposter,
+            repost(&note, &reposter_pubkey, Some("wss://relay.example.com")),
+        );
+        assert_eq!(event.kind, REPOST_KIND);
+        assert!(event.tag_values("k").next().is_none());
+        let read = Repost::from_event(&event, &secp).unwrap();
+        assert_eq!(read.target_id, note.id().unwrap());
+        assert_eq!(read.relay.as_deref(), Some("wss://relay.example.com"));
+        assert_eq!(read.target_author, Some(author_pubkey.clone()));
+        assert_eq!(read.target_kind, Some(TEXT_NOTE_KIND));
+        assert_eq!(read.embedded.unwrap().id(), note.id());
+
+        let event = signed_by(&reposter, repost(&article, &reposter_pubkey, None));
+        assert_eq!(event.kind, GENERIC_REPOST_KIND);
+        let read = Repost::from_event(&event, &secp).unwrap();
+        assert_eq!(read.relay, None);
+        assert_eq!(read.target_author, Some(author_pubkey));
+        assert_eq!(read.target_kind, Some(30023));
+
+        assert!(Repost::from_event(&note, &secp).is_err());
+    }
+
+    #[test]
+    fn drops_embedded_events_that_dont_hold() {
+        let (author, _) = keys();
+        let (reposter, reposter_pubkey) = keys();
+        let note = signed(&author, TEXT_NOTE_KIND, "hello", vec![]);
+        let other = signed(&author, TEXT_NOTE_KIND, "something else", vec![]);
+        let secp = Secp256k1::new();
+
+        let mut forged = note.clone();
+        forged.content = "goodbye".to_string();
+        let mut event = repost(&note, &reposter_pubkey, None);
+        event.content = serde_json::to_string(&forged).unwrap();
+        let read = Repost::from_event(&signed_by(&reposter, event), &secp).unwrap();
+        assert_eq!(read.target_id, note.id().unwrap());
+        assert!(read.embedded.is_none());
+
+        let mut event = repost(&note, &reposter_pubkey, None);
+        event.content = serde_json::to_string(&other).unwrap();
+        let read = Repost::from_event(&signed_by(&reposter, event), &secp).unwrap();
+        assert!(read.embedded.is_none());
+    }
+
+    #[test]
+    fn quotes_notes() {
+        let (author, author_pubkey) = keys();
+        let (_, quoter_pubkey) = keys();
+        let note = signed(&author, TEXT_NOTE_KIND, "hello", vec![]);
+        let id = note.id().unwrap();
+
+        let event = quote(&note, &quoter_pubkey, "look at this\n");
+
+        assert_eq!(event.kind, TEXT_NOTE_KIND);
+        assert_eq!(
+            event.content,
+            format!("look at this\n\nnostr:{}", Entity::Note(id.to_string()))
+        );
+        assert_eq!(quoted_ids(&event).collect::<Vec<_>>(), vec![id]);
+        assert_eq!(event.tags[0], vec!["q", id, "", author_pubkey.as_str()]);
+        assert_eq!(
+            event.tag_values("p").collect::<Vec<_>>(),
+            vec![author_pubkey.as_str()]
+        );
+    }
+}
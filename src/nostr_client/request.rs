@@ -1,36 +1,100 @@
+use std::collections::BTreeMap;
+
 use super::event::Event as EventType;
 use serde::{ser::SerializeSeq, Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+/// A subscription filter as described by NIP-01. Empty fields are left out
+/// of the serialized form, since relays treat present fields as constraints.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct Filter {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     authors: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     kinds: Vec<u64>,
-    tag_values: Vec<String>,
-    since: u64,
-    until: u64,
-    limit: u64,
+    /// Tag queries, keyed by `#` followed by the tag name
+    #[serde(flatten)]
+    tags: BTreeMap<String, Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    since: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    until: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<u64>,
 }
 
 impl Filter {
-    pub fn new(
-        ids: Vec<String>,
-        authors: Vec<String>,
-        kinds: Vec<u64>,
-        tag_values: Vec<String>,
-        since: u64,
-        until: u64,
-        limit: u64,
-    ) -> Self {
-        Self {
-            ids,
-            authors,
-            kinds,
-            tag_values,
-            since,
-            until,
-            limit,
+    /// Creates a filter that matches every event, to be narrowed down with
+    /// the methods below
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ids(mut self, ids: Vec<String>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    pub fn authors(mut self, authors: Vec<String>) -> Self {
+        self.authors = authors;
+        self
+    }
+
+    pub fn kinds(mut self, kinds: Vec<u64>) -> Self {
+        self.kinds = kinds;
+        self
+    }
+
+    /// Matches events that have a `tag_key` tag with any of `values`
+    pub fn tag(mut self, tag_key: char, values: Vec<String>) -> Self {
+        self.tags.insert(format!("#{}", tag_key), values);
+        self
+    }
+
+    pub fn since(mut self, since: u64) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Lowers the limit of the filter to `max_limit` if it asks for more
+    pub fn clamp_limit(&mut self, max_limit: u64) {
+        self.limit = Some(self.limit.map_or(max_limit, |limit| limit.min(max_limit)));
+    }
+
+    /// Checks whether `event` would be sent by a relay for this filter
+    #[cfg(test)]
+    pub fn matches(&self, event: &EventType) -> bool {
+        if !self.ids.is_empty()
+            && !event
+                .id()
+                .is_some_and(|id| self.ids.iter().any(|i| i == id))
+        {
+            return false;
+        }
+        if !self.authors.is_empty() && !self.authors.contains(&event.pubkey()) {
+            return false;
+        }
+        if !self.kinds.is_empty() && !self.kinds.contains(&event.kind) {
+            return false;
         }
+        if self.since.is_some_and(|since| event.created_at < since)
+            || self.until.is_some_and(|until| event.created_at > until)
+        {
+            return false;
+        }
+
+        self.tags.iter().all(|(key, values)| {
+            let tag_key = key.trim_start_matches('#');
+            event
+                .tag_values(tag_key)
+                .any(|value| values.iter().any(|v| v == value))
+        })
     }
 }
 
@@ -47,8 +111,8 @@ pub enum Request {
     },
 }
 
-/// The protocol specifies that the requests are formatted in a little bit 
-/// of a different fashion, by using JSON arrays, so we implement the 
+/// The protocol specifies that the requests are formatted in a little bit
+/// of a different fashion, by using JSON arrays, so we implement the
 /// `Serialize` trait instead od deriving it on the struct directly
 impl serde::Serialize for Request {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -24,6 +24,9 @@ pub enum Response {
     Notice {
         message: String,
     },
+    Auth {
+        challenge: String,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -35,19 +38,17 @@ impl FromStr for Response {
     type Err = ParseResponseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let val: Value = serde_json::from_str(s).unwrap();
-        let mut array = val.as_array().unwrap().iter();
+        // Relays we connect to on demand may send anything, so malformed
+        // messages are reported instead of trusted
+        let val: Value = serde_json::from_str(s).map_err(|_| ParseResponseError)?;
+        let array = val.as_array().ok_or(ParseResponseError)?;
 
-        match array.next().unwrap().as_str().unwrap() {
+        match string_at(array, 0)?.as_str() {
             "EVENT" => {
-                let subscription_id = array.next().unwrap().as_str().unwrap().to_string();
-                let event_obj = array.next().unwrap().as_object().unwrap();
-
-                let event = EventType::new(
-                    event_obj["pubkey"].as_str().unwrap(),
-                    event_obj["kind"].as_u64().unwrap(),
-                    event_obj["content"].to_string(),
-                );
+                let subscription_id = string_at(array, 1)?;
+                let event_obj = array.get(2).ok_or(ParseResponseError)?.clone();
+                let event = serde_json::from_value::<EventType>(event_obj)
+                    .map_err(|_| ParseResponseError)?;
 
                 Ok(Response::Event {
                     subscription_id,
@@ -55,9 +56,12 @@ impl FromStr for Response {
                 })
             }
             "OK" => {
-                let event_id = array.next().unwrap().as_str().unwrap().to_string();
-                let accepted = array.next().unwrap().as_bool().unwrap();
-                let message = array.next().unwrap().as_str().unwrap().to_string();
+                let event_id = string_at(array, 1)?;
+                let accepted = array
+                    .get(2)
+                    .and_then(Value::as_bool)
+                    .ok_or(ParseResponseError)?;
+                let message = string_at(array, 3).unwrap_or_default();
 
                 Ok(Response::Ok {
                     event_id,
@@ -66,13 +70,13 @@ impl FromStr for Response {
                 })
             }
             "EOSE" => {
-                let subscription_id = array.next().unwrap().as_str().unwrap().to_string();
+                let subscription_id = string_at(array, 1)?;
 
                 Ok(Response::Eose { subscription_id })
             }
             "CLOSED" => {
-                let subscription_id = array.next().unwrap().as_str().unwrap().to_string();
-                let message = array.next().unwrap().as_str().unwrap().to_string();
+                let subscription_id = string_at(array, 1)?;
+                let message = string_at(array, 2).unwrap_or_default();
 
                 Ok(Response::Closed {
                     subscription_id,
@@ -80,11 +84,24 @@ impl FromStr for Response {
                 })
             }
             "NOTICE" => {
-                let message = array.next().unwrap().as_str().unwrap().to_string();
+                let message = string_at(array, 1)?;
 
                 Ok(Response::Notice { message })
             }
-            str => panic!("Invalid response string {}", str),
+            "AUTH" => {
+                let challenge = string_at(array, 1)?;
+
+                Ok(Response::Auth { challenge })
+            }
+            _ => Err(ParseResponseError),
         }
     }
 }
+
+fn string_at(array: &[Value], index: usize) -> Result<String, ParseResponseError> {
+    array
+        .get(index)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or(ParseResponseError)
+}
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use secp256k1::{All, Secp256k1};
+
+use super::encryption;
+use super::event::{pubkey_from_secret, Event as EventType};
+use super::http_auth;
+use super::nostr_connect::RemoteSigner;
+
+/// Signs and encrypts on behalf of the user. Everything the client
+/// publishes goes through it, so the secret key of the user never has to
+/// be on the machine when a remote signer holds it.
+pub enum Signer {
+    /// Signs with the secret key of the user, held in memory
+    Local {
+        secret_key: String,
+        pubkey: String,
+        secp: Secp256k1<All>,
+    },
+    /// Asks a NIP-46 remote signer
+    Remote(Box<RemoteSigner>),
+}
+
+impl Signer {
+    pub fn local(secret_key: &str) -> Result<Signer> {
+        let secp = Secp256k1::new();
+        let pubkey = pubkey_from_secret(secret_key, &secp)?;
+
+        Ok(Signer::Local {
+            secret_key: secret_key.to_string(),
+            pubkey,
+            secp,
+        })
+    }
+
+    /// The pubkey of the user
+    pub fn pubkey(&self) -> &str {
+        match self {
+            Signer::Local { pubkey, .. } => pubkey,
+            Signer::Remote(remote) => remote.pubkey(),
+        }
+    }
+
+    /// Calls `callback` with the pages the user has to visit for a remote
+    /// signer to go on with a request. Local keys never need them.
+    pub fn on_auth_url(&mut self, callback: impl Fn(&str) + Send + 'static) {
+        if let Signer::Remote(remote) = self {
+            remote.on_auth_url(callback);
+        }
+    }
+
+    /// Gives `event` its id and signature
+    pub async fn sign(&mut self, event: &mut EventType) -> Result<()> {
+        match self {
+            Signer::Local {
+                secret_key, secp, ..
+            } => event.setup(secret_key, secp),
+            Signer::Remote(remote) => {
+                *event = remote.sign_event(event).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Signs a NIP-98 event for a `method` request to `url` sending `body`,
+    /// and returns the value of the `Authorization` header carrying it
+    pub async fn http_authorization(
+        &mut self,
+        url: &str,
+        method: &str,
+        body: Option<&[u8]>,
+    ) -> Result<String> {
+        let mut event = http_auth::auth_event(self.pubkey(), url, method, body);
+        self.sign(&mut event).await?;
+
+        http_auth::header(&event)
+    }
+
+    /// Encrypts `plaintext` from the user to `pubkey` with NIP-44
+    pub async fn nip44_encrypt(&mut self, pubkey: &str, plaintext: &str) -> Result<String> {
+        match self {
+            Signer::Local { secret_key, .. } => {
+                encryption::nip44_encrypt(secret_key, pubkey, plaintext)
+            }
+            Signer::Remote(remote) => remote.nip44_encrypt(pubkey, plaintext).await,
+        }
+    }
+
+    /// Decrypts a `payload` exchanged between the user and `pubkey`,
+    /// whichever of NIP-04 and NIP-44 it was encrypted with
+    pub async fn decrypt(&mut self, pubkey: &str, payload: &str) -> Result<String> {
+        match self {
+            Signer::Local { secret_key, .. } => encryption::decrypt(secret_key, pubkey, payload),
+            Signer::Remote(remote) if encryption::is_nip04(payload) => {
+                remote.nip04_decrypt(pubkey, payload).await
+            }
+            Signer::Remote(remote) => remote.nip44_decrypt(pubkey, payload).await,
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Stand-ins for relays and HTTP servers, so that tests can exercise the
+//! network code in-process, and builders for what they serve.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bech32::{Bech32, ByteIterExt, Fe32, Fe32IterExt, Hrp};
+use futures_util::{SinkExt, StreamExt};
+use secp256k1::{Secp256k1, SecretKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, watch},
+    task::JoinHandle,
+};
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+
+use super::event::{pubkey_from_secret, unix_time, Event};
+use super::request::Filter;
+
+/// Generates a new key pair, returning the hex secret and public keys
+pub fn keys() -> (String, String) {
+    let secp = Secp256k1::new();
+    let (secret_key, _) = secp.generate_keypair(&mut rand::thread_rng());
+    let secret_key = secret_key.display_secret().to_string();
+    let pubkey = pubkey_from_secret(&secret_key, &secp).unwrap();
+
+    (secret_key, pubkey)
+}
+
+/// Builds an event of `kind` signed with `secret_key`
+pub fn signed(secret_key: &str, kind: u64, content: &str, tags: Vec<Vec<&str>>) -> Event {
+    let secp = Secp256k1::new();
+    let mut event = Event::new(
+        &pubkey_from_secret(secret_key, &secp).unwrap(),
+        kind,
+        content.to_string(),
+    );
+    event.tags = tags
+        .into_iter()
+        .map(|tag| tag.into_iter().map(str::to_string).collect())
+        .collect();
+    event.setup(secret_key, &secp).unwrap();
+
+    event
+}
+
+/// Builds a BOLT 11 invoice for `amount_msats` with the given tagged
+/// fields, like `('h', description_hash)`, signed by the node with
+/// `secret_key`
+pub fn invoice(secret_key: &str, amount_msats: u64, fields: Vec<(char, Vec<u8>)>) -> String {
+    let to_words = |bytes: &[u8]| -> Vec<u8> {
+        bytes
+            .iter()
+            .copied()
+            .bytes_to_fes()
+            .map(Fe32::to_u8)
+            .collect()
+    };
+
+    let hrp = format!("lnbc{}p", amount_msats * 10);
+    let timestamp = unix_time();
+    let mut words: Vec<u8> = (0..7)
+        .rev()
+        .map(|index| ((timestamp >> (5 * index)) & 31) as u8)
+        .collect();
+    for (tag, data) in fields {
+        let data = to_words(&data);
+        words.push(Fe32::from_char(tag).unwrap().to_u8());
+        words.push((data.len() >> 5) as u8);
+        words.push((data.len() & 31) as u8);
+        words.extend(data);
+    }
+
+    // The signature covers the human-readable part and the words, padded
+    // to bytes
+    let mut signed = hrp.as_bytes().to_vec();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for word in &words {
+        buffer = (buffer << 5) | u32::from(*word);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            signed.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bits > 0 {
+        signed.push((buffer << (8 - bits)) as u8);
+    }
+    let message = secp256k1::Message::from_digest_slice(&Sha256::digest(&signed)).unwrap();
+    let secret_key: SecretKey = secret_key.parse().unwrap();
+    let (recovery_id, signature) = Secp256k1::new()
+        .sign_ecdsa_recoverable(&message, &secret_key)
+        .serialize_compact();
+    let mut signature = signature.to_vec();
+    signature.push(recovery_id.to_i32() as u8);
+    words.extend(to_words(&signature));
+
+    words
+        .into_iter()
+        .map(|word| Fe32::try_from(word).unwrap())
+        .with_checksum::<Bech32>(&Hrp::parse(&hrp).unwrap())
+        .chars()
+        .collect()
+}
+
+/// What a relay sends to all of its clients, besides answering them
+#[derive(Clone)]
+enum Broadcast {
+    /// An event that was just published, for the matching subscriptions
+    Event(Event),
+    /// A message sent as is
+    Raw(String),
+}
+
+/// An in-memory relay listening on localhost. It stores every valid event
+/// it receives, and answers `REQ`s from what it stored followed by live
+/// events. It can be stopped and restarted on the same address, dropping
+/// its clients in between.
+pub struct TestRelay {
+    pub url: String,
+    addr: SocketAddr,
+    /// NIP-11 document served to plain HTTP requests
+    info: Option<Value>,
+    events: Arc<Mutex<Vec<Event>>>,
+    broadcast: broadcast::Sender<Broadcast>,
+    shutdown: watch::Sender<bool>,
+    listener: JoinHandle<()>,
+}
+
+impl TestRelay {
+    pub async fn start() -> TestRelay {
+        Self::start_with_info(None).await
+    }
+
+    /// Starts a relay that serves `info` as its NIP-11 document
+    pub async fn start_with_info(info: Option<Value>) -> TestRelay {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (broadcast, _) = broadcast::channel(1000);
+        let (shutdown, _) = watch::channel(false);
+
+        let listener = tokio::spawn(listen(
+            listener,
+            info.clone(),
+            events.clone(),
+            broadcast.clone(),
+            shutdown.subscribe(),
+        ));
+
+        TestRelay {
+            url: format!("ws://{}", addr),
+            addr,
+            info,
+            events,
+            broadcast,
+            shutdown,
+            listener,
+        }
+    }
+
+    /// Stops listening and drops every client
+    pub fn stop(&mut self) {
+        self.listener.abort();
+        let _ = self.shutdown.send(true);
+    }
+
+    /// Listens again on the same address, keeping the stored events
+    pub async fn restart(&mut self) {
+        // The stopped listener is only closed once its task gets cancelled
+        let mut listener = TcpListener::bind(self.addr).await;
+        for _ in 0..50 {
+            if listener.is_ok() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            listener = TcpListener::bind(self.addr).await;
+        }
+        let listener = listener.unwrap();
+        let (shutdown, _) = watch::channel(false);
+        self.shutdown = shutdown;
+        self.listener = tokio::spawn(listen(
+            listener,
+            self.info.clone(),
+            self.events.clone(),
+            self.broadcast.clone(),
+            self.shutdown.subscribe(),
+        ));
+    }
+
+    /// Stores `event` as if it was published, sending it to matching
+    /// subscriptions
+    pub fn publish(&self, event: Event) {
+        self.events.lock().unwrap().push(event.clone());
+        let _ = self.broadcast.send(Broadcast::Event(event));
+    }
+
+    /// Sends `message` as is to every client
+    pub fn send_raw(&self, message: &str) {
+        let _ = self.broadcast.send(Broadcast::Raw(message.to_string()));
+    }
+
+    /// The events the relay received or was given so far
+    pub fn events(&self) -> Vec<Event> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl Drop for TestRelay {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+async fn listen(
+    listener: TcpListener,
+    info: Option<Value>,
+    events: Arc<Mutex<Vec<Event>>>,
+    broadcast: broadcast::Sender<Broadcast>,
+    shutdown: watch::Receiver<bool>,
+) {
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(serve_client(
+            stream,
+            info.clone(),
+            events.clone(),
+            broadcast.clone(),
+            shutdown.clone(),
+        ));
+    }
+}
+
+async fn serve_client(
+    mut stream: TcpStream,
+    info: Option<Value>,
+    events: Arc<Mutex<Vec<Event>>>,
+    broadcast: broadcast::Sender<Broadcast>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    // Requests that don't upgrade to a websocket ask for the NIP-11 document
+    let mut head = vec![0; 4096];
+    let Ok(read) = stream.peek(&mut head).await else {
+        return;
+    };
+    let head = String::from_utf8_lossy(&head[..read]).to_lowercase();
+    if !head.contains("upgrade: websocket") {
+        let _ = read_request(&mut stream).await;
+        let (status, body) = match info {
+            Some(info) => (200, info.to_string()),
+            None => (404, String::new()),
+        };
+        let _ = write_response(&mut stream, status, &body).await;
+        return;
+    }
+
+    let Ok(ws) = accept_async(stream).await else {
+        return;
+    };
+    let (mut sink, mut source) = ws.split();
+    let mut received = broadcast.subscribe();
+    let mut subscriptions: Vec<(String, Vec<Filter>)> = Vec::new();
+    let secp = Secp256k1::verification_only();
+
+    loop {
+        let mut replies = Vec::new();
+        tokio::select! {
+            message = source.next() => {
+                let Some(Ok(Message::Text(text))) = message else {
+                    return;
+                };
+                let Ok(Value::Array(message)) = serde_json::from_str(&text) else {
+                    continue;
+                };
+
+                match message.first().and_then(Value::as_str) {
+                    Some("EVENT") => {
+                        let Ok(event) = serde_json::from_value::<Event>(message[1].clone()) else {
+                            continue;
+                        };
+                        let valid = event.verify(&secp).is_ok();
+                        replies.push(json!(["OK", event.id(), valid, ""]));
+                        if valid {
+                            events.lock().unwrap().push(event.clone());
+                            let _ = broadcast.send(Broadcast::Event(event));
+                        }
+                    }
+                    Some("REQ") => {
+                        let id = message[1].as_str().unwrap_or_default().to_string();
+                        let filters: Vec<Filter> = message[2..]
+                            .iter()
+                            .filter_map(|filter| serde_json::from_value(filter.clone()).ok())
+                            .collect();
+                        for event in events.lock().unwrap().iter() {
+                            if filters.iter().any(|filter| filter.matches(event)) {
+                                replies.push(json!(["EVENT", id, event]));
+                            }
+                        }
+                        replies.push(json!(["EOSE", id]));
+                        subscriptions.push((id, filters));
+                    }
+                    Some("CLOSE") => {
+                        subscriptions.retain(|(id, _)| Some(id.as_str()) != message[1].as_str());
+                    }
+                    _ => {}
+                }
+            }
+            Ok(sent) = received.recv() => match sent {
+                Broadcast::Event(event) => {
+                    for (id, filters) in &subscriptions {
+                        if filters.iter().any(|filter| filter.matches(&event)) {
+                            replies.push(json!(["EVENT", id, event]));
+                        }
+                    }
+                }
+                Broadcast::Raw(message) => {
+                    if sink.send(Message::Text(message)).await.is_err() {
+                        return;
+                    }
+                }
+            },
+            _ = shutdown.changed() => return,
+        }
+
+        for reply in replies {
+            if sink.send(Message::Text(reply.to_string())).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Starts an HTTP server on localhost that answers every request with the
+/// status and JSON body returned by `handler`, which gets the full request.
+/// Returns the base url of the server.
+pub async fn http_server(
+    handler: impl Fn(&str) -> (u16, String) + Send + Sync + 'static,
+) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handler = Arc::new(handler);
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let Ok(request) = read_request(&mut stream).await else {
+                    return;
+                };
+                let (status, body) = handler(&request);
+                let _ = write_response(&mut stream, status, &body).await;
+            });
+        }
+    });
+
+    format!("http://{}", addr)
+}
+
+/// Reads an HTTP request up to the end of its body
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 8192];
+    loop {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+
+        let text = String::from_utf8_lossy(&request);
+        if let Some(end) = text.find("\r\n\r\n") {
+            let length = text[..end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if request.len() >= end + 4 + length {
+                break;
+            }
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&request).to_string())
+}
+
+async fn write_response(stream: &mut TcpStream, status: u16, body: &str) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {} Test\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+
+    stream.write_all(response.as_bytes()).await
+}
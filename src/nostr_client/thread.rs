@@ -0,0 +1,486 @@
+use std::collections::{HashMap, HashSet};
+
+use super::event::{Event as EventType, TEXT_NOTE_KIND};
+use super::pool::RelayPool;
+use super::request::Filter;
+
+/// How many times missing ancestors are looked for when fetching a thread,
+/// since each round can only reveal the parents of what it found
+const MAX_FETCH_ROUNDS: usize = 5;
+
+/// A reference to another event through an `e` tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventRef {
+    pub id: String,
+    pub relay: Option<String>,
+    pub pubkey: Option<String>,
+}
+
+impl EventRef {
+    fn from_tag(tag: &[String]) -> Option<EventRef> {
+        let non_empty = |value: Option<&String>| value.filter(|v| !v.is_empty()).cloned();
+
+        Some(EventRef {
+            id: non_empty(tag.get(1))?,
+            relay: non_empty(tag.get(2)),
+            pubkey: non_empty(tag.get(4)),
+        })
+    }
+}
+
+/// Where an event sits in a conversation, read from its `e` tags as
+/// described by NIP-10
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThreadRefs {
+    /// The event that started the conversation
+    pub root: Option<EventRef>,
+    /// The event being directly replied to
+    pub reply: Option<EventRef>,
+    /// Other events that are only cited
+    pub mentions: Vec<EventRef>,
+}
+
+impl ThreadRefs {
+    /// Reads the `e` tags of `event`, understanding both marked tags and the
+    /// deprecated positional ones, where the first tag is the root, the last
+    /// one the parent and the ones in between mentions.
+    pub fn from_event(event: &EventType) -> ThreadRefs {
+        let tags: Vec<&Vec<String>> = event.tags_named("e").collect();
+        let marked = tags
+            .iter()
+            .any(|tag| matches!(tag.get(3).map(String::as_str), Some("root" | "reply")));
+
+        let mut refs = ThreadRefs::default();
+        if marked {
+            for tag in tags {
+                let Some(event_ref) = EventRef::from_tag(tag) else {
+                    continue;
+                };
+                match tag.get(3).map(String::as_str) {
+                    Some("root") => refs.root = Some(event_ref),
+                    Some("reply") => refs.reply = Some(event_ref),
+                    _ => refs.mentions.push(event_ref),
+                }
+            }
+        } else {
+            let mut refs_iter = tags.iter().filter_map(|tag| EventRef::from_tag(tag));
+            refs.root = refs_iter.next();
+            refs.mentions = refs_iter.collect();
+            refs.reply = refs.mentions.pop();
+        }
+
+        // A direct reply to the root only marks the root
+        if refs.reply.is_none() {
+            refs.reply = refs.root.clone();
+        }
+
+        refs
+    }
+
+    /// Id of the conversation `event` belongs to, which is its own id when
+    /// it isn't a reply
+    pub fn root_id(event: &EventType) -> Option<String> {
+        match ThreadRefs::from_event(event).root {
+            Some(root) => Some(root.id),
+            None => event.id().map(str::to_string),
+        }
+    }
+}
+
+/// Builds the unsigned reply of `pubkey` to `parent`, tagging the root and
+/// the parent with their markers and every participant of the thread
+pub fn reply(parent: &EventType, pubkey: &str, content: String) -> EventType {
+    let mut event = EventType::new(pubkey, TEXT_NOTE_KIND, content);
+    let parent_id = parent.id().unwrap_or_default().to_string();
+    let parent_refs = ThreadRefs::from_event(parent);
+
+    match parent_refs.root {
+        Some(root) => {
+            event.tags.push(vec![
+                "e".to_string(),
+                root.id,
+                root.relay.unwrap_or_default(),
+                "root".to_string(),
+                root.pubkey.unwrap_or_default(),
+            ]);
+            event.tags.push(vec![
+                "e".to_string(),
+                parent_id,
+                String::new(),
+                "reply".to_string(),
+                parent.pubkey(),
+            ]);
+        }
+        None => {
+            event.tags.push(vec![
+                "e".to_string(),
+                parent_id,
+                String::new(),
+                "root".to_string(),
+                parent.pubkey(),
+            ]);
+        }
+    }
+
+    let mut participants = vec![parent.pubkey()];
+    participants.extend(parent.tag_values("p").map(str::to_string));
+    let mut tagged = HashSet::new();
+    for participant in participants {
+        if participant != pubkey && tagged.insert(participant.clone()) {
+            event.add_tag("p", &participant, None);
+        }
+    }
+
+    event
+}
+
+/// The events of a conversation, arranged as a tree under its root
+#[derive(Debug, Clone)]
+pub struct Thread {
+    pub root_id: String,
+    events: HashMap<String, EventType>,
+}
+
+impl Thread {
+    pub fn new(root_id: &str) -> Thread {
+        Thread {
+            root_id: root_id.to_string(),
+            events: HashMap::new(),
+        }
+    }
+
+    /// Adds `event` to the thread if it belongs to it, returning whether it
+    /// was added
+    pub fn insert(&mut self, event: EventType) -> bool {
+        let Some(id) = event.id().map(str::to_string) else {
+            return false;
+        };
+        if !self.belongs(&event) {
+            return false;
+        }
+
+        self.events.insert(id, event);
+        true
+    }
+
+    /// Adds the events that belong to the thread. Replies may come before
+    /// their parents, so this keeps going until nothing else fits.
+    pub fn extend(&mut self, events: impl IntoIterator<Item = EventType>) {
+        let mut pending: Vec<EventType> = events.into_iter().collect();
+        loop {
+            let before = pending.len();
+            pending.retain(|event| !self.insert(event.clone()));
+            if pending.len() == before {
+                break;
+            }
+        }
+    }
+
+    /// Whether `event` is the root of the thread, or a reply within it
+    pub fn belongs(&self, event: &EventType) -> bool {
+        if event.id() == Some(self.root_id.as_str()) {
+            return true;
+        }
+
+        let refs = ThreadRefs::from_event(event);
+        refs.root.is_some_and(|root| root.id == self.root_id)
+            || refs
+                .reply
+                .is_some_and(|reply| self.events.contains_key(&reply.id))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&EventType> {
+        self.events.get(id)
+    }
+
+    /// The id of the event `event` replies to within the thread
+    fn parent_id(&self, event: &EventType) -> Option<String> {
+        if event.id() == Some(self.root_id.as_str()) {
+            return None;
+        }
+
+        ThreadRefs::from_event(event).reply.map(|reply| reply.id)
+    }
+
+    /// Ids of the events that replies point to but aren't in the thread yet
+    pub fn missing_ancestors(&self) -> Vec<String> {
+        let mut missing: HashSet<String> = HashSet::new();
+        if !self.events.contains_key(&self.root_id) {
+            missing.insert(self.root_id.clone());
+        }
+        for event in self.events.values() {
+            if let Some(parent) = self.parent_id(event) {
+                if !self.events.contains_key(&parent) {
+                    missing.insert(parent);
+                }
+            }
+        }
+
+        missing.into_iter().collect()
+    }
+
+    /// The events of the thread in reading order, each with its depth in the
+    /// tree. Replies come right after their parent, oldest first. Replies
+    /// whose parent is missing are shown under the root.
+    pub fn flatten(&self) -> Vec<(usize, &EventType)> {
+        let mut children: HashMap<Option<String>, Vec<&EventType>> = HashMap::new();
+        for event in self.events.values() {
+            let parent = match self.parent_id(event) {
+                Some(parent) if self.events.contains_key(&parent) => Some(parent),
+                Some(_) => Some(self.root_id.clone()),
+                None => None,
+            };
+            children.entry(parent).or_default().push(event);
+        }
+        for replies in children.values_mut() {
+            replies.sort_by_key(|event| event.created_at);
+        }
+
+        let mut flattened = Vec::new();
+        let mut stack: Vec<(usize, &EventType)> = Vec::new();
+        // Without the root, its direct replies are the top of the tree
+        let top = match self.events.get(&self.root_id) {
+            Some(root) => vec![root],
+            None => children
+                .get(&Some(self.root_id.clone()))
+                .cloned()
+                .unwrap_or_default(),
+        };
+        stack.extend(top.into_iter().rev().map(|event| (0, event)));
+
+        while let Some((depth, event)) = stack.pop() {
+            flattened.push((depth, event));
+
+            let id = event.id().map(str::to_string);
+            if let Some(replies) = children.get(&id) {
+                stack.extend(replies.iter().rev().map(|reply| (depth + 1, *reply)));
+            }
+        }
+
+        flattened
+    }
+
+    /// Fetches the conversation started by `root_id`: the root itself, the
+    /// replies to it, and any ancestor the replies point to that wasn't
+    /// found along with them. `relays` are asked on top of the bootstrap
+    /// relays and the inbox relays of the participants.
+    pub async fn fetch(pool: &mut RelayPool, root_id: &str, relays: &[String]) -> Thread {
+        let mut thread = Thread::new(root_id);
+        let mut relays: Vec<String> = relays.to_vec();
+        relays.extend(pool.bootstrap_relays().iter().cloned());
+
+        let filters = vec![
+            Filter::new().ids(vec![root_id.to_string()]),
+            Filter::new()
+                .kinds(vec![TEXT_NOTE_KIND])
+                .tag('e', vec![root_id.to_string()]),
+        ];
+        let mut events = pool.fetch_from(&relays, filters).await;
+
+        for _ in 0..MAX_FETCH_ROUNDS {
+            // Replies reach the inbox relays of the users they mention
+            if let Some(root) = events.iter().find(|event| event.id() == Some(root_id)) {
+                let author = root.pubkey();
+                pool.load_relay_lists(std::slice::from_ref(&author)).await;
+                relays.extend(pool.inbox_relays(&author));
+            }
+
+            thread.extend(events);
+
+            let missing = thread.missing_ancestors();
+            if missing.is_empty() {
+                break;
+            }
+            events = pool
+                .fetch_from(&relays, vec![Filter::new().ids(missing)])
+                .await;
+            if events.is_empty() {
+                break;
+            }
+        }
+
+        thread
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::event::pubkey_from_secret;
+    use crate::nostr_client::testing::{keys, signed};
+    use secp256k1::Secp256k1;
+
+    /// A note of `secret_key` published at `created_at`
+    fn note(secret_key: &str, created_at: u64, tags: Vec<Vec<&str>>) -> EventType {
+        let secp = Secp256k1::new();
+        let pubkey = pubkey_from_secret(secret_key, &secp).unwrap();
+        let mut event = EventType::new(&pubkey, TEXT_NOTE_KIND, "note".to_string());
+        event.created_at = created_at;
+        event.tags = tags
+            .into_iter()
+            .map(|tag| tag.into_iter().map(str::to_string).collect())
+            .collect();
+        event.setup(secret_key, &secp).unwrap();
+
+        event
+    }
+
+    fn ids(flattened: &[(usize, &EventType)]) -> Vec<(usize, String)> {
+        flattened
+            .iter()
+            .map(|(depth, event)| (*depth, event.id().unwrap().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_marked_tags() {
+        let (secret_key, _) = keys();
+        let event = signed(
+            &secret_key,
+            TEXT_NOTE_KIND,
+            "",
+            vec![
+                vec!["e", "mention", ""],
+                vec!["e", "parent", "wss://relay.example.com", "reply", "author"],
+                vec!["e", "root", "", "root"],
+            ],
+        );
+
+        let refs = ThreadRefs::from_event(&event);
+        assert_eq!(refs.root.unwrap().id, "root");
+        let reply = refs.reply.unwrap();
+        assert_eq!(reply.id, "parent");
+        assert_eq!(reply.relay.as_deref(), Some("wss://relay.example.com"));
+        assert_eq!(reply.pubkey.as_deref(), Some("author"));
+        assert_eq!(refs.mentions.len(), 1);
+        assert_eq!(refs.mentions[0].id, "mention");
+        assert_eq!(ThreadRefs::root_id(&event).as_deref(), Some("root"));
+    }
+
+    #[test]
+    fn reads_positional_tags() {
+        let (secret_key, _) = keys();
+        let event = signed(
+            &secret_key,
+            TEXT_NOTE_KIND,
+            "",
+            vec![vec!["e", "root"], vec!["e", "mention"], vec!["e", "parent"]],
+        );
+        let refs = ThreadRefs::from_event(&event);
+        assert_eq!(refs.root.unwrap().id, "root");
+        assert_eq!(refs.reply.unwrap().id, "parent");
+        assert_eq!(refs.mentions[0].id, "mention");
+
+        // A single tag is both the root and the parent
+        let event = signed(&secret_key, TEXT_NOTE_KIND, "", vec![vec!["e", "root"]]);
+        let refs = ThreadRefs::from_event(&event);
+        assert_eq!(refs.root, refs.reply);
+        assert!(refs.mentions.is_empty());
+
+        // Notes that reply to nothing are their own root
+        let event = signed(&secret_key, TEXT_NOTE_KIND, "", vec![]);
+        assert_eq!(ThreadRefs::from_event(&event), ThreadRefs::default());
+        assert_eq!(ThreadRefs::root_id(&event).as_deref(), event.id());
+    }
+
+    #[test]
+    fn tags_the_root_the_parent_and_the_participants() {
+        let (alice, alice_pubkey) = keys();
+        let (bob, bob_pubkey) = keys();
+        let (_, carol_pubkey) = keys();
+
+        let root = note(&alice, 10, vec![]);
+        let reply_to_root = reply(&root, &bob_pubkey, "hi".to_string());
+        assert_eq!(
+            reply_to_root.tags,
+            vec![
+                vec!["e", root.id().unwrap(), "", "root", &alice_pubkey],
+                vec!["p", &alice_pubkey],
+            ]
+        );
+
+        let parent = note(
+            &bob,
+            20,
+            vec![
+                vec![
+                    "e",
+                    root.id().unwrap(),
+                    "wss://relay.example.com",
+                    "root",
+                    &alice_pubkey,
+                ],
+                vec!["p", &alice_pubkey],
+                vec!["p", &carol_pubkey],
+                vec!["p", &carol_pubkey],
+            ],
+        );
+        let answer = reply(&parent, &alice_pubkey, "hello".to_string());
+        let refs = ThreadRefs::from_event(&answer);
+        let answer_root = refs.root.unwrap();
+        assert_eq!(answer_root.id, root.id().unwrap());
+        assert_eq!(
+            answer_root.relay.as_deref(),
+            Some("wss://relay.example.com")
+        );
+        assert_eq!(refs.reply.unwrap().id, parent.id().unwrap());
+        // The author of the reply isn't tagged, and no one is tagged twice
+        assert_eq!(
+            answer.tag_values("p").collect::<Vec<_>>(),
+            vec![bob_pubkey.as_str(), carol_pubkey.as_str()]
+        );
+    }
+
+    #[test]
+    fn flattens_in_reading_order() {
+        let (secret_key, _) = keys();
+        let root = note(&secret_key, 10, vec![]);
+        let root_id = root.id().unwrap().to_string();
+        let later = note(&secret_key, 30, vec![vec!["e", &root_id, "", "root"]]);
+        let earlier = note(&secret_key, 20, vec![vec!["e", &root_id, "", "root"]]);
+        let nested = note(
+            &secret_key,
+            40,
+            vec![
+                vec!["e", &root_id, "", "root"],
+                vec!["e", later.id().unwrap(), "", "reply"],
+            ],
+        );
+        let orphan = note(
+            &secret_key,
+            50,
+            vec![
+                vec!["e", &root_id, "", "root"],
+                vec!["e", "gone", "", "reply"],
+            ],
+        );
+        let unrelated = note(&secret_key, 60, vec![vec!["e", "other", "", "root"]]);
+
+        // Replies may arrive before what they answer
+        let mut thread = Thread::new(&root_id);
+        thread.extend(vec![
+            nested.clone(),
+            orphan.clone(),
+            later.clone(),
+            unrelated,
+            earlier.clone(),
+        ]);
+        assert_eq!(thread.missing_ancestors().len(), 2);
+        assert!(thread.missing_ancestors().contains(&"gone".to_string()));
+
+        // Without the root, its replies are the top of the tree
+        let expected = |offset: usize| {
+            [(0, &earlier), (0, &later), (1, &nested), (0, &orphan)]
+                .iter()
+                .map(|(depth, event)| (depth + offset, event.id().unwrap().to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&thread.flatten()), expected(0));
+
+        thread.insert(root);
+        let mut with_root = vec![(0, root_id.clone())];
+        with_root.extend(expected(1));
+        assert_eq!(ids(&thread.flatten()), with_root);
+        assert_eq!(thread.missing_ancestors(), vec!["gone".to_string()]);
+    }
+}
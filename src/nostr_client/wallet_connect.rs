@@ -0,0 +1,533 @@
+use std::{error::Error, fmt, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Result};
+use secp256k1::{All, Secp256k1};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::time::timeout;
+use url::form_urlencoded;
+
+use super::encryption;
+use super::event::{pubkey_from_secret, unix_time, Event as EventType};
+use super::nostr_connect::parse_uri;
+use super::pool::{any_accepted, RelayPool};
+use super::request::Filter;
+use super::response::Response as RelayResponse;
+
+/// Replaceable event in which a wallet service lists what it can do, as
+/// described by NIP-47
+pub const WALLET_INFO_KIND: u64 = 13194;
+
+/// Kind of the encrypted requests sent to a wallet service
+pub const WALLET_REQUEST_KIND: u64 = 23194;
+
+/// Kind of the encrypted responses of a wallet service
+pub const WALLET_RESPONSE_KIND: u64 = 23195;
+
+/// How long a request waits for its response. Paying goes through the
+/// lightning network first.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Name of the NIP-44 encryption in the `encryption` tags
+const NIP44_ENCRYPTION: &str = "nip44_v2";
+
+/// A `nostr+walletconnect://` URI, which a wallet service hands out for a
+/// client to use the wallet through it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletConnectUri {
+    pub wallet_pubkey: String,
+    pub relays: Vec<String>,
+    /// Hex-encoded secret key the client talks to the wallet service with
+    pub secret: String,
+    /// Lightning address of the wallet, if it has one
+    pub lud16: Option<String>,
+}
+
+impl FromStr for WalletConnectUri {
+    type Err = anyhow::Error;
+
+    fn from_str(uri: &str) -> Result<WalletConnectUri> {
+        let (wallet_pubkey, query) = parse_uri(uri, "nostr+walletconnect")?;
+        let secret = query
+            .value("secret")
+            .ok_or(anyhow!("The URI has no secret"))?;
+        pubkey_from_secret(&secret, &Secp256k1::new())
+            .map_err(|_| anyhow!("The secret of the URI is not a valid key"))?;
+
+        Ok(WalletConnectUri {
+            wallet_pubkey,
+            relays: query.values("relay"),
+            secret,
+            lud16: query.value("lud16"),
+        })
+    }
+}
+
+impl fmt::Display for WalletConnectUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        for relay in &self.relays {
+            query.append_pair("relay", relay);
+        }
+        query.append_pair("secret", &self.secret);
+        if let Some(lud16) = &self.lud16 {
+            query.append_pair("lud16", lud16);
+        }
+
+        write!(
+            f,
+            "nostr+walletconnect://{}?{}",
+            self.wallet_pubkey,
+            query.finish()
+        )
+    }
+}
+
+/// Why a request got no answer in time from the wallet service, which
+/// may still have done what it was asked
+#[derive(Debug)]
+pub struct NoAnswer;
+
+impl fmt::Display for NoAnswer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The wallet didn't answer")
+    }
+}
+
+impl Error for NoAnswer {}
+
+/// A request to a wallet service
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WalletRequest {
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// The answer of a wallet service to a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WalletResponse {
+    pub result_type: String,
+    #[serde(default)]
+    pub error: Option<WalletError>,
+    #[serde(default)]
+    pub result: Option<Value>,
+}
+
+/// Why a wallet service couldn't do what it was asked, with a code like
+/// `INSUFFICIENT_BALANCE`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletError {
+    pub code: String,
+    #[serde(default)]
+    pub message: String,
+}
+
+/// A payment sent or received by the wallet, or an invoice it made
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transaction {
+    /// `incoming` or `outgoing`
+    #[serde(rename = "type", default)]
+    pub direction: String,
+    #[serde(default)]
+    pub invoice: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub preimage: Option<String>,
+    #[serde(default)]
+    pub payment_hash: String,
+    /// In millisatoshis
+    #[serde(default)]
+    pub amount: u64,
+    /// In millisatoshis
+    #[serde(default)]
+    pub fees_paid: u64,
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// When it was paid, if it was
+    #[serde(default)]
+    pub settled_at: Option<u64>,
+}
+
+impl Transaction {
+    pub fn is_incoming(&self) -> bool {
+        self.direction == "incoming"
+    }
+}
+
+/// Which transactions `list_transactions` asks for. Everything left out
+/// is up to the wallet service.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TransactionFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    /// Whether invoices that weren't paid are included
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub unpaid: bool,
+    /// `incoming` or `outgoing`, for only those
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub direction: Option<String>,
+}
+
+/// A connection to a NIP-47 wallet service, which pays and makes lightning
+/// invoices for the wallet it's tied to when asked to over relays
+pub struct Wallet {
+    uri: WalletConnectUri,
+    client_pubkey: String,
+    pool: RelayPool,
+    secp: Secp256k1<All>,
+    /// What the wallet service said it can do, like `pay_invoice`
+    pub methods: Vec<String>,
+    /// Whether the wallet service takes requests encrypted with NIP-44,
+    /// rather than NIP-04
+    nip44: bool,
+    /// How long a request waits for its response
+    timeout: Duration,
+}
+
+impl Wallet {
+    /// Starts listening at the relays of `uri` for responses, and reads
+    /// what the wallet service can do
+    pub async fn connect(uri: WalletConnectUri) -> Result<Wallet> {
+        let secp = Secp256k1::new();
+        let client_pubkey = pubkey_from_secret(&uri.secret, &secp)?;
+
+        let mut pool = RelayPool::new(uri.relays.clone());
+        let responses = Filter::new()
+            .kinds(vec![WALLET_RESPONSE_KIND])
+            .authors(vec![uri.wallet_pubkey.clone()])
+            .tag('p', vec![client_pubkey.clone()])
+            .since(unix_time());
+        if pool
+            .subscribe_to(&uri.relays, vec![responses])
+            .await
+            .is_empty()
+        {
+            return Err(anyhow!("Could not reach the relays of the wallet"));
+        }
+
+        let info = Filter::new()
+            .kinds(vec![WALLET_INFO_KIND])
+            .authors(vec![uri.wallet_pubkey.clone()]);
+        let info = pool
+            .fetch_from(&uri.relays, vec![info])
+            .await
+            .into_iter()
+            .max_by_key(|event| event.created_at);
+        let methods = info
+            .as_ref()
+            .map(|info| {
+                info.content
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let nip44 = info.as_ref().is_some_and(|info| {
+            info.tag_values("encryption")
+                .any(|encryption| encryption.split_whitespace().any(|e| e == NIP44_ENCRYPTION))
+        });
+
+        Ok(Wallet {
+            uri,
+            client_pubkey,
+            pool,
+            secp,
+            methods,
+            nip44,
+            timeout: REQUEST_TIMEOUT,
+        })
+    }
+
+    /// Lightning address of the wallet, if the URI gave one
+    pub fn lud16(&self) -> Option<&str> {
+        self.uri.lud16.as_deref()
+    }
+
+    /// Pays `invoice`, returning the preimage proving it was paid. Fails
+    /// with [`NoAnswer`] when it can't tell whether it was.
+    pub async fn pay_invoice(&mut self, invoice: &str) -> Result<String> {
+        let result = self
+            .call("pay_invoice", json!({ "invoice": invoice }))
+            .await?;
+
+        result["preimage"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or(anyhow!("The wallet sent no preimage"))
+    }
+
+    /// The balance of the wallet, in millisatoshis
+    pub async fn get_balance(&mut self) -> Result<u64> {
+        let result = self.call("get_balance", json!({})).await?;
+
+        result["balance"]
+            .as_u64()
+            .ok_or(anyhow!("The wallet sent no balance"))
+    }
+
+    /// Makes an invoice for `amount_msats` to be paid to the wallet
+    pub async fn make_invoice(
+        &mut self,
+        amount_msats: u64,
+        description: &str,
+        expiry: Option<u64>,
+    ) -> Result<Transaction> {
+        let mut params = json!({ "amount": amount_msats, "description": description });
+        if let Some(expiry) = expiry {
+            params["expiry"] = json!(expiry);
+        }
+        let result = self.call("make_invoice", params).await?;
+
+        Ok(serde_json::from_value(result)?)
+    }
+
+    /// The payments of the wallet that `filter` asks for, newest first
+    pub async fn list_transactions(
+        &mut self,
+        filter: &TransactionFilter,
+    ) -> Result<Vec<Transaction>> {
+        let result = self
+            .call("list_transactions", serde_json::to_value(filter)?)
+            .await?;
+
+        Ok(serde_json::from_value(result["transactions"].clone())?)
+    }
+
+    /// Sends a request to the wallet service and waits for its result
+    async fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        // Services that didn't say what they can do are asked anyway
+        if !self.methods.is_empty() && !self.methods.iter().any(|known| known == method) {
+            return Err(anyhow!("The wallet doesn't support {}", method));
+        }
+
+        let request = WalletRequest {
+            method: method.to_string(),
+            params,
+        };
+        let json = serde_json::to_string(&request)?;
+        let wallet_pubkey = self.uri.wallet_pubkey.clone();
+
+        let content = match self.nip44 {
+            true => encryption::nip44_encrypt(&self.uri.secret, &wallet_pubkey, &json)?,
+            false => encryption::nip04_encrypt(&self.uri.secret, &wallet_pubkey, &json)?,
+        };
+        let mut event = EventType::new(&self.client_pubkey, WALLET_REQUEST_KIND, content);
+        event.add_tag("p", &wallet_pubkey, None);
+        if self.nip44 {
+            event.add_tag("encryption", NIP44_ENCRYPTION, None);
+        }
+        event.setup(&self.uri.secret, &self.secp)?;
+        let request_id = event.id().unwrap_or_default().to_string();
+
+        let relays = self.uri.relays.clone();
+        if !any_accepted(&self.pool.publish_to(&relays, &event).await) {
+            return Err(anyhow!("No relay accepted the request to the wallet"));
+        }
+
+        let waiting = self.timeout;
+        let wait = async {
+            loop {
+                let response = self.next_response(&request_id).await;
+                if response.result_type != method {
+                    continue;
+                }
+
+                return match (response.error, response.result) {
+                    (Some(error), _) => Err(anyhow!(
+                        "The wallet refused to {}: {} ({})",
+                        method,
+                        error.message,
+                        error.code
+                    )),
+                    (None, Some(result)) => Ok(result),
+                    (None, None) => Err(anyhow!("The wallet sent no result")),
+                };
+            }
+        };
+        timeout(waiting, wait).await.map_err(|_| NoAnswer)?
+    }
+
+    /// Waits for the response to the request with id `request_id`,
+    /// skipping whatever can't be read
+    async fn next_response(&mut self, request_id: &str) -> WalletResponse {
+        loop {
+            let (_, RelayResponse::Event { event, .. }) = self.pool.next_response().await else {
+                continue;
+            };
+            let answers = event.kind == WALLET_RESPONSE_KIND
+                && event.pubkey() == self.uri.wallet_pubkey
+                && event.tag_values("e").any(|id| id == request_id);
+            if !answers {
+                continue;
+            }
+
+            let json = encryption::decrypt(&self.uri.secret, &event.pubkey(), &event.content);
+            if let Some(response) = json.ok().and_then(|json| serde_json::from_str(&json).ok()) {
+                return response;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::task::JoinHandle;
+
+    use super::*;
+    use crate::nostr_client::testing::{keys, signed, TestRelay};
+
+    /// Answers the requests sent to the wallet service with `secret_key` at
+    /// `relay` with what `answer` returns, if anything. Each answer follows
+    /// one to another request and one from another key, which clients have
+    /// to skip.
+    fn serve(
+        secret_key: String,
+        relay: String,
+        answer: fn(&WalletRequest) -> Option<Value>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let pubkey = pubkey_from_secret(&secret_key, &Secp256k1::new()).unwrap();
+            let (impostor, _) = keys();
+            let mut pool = RelayPool::new(vec![relay.clone()]);
+            let requests = Filter::new()
+                .kinds(vec![WALLET_REQUEST_KIND])
+                .tag('p', vec![pubkey.clone()]);
+            pool.subscribe_to(std::slice::from_ref(&relay), vec![requests])
+                .await;
+
+            loop {
+                let (_, RelayResponse::Event { event, .. }) = pool.next_response().await else {
+                    continue;
+                };
+                let client = event.pubkey();
+                let json = encryption::decrypt(&secret_key, &client, &event.content).unwrap();
+                let request: WalletRequest = serde_json::from_str(&json).unwrap();
+                let Some(result) = answer(&request) else {
+                    continue;
+                };
+
+                let respond = |secret_key: &str, request_id: &str, result: Value| {
+                    let response = json!({ "result_type": request.method, "result": result });
+                    let content =
+                        encryption::nip44_encrypt(secret_key, &client, &response.to_string())
+                            .unwrap();
+                    signed(
+                        secret_key,
+                        WALLET_RESPONSE_KIND,
+                        &content,
+                        vec![vec!["e", request_id], vec!["p", &client]],
+                    )
+                };
+                let request_id = event.id().unwrap();
+                let other_request = "0".repeat(64);
+                for response in [
+                    respond(&secret_key, &other_request, json!({})),
+                    respond(&impostor, request_id, json!({})),
+                    respond(&secret_key, request_id, result),
+                ] {
+                    pool.publish_to(std::slice::from_ref(&relay), &response)
+                        .await;
+                }
+            }
+        })
+    }
+
+    /// A wallet service at `relay` answering with `answer`, and the URI to
+    /// reach it
+    fn wallet_service(
+        relay: &TestRelay,
+        answer: fn(&WalletRequest) -> Option<Value>,
+    ) -> (WalletConnectUri, JoinHandle<()>) {
+        let (wallet_secret_key, wallet_pubkey) = keys();
+        relay.publish(signed(
+            &wallet_secret_key,
+            WALLET_INFO_KIND,
+            "pay_invoice get_balance",
+            vec![vec!["encryption", "nip44_v2 nip04"]],
+        ));
+        let service = serve(wallet_secret_key, relay.url.clone(), answer);
+
+        let (client_secret_key, _) = keys();
+        let uri = WalletConnectUri {
+            wallet_pubkey,
+            relays: vec![relay.url.clone()],
+            secret: client_secret_key,
+            lud16: None,
+        };
+        (uri, service)
+    }
+
+    #[test]
+    fn reads_uris() {
+        let (secret_key, pubkey) = keys();
+        let uri: WalletConnectUri = format!(
+            "nostr+walletconnect://{}?relay=wss%3A%2F%2Frelay.example.com&secret={}&lud16=user%40wallet.example",
+            pubkey, secret_key
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(uri.relays, vec!["wss://relay.example.com"]);
+        assert_eq!(uri.lud16.as_deref(), Some("user@wallet.example"));
+        assert_eq!(uri.to_string().parse::<WalletConnectUri>().unwrap(), uri);
+
+        let without_secret = format!("nostr+walletconnect://{}?relay=wss://r.example", pubkey);
+        assert!(without_secret.parse::<WalletConnectUri>().is_err());
+    }
+
+    #[tokio::test]
+    async fn pays_and_reads_the_balance() {
+        let relay = TestRelay::start().await;
+        let (uri, service) = wallet_service(&relay, |request| match request.method.as_str() {
+            "pay_invoice" if request.params["invoice"] == "lnbc210n1invoice" => {
+                Some(json!({ "preimage": "0123" }))
+            }
+            "get_balance" => Some(json!({ "balance": 21_000 })),
+            _ => None,
+        });
+
+        let mut wallet = Wallet::connect(uri).await.unwrap();
+        assert_eq!(wallet.methods, vec!["pay_invoice", "get_balance"]);
+        assert!(wallet.nip44);
+        assert_eq!(
+            wallet.pay_invoice("lnbc210n1invoice").await.unwrap(),
+            "0123"
+        );
+        assert_eq!(wallet.get_balance().await.unwrap(), 21_000);
+        let err = wallet.make_invoice(21_000, "", None).await.unwrap_err();
+        assert!(err.to_string().contains("make_invoice"), "{}", err);
+
+        // Requests are encrypted as the wallet service said it reads them
+        let sent = relay
+            .events()
+            .into_iter()
+            .filter(|event| event.kind == WALLET_REQUEST_KIND)
+            .collect::<Vec<_>>();
+        assert_eq!(sent.len(), 2);
+        assert!(sent
+            .iter()
+            .all(|event| event.tag_values("encryption").next() == Some(NIP44_ENCRYPTION)));
+        service.abort();
+    }
+
+    #[tokio::test]
+    async fn tells_when_payments_got_no_answer() {
+        let relay = TestRelay::start().await;
+        let (uri, service) = wallet_service(&relay, |_| None);
+
+        let mut wallet = Wallet::connect(uri).await.unwrap();
+        wallet.timeout = Duration::from_millis(300);
+        let err = wallet.pay_invoice("lnbc210n1invoice").await.unwrap_err();
+        assert!(err.is::<NoAnswer>());
+        service.abort();
+    }
+}
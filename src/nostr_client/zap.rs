@@ -0,0 +1,536 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use bech32::{Bech32, Hrp};
+use secp256k1::{Secp256k1, Verification};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::bolt11::Invoice;
+use super::event::{unix_time, Event as EventType};
+use super::profile::Profile;
+
+/// Event the sender of a zap signs and hands to the recipient's lightning
+/// service along with the payment, described by NIP-57
+pub const ZAP_REQUEST_KIND: u64 = 9734;
+
+/// Event the lightning service of the recipient publishes once a zap is
+/// paid, holding the zap request
+pub const ZAP_RECEIPT_KIND: u64 = 9735;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a kind 9734 zap request asks for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZapRequest {
+    /// The user zapping
+    pub sender: String,
+    /// The user zapped
+    pub recipient: String,
+    /// The event zapped, if it's not the profile
+    pub target_id: Option<String>,
+    /// The coordinate of the addressable event zapped, if any
+    pub target_address: Option<String>,
+    /// How much the sender means to pay, if they said
+    pub amount_msats: Option<u64>,
+    /// Relays the receipt should be published to
+    pub relays: Vec<String>,
+    /// What the sender says along with the zap
+    pub comment: String,
+}
+
+impl ZapRequest {
+    pub fn from_event(event: &EventType) -> Result<ZapRequest> {
+        if event.kind != ZAP_REQUEST_KIND {
+            return Err(anyhow!("Event of kind {} is not a zap request", event.kind));
+        }
+
+        let mut recipients = event.tag_values("p");
+        let recipient = recipients
+            .next()
+            .ok_or(anyhow!("Zap request without a `p` tag"))?
+            .to_string();
+        if recipients.next().is_some() {
+            return Err(anyhow!("Zap request with more than one `p` tag"));
+        }
+
+        let amount_msats = match event.tag_values("amount").next() {
+            Some(amount) => Some(
+                amount
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid amount {}", amount))?,
+            ),
+            None => None,
+        };
+
+        Ok(ZapRequest {
+            sender: event.pubkey(),
+            recipient,
+            target_id: event.tag_values("e").next().map(str::to_string),
+            target_address: event.tag_values("a").next().map(str::to_string),
+            amount_msats,
+            relays: event
+                .tags_named("relays")
+                .flat_map(|tag| tag.iter().skip(1).cloned())
+                .collect(),
+            comment: event.content.clone(),
+        })
+    }
+}
+
+/// A zap, read from its kind 9735 receipt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zap {
+    /// The pubkey of the lightning service that published the receipt
+    pub provider: String,
+    pub request: ZapRequest,
+    /// How much was paid, as the invoice says
+    pub amount_msats: u64,
+}
+
+impl Zap {
+    /// Reads a zap receipt, checking it against the zap request it holds:
+    /// the request has to be signed, and to match the signed invoice paid
+    /// and what the receipt says was zapped.
+    ///
+    /// Only the `nostrPubkey` of the recipient's lightning service can tell
+    /// whether it published the receipt, which takes fetching it, see
+    /// [`LnurlPay`]. The pubkey is kept in `provider` for that, see
+    /// [`ZapTotals::set_provider`].
+    pub fn from_receipt<C: Verification>(event: &EventType, secp: &Secp256k1<C>) -> Result<Zap> {
+        if event.kind != ZAP_RECEIPT_KIND {
+            return Err(anyhow!("Event of kind {} is not a zap receipt", event.kind));
+        }
+
+        let description = event
+            .tag_values("description")
+            .next()
+            .ok_or(anyhow!("Zap receipt without a `description` tag"))?;
+        let request_event: EventType = serde_json::from_str(description)?;
+        request_event
+            .verify(secp)
+            .map_err(|_| anyhow!("The zap request isn't signed"))?;
+        let request = ZapRequest::from_event(&request_event)?;
+
+        let invoice: Invoice = event
+            .tag_values("bolt11")
+            .next()
+            .ok_or(anyhow!("Zap receipt without a `bolt11` tag"))?
+            .parse()?;
+        let hash = hex::encode(Sha256::digest(description.as_bytes()));
+        if invoice.description_hash != Some(hash) {
+            return Err(anyhow!("The invoice wasn't made for the zap request"));
+        }
+        let amount_msats = invoice
+            .amount_msats
+            .ok_or(anyhow!("The invoice has no amount"))?;
+        if request
+            .amount_msats
+            .is_some_and(|amount| amount != amount_msats)
+        {
+            return Err(anyhow!("The invoice doesn't pay what was requested"));
+        }
+
+        if event.tag_values("p").next() != Some(request.recipient.as_str()) {
+            return Err(anyhow!("The receipt isn't for the user zapped"));
+        }
+        if let Some(target_id) = &request.target_id {
+            if event.tag_values("e").next() != Some(target_id.as_str()) {
+                return Err(anyhow!("The receipt isn't for the event zapped"));
+            }
+        }
+
+        Ok(Zap {
+            provider: event.pubkey(),
+            request,
+            amount_msats,
+        })
+    }
+}
+
+/// Builds the unsigned zap request of `pubkey` to `recipient`, for `target`
+/// if it's an event rather than their profile. `lnurl` is the bech32 form
+/// of the recipient's pay endpoint, see [`encode_lnurl`].
+pub fn zap_request(
+    pubkey: &str,
+    recipient: &str,
+    target: Option<&EventType>,
+    amount_msats: u64,
+    relays: &[String],
+    lnurl: &str,
+    comment: &str,
+) -> EventType {
+    let mut event = EventType::new(pubkey, ZAP_REQUEST_KIND, comment.to_string());
+
+    let mut relays_tag = vec!["relays".to_string()];
+    relays_tag.extend(relays.iter().cloned());
+    event.tags.push(relays_tag);
+    event.add_tag("amount", &amount_msats.to_string(), None);
+    event.add_tag("lnurl", lnurl, None);
+    event.add_tag("p", recipient, None);
+    if let Some(target) = target {
+        event.add_tag("e", target.id().unwrap_or_default(), None);
+        if let (30000..=39999, Some(address)) = (target.kind, target.address()) {
+            event.add_tag("a", &address, None);
+        }
+        event.add_tag("k", &target.kind.to_string(), None);
+    }
+
+    event
+}
+
+/// Zaps received so far, summed per event and per user zapped. Zaps only
+/// count once the lightning service of the user zapped is known to have
+/// published their receipt.
+#[derive(Debug, Default)]
+pub struct ZapTotals {
+    /// How many millisatoshis every event got, from how many zaps
+    events: HashMap<String, (u64, usize)>,
+    /// How many millisatoshis every user got, from how many zaps
+    users: HashMap<String, (u64, usize)>,
+    /// Receipts already read, since relays send them again
+    seen: HashSet<String>,
+    /// The pubkey the lightning service of every user publishes receipts
+    /// with, once known. Users without one can't be zapped.
+    providers: HashMap<String, Option<String>>,
+    /// Zaps waiting for the lightning service of the user zapped to be
+    /// known, by user
+    unverified: HashMap<String, Vec<Zap>>,
+}
+
+impl ZapTotals {
+    pub fn new() -> ZapTotals {
+        ZapTotals::default()
+    }
+
+    /// Counts `event` if it's a valid zap receipt that wasn't counted yet,
+    /// published by the lightning service of the user zapped, returning
+    /// whether it was. Until that service is known, the zap waits for
+    /// [`ZapTotals::set_provider`].
+    pub fn insert<C: Verification>(&mut self, event: &EventType, secp: &Secp256k1<C>) -> bool {
+        let Some(id) = event.id() else {
+            return false;
+        };
+        if self.seen.contains(id) {
+            return false;
+        }
+        let Ok(zap) = Zap::from_receipt(event, secp) else {
+            return false;
+        };
+        self.seen.insert(id.to_string());
+
+        match self.providers.get(&zap.request.recipient) {
+            Some(provider) if provider.as_ref() == Some(&zap.provider) => {
+                self.count(&zap);
+                true
+            }
+            Some(_) => false,
+            None => {
+                let recipient = zap.request.recipient.clone();
+                self.unverified.entry(recipient).or_default().push(zap);
+                false
+            }
+        }
+    }
+
+    /// The users zapped whose lightning service has to be known for the
+    /// zaps to them to count
+    pub fn unverified(&self) -> impl Iterator<Item = &String> {
+        self.unverified.keys()
+    }
+
+    /// Takes `provider` as the pubkey the lightning service of `pubkey`
+    /// publishes receipts with, or none if they can't be zapped, counting
+    /// the zaps to them it published. Returns whether any was.
+    pub fn set_provider(&mut self, pubkey: &str, provider: Option<String>) -> bool {
+        let mut counted = false;
+        for zap in self.unverified.remove(pubkey).unwrap_or_default() {
+            if provider.as_ref() == Some(&zap.provider) {
+                self.count(&zap);
+                counted = true;
+            }
+        }
+        self.providers.insert(pubkey.to_string(), provider);
+
+        counted
+    }
+
+    fn count(&mut self, zap: &Zap) {
+        let add = |total: &mut (u64, usize)| {
+            total.0 += zap.amount_msats;
+            total.1 += 1;
+        };
+        if let Some(target_id) = &zap.request.target_id {
+            add(self.events.entry(target_id.clone()).or_default());
+        }
+        add(self.users.entry(zap.request.recipient.clone()).or_default());
+    }
+
+    /// How many millisatoshis `event_id` was zapped, and how many times
+    pub fn of_event(&self, event_id: &str) -> (u64, usize) {
+        self.events.get(event_id).copied().unwrap_or_default()
+    }
+
+    /// How many millisatoshis `pubkey` was zapped, on their profile and
+    /// their events, and how many times
+    pub fn of_user(&self, pubkey: &str) -> (u64, usize) {
+        self.users.get(pubkey).copied().unwrap_or_default()
+    }
+}
+
+/// The pay endpoint of a lightning service, as described by LUD-06, with
+/// the NIP-57 fields telling whether it takes zaps
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LnurlPay {
+    pub callback: String,
+    pub min_sendable: u64,
+    pub max_sendable: u64,
+    #[serde(default)]
+    pub allows_nostr: bool,
+    /// The pubkey the service publishes zap receipts with
+    #[serde(default)]
+    pub nostr_pubkey: Option<String>,
+    #[serde(default)]
+    pub comment_allowed: Option<usize>,
+    /// The url the endpoint was fetched from
+    #[serde(skip)]
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackResponse {
+    pr: Option<String>,
+    reason: Option<String>,
+}
+
+impl LnurlPay {
+    /// Fetches the pay endpoint at `url`, see [`pay_url`]
+    pub async fn fetch(url: &str) -> Result<LnurlPay> {
+        let mut pay: LnurlPay = http_client()
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        pay.url = url.to_string();
+
+        Ok(pay)
+    }
+
+    /// Whether the service publishes receipts for zaps
+    pub fn allows_zaps(&self) -> bool {
+        self.allows_nostr && self.nostr_pubkey.is_some()
+    }
+
+    /// The pubkey the service publishes zap receipts with, if it does
+    pub fn zap_provider(&self) -> Option<String> {
+        self.nostr_pubkey.clone().filter(|_| self.allows_nostr)
+    }
+
+    /// Asks the service for an invoice paying the zap described by the
+    /// signed `zap_request`, and checks it's for the amount requested
+    pub async fn invoice(&self, zap_request: &EventType) -> Result<Invoice> {
+        if !self.allows_zaps() {
+            return Err(anyhow!("The recipient's wallet doesn't take zaps"));
+        }
+        let amount_msats = ZapRequest::from_event(zap_request)?
+            .amount_msats
+            .ok_or(anyhow!("The zap request has no amount"))?;
+        if amount_msats < self.min_sendable || amount_msats > self.max_sendable {
+            return Err(anyhow!(
+                "The recipient's wallet takes between {} and {} sats",
+                self.min_sendable.div_ceil(1000),
+                self.max_sendable / 1000
+            ));
+        }
+
+        let response: CallbackResponse = http_client()
+            .get(&self.callback)
+            .query(&[
+                ("amount", amount_msats.to_string()),
+                ("nostr", serde_json::to_string(zap_request)?),
+                ("lnurl", encode_lnurl(&self.url)?),
+            ])
+            .send()
+            .await?
+            .json()
+            .await?;
+        let invoice: Invoice = match (response.pr, response.reason) {
+            (Some(pr), _) => pr.parse()?,
+            (None, Some(reason)) => return Err(anyhow!("The wallet refused: {}", reason)),
+            (None, None) => return Err(anyhow!("The wallet sent no invoice")),
+        };
+
+        if invoice.amount_msats != Some(amount_msats) {
+            return Err(anyhow!("The invoice isn't for the amount requested"));
+        }
+        if invoice.is_expired(unix_time()) {
+            return Err(anyhow!("The wallet sent an expired invoice"));
+        }
+        Ok(invoice)
+    }
+}
+
+/// The url of the pay endpoint of the user behind `profile`, read from their
+/// lightning address or, failing that, their LNURL
+pub fn pay_url(profile: &Profile) -> Result<String> {
+    if let Some(address) = &profile.lud16 {
+        let (name, domain) = address
+            .trim()
+            .split_once('@')
+            .ok_or(anyhow!("Invalid lightning address {}", address))?;
+        return Ok(format!("https://{}/.well-known/lnurlp/{}", domain, name));
+    }
+
+    if let Some(lnurl) = &profile.lud06 {
+        let lnurl = lnurl.trim().trim_start_matches("lightning:");
+        let (hrp, data) = bech32::decode(lnurl).map_err(|err| anyhow!("Invalid LNURL: {}", err))?;
+        if hrp.to_lowercase() != "lnurl" {
+            return Err(anyhow!("Invalid LNURL {}", lnurl));
+        }
+        return Ok(String::from_utf8(data)?);
+    }
+
+    Err(anyhow!("No lightning address"))
+}
+
+/// The bech32 form of `url`, as found in the `lnurl` tag of zap requests
+pub fn encode_lnurl(url: &str) -> Result<String> {
+    let hrp = Hrp::parse("lnurl")?;
+    Ok(bech32::encode::<Bech32>(hrp, url.as_bytes())?)
+}
+
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("Failed to build the http client")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_client::testing::{invoice, keys, signed};
+
+    const NOTE_ID: &str = "e8b487c079b0f67c695ae6c4c2552a47f38adfa2533cc5926bd2c102942fdcb7";
+
+    /// A zap request of 21 sats from `sender` to `recipient` for a note
+    fn zap_request(sender: &str, recipient: &str) -> String {
+        let request = signed(
+            sender,
+            ZAP_REQUEST_KIND,
+            "great note",
+            vec![
+                vec!["relays", "wss://relay.example"],
+                vec!["amount", "21000"],
+                vec!["p", recipient],
+                vec!["e", NOTE_ID],
+            ],
+        );
+        serde_json::to_string(&request).unwrap()
+    }
+
+    /// The receipt `provider` publishes for `description`, paid with
+    /// `bolt11`
+    fn receipt(provider: &str, recipient: &str, bolt11: &str, description: &str) -> EventType {
+        signed(
+            provider,
+            ZAP_RECEIPT_KIND,
+            "",
+            vec![
+                vec!["p", recipient],
+                vec!["e", NOTE_ID],
+                vec!["bolt11", bolt11],
+                vec!["description", description],
+            ],
+        )
+    }
+
+    /// A receipt from `provider` for a zap of 21 sats to `recipient`
+    fn paid(provider: &str, recipient: &str) -> EventType {
+        let (sender, _) = keys();
+        let (node, _) = keys();
+        let description = zap_request(&sender, recipient);
+        let hash = Sha256::digest(description.as_bytes()).to_vec();
+        let bolt11 = invoice(&node, 21_000, vec![('h', hash)]);
+        receipt(provider, recipient, &bolt11, &description)
+    }
+
+    #[test]
+    fn reads_receipts() {
+        let secp = Secp256k1::verification_only();
+        let (provider, provider_pubkey) = keys();
+        let (_, recipient) = keys();
+
+        let zap = Zap::from_receipt(&paid(&provider, &recipient), &secp).unwrap();
+        assert_eq!(zap.provider, provider_pubkey);
+        assert_eq!(zap.amount_msats, 21_000);
+        assert_eq!(zap.request.recipient, recipient);
+        assert_eq!(zap.request.target_id.as_deref(), Some(NOTE_ID));
+        assert_eq!(zap.request.comment, "great note");
+    }
+
+    #[test]
+    fn rejects_invoices_not_made_for_the_request() {
+        let secp = Secp256k1::verification_only();
+        let (provider, _) = keys();
+        let (sender, _) = keys();
+        let (node, _) = keys();
+        let (_, recipient) = keys();
+        let description = zap_request(&sender, &recipient);
+
+        let without_hash = invoice(&node, 21_000, Vec::new());
+        let receipt_without_hash = receipt(&provider, &recipient, &without_hash, &description);
+        assert!(Zap::from_receipt(&receipt_without_hash, &secp).is_err());
+
+        let other_hash = invoice(&node, 21_000, vec![('h', vec![1; 32])]);
+        let receipt_other_hash = receipt(&provider, &recipient, &other_hash, &description);
+        assert!(Zap::from_receipt(&receipt_other_hash, &secp).is_err());
+
+        let hash = Sha256::digest(description.as_bytes()).to_vec();
+        let other_amount = invoice(&node, 1_000, vec![('h', hash)]);
+        let receipt_other_amount = receipt(&provider, &recipient, &other_amount, &description);
+        assert!(Zap::from_receipt(&receipt_other_amount, &secp).is_err());
+    }
+
+    #[test]
+    fn counts_zaps_once_their_provider_is_known() {
+        let secp = Secp256k1::verification_only();
+        let (provider, provider_pubkey) = keys();
+        let (impostor, _) = keys();
+        let (_, recipient) = keys();
+        let mut zaps = ZapTotals::new();
+
+        let first = paid(&provider, &recipient);
+        assert!(!zaps.insert(&first, &secp));
+        assert!(!zaps.insert(&paid(&impostor, &recipient), &secp));
+        assert_eq!(zaps.unverified().collect::<Vec<_>>(), vec![&recipient]);
+        assert_eq!(zaps.of_event(NOTE_ID), (0, 0));
+
+        assert!(zaps.set_provider(&recipient, Some(provider_pubkey)));
+        assert_eq!(zaps.unverified().count(), 0);
+        assert_eq!(zaps.of_event(NOTE_ID), (21_000, 1));
+        assert_eq!(zaps.of_user(&recipient), (21_000, 1));
+
+        assert!(zaps.insert(&paid(&provider, &recipient), &secp));
+        assert!(!zaps.insert(&paid(&impostor, &recipient), &secp));
+        assert!(!zaps.insert(&first, &secp));
+        assert_eq!(zaps.of_event(NOTE_ID), (42_000, 2));
+    }
+
+    #[test]
+    fn does_not_count_zaps_to_users_without_a_provider() {
+        let secp = Secp256k1::verification_only();
+        let (provider, _) = keys();
+        let (_, recipient) = keys();
+        let mut zaps = ZapTotals::new();
+
+        zaps.insert(&paid(&provider, &recipient), &secp);
+        assert!(!zaps.set_provider(&recipient, None));
+        assert!(!zaps.insert(&paid(&provider, &recipient), &secp));
+        assert_eq!(zaps.of_user(&recipient), (0, 0));
+    }
+}
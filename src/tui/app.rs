@@ -1,79 +1,2364 @@
+use ratatui::widgets::ListState;
+use secp256k1::{Secp256k1, VerifyOnly};
+use std::collections::{HashMap, HashSet};
 use std::error;
-use crate::nostr_client::response::Response;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+use super::bunker::{BunkerCommand, BunkerEvent, BunkerState};
+use super::network::Command;
+use crate::nostr_client::article::Article;
+use crate::nostr_client::badge::{
+    self, BadgeAward, BadgeAwards, BadgeDefinition, BADGE_AWARD_KIND, PROFILE_BADGES_IDENTIFIER,
+    PROFILE_BADGES_KIND,
+};
+use crate::nostr_client::bunker::Decision;
+use crate::nostr_client::calendar::{
+    self, Calendar, CalendarEvent, Rsvp, RsvpStatus, CALENDAR_KIND, RSVP_KIND,
+};
+use crate::nostr_client::channel::{
+    self, ChannelMetadata, Channels, CHANNEL_CREATION_KIND, CHANNEL_HIDE_MESSAGE_KIND,
+    CHANNEL_MESSAGE_KIND, CHANNEL_METADATA_KIND, CHANNEL_MUTE_USER_KIND,
+};
+use crate::nostr_client::contact_list::{ContactList, CONTACT_LIST_KIND};
+use crate::nostr_client::content_warning;
+use crate::nostr_client::deletion::{self, Deletion, Deletions, DELETION_KIND};
+use crate::nostr_client::entity::Entity;
+use crate::nostr_client::event::{unix_time, Event as NostrEvent, TEXT_NOTE_KIND};
+use crate::nostr_client::expiration;
+use crate::nostr_client::file_metadata::{self, FileMetadata, FILE_METADATA_KIND};
+use crate::nostr_client::group::{self, GroupId, Groups};
+use crate::nostr_client::identifier::Identifier;
+use crate::nostr_client::label::{self, Label, Labels, LABEL_KIND, UGC_NAMESPACE};
+use crate::nostr_client::list::{
+    self, List, Mutes, BOOKMARKS_KIND, FOLLOW_SET_KIND, GROUPS_KIND, MUTE_LIST_KIND, PIN_LIST_KIND,
+};
+use crate::nostr_client::nostr_connect::ConnectUri;
+use crate::nostr_client::profile::{ProfileCache, METADATA_KIND};
+use crate::nostr_client::reaction::{self, ReactionContent, ReactionCounts, REACTION_KIND};
+use crate::nostr_client::reference;
+use crate::nostr_client::relay_list::normalize_url;
+use crate::nostr_client::report::{self, Report, ReportType, Reports, REPORT_KIND};
+use crate::nostr_client::repost::{self, Repost, GENERIC_REPOST_KIND, REPOST_KIND};
+use crate::nostr_client::thread::{self, Thread, ThreadRefs};
+use crate::nostr_client::wallet_connect::Transaction;
+use crate::nostr_client::zap::{self, ZapTotals, ZAP_RECEIPT_KIND};
 
 /// Application result type.
 pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
 
 const MAX_ITEMS_ON_SCREEN: usize = 5;
 
+/// What the main area of the screen shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum View {
+    /// Notes of the followed users.
+    Home,
+    /// Profile and notes of a single user.
+    Profile(String),
+    /// A conversation, by the id of the note that started it.
+    Thread(String),
+    /// A replaceable or addressable event, by its coordinate.
+    Address(String),
+    /// The notes a list points to, or the ones of the users it holds, by
+    /// the coordinate of the list.
+    List(String),
+    /// The apps the bunker signs for, and the requests they make.
+    Bunker,
+    /// The balance and latest payments of the wallet of the user.
+    Wallet,
+    /// The public chat channels found so far.
+    Channels,
+    /// The messages of a public chat channel, by the id of the channel.
+    Channel(String),
+    /// The messages of a NIP-29 group, next to the groups of the user.
+    Group(GroupId),
+    /// The badges awarded to the user, which they may show on their
+    /// profile.
+    Badges,
+    /// The upcoming calendar events of the user and the users they follow.
+    Agenda,
+}
+
+/// What the text typed into the input box is for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputPurpose {
+    /// Looking a user up by their NIP-05 identifier.
+    Lookup,
+    /// Writing a new note.
+    Note,
+    /// Opening a `nostr:` URI.
+    Open,
+    /// Replying to the note with the given id.
+    Reply(String),
+    /// Quoting the note with the given id.
+    Quote(String),
+    /// Confirming the deletion of the user's event with the given id, along
+    /// with a reason.
+    Delete(String),
+    /// Reacting with an emoji to the note with the given id.
+    React(String),
+    /// Publishing the article in the Markdown file at the typed path, or
+    /// saving it as a draft.
+    Article { draft: bool },
+    /// Choosing how long the notes written from now on last.
+    Expiration,
+    /// Muting a word or a hashtag, or unmuting it.
+    MuteWord,
+    /// Connecting the bunker to the app showing a `nostrconnect://` URI.
+    ConnectApp,
+    /// Zapping a user, or one of their notes by id.
+    Zap {
+        recipient: String,
+        target_id: Option<String>,
+    },
+    /// Making an invoice for the wallet of the user to get paid.
+    Receive,
+    /// Naming a new public chat channel.
+    CreateChannel,
+    /// Writing a message to the channel with the given id, in reply to the
+    /// message with the given id if any.
+    ChannelMessage {
+        channel_id: String,
+        reply_to: Option<String>,
+    },
+    /// Changing a field of the channel with the given id, which the user
+    /// created.
+    EditChannel(String),
+    /// Hiding the message with the given id from the user, along with a
+    /// reason.
+    HideMessage(String),
+    /// Opening a NIP-29 group by its `host'id`.
+    OpenGroup,
+    /// Writing a message to a group, in reply to the message with the given
+    /// id if any.
+    GroupMessage {
+        group: GroupId,
+        reply_to: Option<String>,
+    },
+    /// Asking to join a group, with an invite code if the user has one.
+    JoinGroup(GroupId),
+    /// Confirming the user leaves a group, along with a reason.
+    LeaveGroup(GroupId),
+    /// Reporting the note with the given id, or the user `pubkey` if there's
+    /// none, for what and why.
+    Report { id: Option<String>, pubkey: String },
+    /// Labeling the note with the given id, or the user `pubkey` if there's
+    /// none.
+    Label { id: Option<String>, pubkey: String },
+    /// Answering the calendar event with the given coordinate.
+    Rsvp(String),
+    /// Confirming a new contact list is started by following the given
+    /// user, since none of the user's could be found.
+    NewContactList(String),
+    /// Adding a relay to the relay list of the user, or removing it.
+    Relay,
+    /// Setting a field of the user's profile.
+    EditProfile,
+}
+
+impl InputPurpose {
+    /// Whether a note is being written, which may mention users and link
+    /// to uploaded files.
+    pub fn is_composing(&self) -> bool {
+        matches!(
+            self,
+            InputPurpose::Note | InputPurpose::Reply(_) | InputPurpose::Quote(_)
+        )
+    }
+}
+
+/// Text being typed by the user.
+#[derive(Debug)]
+pub struct InputBox {
+    pub purpose: InputPurpose,
+    pub text: String,
+    /// Files uploaded while writing a note, described in its `imeta` tags
+    /// if it still links to them.
+    pub attachments: Vec<FileMetadata>,
+}
+
 /// Application.
 #[derive(Debug)]
 pub struct App {
     /// Is the application running?
     pub running: bool,
-    /// Messages received from the relay
-    pub feed: Vec<Response>,
+    /// Notes received from the relays, newest first. Each view shows the
+    /// ones it is interested in.
+    pub feed: Vec<NostrEvent>,
 
     pub feed_capacity: usize,
-    pub current_min_index: usize,
-    pub current_max_index: usize,
+    /// Which note of the current view is selected
+    pub feed_state: ListState,
+    /// Views opened so far, the last one being on screen
+    pub views: Vec<View>,
+    /// The conversation shown by the current thread view
+    pub thread: Option<Thread>,
+    /// How many lines of the event shown by an address view were scrolled
+    /// past
+    pub reader_scroll: u16,
+
+    /// Public key of the user
+    pub pubkey: String,
+    /// Users followed by the user
+    pub contacts: ContactList,
+    /// Newest known profile of every author seen so far
+    pub profiles: ProfileCache,
+    /// NIP-05 identifiers that were checked against their domain, by pubkey
+    pub verified: HashMap<String, Identifier>,
+    /// Reactions to the notes, by note id
+    pub reactions: ReactionCounts,
+    /// Zaps received by notes and users
+    pub zaps: ZapTotals,
+    /// Users zapped whose lightning service was asked about, to check the
+    /// zaps to them
+    zap_providers_asked: HashSet<String>,
+    /// Reports about notes and users
+    pub reports: Reports,
+    /// Labels of notes and users
+    pub labels: Labels,
+    /// Badges awarded to users
+    pub badge_awards: BadgeAwards,
+    /// Lightning address of the wallet of the user, if it has one
+    pub wallet_address: Option<String>,
+    /// Balance of the wallet of the user, in millisatoshis, once known
+    pub wallet_balance: Option<u64>,
+    /// Latest payments of the wallet of the user, newest first
+    pub transactions: Vec<Transaction>,
+    /// Id of the note each repost in the feed points to
+    pub reposts: HashMap<String, String>,
+    /// Notes embedded in reposts, by id, once their signature was checked
+    pub reposted: HashMap<String, NostrEvent>,
+    /// Public chat channels and their messages
+    pub channels: Channels,
+    /// NIP-29 groups and their events
+    pub groups: Groups,
+    /// Events their authors asked to delete
+    pub deletions: Deletions,
+    /// Newest version of each addressable event received, by coordinate
+    pub addressable: HashMap<String, NostrEvent>,
+    /// Newest version of each list received, by coordinate. The ones of
+    /// the user include their private items.
+    pub lists: HashMap<String, List>,
+    /// Whether the lists of the user were fetched, so the ones missing from
+    /// `lists` can be started anew.
+    pub lists_fetched: bool,
+    /// What the mute list of the user hides
+    mutes: Mutes,
+    /// Events received that expire at some point, with when and their
+    /// author, so they can be dropped once they do
+    expiring: Vec<(u64, String, String)>,
+    /// Notes the user chose to read despite their content warning
+    pub expanded: HashSet<String>,
+    /// How many seconds the notes the user writes last, if they expire
+    pub note_expiration: Option<u64>,
+    /// What the bunker shows, when signing for other apps instead of
+    /// showing the feed
+    pub bunker: Option<BunkerState>,
 
     /// This is the place where the user may type in some data
     /// to send
-    pub input_box: Option<String>,
+    pub input_box: Option<InputBox>,
+    /// Message shown at the bottom of the screen
+    pub status: Option<String>,
+
+    /// Requests for the network task.
+    commands: mpsc::UnboundedSender<Command>,
+    /// Checks the signature of events embedded in others
+    secp: Secp256k1<VerifyOnly>,
 }
 
-impl Default for App {
-    fn default() -> Self {
+impl App {
+    /// Constructs a new instance of [`App`].
+    pub fn new(pubkey: &str, commands: mpsc::UnboundedSender<Command>) -> Self {
         Self {
             running: true,
             feed: Vec::new(),
-            feed_capacity: 20,
-            current_min_index: 0,
-            current_max_index: 0,
+            feed_capacity: 2000,
+            feed_state: ListState::default(),
+            views: vec![View::Home],
+            thread: None,
+            reader_scroll: 0,
+            pubkey: pubkey.to_string(),
+            contacts: ContactList::default(),
+            profiles: ProfileCache::new(),
+            verified: HashMap::new(),
+            reactions: ReactionCounts::new(),
+            zaps: ZapTotals::new(),
+            zap_providers_asked: HashSet::new(),
+            reports: Reports::new(),
+            labels: Labels::new(),
+            badge_awards: BadgeAwards::new(),
+            wallet_address: None,
+            wallet_balance: None,
+            transactions: Vec::new(),
+            reposts: HashMap::new(),
+            reposted: HashMap::new(),
+            channels: Channels::new(),
+            groups: Groups::new(),
+            deletions: Deletions::new(),
+            addressable: HashMap::new(),
+            lists: HashMap::new(),
+            lists_fetched: false,
+            mutes: Mutes::default(),
+            expiring: Vec::new(),
+            expanded: HashSet::new(),
+            note_expiration: None,
+            bunker: None,
             input_box: None,
+            status: None,
+            commands,
+            secp: Secp256k1::verification_only(),
         }
     }
-}
 
-impl App {
-    /// Constructs a new instance of [`App`].
-    pub fn new() -> Self {
-        Self::default()
+    /// Handles the tick event of the terminal.
+    pub fn tick(&mut self) {
+        self.purge_expired(unix_time());
     }
 
-    /// Handles the tick event of the terminal.
-    pub fn tick(&self) {}
+    /// Signs for other apps instead of showing the feed.
+    pub fn serve_bunker(&mut self, bunker: BunkerState) {
+        self.bunker = Some(bunker);
+        self.views = vec![View::Bunker];
+        self.feed_state.select(Some(0));
+    }
+
+    pub fn handle_bunker_event(&mut self, event: BunkerEvent) {
+        if let Some(bunker) = &mut self.bunker {
+            bunker.handle_event(event);
+        }
+    }
+
+    /// Answers the oldest request of an app waiting for the user.
+    pub fn decide(&mut self, decision: Decision) {
+        if let Some(bunker) = &mut self.bunker {
+            bunker.decide(decision);
+        }
+    }
+
+    /// Forgets the app selected in the bunker view, which then has to
+    /// connect again.
+    pub fn forget_selected_app(&mut self) {
+        let Some(bunker) = &self.bunker else {
+            return;
+        };
+        let selected = self.feed_state.selected().unwrap_or(0);
+        if let Some(app) = bunker.apps.get(selected) {
+            self.status = Some(format!("Forgot {}", app.display_name()));
+            bunker.send(BunkerCommand::Forget(app.pubkey.clone()));
+        }
+    }
 
     /// Set running to false to quit the application.
     pub fn quit(&mut self) {
         self.running = false;
     }
 
-    pub fn add_events(&mut self, events: &mut Vec<Response>) {
-        // If we get more items than what we can store
-        if self.feed.len() + events.len() > self.feed_capacity {
-            // Remove the first `events.len()` items from the feed
-            // to make room for the new ones
-            self.feed.drain(..events.len());
+    /// Updates the application with an event received from a relay.
+    pub fn handle_nostr_event(&mut self, event: NostrEvent) {
+        if self.deletions.is_deleted(&event) || expiration::is_expired(&event, unix_time()) {
+            return;
+        }
+        self.track_expiration(&event);
+
+        match event.kind {
+            METADATA_KIND => {
+                self.profiles.insert(&event);
+                self.verify_zaps();
+            }
+            CONTACT_LIST_KIND if event.pubkey() == self.pubkey => {
+                if let Ok(contacts) = ContactList::from_event(&event) {
+                    if contacts.created_at >= self.contacts.created_at {
+                        self.contacts = contacts;
+                    }
+                }
+            }
+            TEXT_NOTE_KIND | FILE_METADATA_KIND => self.add_event(event),
+            REACTION_KIND => {
+                self.reactions.insert(&event);
+            }
+            ZAP_RECEIPT_KIND => {
+                self.zaps.insert(&event, &self.secp);
+                self.verify_zaps();
+            }
+            REPORT_KIND => self.add_report(&event),
+            LABEL_KIND => {
+                self.labels.insert(&event);
+            }
+            BADGE_AWARD_KIND => {
+                self.badge_awards.insert(&event);
+            }
+            REPOST_KIND | GENERIC_REPOST_KIND => {
+                let (Some(id), Ok(repost)) = (event.id(), Repost::from_event(&event, &self.secp))
+                else {
+                    return;
+                };
+                self.reposts
+                    .insert(id.to_string(), repost.target_id.clone());
+                if let Some(original) = repost.embedded {
+                    if !expiration::is_expired(&original, unix_time()) {
+                        self.track_expiration(&original);
+                        self.reposted.insert(repost.target_id, original);
+                    }
+                }
+                self.add_event(event);
+            }
+            CHANNEL_CREATION_KIND | CHANNEL_METADATA_KIND | CHANNEL_MESSAGE_KIND => {
+                self.add_channel_event(&event)
+            }
+            CHANNEL_HIDE_MESSAGE_KIND | CHANNEL_MUTE_USER_KIND if event.pubkey() == self.pubkey => {
+                self.channels.moderate(&event);
+                self.forget_removed();
+            }
+            DELETION_KIND => self.apply_deletion(&event),
+            kind if list::is_list_kind(kind) => self.add_list(&event),
+            30000..=39999 => self.add_addressable(event),
+            _ => {}
+        }
+    }
+
+    /// Stores a channel, or a message to one, keeping the newest message
+    /// selected when it was before.
+    fn add_channel_event(&mut self, event: &NostrEvent) {
+        let View::Channel(shown) = self.view().clone() else {
+            self.channels.insert(event);
+            return;
+        };
+        let count = self.visible_notes().len();
+        let at_end = self
+            .feed_state
+            .selected()
+            .is_none_or(|selected| selected + 1 >= count);
+
+        if self.channels.insert(event).as_deref() == Some(shown.as_str()) && at_end {
+            self.select_last();
+        }
+    }
+
+    /// Stores an event of a group hosted on `relay`, keeping the newest
+    /// event selected when it was before.
+    pub fn handle_group_event(&mut self, relay: &str, event: NostrEvent) {
+        if self.deletions.is_deleted(&event) || expiration::is_expired(&event, unix_time()) {
+            return;
+        }
+        self.track_expiration(&event);
+
+        let View::Group(shown) = self.view().clone() else {
+            self.groups.insert(relay, &event);
+            return;
+        };
+        let count = self.visible_notes().len();
+        let at_end = self
+            .feed_state
+            .selected()
+            .is_none_or(|selected| selected + 1 >= count);
+
+        if self.groups.insert(relay, &event).as_ref() == Some(&shown) && at_end {
+            self.select_last();
+        }
+        self.forget_removed();
+    }
+
+    fn add_report(&mut self, event: &NostrEvent) {
+        // Reported notes may have to be hidden
+        if self.reports.insert(event) {
+            self.forget_removed();
         }
+    }
+
+    fn select_last(&mut self) {
+        let count = self.visible_notes().len();
+        self.feed_state.select(Some(count.saturating_sub(1)));
+    }
+
+    /// Stores `event` unless a newer version of it is already known.
+    fn add_addressable(&mut self, event: NostrEvent) {
+        let Some(address) = event.address() else {
+            return;
+        };
+
+        let newer = self
+            .addressable
+            .get(&address)
+            .is_none_or(|known| known.created_at < event.created_at);
+        if newer {
+            self.addressable.insert(address, event);
+        }
+    }
+
+    /// Drops the events a deletion request points to, as long as they were
+    /// written by its author.
+    fn apply_deletion(&mut self, event: &NostrEvent) {
+        let Ok(deletion) = Deletion::from_event(event) else {
+            return;
+        };
+        self.deletions.insert(event);
 
-        // Otherwise, we can just append
-        self.feed.append(events);
-        self.feed_capacity += events.len();
+        let deletions = &self.deletions;
+        self.feed.retain(|known| !deletions.is_deleted(known));
+        self.addressable
+            .retain(|_, known| !deletions.is_deleted(known));
+        self.reposted
+            .retain(|_, known| !deletions.is_deleted(known));
+        self.channels
+            .remove_messages(|known| deletions.is_deleted(known));
+        self.groups
+            .remove_events(|known| deletions.is_deleted(known));
+        for id in &deletion.event_ids {
+            self.reactions.remove(id, &deletion.author);
+        }
+
+        self.forget_removed();
+    }
+
+    /// Stores a list of another user. The ones of the user come with their
+    /// private items through `set_list` instead.
+    fn add_list(&mut self, event: &NostrEvent) {
+        if event.pubkey() == self.pubkey {
+            return;
+        }
+        if let Ok(list) = List::from_event(event) {
+            self.set_list(list);
+        }
+    }
+
+    /// Stores `list` unless a newer version of it is already known, hiding
+    /// what the user muted.
+    pub fn set_list(&mut self, list: List) {
+        let address = list.address();
+        if self
+            .lists
+            .get(&address)
+            .is_some_and(|known| known.created_at > list.created_at)
+        {
+            return;
+        }
+
+        if list.kind == MUTE_LIST_KIND && list.author == self.pubkey {
+            self.mutes = Mutes::from_list(&list);
+        }
+        if *self.view() == View::List(address.clone()) {
+            self.fetch_list_items(&list);
+        }
+        self.lists.insert(address, list);
+        self.forget_removed();
+    }
+
+    /// The list of `kind` of the user, or a new one if they have none yet.
+    /// Until their lists are fetched there's no telling, and publishing a
+    /// new one would replace theirs, so they're fetched again instead.
+    fn own_list(&mut self, kind: u64) -> Option<List> {
+        let address = List::new(kind, &self.pubkey).address();
+        if let Some(list) = self.lists.get(&address) {
+            return Some(list.clone());
+        }
+        if !self.lists_fetched {
+            self.status = Some("Your lists weren't fetched yet, trying again…".to_string());
+            self.send(Command::FetchLists);
+            return None;
+        }
+
+        Some(List::new(kind, &self.pubkey))
+    }
+
+    /// The groups the user is in, as their groups list says.
+    pub fn joined_groups(&self) -> Vec<GroupId> {
+        let address = List::new(GROUPS_KIND, &self.pubkey).address();
+        let Some(list) = self.lists.get(&address) else {
+            return Vec::new();
+        };
+
+        list.items()
+            .filter(|item| item.first().map(String::as_str) == Some("group"))
+            .filter_map(|item| GroupId::new(item.get(2)?, item.get(1)?).ok())
+            .collect()
+    }
+
+    /// The groups the user is in, followed by the other ones they opened.
+    pub fn sidebar_groups(&self) -> Vec<GroupId> {
+        let mut groups = self.joined_groups();
+        for group in self.groups.list() {
+            if !groups.contains(&group.id) {
+                groups.push(group.id.clone());
+            }
+        }
+
+        groups
+    }
+
+    /// Adds `group` to `groups`, the groups list of the user, or removes
+    /// it, and publishes the list.
+    fn keep_group(&mut self, mut groups: List, group: &GroupId, joined: bool) {
+        groups.remove("group", &group.id);
+        if joined {
+            groups.public.push(vec![
+                "group".to_string(),
+                group.id.clone(),
+                group.relay.clone(),
+            ]);
+        }
+        self.publish_list(groups);
+    }
+
+    fn publish_list(&mut self, list: List) {
+        self.status = Some("Publishing…".to_string());
+        self.send(Command::PublishList(list));
+    }
+
+    /// Whether the user muted `event` or what it reposts, or had them
+    /// hidden by reporting them.
+    pub fn is_muted(&self, event: &NostrEvent) -> bool {
+        let hidden = |event: &NostrEvent| self.mutes.mutes(event) || self.is_reported_away(event);
+
+        hidden(event) || (repost::is_repost(event) && self.original(event).is_some_and(hidden))
+    }
+
+    /// Whether the reports and labels of `pubkey` are followed: the user
+    /// trusts themselves and the users they follow.
+    pub fn is_trusted(&self, pubkey: &str) -> bool {
+        pubkey == self.pubkey || self.contacts.follows(pubkey)
+    }
+
+    /// The reports trusted users made about `event` or its author.
+    pub fn trusted_reports(&self, event: &NostrEvent) -> Vec<&Report> {
+        let note_reports = event.id().map_or(&[][..], |id| self.reports.of(id));
+        let author = event.pubkey();
+
+        note_reports
+            .iter()
+            .chain(self.reports.of(&author))
+            .filter(|report| self.is_trusted(&report.reporter))
+            .collect()
+    }
+
+    /// Whether `event` is left out for being reported by the user, or by a
+    /// user they follow for something serious enough. The user's own notes
+    /// are always shown to them.
+    fn is_reported_away(&self, event: &NostrEvent) -> bool {
+        event.pubkey() != self.pubkey
+            && self
+                .trusted_reports(event)
+                .iter()
+                .any(|report| report.reporter == self.pubkey || report.report_type.hides())
+    }
+
+    /// The first report of a trusted user about `event` or its author, which
+    /// is then shown behind a warning.
+    pub fn report_flag(&self, event: &NostrEvent) -> Option<&Report> {
+        if event.pubkey() == self.pubkey {
+            return None;
+        }
+
+        self.trusted_reports(event).into_iter().next()
+    }
+
+    /// The labels `event` gives itself, along with the ones trusted users
+    /// gave it or its author.
+    pub fn trusted_labels(&self, event: &NostrEvent) -> Vec<Label> {
+        let mut labels = match event.kind {
+            LABEL_KIND => Vec::new(),
+            _ => label::labels(event),
+        };
+        let author = event.pubkey();
+        let given = event
+            .id()
+            .map_or(&[][..], |id| self.labels.of(id))
+            .iter()
+            .chain(self.labels.of(&author));
+        for (labeler, label) in given {
+            if self.is_trusted(labeler) && !labels.contains(label) {
+                labels.push(label.clone());
+            }
+        }
+
+        labels
+    }
+
+    /// Whether `event` was pinned by its author.
+    pub fn is_pinned(&self, event: &NostrEvent) -> bool {
+        let address = List::new(PIN_LIST_KIND, &event.pubkey()).address();
+        self.lists
+            .get(&address)
+            .is_some_and(|pins| event.id().is_some_and(|id| pins.contains("e", id)))
+    }
+
+    /// Whether the user bookmarked `event`.
+    pub fn is_bookmarked(&self, event: &NostrEvent) -> bool {
+        let address = List::new(BOOKMARKS_KIND, &self.pubkey).address();
+        self.lists
+            .get(&address)
+            .is_some_and(|bookmarks| event.id().is_some_and(|id| bookmarks.contains("e", id)))
+    }
 
-        self.current_min_index = self.current_max_index;
-        self.current_max_index += events.len() - 1;
+    /// Mutes the author of the selected note, privately, or unmutes them.
+    pub fn mute_selected_author(&mut self) {
+        let Some(pubkey) = self.selected_target().map(NostrEvent::pubkey) else {
+            return;
+        };
+        if pubkey == self.pubkey {
+            self.status = Some("You can't mute yourself".to_string());
+            return;
+        }
+
+        let Some(mut mutes) = self.own_list(MUTE_LIST_KIND) else {
+            return;
+        };
+        mutes.toggle("p", &pubkey, true);
+        self.publish_list(mutes);
+    }
+
+    /// Follows the user whose profile is shown, or the author of the
+    /// selected note, or unfollows them if they're followed already.
+    pub fn toggle_follow(&mut self) {
+        let pubkey = match self.view() {
+            View::Profile(pubkey) => pubkey.clone(),
+            _ => match self.selected_target() {
+                Some(event) => event.pubkey(),
+                None => return,
+            },
+        };
+        if pubkey == self.pubkey {
+            self.status = Some("You can't follow yourself".to_string());
+            return;
+        }
+
+        let name = self.profiles.display_name(&pubkey);
+        if self.contacts.follows(&pubkey) {
+            self.status = Some(format!("Unfollowing {}…", name));
+            self.send(Command::Unfollow(pubkey));
+        } else if self.contacts.created_at == 0 {
+            // Publishing a list made of this user only would replace one
+            // that exists but couldn't be fetched
+            self.open_input(InputPurpose::NewContactList(pubkey));
+        } else {
+            self.status = Some(format!("Following {}…", name));
+            self.send(Command::Follow {
+                pubkey,
+                new_list: false,
+            });
+        }
+    }
+
+    /// Mutes the conversation the selected note is part of, privately, or
+    /// unmutes it.
+    pub fn mute_selected_thread(&mut self) {
+        let Some(root_id) = self.selected_target().and_then(ThreadRefs::root_id) else {
+            return;
+        };
+
+        let Some(mut mutes) = self.own_list(MUTE_LIST_KIND) else {
+            return;
+        };
+        mutes.toggle("e", &root_id, true);
+        self.publish_list(mutes);
+    }
+
+    /// Bookmarks the selected note, or removes it from the bookmarks.
+    pub fn bookmark_selected(&mut self) {
+        let Some(id) = self
+            .selected_target()
+            .and_then(|event| event.id().map(str::to_string))
+        else {
+            return;
+        };
+
+        let Some(mut bookmarks) = self.own_list(BOOKMARKS_KIND) else {
+            return;
+        };
+        bookmarks.toggle("e", &id, false);
+        self.publish_list(bookmarks);
+    }
+
+    /// Pins the selected note to the user's profile, or unpins it. Only
+    /// their own notes can be pinned.
+    pub fn pin_selected(&mut self) {
+        let Some(event) = self.selected_target() else {
+            return;
+        };
+        if event.pubkey() != self.pubkey {
+            self.status = Some("Only your own notes can be pinned".to_string());
+            return;
+        }
+        let Some(id) = event.id().map(str::to_string) else {
+            return;
+        };
+
+        let Some(mut pins) = self.own_list(PIN_LIST_KIND) else {
+            return;
+        };
+        pins.toggle("e", &id, false);
+        self.publish_list(pins);
+    }
+
+    /// Opens the notes the user bookmarked.
+    pub fn open_bookmarks(&mut self) {
+        let address = List::new(BOOKMARKS_KIND, &self.pubkey).address();
+        self.open_list(address);
+    }
+
+    /// Opens the list with coordinate `address`, fetching what it holds
+    /// once it's known.
+    fn open_list(&mut self, address: String) {
+        if let Some(list) = self.lists.get(&address) {
+            self.fetch_list_items(list);
+        }
+        self.push_view(View::List(address));
+    }
+
+    fn fetch_list_items(&self, list: &List) {
+        self.send(Command::FetchListItems {
+            ids: list.values("e").map(str::to_string).collect(),
+            authors: match list.kind {
+                FOLLOW_SET_KIND => list.values("p").map(str::to_string).collect(),
+                _ => Vec::new(),
+            },
+        });
+    }
+
+    /// Remembers to drop `event` once it expires, if it does.
+    fn track_expiration(&mut self, event: &NostrEvent) {
+        if let (Some(at), Some(id)) = (expiration::expiration(event), event.id()) {
+            if !self.expiring.iter().any(|(_, known, _)| known == id) {
+                self.expiring.push((at, id.to_string(), event.pubkey()));
+            }
+        }
+    }
+
+    /// Drops the events that expired by `now`, wherever they were kept.
+    fn purge_expired(&mut self, now: u64) {
+        if self.expiring.iter().all(|(at, _, _)| *at > now) {
+            return;
+        }
+
+        let (expired, expiring) = std::mem::take(&mut self.expiring)
+            .into_iter()
+            .partition(|(at, _, _)| *at <= now);
+        self.expiring = expiring;
+
+        let mut ids = HashSet::new();
+        for (_, id, author) in expired {
+            self.reactions.remove(&id, &author);
+            ids.insert(id);
+        }
+        let expired = |event: &NostrEvent| event.id().is_some_and(|id| ids.contains(id));
+        self.feed.retain(|known| !expired(known));
+        self.addressable.retain(|_, known| !expired(known));
+        self.reposted.retain(|_, known| !expired(known));
+        self.channels.remove_messages(expired);
+        self.groups.remove_events(expired);
+
+        self.forget_removed();
+    }
+
+    /// Catches the thread and the selection up with notes that were just
+    /// dropped.
+    fn forget_removed(&mut self) {
+        // The thread is rebuilt without them
+        self.thread = None;
+        self.sync_thread();
+
+        let visible = self.visible_notes().len();
+        if self
+            .feed_state
+            .selected()
+            .is_some_and(|selected| selected >= visible)
+        {
+            self.feed_state.select(visible.checked_sub(1));
+        }
+    }
+
+    /// Records that `identifier` was verified to belong to `pubkey`.
+    pub fn set_verified(&mut self, pubkey: String, identifier: Identifier) {
+        self.verified.insert(pubkey, identifier);
+    }
+
+    /// The verified identifier of `pubkey`, as long as their current profile
+    /// still claims it.
+    pub fn verified_identifier(&self, pubkey: &str) -> Option<&Identifier> {
+        let identifier = self.verified.get(pubkey)?;
+        let claimed = self.profiles.get(pubkey)?.nip05.as_deref()?;
+
+        (claimed.parse::<Identifier>().ok().as_ref() == Some(identifier)).then_some(identifier)
+    }
+
+    /// The view currently on screen.
+    pub fn view(&self) -> &View {
+        self.views.last().unwrap_or(&View::Home)
+    }
+
+    /// Opens `view` on top of the current one.
+    pub fn push_view(&mut self, view: View) {
+        if *self.view() != view {
+            self.views.push(view);
+            self.feed_state.select(Some(0));
+            self.reader_scroll = 0;
+            self.sync_thread();
+        }
+    }
+
+    /// Goes back to the previous view, or quits from the home view.
+    pub fn back(&mut self) {
+        if self.views.len() > 1 {
+            self.views.pop();
+            self.feed_state.select(Some(0));
+            self.reader_scroll = 0;
+            self.sync_thread();
+        } else {
+            self.quit();
+        }
+    }
+
+    /// Opens the profile of `pubkey`, asking the relays for their notes,
+    /// `relays` included.
+    pub fn open_profile(&mut self, pubkey: &str, relays: Vec<String>) {
+        self.send(Command::FetchAuthor {
+            pubkey: pubkey.to_string(),
+            relays,
+        });
+        self.push_view(View::Profile(pubkey.to_string()));
+    }
+
+    /// Opens the profile of the author of the selected note.
+    pub fn open_selected_author(&mut self) {
+        if let Some(event) = self.selected_target() {
+            let pubkey = event.pubkey();
+            self.open_profile(&pubkey, Vec::new());
+        }
+    }
+
+    /// Opens the view `entity` points to, fetching what it shows from the
+    /// relays it suggests. Notes open once they are found, within their
+    /// conversation.
+    pub fn open_entity(&mut self, entity: Entity) {
+        match entity {
+            Entity::Pubkey(pubkey) => self.open_profile(&pubkey, Vec::new()),
+            Entity::Profile { pubkey, relays } => self.open_profile(&pubkey, relays),
+            Entity::Note(id) => {
+                self.status = Some("Looking for the note…".to_string());
+                self.send(Command::FetchNote {
+                    id,
+                    relays: Vec::new(),
+                    author: None,
+                });
+            }
+            Entity::Event {
+                id, relays, author, ..
+            } => {
+                self.status = Some("Looking for the note…".to_string());
+                self.send(Command::FetchNote { id, relays, author });
+            }
+            Entity::Address {
+                identifier,
+                pubkey,
+                kind,
+                relays,
+            } => {
+                let address = format!("{}:{}:{}", kind, pubkey, identifier);
+                self.send(Command::FetchAddress {
+                    kind,
+                    pubkey,
+                    identifier,
+                    relays,
+                });
+                match list::is_list_kind(kind) {
+                    true => self.open_list(address),
+                    false => self.push_view(View::Address(address)),
+                }
+            }
+        }
+    }
+
+    /// Shows the note with id `id`, which was just found, within its
+    /// conversation.
+    pub fn open_found_note(&mut self, id: &str, root_id: &str) {
+        self.status = None;
+        match self.channels.get(root_id) {
+            Some(_) => self.push_view(View::Channel(root_id.to_string())),
+            None => self.push_view(View::Thread(root_id.to_string())),
+        }
+
+        let position = self
+            .visible_notes()
+            .iter()
+            .position(|event| event.id() == Some(id));
+        if position.is_some() {
+            self.feed_state.select(position);
+        }
+    }
+
+    /// Opens the conversation the selected note is part of, asking the
+    /// relays for the rest of it.
+    pub fn open_selected_thread(&mut self) {
+        let Some(event) = self.selected_target() else {
+            return;
+        };
+        let refs = ThreadRefs::from_event(event);
+        let (root_id, relays) = match refs.root {
+            Some(root) => (root.id, root.relay.into_iter().collect()),
+            None => (event.id().unwrap_or_default().to_string(), Vec::new()),
+        };
+
+        self.send(Command::FetchThread {
+            root_id: root_id.clone(),
+            relays,
+        });
+        self.push_view(View::Thread(root_id));
+    }
+
+    /// Rebuilds the conversation of the current thread view from the notes
+    /// received so far, unless it's already the one being shown.
+    fn sync_thread(&mut self) {
+        let View::Thread(root_id) = self.view() else {
+            return;
+        };
+        if self
+            .thread
+            .as_ref()
+            .is_some_and(|thread| thread.root_id == *root_id)
+        {
+            return;
+        }
+
+        let mut thread = Thread::new(root_id);
+        thread.extend(self.feed.iter().rev().cloned());
+        self.thread = Some(thread);
+    }
+
+    /// Whether `event` belongs to the current view.
+    pub fn is_visible(&self, event: &NostrEvent) -> bool {
+        if self.is_muted(event) {
+            return false;
+        }
+        let author = event.pubkey();
+
+        match self.view() {
+            View::Home => {
+                author == self.pubkey
+                    || self.contacts.follows(&author)
+                    || event.tag_values("p").any(|pubkey| pubkey == self.pubkey)
+            }
+            View::Profile(pubkey) => author == *pubkey,
+            View::Address(_)
+            | View::Bunker
+            | View::Wallet
+            | View::Channels
+            | View::Channel(_)
+            | View::Group(_)
+            | View::Badges
+            | View::Agenda => false,
+            View::List(address) => self.lists.get(address).is_some_and(|list| {
+                event.id().is_some_and(|id| list.contains("e", id))
+                    || (list.kind == FOLLOW_SET_KIND && list.contains("p", &author))
+            }),
+            View::Thread(_) => self
+                .thread
+                .as_ref()
+                .is_some_and(|thread| event.id().is_some_and(|id| thread.get(id).is_some())),
+        }
+    }
+
+    /// The notes of the current view, newest first, or in reading order
+    /// for a thread or a channel.
+    pub fn visible_notes(&self) -> Vec<&NostrEvent> {
+        if let (View::Thread(_), Some(thread)) = (self.view(), &self.thread) {
+            return thread
+                .flatten()
+                .into_iter()
+                .map(|(_, event)| event)
+                .filter(|event| !self.is_muted(event))
+                .collect();
+        }
+        if let View::Channel(id) = self.view() {
+            return self
+                .channels
+                .messages(id)
+                .into_iter()
+                .filter(|event| !self.is_muted(event))
+                .collect();
+        }
+        if let View::Group(id) = self.view() {
+            return self
+                .groups
+                .events(id)
+                .into_iter()
+                .filter(|event| !self.is_muted(event))
+                .collect();
+        }
+
+        let mut notes: Vec<&NostrEvent> = self
+            .feed
+            .iter()
+            .filter(|event| self.is_visible(event))
+            .collect();
+        // Profiles show the notes their user pinned first
+        if let View::Profile(_) = self.view() {
+            notes.sort_by_key(|event| !self.is_pinned(event));
+        }
+        notes
+    }
+
+    pub fn selected_note(&self) -> Option<&NostrEvent> {
+        let selected = self.feed_state.selected()?;
+        self.visible_notes().get(selected).copied()
+    }
+
+    /// The note actions on the selected one apply to: the note a repost
+    /// points to, or the selected note itself.
+    pub fn selected_target(&self) -> Option<&NostrEvent> {
+        let event = self.selected_note()?;
+        match repost::is_repost(event) {
+            true => self.original(event),
+            false => Some(event),
+        }
+    }
+
+    /// The note reposted by `event`, if it was received.
+    pub fn original(&self, event: &NostrEvent) -> Option<&NostrEvent> {
+        let target_id = self.reposts.get(event.id()?)?;
+        self.find_note(target_id)
+    }
+
+    /// Looks a note up by id, among the ones received or reposted, and the
+    /// messages of the channels.
+    pub fn find_note(&self, id: &str) -> Option<&NostrEvent> {
+        self.reposted
+            .get(id)
+            .or_else(|| self.feed.iter().find(|event| event.id() == Some(id)))
+            .or_else(|| self.channels.find_message(id))
+            .or_else(|| self.groups.find_message(id))
+    }
+
+    /// Inserts `event` into the feed, keeping it sorted from newest to
+    /// oldest. Once the feed is full, the oldest notes are dropped.
+    pub fn add_event(&mut self, event: NostrEvent) {
+        if self.feed.iter().any(|known| known.id() == event.id()) {
+            return;
+        }
+
+        let position = self
+            .feed
+            .partition_point(|known| known.created_at >= event.created_at);
+        let visible_position = self.feed[..position]
+            .iter()
+            .filter(|known| self.is_visible(known))
+            .count();
+        let visible = self.is_visible(&event);
+
+        // Threads keep their own order, so the selected note is looked up
+        // again once the new one is in
+        if matches!(self.view(), View::Thread(_)) {
+            let selected_id = self
+                .selected_note()
+                .and_then(|note| note.id().map(str::to_string));
+            if let Some(thread) = self.thread.as_mut() {
+                thread.extend([event.clone()]);
+            }
+            self.feed.insert(position, event);
+            self.feed.truncate(self.feed_capacity);
+
+            let notes = self.visible_notes();
+            if let Some(position) = notes
+                .iter()
+                .position(|note| note.id() == selected_id.as_deref())
+            {
+                self.feed_state.select(Some(position));
+            }
+            return;
+        }
+
+        self.feed.insert(position, event);
+        self.feed.truncate(self.feed_capacity);
+
+        if !visible {
+            return;
+        }
+
+        // Keep the selected note in place when a newer one comes in above it
+        match self.feed_state.selected() {
+            Some(selected) if visible_position <= selected => {
+                self.feed_state.select(Some(selected + 1));
+            }
+            None => self.feed_state.select(Some(0)),
+            _ => {}
+        }
     }
 
     pub fn scroll_up(&mut self) {
-        self.current_min_index.checked_sub(MAX_ITEMS_ON_SCREEN).unwrap_or(0);
-        self.current_max_index = self.current_min_index + MAX_ITEMS_ON_SCREEN;
+        if matches!(self.view(), View::Address(_)) {
+            self.reader_scroll = self.reader_scroll.saturating_sub(1);
+            return;
+        }
+
+        let selected = self.feed_state.selected().unwrap_or(0);
+        self.feed_state.select(Some(selected.saturating_sub(1)));
     }
 
     pub fn scroll_down(&mut self) {
-        let tmp = self.current_max_index;
-        self.current_max_index.checked_add(MAX_ITEMS_ON_SCREEN).unwrap_or(self.feed.len() - 1);
-        self.current_min_index = self.current_min_index + tmp;
+        // How far the reader can scroll is only known when rendering
+        if matches!(self.view(), View::Address(_)) {
+            self.reader_scroll = self.reader_scroll.saturating_add(1);
+            return;
+        }
+
+        let visible = match (self.view(), &self.bunker) {
+            (View::Bunker, Some(bunker)) => bunker.apps.len(),
+            (View::Wallet, _) => self.transactions.len(),
+            (View::Channels, _) => self.channels.list().len(),
+            (View::Badges, _) => self.awarded_badges().len(),
+            (View::Agenda, _) => self.agenda().len(),
+            _ => self.visible_notes().len(),
+        };
+        if visible == 0 {
+            return;
+        }
+
+        let selected = self
+            .feed_state
+            .selected()
+            .map_or(0, |selected| selected + 1);
+        self.feed_state.select(Some(selected.min(visible - 1)));
+    }
+
+    pub fn page_up(&mut self) {
+        for _ in 0..MAX_ITEMS_ON_SCREEN {
+            self.scroll_up();
+        }
+    }
+
+    pub fn page_down(&mut self) {
+        for _ in 0..MAX_ITEMS_ON_SCREEN {
+            self.scroll_down();
+        }
+    }
+
+    /// Opens the input box to reply to the selected note.
+    pub fn reply_to_selected(&mut self) {
+        if let Some(id) = self.selected_target().and_then(|event| event.id()) {
+            self.open_input(InputPurpose::Reply(id.to_string()));
+        }
+    }
+
+    /// Asks to confirm the deletion of the selected note, which has to be
+    /// the user's own. Reposts are deleted themselves, not what they repost.
+    pub fn delete_selected(&mut self) {
+        let Some(event) = self.selected_note() else {
+            return;
+        };
+        if event.pubkey() != self.pubkey {
+            self.status = Some("Only your own notes can be deleted".to_string());
+            return;
+        }
+
+        if let Some(id) = event.id().map(str::to_string) {
+            self.open_input(InputPurpose::Delete(id));
+        }
+    }
+
+    /// Opens the input box to report the selected note, or its author if
+    /// `author` is set.
+    pub fn report_selected(&mut self, author: bool) {
+        let Some(event) = self.selected_target() else {
+            return;
+        };
+        let pubkey = event.pubkey();
+        if pubkey == self.pubkey {
+            self.status = Some("You can't report yourself".to_string());
+            return;
+        }
+
+        let id = match author {
+            true => None,
+            false => event.id().map(str::to_string),
+        };
+        self.open_input(InputPurpose::Report { id, pubkey });
+    }
+
+    /// Opens the input box to label the selected note, or its author if
+    /// `author` is set.
+    pub fn label_selected(&mut self, author: bool) {
+        let Some(event) = self.selected_target() else {
+            return;
+        };
+
+        let pubkey = event.pubkey();
+        let id = match author {
+            true => None,
+            false => event.id().map(str::to_string),
+        };
+        self.open_input(InputPurpose::Label { id, pubkey });
+    }
+
+    /// Reposts the selected note.
+    pub fn repost_selected(&mut self) {
+        let Some(target) = self.selected_target() else {
+            return;
+        };
+
+        let repost = repost::repost(target, &self.pubkey, None);
+        self.status = Some("Publishing…".to_string());
+        self.send(Command::Publish(repost));
+    }
+
+    /// Shows the content of the selected note despite its content warning,
+    /// or hides it again.
+    pub fn toggle_content_warning(&mut self) {
+        let Some(event) = self.selected_target() else {
+            return;
+        };
+        if content_warning::content_warning(event).is_none() && self.report_flag(event).is_none() {
+            return;
+        }
+
+        if let Some(id) = event.id().map(str::to_string) {
+            if !self.expanded.remove(&id) {
+                self.expanded.insert(id);
+            }
+        }
+    }
+
+    /// Opens the input box to quote the selected note.
+    pub fn quote_selected(&mut self) {
+        if let Some(id) = self.selected_target().and_then(|event| event.id()) {
+            self.open_input(InputPurpose::Quote(id.to_string()));
+        }
+    }
+
+    /// Likes the selected note.
+    pub fn like_selected(&mut self) {
+        let id = self
+            .selected_target()
+            .and_then(|event| event.id().map(str::to_string));
+        if let Some(id) = id {
+            self.react(&id, ReactionContent::Like);
+        }
+    }
+
+    /// Opens the input box to react to the selected note with an emoji.
+    pub fn react_to_selected(&mut self) {
+        if let Some(id) = self.selected_target().and_then(|event| event.id()) {
+            self.open_input(InputPurpose::React(id.to_string()));
+        }
+    }
+
+    /// Publishes a reaction to the note with id `target_id`, unless the user
+    /// already reacted to it.
+    fn react(&mut self, target_id: &str, content: ReactionContent) {
+        if self
+            .reactions
+            .reaction_of(&self.pubkey, target_id)
+            .is_some()
+        {
+            self.status = Some("You already reacted to this note".to_string());
+            return;
+        }
+        let Some(target) = self.find_note(target_id) else {
+            return;
+        };
+
+        let reaction = reaction::react(target, &self.pubkey, content);
+        self.status = Some("Publishing…".to_string());
+        self.send(Command::Publish(reaction));
+    }
+
+    /// Opens the input box to zap the author of the selected note, for that
+    /// note.
+    pub fn zap_selected(&mut self) {
+        let Some(target) = self.selected_target() else {
+            return;
+        };
+        let recipient = target.pubkey();
+        let target_id = target.id().map(str::to_string);
+        self.zap(recipient, target_id);
+    }
+
+    /// Opens the input box to zap the user whose profile is shown.
+    pub fn zap_profile(&mut self) {
+        if let View::Profile(pubkey) = self.view().clone() {
+            self.zap(pubkey, None);
+        }
+    }
+
+    fn zap(&mut self, recipient: String, target_id: Option<String>) {
+        if self.pay_url(&recipient).is_none() {
+            self.status = Some(format!(
+                "{} has no lightning address",
+                self.profiles.display_name(&recipient)
+            ));
+            return;
+        }
+        self.open_input(InputPurpose::Zap {
+            recipient,
+            target_id,
+        });
+    }
+
+    /// Asks about the lightning services of the users zapped, once their
+    /// profile is known, for the zaps to them to count.
+    fn verify_zaps(&mut self) {
+        let asking: Vec<(String, String)> = self
+            .zaps
+            .unverified()
+            .filter(|pubkey| !self.zap_providers_asked.contains(*pubkey))
+            .filter_map(|pubkey| Some((pubkey.clone(), self.pay_url(pubkey)?)))
+            .collect();
+
+        for (pubkey, pay_url) in asking {
+            self.zap_providers_asked.insert(pubkey.clone());
+            self.send(Command::FetchZapProvider { pubkey, pay_url });
+        }
+    }
+
+    /// The pay endpoint of the lightning service of `pubkey`, if their
+    /// profile has one.
+    fn pay_url(&self, pubkey: &str) -> Option<String> {
+        let profile = self.profiles.get(pubkey)?;
+        zap::pay_url(profile).ok()
+    }
+
+    /// Opens the wallet view, asking the wallet for its balance and
+    /// payments.
+    pub fn open_wallet(&mut self) {
+        self.status = Some("Asking the wallet…".to_string());
+        self.send(Command::FetchWallet);
+        self.push_view(View::Wallet);
+    }
+
+    pub fn set_wallet(
+        &mut self,
+        lud16: Option<String>,
+        balance: u64,
+        transactions: Vec<Transaction>,
+    ) {
+        self.status = None;
+        self.wallet_address = lud16;
+        self.wallet_balance = Some(balance);
+        self.transactions = transactions;
+        if matches!(self.view(), View::Wallet) && self.feed_state.selected().is_none() {
+            self.feed_state.select(Some(0));
+        }
+    }
+
+    /// Opens the agenda, fetching the calendar events of the user and the
+    /// users they follow.
+    pub fn open_agenda(&mut self) {
+        let (_, authors) = self.contacts.home_feed(&self.pubkey);
+        self.send(Command::FetchAgenda(authors));
+        self.push_view(View::Agenda);
+    }
+
+    /// The calendar events of the user and the users they follow that
+    /// aren't over, along with the ones in their calendars, soonest first.
+    pub fn agenda(&self) -> Vec<CalendarEvent> {
+        let followed = |pubkey: &str| pubkey == self.pubkey || self.contacts.follows(pubkey);
+        let listed: HashSet<String> = self
+            .addressable
+            .values()
+            .filter(|event| event.kind == CALENDAR_KIND && followed(&event.pubkey()))
+            .filter_map(|event| Calendar::from_event(event).ok())
+            .flat_map(|calendar| calendar.events)
+            .collect();
+
+        let now = unix_time();
+        let mut agenda: Vec<CalendarEvent> = self
+            .addressable
+            .iter()
+            .filter(|(address, event)| followed(&event.pubkey()) || listed.contains(*address))
+            .filter_map(|(_, event)| CalendarEvent::from_event(event).ok())
+            .filter(|calendar_event| calendar_event.is_upcoming(now))
+            .collect();
+        agenda.sort_by_key(CalendarEvent::starts_at);
+
+        agenda
+    }
+
+    /// The latest answer of each user to the calendar event with coordinate
+    /// `address`, leaving out muted users.
+    pub fn rsvps(&self, address: &str) -> Vec<Rsvp> {
+        let mut events: Vec<&NostrEvent> = self
+            .addressable
+            .values()
+            .filter(|event| event.kind == RSVP_KIND && !self.mutes.mutes(event))
+            .collect();
+        events.sort_by_key(|event| std::cmp::Reverse(event.created_at));
+
+        let mut rsvps: Vec<Rsvp> = Vec::new();
+        for rsvp in events
+            .into_iter()
+            .filter_map(|event| Rsvp::from_event(event).ok())
+            .filter(|rsvp| rsvp.address == address)
+        {
+            if !rsvps.iter().any(|known| known.author == rsvp.author) {
+                rsvps.push(rsvp);
+            }
+        }
+
+        rsvps
+    }
+
+    /// Opens the input box to answer the selected calendar event.
+    pub fn rsvp_selected(&mut self) {
+        let selected = self.feed_state.selected().unwrap_or(0);
+        if let Some(calendar_event) = self.agenda().get(selected) {
+            self.open_input(InputPurpose::Rsvp(calendar_event.address()));
+        }
+    }
+
+    /// Opens the list of badges awarded to the user, fetching them.
+    pub fn open_badges(&mut self) {
+        self.send(Command::FetchBadges(self.pubkey.clone()));
+        self.push_view(View::Badges);
+    }
+
+    /// The badges awarded to the user, newest first.
+    pub fn awarded_badges(&self) -> Vec<&BadgeAward> {
+        self.badge_awards.to(&self.pubkey)
+    }
+
+    /// The definition of the badge with coordinate `address`, once received.
+    pub fn badge_definition(&self, address: &str) -> Option<BadgeDefinition> {
+        self.addressable
+            .get(address)
+            .and_then(|event| BadgeDefinition::from_event(event).ok())
+    }
+
+    /// The badges `pubkey` shows on their profile, in their order. Badges
+    /// whose award wasn't made to them by the issuer of the badge are left
+    /// out.
+    pub fn profile_badges(&self, pubkey: &str) -> Vec<BadgeDefinition> {
+        self.shown_badges(pubkey)
+            .iter()
+            .filter_map(|(address, award_id)| {
+                let award = self.badge_awards.get(award_id)?;
+                let definition = self.badge_definition(address)?;
+                let awarded = award.awards(&definition)
+                    && award.recipients.iter().any(|recipient| recipient == pubkey);
+
+                awarded.then_some(definition)
+            })
+            .collect()
+    }
+
+    /// Whether the user shows the badge of `award` on their profile.
+    pub fn is_badge_shown(&self, award: &BadgeAward) -> bool {
+        self.shown_badges(&self.pubkey)
+            .iter()
+            .any(|(_, award_id)| *award_id == award.id)
+    }
+
+    /// The coordinates and award ids of the badges `pubkey` chose to show.
+    fn shown_badges(&self, pubkey: &str) -> Vec<(String, String)> {
+        let address = format!(
+            "{}:{}:{}",
+            PROFILE_BADGES_KIND, pubkey, PROFILE_BADGES_IDENTIFIER
+        );
+        self.addressable
+            .get(&address)
+            .map(badge::profile_badges)
+            .unwrap_or_default()
+    }
+
+    /// Accepts the selected badge, showing it on the profile of the user
+    /// after the ones there already, or hides it from there if `show` isn't
+    /// set.
+    pub fn show_selected_badge(&mut self, show: bool) {
+        let selected = self.feed_state.selected().unwrap_or(0);
+        let Some(award) = self
+            .awarded_badges()
+            .get(selected)
+            .map(|award| (*award).clone())
+        else {
+            return;
+        };
+        if self.is_badge_shown(&award) == show {
+            return;
+        }
+
+        let mut badges = self.shown_badges(&self.pubkey);
+        match show {
+            true => badges.push((award.address, award.id)),
+            false => badges.retain(|(_, award_id)| *award_id != award.id),
+        }
+        let profile_badges = badge::set_profile_badges(&self.pubkey, &badges);
+        self.status = Some("Publishing…".to_string());
+        self.send(Command::Publish(profile_badges));
+    }
+
+    /// Opens the list of public chat channels, asking the relays for the
+    /// latest ones.
+    pub fn open_channels(&mut self) {
+        self.send(Command::FetchChannels);
+        self.push_view(View::Channels);
+    }
+
+    /// Opens the channel selected in the list of channels.
+    pub fn open_selected_channel(&mut self) {
+        let selected = self.feed_state.selected().unwrap_or(0);
+        if let Some(id) = self
+            .channels
+            .list()
+            .get(selected)
+            .map(|channel| channel.id.clone())
+        {
+            self.open_channel(&id, Vec::new());
+        }
+    }
+
+    /// Opens the channel with id `id`, asking the relays for its messages,
+    /// `relays` included.
+    pub fn open_channel(&mut self, id: &str, mut relays: Vec<String>) {
+        if let Some(channel) = self.channels.get(id) {
+            relays.extend(channel.metadata.relays.iter().cloned());
+        }
+        self.send(Command::OpenChannel {
+            id: id.to_string(),
+            relays,
+        });
+        self.push_view(View::Channel(id.to_string()));
+        self.select_last();
+    }
+
+    /// Opens the input box to write to the channel shown, in reply to the
+    /// selected message if `reply` is set.
+    pub fn write_to_channel(&mut self, reply: bool) {
+        let View::Channel(channel_id) = self.view().clone() else {
+            return;
+        };
+        let reply_to = match reply {
+            true => match self.selected_note().and_then(|message| message.id()) {
+                Some(id) => Some(id.to_string()),
+                None => return,
+            },
+            false => None,
+        };
+
+        self.open_input(InputPurpose::ChannelMessage {
+            channel_id,
+            reply_to,
+        });
+    }
+
+    /// Opens the input box to change the name, about or picture of the
+    /// channel shown, if the user created it.
+    pub fn edit_channel(&mut self) {
+        let View::Channel(channel_id) = self.view().clone() else {
+            return;
+        };
+        match self.channels.get(&channel_id) {
+            Some(channel) if channel.creator == self.pubkey => {
+                self.open_input(InputPurpose::EditChannel(channel_id))
+            }
+            Some(_) => self.status = Some("Only its creator can edit a channel".to_string()),
+            None => self.status = Some("The channel wasn't found yet".to_string()),
+        }
+    }
+
+    /// Asks for a reason to hide the selected message of the channel shown.
+    pub fn hide_selected_message(&mut self) {
+        if let Some(id) = self.selected_note().and_then(|message| message.id()) {
+            self.open_input(InputPurpose::HideMessage(id.to_string()));
+        }
+    }
+
+    /// Mutes the author of the selected message in every channel.
+    pub fn mute_selected_chatter(&mut self) {
+        let Some(pubkey) = self.selected_note().map(NostrEvent::pubkey) else {
+            return;
+        };
+        if pubkey == self.pubkey {
+            self.status = Some("You can't mute yourself".to_string());
+            return;
+        }
+
+        let mute = channel::mute_user(&self.pubkey, &pubkey, "");
+        self.status = Some("Publishing…".to_string());
+        self.send(Command::Publish(mute));
+    }
+
+    /// Opens the first group of the user, or asks which one to open if
+    /// they know none.
+    pub fn open_groups(&mut self) {
+        match self.sidebar_groups().into_iter().next() {
+            Some(group) => self.open_group(group),
+            None => self.open_input(InputPurpose::OpenGroup),
+        }
+    }
+
+    /// Opens `group`, asking its relay for its latest events. Switching
+    /// from a group to another one doesn't stack them.
+    pub fn open_group(&mut self, group: GroupId) {
+        self.send(Command::OpenGroup(group.clone()));
+        if matches!(self.view(), View::Group(_)) {
+            self.views.pop();
+        }
+        self.push_view(View::Group(group));
+        self.select_last();
+    }
+
+    /// Opens the group `step` places after the one shown in the sidebar,
+    /// or before it if `step` is negative.
+    pub fn switch_group(&mut self, step: isize) {
+        let View::Group(shown) = self.view() else {
+            return;
+        };
+        let groups = self.sidebar_groups();
+        let Some(position) = groups.iter().position(|group| group == shown) else {
+            return;
+        };
+
+        let next = (position as isize + step).rem_euclid(groups.len() as isize) as usize;
+        if next != position {
+            self.open_group(groups[next].clone());
+        }
+    }
+
+    /// Opens the input box to write to the group shown, in reply to the
+    /// selected message if `reply` is set.
+    pub fn write_to_group(&mut self, reply: bool) {
+        let View::Group(group) = self.view().clone() else {
+            return;
+        };
+        let reply_to = match reply {
+            true => match self.selected_note().and_then(|message| message.id()) {
+                Some(id) => Some(id.to_string()),
+                None => return,
+            },
+            false => None,
+        };
+
+        self.open_input(InputPurpose::GroupMessage { group, reply_to });
+    }
+
+    /// Asks for an invite code to join the group shown, unless the user is
+    /// in it already. Groups are only joined once the groups list of the
+    /// user is known, to keep track of them.
+    pub fn join_group(&mut self) {
+        let View::Group(id) = self.view().clone() else {
+            return;
+        };
+        if self
+            .groups
+            .get(&id)
+            .is_some_and(|group| group.is_member(&self.pubkey))
+        {
+            self.status = Some("You're in this group already".to_string());
+            return;
+        }
+        if self.own_list(GROUPS_KIND).is_none() {
+            return;
+        }
+
+        self.open_input(InputPurpose::JoinGroup(id));
+    }
+
+    /// Asks to confirm the user leaves the group shown, once their groups
+    /// list is known.
+    pub fn leave_group(&mut self) {
+        if let View::Group(id) = self.view().clone() {
+            if self.own_list(GROUPS_KIND).is_some() {
+                self.open_input(InputPurpose::LeaveGroup(id));
+            }
+        }
+    }
+
+    /// Adds the author of the selected event of the group shown to it, like
+    /// a user asking to join. Only admins can.
+    pub fn add_selected_user(&mut self) {
+        let Some((id, user)) = self.moderated_user() else {
+            return;
+        };
+
+        let put = group::put_user(&self.pubkey, &id, &user, &[]);
+        self.publish_to_group(put, id);
+    }
+
+    /// Removes the author of the selected event of the group shown from it.
+    /// Only admins can.
+    pub fn remove_selected_user(&mut self) {
+        let Some((id, user)) = self.moderated_user() else {
+            return;
+        };
+
+        let remove = group::remove_user(&self.pubkey, &id, &user, "");
+        self.publish_to_group(remove, id);
+    }
+
+    /// Deletes the selected event of the group shown from it. Only admins
+    /// can.
+    pub fn delete_selected_group_event(&mut self) {
+        let View::Group(id) = self.view().clone() else {
+            return;
+        };
+        if !self.is_group_admin(&id) {
+            self.status = Some("Only the admins of the group can do that".to_string());
+            return;
+        }
+        let Some(event_id) = self.selected_note().and_then(|event| event.id()) else {
+            return;
+        };
+
+        let delete = group::delete_event(&self.pubkey, &id, event_id, "");
+        self.publish_to_group(delete, id);
+    }
+
+    /// The group shown and the author of its selected event, as long as
+    /// the user is one of its admins.
+    fn moderated_user(&mut self) -> Option<(GroupId, String)> {
+        let View::Group(id) = self.view().clone() else {
+            return None;
+        };
+        if !self.is_group_admin(&id) {
+            self.status = Some("Only the admins of the group can do that".to_string());
+            return None;
+        }
+        let user = self.selected_note()?.pubkey();
+
+        Some((id, user))
+    }
+
+    fn is_group_admin(&self, id: &GroupId) -> bool {
+        self.groups
+            .get(id)
+            .is_some_and(|group| group.is_admin(&self.pubkey))
+    }
+
+    fn publish_to_group(&mut self, event: NostrEvent, group: GroupId) {
+        self.status = Some("Publishing…".to_string());
+        self.send(Command::PublishToGroup { event, group });
+    }
+
+    /// Opens the input box to type something in.
+    pub fn open_input(&mut self, purpose: InputPurpose) {
+        self.input_box = Some(InputBox {
+            purpose,
+            text: String::new(),
+            attachments: Vec::new(),
+        });
+    }
+
+    /// Acts on what was typed into the input box, and closes it.
+    pub fn submit_input(&mut self) {
+        let Some(input) = self.input_box.take() else {
+            return;
+        };
+
+        match input.purpose {
+            InputPurpose::Lookup => match input.text.parse::<Identifier>() {
+                Ok(identifier) => {
+                    self.status = Some(format!("Looking up {}…", identifier));
+                    self.send(Command::Lookup(identifier));
+                }
+                Err(err) => self.status = Some(err.to_string()),
+            },
+            InputPurpose::Open => match Entity::from_uri(&input.text) {
+                Ok(entity) => self.open_entity(entity),
+                Err(err) => self.status = Some(format!("Can't open that: {}", err)),
+            },
+            InputPurpose::Note => {
+                if input.text.trim().is_empty() {
+                    return;
+                }
+
+                let content = self.link_mentions(&input.text);
+                let note = NostrEvent::new(&self.pubkey, TEXT_NOTE_KIND, content);
+                self.publish_note(note, &input.attachments);
+            }
+            InputPurpose::Reply(parent_id) => {
+                if input.text.trim().is_empty() {
+                    return;
+                }
+                let content = self.link_mentions(&input.text);
+                let Some(parent) = self.find_note(&parent_id) else {
+                    self.status = Some("The note being replied to is gone".to_string());
+                    return;
+                };
+
+                let reply = thread::reply(parent, &self.pubkey, content);
+                self.publish_note(reply, &input.attachments);
+            }
+            InputPurpose::Quote(target_id) => {
+                if input.text.trim().is_empty() {
+                    return;
+                }
+                let content = self.link_mentions(&input.text);
+                let Some(target) = self.find_note(&target_id) else {
+                    self.status = Some("The note being quoted is gone".to_string());
+                    return;
+                };
+
+                let quote = repost::quote(target, &self.pubkey, &content);
+                self.publish_note(quote, &input.attachments);
+            }
+            InputPurpose::Delete(target_id) => {
+                let Some(target) = self.find_note(&target_id) else {
+                    return;
+                };
+
+                let deletion = deletion::delete(&[target], &self.pubkey, input.text.trim());
+                self.status = Some("Deleting…".to_string());
+                self.send(Command::Publish(deletion));
+            }
+            InputPurpose::Report { id, pubkey } => {
+                let text = input.text.trim();
+                let (report_type, reason) = text.split_once(' ').unwrap_or((text, ""));
+                let report_type = match report_type.parse::<ReportType>() {
+                    Ok(report_type) => report_type,
+                    Err(err) => {
+                        self.status = Some(err.to_string());
+                        return;
+                    }
+                };
+
+                let report = match id.as_deref().and_then(|id| self.find_note(id)) {
+                    Some(note) => report::report_note(&self.pubkey, note, report_type, reason),
+                    None => report::report_user(&self.pubkey, &pubkey, report_type, reason),
+                };
+                self.status = Some("Publishing…".to_string());
+                self.send(Command::Publish(report));
+            }
+            InputPurpose::Label { id, pubkey } => {
+                let text = input.text.trim();
+                let label = match text.rsplit_once(' ') {
+                    Some((namespace, value)) => Label::new(namespace.trim(), value),
+                    None if !text.is_empty() => Label::new(UGC_NAMESPACE, text),
+                    None => return,
+                };
+
+                let labeling = match id {
+                    Some(id) => match self.find_note(&id) {
+                        Some(note) => label::label_note(&self.pubkey, note, &label),
+                        None => return,
+                    },
+                    None => label::label_user(&self.pubkey, &pubkey, &label),
+                };
+                self.status = Some("Publishing…".to_string());
+                self.send(Command::Publish(labeling));
+            }
+            InputPurpose::NewContactList(pubkey) => {
+                if !input.text.trim().eq_ignore_ascii_case("yes") {
+                    self.status = Some("No contact list was started".to_string());
+                    return;
+                }
+
+                self.status = Some(format!(
+                    "Following {}…",
+                    self.profiles.display_name(&pubkey)
+                ));
+                self.send(Command::Follow {
+                    pubkey,
+                    new_list: true,
+                });
+            }
+            InputPurpose::Rsvp(address) => {
+                let text = input.text.trim();
+                let (status, note) = text.split_once(' ').unwrap_or((text, ""));
+                let status = match status.parse::<RsvpStatus>() {
+                    Ok(status) => status,
+                    Err(err) => {
+                        self.status = Some(err.to_string());
+                        return;
+                    }
+                };
+                let Some(calendar_event) = self
+                    .addressable
+                    .get(&address)
+                    .and_then(|event| CalendarEvent::from_event(event).ok())
+                else {
+                    return;
+                };
+
+                let rsvp = calendar::rsvp(&self.pubkey, &calendar_event, status, note.trim());
+                self.status = Some("Publishing…".to_string());
+                self.send(Command::Publish(rsvp));
+            }
+            InputPurpose::React(target_id) => {
+                let content = match input.text.trim() {
+                    "" => return,
+                    "+" => ReactionContent::Like,
+                    "-" => ReactionContent::Dislike,
+                    emoji => ReactionContent::Emoji(emoji.to_string()),
+                };
+                self.react(&target_id, content);
+            }
+            InputPurpose::Article { draft } => {
+                let path = input.text.trim();
+                if path.is_empty() {
+                    return;
+                }
+                let path = match (path.strip_prefix("~/"), std::env::var("HOME")) {
+                    (Some(rest), Ok(home)) => PathBuf::from(home).join(rest),
+                    _ => PathBuf::from(path),
+                };
+
+                match Article::read_file(&path, draft) {
+                    Ok(article) => {
+                        let address = article.address(&self.pubkey);
+                        self.status = Some("Publishing…".to_string());
+                        self.send(Command::PublishArticle(article));
+                        self.push_view(View::Address(address));
+                    }
+                    Err(err) => self.status = Some(err.to_string()),
+                }
+            }
+            InputPurpose::ConnectApp => match input.text.parse::<ConnectUri>() {
+                Ok(uri) => {
+                    if let Some(bunker) = &self.bunker {
+                        self.status = Some("Approve the app to connect to it".to_string());
+                        bunker.send(BunkerCommand::Connect(uri));
+                    }
+                }
+                Err(err) => self.status = Some(format!("Invalid URI: {}", err)),
+            },
+            InputPurpose::Zap {
+                recipient,
+                target_id,
+            } => {
+                let (amount, comment) = input
+                    .text
+                    .trim()
+                    .split_once(' ')
+                    .unwrap_or((input.text.trim(), ""));
+                let Ok(sats) = amount.parse::<u64>() else {
+                    self.status = Some("Start with the amount to zap, in sats".to_string());
+                    return;
+                };
+                let target = match &target_id {
+                    Some(id) => match self.find_note(id) {
+                        Some(target) => Some(target.clone()),
+                        None => return,
+                    },
+                    None => None,
+                };
+                let Some(pay_url) = self.pay_url(&recipient) else {
+                    return;
+                };
+
+                self.status = Some("Asking for an invoice…".to_string());
+                self.send(Command::Zap {
+                    recipient,
+                    target,
+                    pay_url,
+                    amount_msats: sats * 1000,
+                    comment: comment.trim().to_string(),
+                });
+            }
+            InputPurpose::Receive => {
+                let (amount, description) = input
+                    .text
+                    .trim()
+                    .split_once(' ')
+                    .unwrap_or((input.text.trim(), ""));
+                let Ok(sats) = amount.parse::<u64>() else {
+                    self.status = Some("Start with the amount to receive, in sats".to_string());
+                    return;
+                };
+
+                self.status = Some("Asking the wallet for an invoice…".to_string());
+                self.send(Command::MakeInvoice {
+                    amount_msats: sats * 1000,
+                    description: description.trim().to_string(),
+                });
+            }
+            InputPurpose::CreateChannel => {
+                let name = input.text.trim();
+                if name.is_empty() {
+                    return;
+                }
+
+                let metadata = ChannelMetadata {
+                    name: name.to_string(),
+                    ..ChannelMetadata::default()
+                };
+                let creation = channel::create_channel(&self.pubkey, &metadata);
+                self.status = Some("Publishing…".to_string());
+                self.send(Command::Publish(creation));
+            }
+            InputPurpose::ChannelMessage {
+                channel_id,
+                reply_to,
+            } => {
+                if input.text.trim().is_empty() {
+                    return;
+                }
+                let content = self.link_mentions(&input.text);
+                let Some(channel) = self.channels.get(&channel_id) else {
+                    self.status = Some("The channel wasn't found yet".to_string());
+                    return;
+                };
+                let reply_to = reply_to.as_deref().and_then(|id| self.find_note(id));
+
+                let mut message = channel::message(&self.pubkey, channel, content, reply_to);
+                reference::tag_references(&mut message);
+                let relays = channel.metadata.relays.clone();
+                self.status = Some("Publishing…".to_string());
+                self.send(Command::PublishTo {
+                    event: message,
+                    relays,
+                });
+            }
+            InputPurpose::EditChannel(channel_id) => {
+                let text = input.text.trim();
+                if text.is_empty() {
+                    return;
+                }
+                let (field, value) = text.split_once(' ').unwrap_or((text, ""));
+                let Some(channel) = self.channels.get(&channel_id) else {
+                    self.status = Some("The channel wasn't found yet".to_string());
+                    return;
+                };
+
+                let mut metadata = channel.metadata.clone();
+                if let Err(err) = metadata.set(field, value.trim()) {
+                    self.status = Some(err.to_string());
+                    return;
+                }
+                let event =
+                    channel::set_metadata(&self.pubkey, &channel.id, channel.relay(), &metadata);
+                let relays = channel.metadata.relays.clone();
+                self.status = Some("Publishing…".to_string());
+                self.send(Command::PublishTo { event, relays });
+            }
+            InputPurpose::HideMessage(message_id) => {
+                let hide = channel::hide_message(&self.pubkey, &message_id, &input.text);
+                self.status = Some("Publishing…".to_string());
+                self.send(Command::Publish(hide));
+            }
+            InputPurpose::OpenGroup => match input.text.parse::<GroupId>() {
+                Ok(group) => self.open_group(group),
+                Err(err) => self.status = Some(err.to_string()),
+            },
+            InputPurpose::GroupMessage { group, reply_to } => {
+                if input.text.trim().is_empty() {
+                    return;
+                }
+                let content = self.link_mentions(&input.text);
+                let reply_to = reply_to.as_deref().and_then(|id| self.find_note(id));
+
+                let mut message = group::message(&self.pubkey, &group, content, reply_to);
+                reference::tag_references(&mut message);
+                self.publish_to_group(message, group);
+            }
+            InputPurpose::JoinGroup(group) => {
+                // The groups list is updated along with the request
+                let Some(groups) = self.own_list(GROUPS_KIND) else {
+                    return;
+                };
+                let code = Some(input.text.trim()).filter(|code| !code.is_empty());
+                let request = group::join_request(&self.pubkey, &group, "", code);
+                self.publish_to_group(request, group.clone());
+                self.keep_group(groups, &group, true);
+            }
+            InputPurpose::LeaveGroup(group) => {
+                let Some(groups) = self.own_list(GROUPS_KIND) else {
+                    return;
+                };
+                let request = group::leave_request(&self.pubkey, &group, input.text.trim());
+                self.publish_to_group(request, group.clone());
+                self.keep_group(groups, &group, false);
+            }
+            InputPurpose::MuteWord => {
+                let text = input.text.trim().to_lowercase();
+                let (name, value) = match text.strip_prefix('#') {
+                    Some(hashtag) => ("t", hashtag),
+                    None => ("word", text.as_str()),
+                };
+                if value.is_empty() {
+                    return;
+                }
+
+                let Some(mut mutes) = self.own_list(MUTE_LIST_KIND) else {
+                    return;
+                };
+                mutes.toggle(name, value, true);
+                self.publish_list(mutes);
+            }
+            InputPurpose::EditProfile => {
+                let text = input.text.trim();
+                if text.is_empty() {
+                    return;
+                }
+                let (field, value) = text.split_once(' ').unwrap_or((text, ""));
+
+                self.status = Some("Updating your profile…".to_string());
+                self.send(Command::EditProfile {
+                    field: field.to_string(),
+                    value: value.trim().to_string(),
+                });
+            }
+            InputPurpose::Relay => {
+                // `url` is read from and written to, unless `read` or
+                // `write` follows it, and `-url` is removed
+                let mut words = input.text.split_whitespace();
+                let Some(url) = words.next() else {
+                    return;
+                };
+                let (url, read, write) = match (url.strip_prefix('-'), words.next()) {
+                    (Some(url), _) => (url, false, false),
+                    (None, None) => (url, true, true),
+                    (None, Some("read")) => (url, true, false),
+                    (None, Some("write")) => (url, false, true),
+                    (None, Some(marker)) => {
+                        self.status = Some(format!("Expected read or write, not {}", marker));
+                        return;
+                    }
+                };
+                if normalize_url(url).is_none() {
+                    self.status = Some(format!("{} isn't a relay url", url));
+                    return;
+                }
+
+                self.status = Some("Updating your relay list…".to_string());
+                self.send(Command::SetRelay {
+                    url: url.to_string(),
+                    read,
+                    write,
+                });
+            }
+            InputPurpose::Expiration => match input.text.trim() {
+                "" | "0" | "never" => {
+                    self.note_expiration = None;
+                    self.status = Some("New notes won't expire".to_string());
+                }
+                text => match expiration::parse_duration(text) {
+                    Ok(after) => {
+                        self.note_expiration = Some(after);
+                        self.status = Some(format!("New notes expire after {}", text));
+                    }
+                    Err(err) => self.status = Some(err.to_string()),
+                },
+            },
+        }
+    }
+
+    /// Publishes a note written by the user, tagging the users and notes
+    /// its content refers to, and making it expire if the user asked for
+    /// their notes to.
+    fn publish_note(&mut self, mut note: NostrEvent, attachments: &[FileMetadata]) {
+        reference::tag_references(&mut note);
+        for file in attachments {
+            if note.content.contains(&file.url) {
+                file_metadata::attach(&mut note, file);
+            }
+        }
+        if let Some(after) = self.note_expiration {
+            if let Err(err) = expiration::expire_after(&mut note, after) {
+                self.status = Some(err.to_string());
+                return;
+            }
+        }
+        self.status = Some("Publishing…".to_string());
+        self.send(Command::Publish(note));
+    }
+
+    /// Turns the `@name` mentions of known users in `text` into references.
+    fn link_mentions(&self, text: &str) -> String {
+        reference::link_mentions(text, |name| {
+            self.mention_candidates(name)
+                .into_iter()
+                .find(|(_, handle)| handle.eq_ignore_ascii_case(name))
+                .map(|(pubkey, _)| pubkey.to_string())
+        })
+    }
+
+    /// Known users whose handle starts with `prefix`, followed users first.
+    fn mention_candidates(&self, prefix: &str) -> Vec<(&str, String)> {
+        let prefix = prefix.to_lowercase();
+        let mut candidates: Vec<(&str, String)> = self
+            .profiles
+            .iter()
+            .filter_map(|(pubkey, profile)| Some((pubkey.as_str(), profile.handle()?)))
+            .filter(|(_, handle)| handle.to_lowercase().starts_with(&prefix))
+            .collect();
+        candidates.sort_by_key(|(pubkey, handle)| {
+            let followed = *pubkey == self.pubkey || self.contacts.follows(pubkey);
+            (!followed, handle.to_lowercase())
+        });
+
+        candidates
+    }
+
+    /// Completes the `@name` being typed at the end of the input box with
+    /// the handle of the best matching known user.
+    pub fn complete_mention(&mut self) {
+        let Some(input) = &self.input_box else {
+            return;
+        };
+        let word_start = input
+            .text
+            .rfind(char::is_whitespace)
+            .map_or(0, |position| position + 1);
+        let Some(prefix) = input.text[word_start..].strip_prefix('@') else {
+            return;
+        };
+
+        let Some((_, handle)) = self.mention_candidates(prefix).into_iter().next() else {
+            return;
+        };
+        if let Some(input) = self.input_box.as_mut() {
+            input.text.truncate(word_start);
+            input.text.push_str(&format!("@{} ", handle));
+        }
+    }
+
+    /// Uploads the file whose path was typed last in the note being
+    /// written, to link to it instead once it's uploaded.
+    pub fn upload_typed_file(&mut self) {
+        let Some(input) = self
+            .input_box
+            .as_ref()
+            .filter(|input| input.purpose.is_composing())
+        else {
+            return;
+        };
+        let Some(typed) = input.text.split_whitespace().last().map(str::to_string) else {
+            self.status = Some("Type the path of the file to upload first".to_string());
+            return;
+        };
+
+        self.status = Some(format!("Uploading {}…", typed));
+        self.send(Command::Upload(local_path(&typed)));
+    }
+
+    /// Links the uploaded `file` from the note being written in place of
+    /// the `path` it was uploaded from, or tells where it is when the note
+    /// is gone.
+    pub fn attach_file(&mut self, path: &Path, file: FileMetadata) {
+        self.status = Some(format!("Uploaded to {}", file.url));
+        let Some(input) = self
+            .input_box
+            .as_mut()
+            .filter(|input| input.purpose.is_composing())
+        else {
+            return;
+        };
+
+        let typed = input
+            .text
+            .split_whitespace()
+            .find(|word| local_path(word) == path)
+            .map(str::to_string);
+        match typed {
+            Some(typed) => input.text = input.text.replacen(&typed, &file.url, 1),
+            None => {
+                if !input.text.is_empty() && !input.text.ends_with(char::is_whitespace) {
+                    input.text.push(' ');
+                }
+                input.text.push_str(&file.url);
+            }
+        }
+        input.attachments.push(file);
+    }
+
+    fn send(&self, command: Command) {
+        // The network task only stops when the application quits
+        let _ = self.commands.send(command);
+    }
+}
+
+/// The file `typed` by the user is at, with `~` standing for their home.
+fn local_path(typed: &str) -> PathBuf {
+    match (typed.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(typed),
     }
 }
@@ -0,0 +1,402 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use super::event::Event;
+use crate::nostr_client::bunker::{requested_permissions, Bunker, BunkerApp, Decision, Outcome};
+use crate::nostr_client::event::unix_time;
+use crate::nostr_client::nostr_connect::{ConnectUri, Request, Response, NOSTR_CONNECT_KIND};
+use crate::nostr_client::pool::{any_accepted, RelayPool};
+use crate::nostr_client::profile::short_pubkey;
+use crate::nostr_client::request::Filter;
+use crate::nostr_client::response::Response as RelayResponse;
+
+/// How many handled requests the bunker view keeps showing.
+const LOG_LENGTH: usize = 50;
+
+/// Requests the [`App`] makes to the bunker task.
+///
+/// [`App`]: super::app::App
+#[derive(Debug)]
+pub enum BunkerCommand {
+    /// Answer the request with the given id from the app with the given
+    /// pubkey the way the user decided.
+    Answer {
+        client: String,
+        request_id: String,
+        decision: Decision,
+    },
+    /// Connect to the app that showed the given `nostrconnect://` URI,
+    /// once the user approved what it asks for.
+    Connect(ConnectUri),
+    /// Forget an app, which then has to connect again.
+    Forget(String),
+}
+
+/// What the bunker task tells the [`App`] about.
+///
+/// [`App`]: super::app::App
+#[derive(Clone, Debug)]
+pub enum BunkerEvent {
+    /// A request the user has to approve.
+    Ask(Approval),
+    /// The apps connected, after they changed.
+    Apps(Vec<BunkerApp>),
+    /// A request was answered, as described.
+    Handled(String),
+}
+
+/// A request waiting for the user to approve it.
+#[derive(Clone, Debug)]
+pub struct Approval {
+    /// Pubkey of the app making it
+    pub client: String,
+    pub request_id: String,
+    /// Name of the app making it
+    pub app: String,
+    /// What the app would be allowed to do from then on if the user always
+    /// allows it, like `sign_event:1`
+    pub permission: String,
+    /// What the request is about
+    pub description: String,
+}
+
+/// What the bunker view shows, and how it reaches the bunker task.
+#[derive(Debug)]
+pub struct BunkerState {
+    /// URI apps connect with
+    pub uri: String,
+    pub approvals: VecDeque<Approval>,
+    pub apps: Vec<BunkerApp>,
+    /// Requests answered so far, newest first
+    pub log: VecDeque<String>,
+    commands: mpsc::UnboundedSender<BunkerCommand>,
+}
+
+impl BunkerState {
+    pub fn new(uri: String, commands: mpsc::UnboundedSender<BunkerCommand>) -> Self {
+        Self {
+            uri,
+            approvals: VecDeque::new(),
+            apps: Vec::new(),
+            log: VecDeque::new(),
+            commands,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: BunkerEvent) {
+        match event {
+            BunkerEvent::Ask(approval) => self.approvals.push_back(approval),
+            BunkerEvent::Apps(apps) => self.apps = apps,
+            BunkerEvent::Handled(line) => {
+                self.log.push_front(line);
+                self.log.truncate(LOG_LENGTH);
+            }
+        }
+    }
+
+    /// Answers the oldest request waiting for the user.
+    pub fn decide(&mut self, decision: Decision) {
+        if let Some(approval) = self.approvals.pop_front() {
+            self.send(BunkerCommand::Answer {
+                client: approval.client,
+                request_id: approval.request_id,
+                decision,
+            });
+        }
+    }
+
+    pub fn send(&self, command: BunkerCommand) {
+        // The bunker task only goes away when the application quits
+        let _ = self.commands.send(command);
+    }
+}
+
+/// What a request waiting for the user is about.
+enum Pending {
+    /// A request an app sent, with the permission the user was asked about
+    Request(Request, String),
+    /// Connecting to the app that showed this `nostrconnect://` URI
+    Connect(ConnectUri),
+}
+
+/// Background task answering the requests other apps make to the bunker
+/// on the relays, asking the user about the ones they didn't allow yet.
+pub struct BunkerService {
+    bunker: Bunker,
+    pool: RelayPool,
+    /// Relays the bunker listens at, including the ones of the apps that
+    /// connected through a `nostrconnect://` URI.
+    relays: Vec<String>,
+    /// File the apps are kept in across runs, if any.
+    apps_file: Option<PathBuf>,
+    /// Channel to the main loop.
+    sender: mpsc::UnboundedSender<Event>,
+    /// Requests from the main loop.
+    commands: mpsc::UnboundedReceiver<BunkerCommand>,
+    /// Requests waiting for the user, by pubkey of the app making them and
+    /// request id, since ids are only unique to an app.
+    pending: HashMap<(String, String), Pending>,
+    /// Events already handled, since every relay sends them.
+    seen: HashSet<String>,
+}
+
+impl BunkerService {
+    /// Constructs a new instance of [`BunkerService`].
+    pub fn new(
+        bunker: Bunker,
+        relays: Vec<String>,
+        apps_file: Option<PathBuf>,
+        sender: mpsc::UnboundedSender<Event>,
+        commands: mpsc::UnboundedReceiver<BunkerCommand>,
+    ) -> Self {
+        Self {
+            bunker,
+            pool: RelayPool::new(relays.clone()),
+            relays,
+            apps_file,
+            sender,
+            commands,
+            pending: HashMap::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Listens for requests until the application quits.
+    pub async fn run(mut self) {
+        let relays = self.relays.clone();
+        self.listen(&relays).await;
+        self.send_apps();
+
+        loop {
+            tokio::select! {
+                Some(command) = self.commands.recv() => self.handle_command(command).await,
+                (_, response) = self.pool.next_response() => {
+                    if let RelayResponse::Event { event, .. } = response {
+                        if event.id().is_some_and(|id| self.seen.insert(id.to_string())) {
+                            self.handle_request(&event.pubkey(), self.bunker.read(&event)).await;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn listen(&mut self, relays: &[String]) {
+        if relays.is_empty() {
+            return;
+        }
+        let filter = Filter::new()
+            .kinds(vec![NOSTR_CONNECT_KIND])
+            .tag('p', vec![self.bunker.pubkey().to_string()])
+            .since(unix_time());
+        if self
+            .pool
+            .subscribe_to(relays, vec![filter])
+            .await
+            .is_empty()
+        {
+            self.status("Could not reach the relays to listen at".to_string());
+        }
+    }
+
+    async fn handle_command(&mut self, command: BunkerCommand) {
+        match command {
+            BunkerCommand::Answer {
+                client,
+                request_id,
+                decision,
+            } => match self.pending.remove(&(client.clone(), request_id)) {
+                Some(Pending::Request(request, permission)) => {
+                    let response = self.bunker.decide(&client, &request, &permission, decision);
+                    self.respond(&client, &request, response).await;
+                    self.save_apps();
+                }
+                Some(Pending::Connect(uri)) => self.connect(uri, decision).await,
+                None => {}
+            },
+            BunkerCommand::Connect(uri) => {
+                // Nothing is granted until the user saw what the app asks for
+                let request = Request::new("connect", Vec::new());
+                let approval = Approval {
+                    client: uri.client_pubkey.clone(),
+                    request_id: request.id.clone(),
+                    app: uri
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| short_pubkey(&uri.client_pubkey)),
+                    permission: match uri.permissions.join(",") {
+                        permissions if permissions.is_empty() => "connect".to_string(),
+                        permissions => permissions,
+                    },
+                    description: describe_connect(&uri.permissions),
+                };
+                self.pending.insert(
+                    (uri.client_pubkey.clone(), request.id),
+                    Pending::Connect(uri),
+                );
+                self.send(BunkerEvent::Ask(approval));
+            }
+            BunkerCommand::Forget(client) => {
+                self.bunker.apps.remove(&client);
+                self.save_apps();
+            }
+        }
+    }
+
+    /// Connects to the app that showed `uri`, the way the user decided.
+    async fn connect(&mut self, uri: ConnectUri, decision: Decision) {
+        // The app only hears back once connected
+        if decision == Decision::Deny {
+            self.send(BunkerEvent::Handled(format!(
+                "{} · connect · refused",
+                self.app_name(&uri.client_pubkey)
+            )));
+            return;
+        }
+
+        let relays: Vec<String> = uri
+            .relays
+            .iter()
+            .filter(|relay| !self.relays.contains(relay))
+            .cloned()
+            .collect();
+        self.listen(&relays).await;
+        self.relays.extend(relays);
+
+        let request = Request::new("connect", Vec::new());
+        let response = self.bunker.accept(&uri, decision == Decision::Always);
+        self.respond(&uri.client_pubkey, &request, response).await;
+        self.save_apps();
+    }
+
+    async fn handle_request(&mut self, client: &str, request: anyhow::Result<Request>) {
+        let Ok(request) = request else {
+            return;
+        };
+        // Apps may send a request again while the user is still asked
+        let key = (client.to_string(), request.id.clone());
+        if self.pending.contains_key(&key) {
+            return;
+        }
+
+        match self.bunker.handle(client, &request) {
+            Outcome::Answer(response) => {
+                self.respond(client, &request, response).await;
+                // Apps connecting with a secret are added right away
+                if request.method == "connect" {
+                    self.save_apps();
+                }
+            }
+            Outcome::Ask { permission } => {
+                let approval = Approval {
+                    client: client.to_string(),
+                    request_id: request.id.clone(),
+                    app: self.app_name(client),
+                    permission: permission.clone(),
+                    description: describe(&request),
+                };
+                self.pending
+                    .insert(key, Pending::Request(request, permission));
+                self.send(BunkerEvent::Ask(approval));
+            }
+        }
+    }
+
+    /// Sends `response` to `request` to the app, and logs it.
+    async fn respond(&mut self, client: &str, request: &Request, response: Response) {
+        let outcome = match &response.error {
+            Some(error) => format!("refused: {}", error),
+            None => "done".to_string(),
+        };
+        self.send(BunkerEvent::Handled(format!(
+            "{} · {} · {}",
+            self.app_name(client),
+            describe(request),
+            outcome
+        )));
+
+        let event = match self.bunker.message(client, &response) {
+            Ok(event) => event,
+            Err(err) => return self.status(format!("Could not answer: {}", err)),
+        };
+        let relays = self.relays.clone();
+        if !any_accepted(&self.pool.publish_to(&relays, &event).await) {
+            self.status("No relay accepted the answer".to_string());
+        }
+    }
+
+    fn app_name(&self, client: &str) -> String {
+        match self.bunker.apps.get(client) {
+            Some(app) => app.display_name(),
+            None => short_pubkey(client),
+        }
+    }
+
+    /// Keeps the apps in their file, if any, and shows them.
+    fn save_apps(&self) {
+        if let Some(path) = &self.apps_file {
+            if let Err(err) = self.bunker.save_apps(path) {
+                self.status(format!("Could not save the apps: {}", err));
+            }
+        }
+        self.send_apps();
+    }
+
+    fn send_apps(&self) {
+        let apps = self.bunker.apps.values().cloned().collect();
+        self.send(BunkerEvent::Apps(apps));
+    }
+
+    fn send(&self, event: BunkerEvent) {
+        // The main loop only goes away when the application quits
+        let _ = self.sender.send(Event::Bunker(event));
+    }
+
+    fn status(&self, status: String) {
+        let _ = self.sender.send(Event::Status(status));
+    }
+}
+
+/// What `request` asks for, in words.
+fn describe(request: &Request) -> String {
+    let param = |index: usize| request.params.get(index).map(String::as_str);
+
+    match request.method.as_str() {
+        "connect" => describe_connect(&requested_permissions(request)),
+        "sign_event" => {
+            let event: Value = param(0)
+                .and_then(|json| serde_json::from_str(json).ok())
+                .unwrap_or_default();
+            let content: String = event["content"]
+                .as_str()
+                .unwrap_or_default()
+                .chars()
+                .take(60)
+                .collect();
+            match event["kind"].as_u64() {
+                Some(kind) => format!("sign an event of kind {}: {}", kind, content),
+                None => "sign an event".to_string(),
+            }
+        }
+        method @ ("nip44_encrypt" | "nip04_encrypt") => match param(0) {
+            Some(pubkey) => format!("encrypt ({}) to {}", method, short_pubkey(pubkey)),
+            None => format!("encrypt ({})", method),
+        },
+        method @ ("nip44_decrypt" | "nip04_decrypt") => match param(0) {
+            Some(pubkey) => format!("decrypt ({}) from {}", method, short_pubkey(pubkey)),
+            None => format!("decrypt ({})", method),
+        },
+        method => method.to_string(),
+    }
+}
+
+/// What an app connecting and asking for `permissions` wants, in words.
+fn describe_connect(permissions: &[String]) -> String {
+    match permissions.join(", ") {
+        permissions if permissions.is_empty() => "connect".to_string(),
+        permissions => format!("connect, and be allowed to {} without asking", permissions),
+    }
+}
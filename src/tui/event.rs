@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::time::Duration;
 
 use crossterm::event::{Event as CrosstermEvent, KeyEvent, MouseEvent};
@@ -5,9 +6,16 @@ use futures::{FutureExt, StreamExt};
 use tokio::sync::mpsc;
 
 use super::app::AppResult;
+use super::bunker::BunkerEvent;
+use crate::nostr_client::event::Event as NostrEvent;
+use crate::nostr_client::file_metadata::FileMetadata;
+use crate::nostr_client::identifier::Identifier;
+use crate::nostr_client::list::List;
+use crate::nostr_client::wallet_connect::Transaction;
 
 /// Terminal events.
-#[derive(Clone, Copy, Debug)]
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
 pub enum Event {
     /// Terminal tick.
     Tick,
@@ -17,6 +25,42 @@ pub enum Event {
     Mouse(MouseEvent),
     /// Terminal resize.
     Resize(u16, u16),
+    /// Event received from a relay.
+    Nostr(NostrEvent),
+    /// A pubkey's NIP-05 identifier was verified.
+    Verified(String, Identifier),
+    /// A user looked up by the user was found.
+    UserFound(String),
+    /// A note opened by the user was found, along with the id of the
+    /// conversation it belongs to.
+    NoteFound { id: String, root_id: String },
+    /// A list of the user was read or published, private items included.
+    List(List),
+    /// The lists of the user were fetched, so the ones not received yet
+    /// don't exist.
+    ListsFetched,
+    /// The pubkey the lightning service of a user publishes zap receipts
+    /// with, or none if they can't be zapped.
+    ZapProvider {
+        pubkey: String,
+        provider: Option<String>,
+    },
+    /// Event of a NIP-29 group, received from the relay hosting it.
+    Group { relay: String, event: NostrEvent },
+    /// News from the bunker, when signing for other apps.
+    Bunker(BunkerEvent),
+    /// The lightning address of the wallet of the user, if it has one, its
+    /// balance in millisatoshis, and its latest payments.
+    Wallet {
+        lud16: Option<String>,
+        balance: u64,
+        transactions: Vec<Transaction>,
+    },
+    /// The file of the user at `path` was uploaded, to link to from the
+    /// note being written.
+    Uploaded { path: PathBuf, file: FileMetadata },
+    /// Message to show to the user.
+    Status(String),
 }
 
 /// Terminal event handler.
@@ -78,6 +122,12 @@ impl EventHandler {
         }
     }
 
+    /// Returns a sender through which other tasks can feed events to the
+    /// main loop.
+    pub fn sender(&self) -> mpsc::UnboundedSender<Event> {
+        self.sender.clone()
+    }
+
     /// Receive the next event from the handler thread.
     ///
     /// This function will always block the current thread if
@@ -86,9 +136,6 @@ impl EventHandler {
         self.receiver
             .recv()
             .await
-            .ok_or(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "This is an IO error",
-                )))
+            .ok_or(Box::new(std::io::Error::other("This is an IO error")))
     }
 }
@@ -1,27 +1,277 @@
-use super::app::{App, AppResult};
+use super::app::{App, AppResult, InputPurpose, View};
+use crate::nostr_client::bunker::Decision;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
 /// Handles the key events and updates the state of [`App`].
 pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    // Exit application on `Ctrl-C`, even while typing
+    if key_event.modifiers == KeyModifiers::CONTROL
+        && matches!(key_event.code, KeyCode::Char('c') | KeyCode::Char('C'))
+    {
+        app.quit();
+        return Ok(());
+    }
+
+    if app.input_box.is_some() {
+        return handle_input_events(key_event, app);
+    }
+
+    app.status = None;
+    if *app.view() == View::Bunker {
+        return handle_bunker_events(key_event, app);
+    }
+    if matches!(app.view(), View::Channels | View::Channel(_))
+        && handle_channel_events(key_event, app)
+    {
+        return Ok(());
+    }
+    if matches!(app.view(), View::Group(_)) && handle_group_events(key_event, app) {
+        return Ok(());
+    }
+
     match key_event.code {
-        // Exit application on `ESC` or `q`
-        KeyCode::Esc | KeyCode::Char('q') => {
+        // Exit application on `q`
+        KeyCode::Char('q') => {
             app.quit();
         }
-        // Exit application on `Ctrl-C`
-        KeyCode::Char('c') | KeyCode::Char('C') => {
-            if key_event.modifiers == KeyModifiers::CONTROL {
-                app.quit();
-            }
+        // Go back to the previous view on `ESC`, exiting from the home view
+        KeyCode::Esc => {
+            app.back();
         }
-        KeyCode::Char('j') => {
+        KeyCode::Char('j') | KeyCode::Down => {
             app.scroll_down();
         }
-        KeyCode::Char('k') => {
+        KeyCode::Char('k') | KeyCode::Up => {
             app.scroll_up();
         }
+        KeyCode::PageDown => {
+            app.page_down();
+        }
+        KeyCode::PageUp => {
+            app.page_up();
+        }
+        KeyCode::Char('p') => {
+            app.open_selected_author();
+        }
+        KeyCode::Enter => {
+            app.open_selected_thread();
+        }
+        KeyCode::Char('r') => {
+            app.reply_to_selected();
+        }
+        KeyCode::Char('n') => {
+            app.open_input(InputPurpose::Note);
+        }
+        KeyCode::Char('d') => {
+            app.delete_selected();
+        }
+        KeyCode::Char('b') => {
+            app.repost_selected();
+        }
+        KeyCode::Char('Q') => {
+            app.quote_selected();
+        }
+        KeyCode::Char('+') => {
+            app.like_selected();
+        }
+        KeyCode::Char('R') => {
+            app.react_to_selected();
+        }
+        KeyCode::Char('f') => {
+            app.toggle_follow();
+        }
+        KeyCode::Char('z') => {
+            app.zap_selected();
+        }
+        KeyCode::Char('Z') => {
+            app.zap_profile();
+        }
+        KeyCode::Char('$') => {
+            app.open_wallet();
+        }
+        KeyCode::Char('c') => {
+            app.open_channels();
+        }
+        KeyCode::Char('G') => {
+            app.open_groups();
+        }
+        KeyCode::Char('i') if *app.view() == View::Wallet => {
+            app.open_input(InputPurpose::Receive);
+        }
+        KeyCode::Char('A') => {
+            app.open_badges();
+        }
+        KeyCode::Char('C') => {
+            app.open_agenda();
+        }
+        KeyCode::Char('v') if *app.view() == View::Agenda => {
+            app.rsvp_selected();
+        }
+        KeyCode::Char('a') if *app.view() == View::Badges => {
+            app.show_selected_badge(true);
+        }
+        KeyCode::Char('h') if *app.view() == View::Badges => {
+            app.show_selected_badge(false);
+        }
+        KeyCode::Char('x') => {
+            app.toggle_content_warning();
+        }
+        KeyCode::Char('e') => {
+            app.open_input(InputPurpose::Expiration);
+        }
+        KeyCode::Char('m') => {
+            app.mute_selected_author();
+        }
+        KeyCode::Char('M') => {
+            app.mute_selected_thread();
+        }
+        KeyCode::Char('u') => {
+            app.open_input(InputPurpose::MuteWord);
+        }
+        KeyCode::Char('!') => {
+            app.report_selected(false);
+        }
+        KeyCode::Char('X') => {
+            app.report_selected(true);
+        }
+        KeyCode::Char('l') => {
+            app.label_selected(false);
+        }
+        KeyCode::Char('L') => {
+            app.label_selected(true);
+        }
+        KeyCode::Char('B') => {
+            app.bookmark_selected();
+        }
+        KeyCode::Char('P') => {
+            app.pin_selected();
+        }
+        KeyCode::Char('g') => {
+            app.open_bookmarks();
+        }
+        KeyCode::Char('w') => {
+            app.open_input(InputPurpose::Article { draft: false });
+        }
+        KeyCode::Char('W') => {
+            app.open_input(InputPurpose::Article { draft: true });
+        }
+        KeyCode::Char('o') => {
+            app.open_input(InputPurpose::Open);
+        }
+        KeyCode::Char('E') => {
+            app.open_input(InputPurpose::EditProfile);
+        }
+        KeyCode::Char('S') => {
+            app.open_input(InputPurpose::Relay);
+        }
+        KeyCode::Char('/') => {
+            app.open_input(InputPurpose::Lookup);
+        }
         // Other handlers you could add here.
         _ => {}
     }
     Ok(())
 }
+
+/// Handles the key events of the bunker view.
+fn handle_bunker_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    match key_event.code {
+        KeyCode::Char('q') | KeyCode::Esc => {
+            app.quit();
+        }
+        KeyCode::Char('j') | KeyCode::Down => {
+            app.scroll_down();
+        }
+        KeyCode::Char('k') | KeyCode::Up => {
+            app.scroll_up();
+        }
+        KeyCode::Char('y') => {
+            app.decide(Decision::Once);
+        }
+        KeyCode::Char('a') => {
+            app.decide(Decision::Always);
+        }
+        KeyCode::Char('n') => {
+            app.decide(Decision::Deny);
+        }
+        KeyCode::Char('d') => {
+            app.forget_selected_app();
+        }
+        KeyCode::Char('c') => {
+            app.open_input(InputPurpose::ConnectApp);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Handles the key events that mean something else in the channel views,
+/// returning whether it did. The others act as they do elsewhere.
+fn handle_channel_events(key_event: KeyEvent, app: &mut App) -> bool {
+    let in_channel = matches!(app.view(), View::Channel(_));
+
+    match (key_event.code, in_channel) {
+        (KeyCode::Enter, false) => app.open_selected_channel(),
+        (KeyCode::Char('n'), false) => app.open_input(InputPurpose::CreateChannel),
+        // Messages have no thread of their own to open
+        (KeyCode::Enter, true) => {}
+        (KeyCode::Char('n') | KeyCode::Char('i'), true) => app.write_to_channel(false),
+        (KeyCode::Char('r'), true) => app.write_to_channel(true),
+        (KeyCode::Char('h'), true) => app.hide_selected_message(),
+        (KeyCode::Char('H'), true) => app.mute_selected_chatter(),
+        (KeyCode::Char('t'), true) => app.edit_channel(),
+        _ => return false,
+    }
+    true
+}
+
+/// Handles the key events that mean something else in the group view,
+/// returning whether it did. The others act as they do elsewhere.
+fn handle_group_events(key_event: KeyEvent, app: &mut App) -> bool {
+    match key_event.code {
+        // Messages have no thread of their own to open
+        KeyCode::Enter => {}
+        KeyCode::Tab => app.switch_group(1),
+        KeyCode::BackTab => app.switch_group(-1),
+        KeyCode::Char('o') => app.open_input(InputPurpose::OpenGroup),
+        KeyCode::Char('n') | KeyCode::Char('i') => app.write_to_group(false),
+        KeyCode::Char('r') => app.write_to_group(true),
+        KeyCode::Char('J') => app.join_group(),
+        KeyCode::Char('L') => app.leave_group(),
+        KeyCode::Char('a') => app.add_selected_user(),
+        KeyCode::Char('K') => app.remove_selected_user(),
+        KeyCode::Char('d') => app.delete_selected_group_event(),
+        _ => return false,
+    }
+    true
+}
+
+/// Handles the key events while the input box is open.
+fn handle_input_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    let Some(input) = app.input_box.as_mut() else {
+        return Ok(());
+    };
+
+    match key_event.code {
+        KeyCode::Esc => {
+            app.input_box = None;
+        }
+        KeyCode::Enter => {
+            app.submit_input();
+        }
+        KeyCode::Backspace => {
+            input.text.pop();
+        }
+        KeyCode::Tab => {
+            app.complete_mention();
+        }
+        KeyCode::Char('u') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.upload_typed_file();
+        }
+        KeyCode::Char(c) => {
+            input.text.push(c);
+        }
+        _ => {}
+    }
+    Ok(())
+}
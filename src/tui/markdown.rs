@@ -0,0 +1,269 @@
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+/// Renders the Markdown `text` into styled lines, drawing rules `width`
+/// columns wide. Long lines are left for the widget showing them to wrap.
+pub fn render(text: &str, width: usize) -> Vec<Line<'static>> {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut renderer = Renderer::new(width);
+    for event in Parser::new_ext(text, options) {
+        renderer.handle(event);
+    }
+    renderer.flush();
+
+    // Blocks end with a blank line, which isn't needed after the last one
+    while renderer
+        .lines
+        .last()
+        .is_some_and(|line| line.spans.is_empty())
+    {
+        renderer.lines.pop();
+    }
+    renderer.lines
+}
+
+/// Builds the lines of a document, one parser event at a time.
+struct Renderer {
+    width: usize,
+    lines: Vec<Line<'static>>,
+    /// Spans of the line being built
+    spans: Vec<Span<'static>>,
+    /// Styles of the inline elements the parser is in, innermost last
+    styles: Vec<Style>,
+    /// Next number of each list the parser is in, `None` for bullet lists
+    lists: Vec<Option<u64>>,
+    /// Marker of the list item whose first line is being built
+    bullet: Option<String>,
+    /// Destinations of the links the parser is in
+    links: Vec<String>,
+    quote_depth: usize,
+    in_code_block: bool,
+}
+
+impl Renderer {
+    fn new(width: usize) -> Renderer {
+        Renderer {
+            width,
+            lines: Vec::new(),
+            spans: Vec::new(),
+            styles: vec![Style::default()],
+            lists: Vec::new(),
+            bullet: None,
+            links: Vec::new(),
+            quote_depth: 0,
+            in_code_block: false,
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) if self.in_code_block => {
+                let style = Style::default().fg(Color::Yellow);
+                for line in text.lines() {
+                    self.spans
+                        .push(Span::styled(format!("    {}", line), style));
+                    self.flush();
+                }
+            }
+            Event::Text(text) => self.push(text.to_string()),
+            Event::Code(code) => {
+                let style = self.style().fg(Color::Yellow);
+                self.spans.push(Span::styled(format!("`{}`", code), style));
+            }
+            Event::Html(html) | Event::InlineHtml(html) => {
+                let style = Style::default().fg(Color::DarkGray);
+                for line in html.lines().filter(|line| !line.trim().is_empty()) {
+                    self.spans.push(Span::styled(line.to_string(), style));
+                    self.flush();
+                }
+            }
+            Event::InlineMath(math) | Event::DisplayMath(math) => self.push(math.to_string()),
+            Event::FootnoteReference(label) => self.push(format!("[{}]", label)),
+            Event::SoftBreak => self.push(" ".to_string()),
+            Event::HardBreak => self.flush(),
+            Event::Rule => {
+                self.flush();
+                self.spans.push(Span::styled(
+                    "─".repeat(self.width.min(80)),
+                    Style::default().fg(Color::DarkGray),
+                ));
+                self.flush();
+                self.blank();
+            }
+            Event::TaskListMarker(done) => {
+                self.push(if done { "[x] " } else { "[ ] " }.to_string());
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { level, .. } => {
+                self.flush();
+                let style = match level {
+                    HeadingLevel::H1 => Style::default()
+                        .fg(Color::Magenta)
+                        .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                    HeadingLevel::H2 => Style::default()
+                        .fg(Color::Magenta)
+                        .add_modifier(Modifier::BOLD),
+                    _ => Style::default().add_modifier(Modifier::BOLD),
+                };
+                self.styles.push(style);
+            }
+            Tag::BlockQuote(_) => {
+                self.flush();
+                self.quote_depth += 1;
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush();
+                if let CodeBlockKind::Fenced(language) = kind {
+                    if !language.is_empty() {
+                        self.spans.push(Span::styled(
+                            format!("    {}", language),
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                        self.flush();
+                    }
+                }
+                self.in_code_block = true;
+            }
+            Tag::List(start) => {
+                self.flush();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+                let bullet = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.bullet = Some(bullet);
+            }
+            Tag::Emphasis => self.push_style(Modifier::ITALIC),
+            Tag::Strong => self.push_style(Modifier::BOLD),
+            Tag::Strikethrough => self.push_style(Modifier::CROSSED_OUT),
+            Tag::Link { dest_url, .. } => {
+                let style = self
+                    .style()
+                    .fg(Color::Blue)
+                    .add_modifier(Modifier::UNDERLINED);
+                self.styles.push(style);
+                self.links.push(dest_url.to_string());
+            }
+            Tag::Image { dest_url, .. } => {
+                self.push("🖼 ".to_string());
+                let style = self.style().fg(Color::Blue);
+                self.styles.push(style);
+                self.links.push(dest_url.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                self.flush();
+                // Items of tight lists are kept together
+                if self.lists.is_empty() {
+                    self.blank();
+                }
+            }
+            TagEnd::Heading(_) => {
+                self.styles.pop();
+                self.flush();
+                self.blank();
+            }
+            TagEnd::BlockQuote(_) => {
+                self.flush();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+                self.blank();
+            }
+            TagEnd::CodeBlock => {
+                self.in_code_block = false;
+                self.blank();
+            }
+            TagEnd::List(_) => {
+                self.flush();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank();
+                }
+            }
+            TagEnd::Item => self.flush(),
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => {
+                self.styles.pop();
+            }
+            TagEnd::Link | TagEnd::Image => {
+                self.styles.pop();
+                let Some(url) = self.links.pop() else {
+                    return;
+                };
+                // Autolinks already show their destination
+                let shown = self.spans.last().is_some_and(|span| span.content == url);
+                if !shown && !url.is_empty() {
+                    self.spans.push(Span::styled(
+                        format!(" ({})", url),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn style(&self) -> Style {
+        self.styles.last().copied().unwrap_or_default()
+    }
+
+    fn push_style(&mut self, modifier: Modifier) {
+        let style = self.style().add_modifier(modifier);
+        self.styles.push(style);
+    }
+
+    /// Adds `text` to the line being built, in the current style.
+    fn push(&mut self, text: String) {
+        let style = self.style();
+        self.spans.push(Span::styled(text, style));
+    }
+
+    /// Ends the line being built, prefixing it with the quote borders and
+    /// list indentation it's in.
+    fn flush(&mut self) {
+        if self.spans.is_empty() {
+            return;
+        }
+
+        let mut line = Vec::new();
+        if self.quote_depth > 0 {
+            line.push(Span::styled(
+                "│ ".repeat(self.quote_depth),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        if !self.lists.is_empty() {
+            let indent = "  ".repeat(self.lists.len() - 1);
+            let bullet = self.bullet.take().unwrap_or_else(|| "  ".to_string());
+            line.push(Span::raw(format!("{}{}", indent, bullet)));
+        }
+        line.append(&mut self.spans);
+
+        self.lines.push(Line::from(line));
+    }
+
+    /// Separates two blocks with an empty line, unless there already is one.
+    fn blank(&mut self) {
+        if self.lines.last().is_some_and(|line| !line.spans.is_empty()) {
+            self.lines.push(Line::default());
+        }
+    }
+}
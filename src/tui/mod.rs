@@ -8,7 +8,17 @@ pub mod event;
 pub mod ui;
 
 /// Terminal user interface.
+#[allow(clippy::module_inception)]
 pub mod tui;
 
 /// Event handler.
 pub mod handler;
+
+/// Relay communication.
+pub mod network;
+
+/// Markdown renderer.
+pub mod markdown;
+
+/// Remote signing for other apps.
+pub mod bunker;
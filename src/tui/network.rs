@@ -0,0 +1,1544 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use secp256k1::{All, Secp256k1};
+use tokio::sync::{mpsc, Mutex};
+
+use super::event::Event;
+use crate::config::Config;
+use crate::nostr_client::article::{Article, ARTICLE_KIND};
+use crate::nostr_client::badge::{
+    self, BadgeAward, BADGE_AWARD_KIND, BADGE_DEFINITION_KIND, PROFILE_BADGES_IDENTIFIER,
+    PROFILE_BADGES_KIND,
+};
+use crate::nostr_client::calendar::{
+    Calendar, CALENDAR_KIND, DATE_EVENT_KIND, RSVP_KIND, TIME_EVENT_KIND,
+};
+use crate::nostr_client::channel::{
+    CHANNEL_CREATION_KIND, CHANNEL_HIDE_MESSAGE_KIND, CHANNEL_MESSAGE_KIND, CHANNEL_METADATA_KIND,
+    CHANNEL_MUTE_USER_KIND,
+};
+use crate::nostr_client::connection::AuthRequired;
+use crate::nostr_client::contact_list::{self, Contact, ContactList};
+use crate::nostr_client::deletion::DELETION_KIND;
+use crate::nostr_client::event::{unix_time, Event as NostrEvent, TEXT_NOTE_KIND};
+use crate::nostr_client::file_metadata::FILE_METADATA_KIND;
+use crate::nostr_client::file_storage;
+use crate::nostr_client::group::{
+    self, GroupId, GROUP_ADMINS_KIND, GROUP_CHAT_KIND, GROUP_DELETE_EVENT_KIND,
+    GROUP_EDIT_METADATA_KIND, GROUP_JOIN_REQUEST_KIND, GROUP_LEAVE_REQUEST_KIND,
+    GROUP_MEMBERS_KIND, GROUP_METADATA_KIND, GROUP_PUT_USER_KIND, GROUP_REMOVE_USER_KIND,
+    GROUP_ROLES_KIND,
+};
+use crate::nostr_client::identifier::{Identifier, IdentifierVerifier};
+use crate::nostr_client::label::LABEL_KIND;
+use crate::nostr_client::list::{
+    self, List, BOOKMARKS_KIND, BOOKMARK_SET_KIND, CURATION_SET_KIND, FOLLOW_SET_KIND, GROUPS_KIND,
+    MUTE_LIST_KIND, PIN_LIST_KIND,
+};
+use crate::nostr_client::pool::{any_accepted, RelayPool};
+use crate::nostr_client::profile::{self, Profile, METADATA_KIND};
+use crate::nostr_client::reaction::REACTION_KIND;
+use crate::nostr_client::reference;
+use crate::nostr_client::relay_list;
+use crate::nostr_client::report::REPORT_KIND;
+use crate::nostr_client::repost::{self, Repost, GENERIC_REPOST_KIND, REPOST_KIND};
+use crate::nostr_client::request::Filter;
+use crate::nostr_client::response::Response;
+use crate::nostr_client::signer::Signer;
+use crate::nostr_client::thread::{Thread, ThreadRefs};
+use crate::nostr_client::wallet_connect::{NoAnswer, TransactionFilter, Wallet, WalletConnectUri};
+use crate::nostr_client::zap::{self, LnurlPay, ZAP_RECEIPT_KIND};
+
+/// How many notes are fetched when opening a user's profile.
+const AUTHOR_NOTES_LIMIT: u64 = 50;
+
+/// How many notes are fetched for the users of a follow set.
+const LIST_NOTES_LIMIT: u64 = 100;
+
+/// How many zap receipts are fetched when opening a user's profile, to sum
+/// up the zaps they got.
+const PROFILE_ZAPS_LIMIT: u64 = 500;
+
+/// How many of the latest badges awarded to a user are fetched.
+const BADGE_AWARDS_LIMIT: u64 = 100;
+
+/// How many of the latest calendar events and calendars of the users
+/// followed are fetched for the agenda.
+const AGENDA_LIMIT: u64 = 500;
+
+/// How many of the latest channels are fetched when listing them.
+const CHANNELS_LIMIT: u64 = 100;
+
+/// How many of the latest messages are fetched when opening a channel.
+const CHANNEL_MESSAGES_LIMIT: u64 = 200;
+
+/// How many of the latest events of a group are fetched when opening it.
+const GROUP_EVENTS_LIMIT: u64 = 200;
+
+/// How many of the latest notes mentioning the user are fetched for the
+/// home feed.
+const MENTIONS_LIMIT: u64 = 100;
+
+/// How many of the latest reports and labels of the users trusted by the
+/// user are fetched.
+const MODERATION_LIMIT: u64 = 500;
+
+/// How many payments the wallet view shows.
+const TRANSACTIONS_LIMIT: u64 = 50;
+
+/// How often the profiles of the authors received in the meantime, the
+/// reactions to their notes and the notes they repost or quote are fetched,
+/// so they're asked for in batches.
+const ACTIVITY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Requests the [`App`] makes to the network task.
+///
+/// [`App`]: super::app::App
+#[derive(Debug)]
+pub enum Command {
+    /// Fetch the profile and latest notes of a user, also asking the given
+    /// relays besides the ones the user publishes to.
+    FetchAuthor { pubkey: String, relays: Vec<String> },
+    /// Find the user behind a NIP-05 identifier.
+    Lookup(Identifier),
+    /// Fetch the conversation started by an event, also asking the given
+    /// relays.
+    FetchThread {
+        root_id: String,
+        relays: Vec<String>,
+    },
+    /// Fetch a note and the conversation it belongs to, also asking the
+    /// given relays and the ones its author, if known, writes to.
+    FetchNote {
+        id: String,
+        relays: Vec<String>,
+        author: Option<String>,
+    },
+    /// Fetch the newest version of an addressable event, also asking the
+    /// given relays.
+    FetchAddress {
+        kind: u64,
+        pubkey: String,
+        identifier: String,
+        relays: Vec<String>,
+    },
+    /// Have the signer of the user sign an event, and publish it.
+    Publish(NostrEvent),
+    /// Same as `Publish`, also sending the event to the given relays.
+    PublishTo {
+        event: NostrEvent,
+        relays: Vec<String>,
+    },
+    /// Publish an article of the user, keeping the date it was first
+    /// published at if it's an edit.
+    PublishArticle(Article),
+    /// Publish a list of the user, encrypting its private items.
+    PublishList(List),
+    /// Fetch the lists of the user again, after they couldn't be.
+    FetchLists,
+    /// Follow a user. Without a contact list of the user, one is only
+    /// started if `new_list` is set.
+    Follow { pubkey: String, new_list: bool },
+    /// Stop following a user.
+    Unfollow(String),
+    /// Set a field of the user's profile, or remove it if `value` is empty.
+    EditProfile { field: String, value: String },
+    /// Add a relay to the relay list of the user, or change what it's used
+    /// for. A relay neither read from nor written to is removed.
+    SetRelay {
+        url: String,
+        read: bool,
+        write: bool,
+    },
+    /// Fetch the notes a list points to, and the latest ones of the users
+    /// it holds.
+    FetchListItems {
+        ids: Vec<String>,
+        authors: Vec<String>,
+    },
+    /// Get an invoice zapping a user, or the given event of theirs, from
+    /// the lightning service behind `pay_url`.
+    Zap {
+        recipient: String,
+        target: Option<NostrEvent>,
+        pay_url: String,
+        amount_msats: u64,
+        comment: String,
+    },
+    /// Find out which pubkey the lightning service of a user, behind
+    /// `pay_url`, publishes zap receipts with.
+    FetchZapProvider { pubkey: String, pay_url: String },
+    /// Fetch the badges awarded to a user and the ones they show, along
+    /// with their definitions.
+    FetchBadges(String),
+    /// Fetch the calendar events and calendars of the given users, the
+    /// events in those calendars, and the RSVPs to all of them.
+    FetchAgenda(Vec<String>),
+    /// Fetch the latest public chat channels.
+    FetchChannels,
+    /// Fetch a channel and its latest messages, also asking the given
+    /// relays, and listen for new ones.
+    OpenChannel { id: String, relays: Vec<String> },
+    /// Fetch a group and its latest events from the relay hosting it, and
+    /// listen there for new ones.
+    OpenGroup(GroupId),
+    /// Have the signer of the user sign an event of a group, and publish it
+    /// to the relay hosting the group only.
+    PublishToGroup { event: NostrEvent, group: GroupId },
+    /// Upload a file of the user to their media server, signing the
+    /// request for it.
+    Upload(PathBuf),
+    /// Fetch the balance and latest payments of the wallet of the user.
+    FetchWallet,
+    /// Have the wallet of the user make an invoice for others to pay.
+    MakeInvoice {
+        amount_msats: u64,
+        description: String,
+    },
+}
+
+/// Background task talking to the relays on behalf of the [`App`].
+///
+/// Everything it receives is forwarded to the main loop as
+/// [`Event::Nostr`], where the application state gets updated.
+///
+/// [`App`]: super::app::App
+pub struct Network {
+    pool: RelayPool,
+    /// Signs and encrypts for the user, see [`Signer`]. It's shared with
+    /// the commands run in the background.
+    signer: Arc<Mutex<Signer>>,
+    /// The pubkey of the user.
+    pubkey: String,
+    /// The contact list of the user, once one was fetched or started.
+    contacts: Option<ContactList>,
+    secp: Secp256k1<All>,
+    verifier: Arc<IdentifierVerifier>,
+    /// The wallet of the user, which pays their zaps.
+    wallet: Arc<Mutex<LazyWallet>>,
+    /// Address of the NIP-96 server files are uploaded to.
+    media_server: String,
+    /// Channel to the main loop.
+    sender: mpsc::UnboundedSender<Event>,
+    /// Requests from the main loop.
+    commands: mpsc::UnboundedReceiver<Command>,
+    /// Authors whose profile was already requested.
+    known_authors: HashSet<String>,
+    /// Authors whose profile wasn't requested yet, to ask for in a batch.
+    pending_authors: Vec<String>,
+    /// Notes whose reactions weren't fetched yet, with their authors.
+    pending_notes: Vec<(String, String)>,
+    /// Notes whose reactions were already fetched.
+    known_notes: HashSet<String>,
+    /// Reposted or quoted notes that weren't received yet, with a relay
+    /// they may be found at and their author, if known.
+    pending_targets: Vec<(String, Option<String>, Option<String>)>,
+    /// Subscriptions to the messages of the channel open, per relay.
+    channel_subscriptions: Vec<(String, String)>,
+    /// Subscription to the events of the group open, on its relay.
+    group_subscriptions: Vec<(String, String)>,
+    /// Subscriptions to the notes, reports and labels of the users followed.
+    home_subscriptions: Vec<(String, String)>,
+}
+
+impl Network {
+    /// Constructs a new instance of [`Network`].
+    pub fn new(
+        config: Config,
+        mut signer: Signer,
+        sender: mpsc::UnboundedSender<Event>,
+        commands: mpsc::UnboundedReceiver<Command>,
+    ) -> Self {
+        // Remote signers may need the user to approve what they're asked
+        let approvals = sender.clone();
+        signer.on_auth_url(move |url| {
+            let _ = approvals.send(Event::Status(format!("Approve the request at {}", url)));
+        });
+
+        Self {
+            pool: RelayPool::new(config.relays),
+            pubkey: signer.pubkey().to_string(),
+            signer: Arc::new(Mutex::new(signer)),
+            contacts: None,
+            secp: Secp256k1::new(),
+            verifier: Arc::new(IdentifierVerifier::new()),
+            wallet: Arc::new(Mutex::new(LazyWallet {
+                uri: config.wallet,
+                wallet: None,
+            })),
+            media_server: config.media_server,
+            sender,
+            commands,
+            known_authors: HashSet::new(),
+            pending_authors: Vec::new(),
+            pending_notes: Vec::new(),
+            known_notes: HashSet::new(),
+            pending_targets: Vec::new(),
+            channel_subscriptions: Vec::new(),
+            group_subscriptions: Vec::new(),
+            home_subscriptions: Vec::new(),
+        }
+    }
+
+    /// Opens the home feed and keeps forwarding what the relays send.
+    pub async fn run(mut self) {
+        let pubkey = self.pubkey.clone();
+        let contact_list = ContactList::fetch_event(&mut self.pool, &pubkey).await;
+        self.contacts = contact_list
+            .as_ref()
+            .and_then(|event| ContactList::from_event(event).ok());
+        if let Some(event) = contact_list {
+            self.forward(event);
+        }
+        self.fetch_lists().await;
+        self.fetch_channel_moderation().await;
+
+        self.subscribe_home().await;
+        self.fetch_mentions().await;
+        let unusable = self.pool.auth_required_relays();
+        if !unusable.is_empty() {
+            self.send(Event::Status(format!(
+                "Skipping {}: authentication isn't supported",
+                unusable.join(", ")
+            )));
+        }
+
+        let mut activity = tokio::time::interval(ACTIVITY_INTERVAL);
+        loop {
+            tokio::select! {
+                Some(command) = self.commands.recv() => self.handle_command(command).await,
+                _ = activity.tick() => self.fetch_activity().await,
+                (url, response) = self.pool.next_response() => {
+                    let Response::Event { subscription_id, event } = response else {
+                        continue;
+                    };
+                    let subscription = (url, subscription_id);
+                    match self.group_subscriptions.contains(&subscription) {
+                        true => self.forward_group(&subscription.0, event).await,
+                        false => self.handle_event(event).await,
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::FetchAuthor { pubkey, relays } => self.fetch_author(&pubkey, &relays).await,
+            Command::Lookup(identifier) => match self.verifier.lookup(&identifier).await {
+                Ok(entry) => {
+                    self.send(Event::UserFound(entry.pubkey.clone()));
+                    self.fetch_author(&entry.pubkey, &entry.relays).await;
+                }
+                Err(err) => self.send(Event::Status(err.to_string())),
+            },
+            Command::FetchThread { root_id, relays } => self.fetch_thread(&root_id, &relays).await,
+            Command::FetchNote { id, relays, author } => {
+                self.fetch_note(&id, relays, author.as_deref()).await
+            }
+            Command::FetchAddress {
+                kind,
+                pubkey,
+                identifier,
+                relays,
+            } => self.fetch_address(kind, &pubkey, &identifier, relays).await,
+            Command::Publish(event) => {
+                self.publish(event).await;
+            }
+            Command::PublishTo { event, relays } => {
+                self.publish_to(event, &relays).await;
+            }
+            Command::FetchBadges(pubkey) => self.fetch_badges(&pubkey).await,
+            Command::FetchAgenda(authors) => self.fetch_agenda(&authors).await,
+            Command::FetchChannels => self.fetch_channels().await,
+            Command::OpenGroup(group) => self.open_group(&group).await,
+            Command::PublishToGroup { event, group } => self.publish_to_group(event, &group).await,
+            Command::OpenChannel { id, relays } => self.open_channel(&id, relays).await,
+            Command::PublishArticle(article) => self.publish_article(article).await,
+            Command::PublishList(list) => self.publish_list(list).await,
+            Command::FetchLists => self.fetch_lists().await,
+            Command::Follow { pubkey, new_list } => {
+                let known = match new_list {
+                    true => Some(self.contacts.clone().unwrap_or_default()),
+                    false => self.contacts.clone(),
+                };
+                let mut signer = self.signer.lock().await;
+                let published = contact_list::follow(
+                    &mut self.pool,
+                    known.as_ref(),
+                    Contact::new(&pubkey),
+                    &mut signer,
+                )
+                .await;
+                drop(signer);
+                self.update_contacts(published).await;
+            }
+            Command::Unfollow(pubkey) => {
+                let mut signer = self.signer.lock().await;
+                let published = contact_list::unfollow(
+                    &mut self.pool,
+                    self.contacts.as_ref(),
+                    &pubkey,
+                    &mut signer,
+                )
+                .await;
+                drop(signer);
+                self.update_contacts(published).await;
+            }
+            Command::EditProfile { field, value } => {
+                let mut signer = self.signer.lock().await;
+                let updated = profile::update(&mut self.pool, &mut signer, |profile| {
+                    profile.set(&field, &value)
+                })
+                .await;
+                drop(signer);
+                match updated {
+                    Ok(event) => {
+                        self.forward(event);
+                        self.send(Event::Status("Profile updated".to_string()));
+                    }
+                    Err(err) => self.send(Event::Status(format!(
+                        "Could not update your profile: {}",
+                        err
+                    ))),
+                }
+            }
+            Command::SetRelay { url, read, write } => {
+                let mut signer = self.signer.lock().await;
+                let updated = relay_list::update(&mut self.pool, &mut signer, |list| {
+                    list.set(&url, read, write)
+                })
+                .await;
+                drop(signer);
+                match updated {
+                    Ok(list) => self.send(Event::Status(format!(
+                        "Relay list updated, with {} relays",
+                        list.relays.len()
+                    ))),
+                    Err(err) => self.send(Event::Status(format!(
+                        "Could not update your relay list: {}",
+                        err
+                    ))),
+                }
+            }
+            Command::FetchListItems { ids, authors } => self.fetch_list_items(ids, &authors).await,
+            Command::Zap {
+                recipient,
+                target,
+                pay_url,
+                amount_msats,
+                comment,
+            } => {
+                let lnurl = match zap::encode_lnurl(&pay_url) {
+                    Ok(lnurl) => lnurl,
+                    Err(err) => return self.send(Event::Status(format!("Could not zap: {}", err))),
+                };
+                let request = zap::zap_request(
+                    &self.pubkey,
+                    &recipient,
+                    target.as_ref(),
+                    amount_msats,
+                    &self.zap_relays(&recipient),
+                    &lnurl,
+                    &comment,
+                );
+                let background = self.background();
+                tokio::spawn(async move {
+                    if let Err(err) = background.zap(request, &pay_url, amount_msats).await {
+                        background.status(format!("Could not zap: {}", err));
+                    }
+                });
+            }
+            Command::FetchZapProvider { pubkey, pay_url } => {
+                let sender = self.sender.clone();
+                tokio::spawn(async move {
+                    let provider = match LnurlPay::fetch(&pay_url).await {
+                        Ok(pay) => pay.zap_provider(),
+                        Err(_) => None,
+                    };
+                    let _ = sender.send(Event::ZapProvider { pubkey, provider });
+                });
+            }
+            Command::Upload(path) => {
+                let (background, server) = (self.background(), self.media_server.clone());
+                tokio::spawn(async move {
+                    let mut signer = background.signer.lock().await;
+                    match file_storage::upload(&mut signer, &server, &path).await {
+                        Ok(file) => background.send(Event::Uploaded { path, file }),
+                        Err(err) => background.status(format!("Could not upload: {}", err)),
+                    }
+                });
+            }
+            Command::FetchWallet => {
+                let background = self.background();
+                tokio::spawn(async move {
+                    if let Err(err) = background.fetch_wallet().await {
+                        background.status(format!("Could not reach the wallet: {}", err));
+                    }
+                });
+            }
+            Command::MakeInvoice {
+                amount_msats,
+                description,
+            } => {
+                let background = self.background();
+                tokio::spawn(async move {
+                    if let Err(err) = background.make_invoice(amount_msats, &description).await {
+                        background.status(format!("Could not make an invoice: {}", err));
+                    }
+                });
+            }
+        }
+    }
+
+    async fn fetch_thread(&mut self, root_id: &str, relays: &[String]) {
+        let thread = Thread::fetch(&mut self.pool, root_id, relays).await;
+        for (_, event) in thread.flatten() {
+            self.handle_event(event.clone()).await;
+        }
+    }
+
+    /// Fetches the note with id `id` from `relays`, the relays its author
+    /// writes to and the bootstrap relays, then the conversation it is part
+    /// of.
+    async fn fetch_note(&mut self, id: &str, mut relays: Vec<String>, author: Option<&str>) {
+        if let Some(author) = author {
+            self.pool.load_relay_lists(&[author.to_string()]).await;
+            relays.extend(self.pool.outbox_relays(author));
+        }
+        relays.extend(self.pool.bootstrap_relays().iter().cloned());
+
+        let filter = Filter::new().ids(vec![id.to_string()]);
+        let Some(note) = self.pool.fetch_from(&relays, vec![filter]).await.pop() else {
+            self.send(Event::Status("The note wasn't found".to_string()));
+            return;
+        };
+
+        let root_id = ThreadRefs::root_id(&note).unwrap_or_else(|| id.to_string());
+        // Channels and their messages open in the channel
+        if matches!(note.kind, CHANNEL_CREATION_KIND | CHANNEL_MESSAGE_KIND) {
+            self.open_channel(&root_id, relays).await;
+            self.send(Event::NoteFound {
+                id: id.to_string(),
+                root_id,
+            });
+            return;
+        }
+        self.handle_event(note).await;
+        self.send(Event::NoteFound {
+            id: id.to_string(),
+            root_id: root_id.clone(),
+        });
+        self.fetch_thread(&root_id, &relays).await;
+    }
+
+    /// Fetches the newest version of the addressable event of `pubkey` with
+    /// the given kind and identifier, and shows it.
+    async fn fetch_address(
+        &mut self,
+        kind: u64,
+        pubkey: &str,
+        identifier: &str,
+        relays: Vec<String>,
+    ) {
+        let Some(newest) = self.fetch_newest(kind, pubkey, identifier, relays).await else {
+            self.send(Event::Status("The event wasn't found".to_string()));
+            return;
+        };
+
+        self.fetch_profiles(&[pubkey.to_string()]).await;
+        self.forward(newest);
+    }
+
+    /// Fetches the newest version of the addressable event of `pubkey` with
+    /// the given kind and identifier, from `relays`, the relays its author
+    /// writes to and the bootstrap relays.
+    async fn fetch_newest(
+        &mut self,
+        kind: u64,
+        pubkey: &str,
+        identifier: &str,
+        mut relays: Vec<String>,
+    ) -> Option<NostrEvent> {
+        let authors = vec![pubkey.to_string()];
+        self.pool.load_relay_lists(&authors).await;
+        relays.extend(self.pool.outbox_relays(pubkey));
+        relays.extend(self.pool.bootstrap_relays().iter().cloned());
+
+        let filter = Filter::new()
+            .kinds(vec![kind])
+            .authors(authors)
+            .tag('d', vec![identifier.to_string()]);
+        let events = self.pool.fetch_from(&relays, vec![filter]).await;
+        events.into_iter().max_by_key(|event| event.created_at)
+    }
+
+    /// Publishes `article`. Articles that were published before keep their
+    /// original publication date, while drafts have none.
+    async fn publish_article(&mut self, mut article: Article) {
+        if !article.draft {
+            let pubkey = self.pubkey.clone();
+            let published = self
+                .fetch_newest(ARTICLE_KIND, &pubkey, &article.identifier, Vec::new())
+                .await
+                .and_then(|event| Article::from_event(&event).ok());
+            article.published_at = published
+                .and_then(|published| published.published_at)
+                .or(Some(unix_time()));
+        }
+
+        let event = article.to_event(&self.pubkey);
+        self.publish(event).await;
+    }
+
+    /// Fetches the lists and sets of the user, with their private items.
+    async fn fetch_lists(&mut self) {
+        let filter = Filter::new()
+            .kinds(vec![
+                MUTE_LIST_KIND,
+                PIN_LIST_KIND,
+                BOOKMARKS_KIND,
+                GROUPS_KIND,
+                FOLLOW_SET_KIND,
+                BOOKMARK_SET_KIND,
+                CURATION_SET_KIND,
+            ])
+            .authors(vec![self.pubkey.clone()]);
+        let pubkey = [self.pubkey.clone()];
+
+        for event in self.pool.fetch_from_authors(filter, &pubkey).await {
+            self.forward_own_list(&event).await;
+        }
+
+        // Relays that failed to answer are dropped, so lists not received
+        // may still exist if none of the user's relays is left
+        let fetched = self
+            .pool
+            .outbox_relays(&self.pubkey)
+            .iter()
+            .any(|url| self.pool.is_connected(url));
+        match fetched {
+            true => self.send(Event::ListsFetched),
+            false => self.send(Event::Status(
+                "Could not fetch your lists, they can't be changed for now".to_string(),
+            )),
+        }
+    }
+
+    /// Lets the main loop know about a list of the user, once its private
+    /// items are decrypted. When they can't be, the list keeps them
+    /// encrypted as they are, so that publishing it again doesn't lose them.
+    async fn forward_own_list(&mut self, event: &NostrEvent) {
+        let Ok(mut list) = List::from_event(event) else {
+            return;
+        };
+        if let Err(err) = self.read_private_items(&mut list, event).await {
+            list.unreadable_content = Some(event.content.clone());
+            self.send(Event::Status(format!(
+                "Could not read the private items of your list of kind {}: {}",
+                event.kind, err
+            )));
+        }
+
+        self.send(Event::List(list));
+    }
+
+    async fn read_private_items(
+        &mut self,
+        list: &mut List,
+        event: &NostrEvent,
+    ) -> anyhow::Result<()> {
+        if !event.content.is_empty() {
+            let json = self
+                .signer
+                .lock()
+                .await
+                .decrypt(&self.pubkey, &event.content)
+                .await?;
+            list.read_private(&json)?;
+        }
+
+        Ok(())
+    }
+
+    /// Publishes `list`, letting the main loop know once it's accepted.
+    async fn publish_list(&mut self, mut list: List) {
+        let content = match (&list.unreadable_content, list.private.is_empty()) {
+            (Some(content), true) => Ok(content.clone()),
+            (Some(_), false) => Err(anyhow::anyhow!(
+                "its private items couldn't be read, so none can be added"
+            )),
+            (None, true) => Ok(String::new()),
+            (None, false) => match list.private_json() {
+                Ok(json) => {
+                    let mut signer = self.signer.lock().await;
+                    signer.nip44_encrypt(&self.pubkey, &json).await
+                }
+                Err(err) => Err(err),
+            },
+        };
+        let event = match content {
+            Ok(content) => list.to_event(content),
+            Err(err) => {
+                self.send(Event::Status(format!(
+                    "Could not publish the list: {}",
+                    err
+                )));
+                return;
+            }
+        };
+
+        list.created_at = event.created_at;
+        if self.publish(event).await {
+            self.send(Event::List(list));
+        }
+    }
+
+    /// The relays the zaps to `recipient` are looked for on, which their
+    /// receipts go to.
+    fn zap_relays(&self, recipient: &str) -> Vec<String> {
+        let mut relays = self.pool.bootstrap_relays().to_vec();
+        for relay in self.pool.inbox_relays(recipient) {
+            if !relays.contains(&relay) {
+                relays.push(relay);
+            }
+        }
+
+        relays
+    }
+
+    /// What the commands that may take long run with in the background.
+    fn background(&self) -> Background {
+        Background {
+            signer: self.signer.clone(),
+            wallet: self.wallet.clone(),
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Fetches the notes with the given ids, and the latest notes of
+    /// `authors`.
+    async fn fetch_list_items(&mut self, ids: Vec<String>, authors: &[String]) {
+        let mut events = Vec::new();
+        if !ids.is_empty() {
+            let relays = self.pool.bootstrap_relays().to_vec();
+            let filter = Filter::new().ids(ids);
+            events.extend(self.pool.fetch_from(&relays, vec![filter]).await);
+        }
+        if !authors.is_empty() {
+            let filter = Filter::new()
+                .kinds(vec![
+                    TEXT_NOTE_KIND,
+                    REPOST_KIND,
+                    GENERIC_REPOST_KIND,
+                    FILE_METADATA_KIND,
+                ])
+                .limit(LIST_NOTES_LIMIT);
+            events.extend(self.pool.fetch_from_authors(filter, authors).await);
+        }
+
+        for event in events {
+            self.handle_event(event).await;
+        }
+    }
+
+    /// Fetches the calendar events and calendars of `authors` from the
+    /// relays they write to, then the events of other users in those
+    /// calendars, and the RSVPs to all of them.
+    async fn fetch_agenda(&mut self, authors: &[String]) {
+        let filter = Filter::new()
+            .kinds(vec![DATE_EVENT_KIND, TIME_EVENT_KIND, CALENDAR_KIND])
+            .limit(AGENDA_LIMIT);
+        let mut events = self.pool.fetch_from_authors(filter, authors).await;
+
+        let known: HashSet<String> = events.iter().filter_map(NostrEvent::address).collect();
+        let mut listed: HashMap<String, Vec<String>> = HashMap::new();
+        for calendar in events
+            .iter()
+            .filter_map(|event| Calendar::from_event(event).ok())
+        {
+            for address in calendar
+                .events
+                .iter()
+                .filter(|address| !known.contains(*address))
+            {
+                let mut parts = address.splitn(3, ':').skip(1);
+                if let (Some(author), Some(identifier)) = (parts.next(), parts.next()) {
+                    let identifiers = listed.entry(author.to_string()).or_default();
+                    identifiers.push(identifier.to_string());
+                }
+            }
+        }
+        for (author, identifiers) in listed {
+            let filter = Filter::new()
+                .kinds(vec![DATE_EVENT_KIND, TIME_EVENT_KIND])
+                .tag('d', identifiers);
+            events.extend(self.pool.fetch_from_authors(filter, &[author]).await);
+        }
+
+        // Answers are looked for where the users followed write, and on the
+        // bootstrap relays for the ones of everyone else
+        let addresses: Vec<String> = events
+            .iter()
+            .filter(|event| matches!(event.kind, DATE_EVENT_KIND | TIME_EVENT_KIND))
+            .filter_map(NostrEvent::address)
+            .collect();
+        if !addresses.is_empty() {
+            let rsvps = Filter::new().kinds(vec![RSVP_KIND]).tag('a', addresses);
+            let relays = self.pool.bootstrap_relays().to_vec();
+            events.extend(self.pool.fetch_from(&relays, vec![rsvps.clone()]).await);
+            events.extend(self.pool.fetch_from_authors(rsvps, authors).await);
+        }
+
+        let mut people: Vec<String> = events.iter().map(NostrEvent::pubkey).collect();
+        people.sort();
+        people.dedup();
+        self.fetch_profiles(&people).await;
+        for event in events {
+            self.handle_event(event).await;
+        }
+    }
+
+    /// Fetches the hidden messages and muted users of the user, so that
+    /// channels leave them out.
+    async fn fetch_channel_moderation(&mut self) {
+        let filter = Filter::new().kinds(vec![CHANNEL_HIDE_MESSAGE_KIND, CHANNEL_MUTE_USER_KIND]);
+        let pubkey = [self.pubkey.clone()];
+
+        for event in self.pool.fetch_from_authors(filter, &pubkey).await {
+            self.forward(event);
+        }
+    }
+
+    /// Fetches the latest channels from the bootstrap relays, along with
+    /// their metadata.
+    async fn fetch_channels(&mut self) {
+        let relays = self.pool.bootstrap_relays().to_vec();
+        let filter = Filter::new()
+            .kinds(vec![CHANNEL_CREATION_KIND])
+            .limit(CHANNELS_LIMIT);
+        let channels = self.pool.fetch_from(&relays, vec![filter]).await;
+        if channels.is_empty() {
+            self.send(Event::Status("No channel was found".to_string()));
+            return;
+        }
+
+        let ids: Vec<String> = channels
+            .iter()
+            .filter_map(|event| event.id().map(str::to_string))
+            .collect();
+        let metadata = Filter::new()
+            .kinds(vec![CHANNEL_METADATA_KIND])
+            .tag('e', ids);
+        let updates = self.pool.fetch_from(&relays, vec![metadata]).await;
+
+        for event in channels.into_iter().chain(updates) {
+            self.forward(event);
+        }
+    }
+
+    /// Fetches the channel with id `id` and its latest messages from
+    /// `relays` and the bootstrap relays, then listens there for new ones.
+    async fn open_channel(&mut self, id: &str, mut relays: Vec<String>) {
+        relays.extend(self.pool.bootstrap_relays().iter().cloned());
+        relays.sort();
+        relays.dedup();
+
+        let filters = vec![
+            Filter::new().ids(vec![id.to_string()]),
+            Filter::new()
+                .kinds(vec![CHANNEL_METADATA_KIND])
+                .tag('e', vec![id.to_string()]),
+            Filter::new()
+                .kinds(vec![CHANNEL_MESSAGE_KIND])
+                .tag('e', vec![id.to_string()])
+                .limit(CHANNEL_MESSAGES_LIMIT),
+        ];
+        let events = self.pool.fetch_from(&relays, filters).await;
+        let authors: Vec<String> = events.iter().map(NostrEvent::pubkey).collect();
+        self.fetch_profiles(&authors).await;
+        for event in events {
+            self.track_note(&event);
+            self.forward(event);
+        }
+
+        let subscriptions = std::mem::take(&mut self.channel_subscriptions);
+        self.pool.close_subscriptions(&subscriptions).await;
+        let live = Filter::new()
+            .kinds(vec![CHANNEL_MESSAGE_KIND])
+            .tag('e', vec![id.to_string()])
+            .since(unix_time());
+        self.channel_subscriptions = self.pool.subscribe_to(&relays, vec![live]).await;
+    }
+
+    /// Fetches the events of the relay describing `group`, and the latest
+    /// ones of its users, then listens for new ones. Private groups only
+    /// show to members the relay knows, which takes authenticating with
+    /// NIP-42, so they stay empty here.
+    async fn open_group(&mut self, group: &GroupId) {
+        let relays = [group.relay.clone()];
+        if self.group_relay_pubkey(&group.relay).await.is_none() {
+            self.send(Event::Status(format!(
+                "{} doesn't tell which key it signs groups with, so their name and members can't be trusted",
+                group.relay
+            )));
+        }
+        let filters = vec![
+            Filter::new()
+                .kinds(vec![
+                    GROUP_METADATA_KIND,
+                    GROUP_ADMINS_KIND,
+                    GROUP_MEMBERS_KIND,
+                    GROUP_ROLES_KIND,
+                ])
+                .tag('d', vec![group.id.clone()]),
+            Filter::new()
+                .kinds(vec![
+                    GROUP_CHAT_KIND,
+                    GROUP_PUT_USER_KIND,
+                    GROUP_REMOVE_USER_KIND,
+                    GROUP_EDIT_METADATA_KIND,
+                    GROUP_DELETE_EVENT_KIND,
+                    GROUP_JOIN_REQUEST_KIND,
+                    GROUP_LEAVE_REQUEST_KIND,
+                ])
+                .tag('h', vec![group.id.clone()])
+                .limit(GROUP_EVENTS_LIMIT),
+        ];
+        let events = self.pool.fetch_from(&relays, filters.clone()).await;
+        if events.is_empty() {
+            self.send(Event::Status(format!("Nothing was found for {}", group)));
+        }
+
+        let authors: Vec<String> = events
+            .iter()
+            .filter(|event| !group::is_relay_kind(event.kind))
+            .map(NostrEvent::pubkey)
+            .collect();
+        self.fetch_profiles(&authors).await;
+        for event in events {
+            self.forward_group(&group.relay, event).await;
+        }
+
+        let subscriptions = std::mem::take(&mut self.group_subscriptions);
+        self.pool.close_subscriptions(&subscriptions).await;
+        let live = filters
+            .into_iter()
+            .map(|filter| filter.since(unix_time()))
+            .collect();
+        self.group_subscriptions = self.pool.subscribe_to(&relays, live).await;
+    }
+
+    /// Lets the main loop know about an event of a group hosted on `relay`.
+    /// The events describing the group are dropped unless they're signed by
+    /// the relay, as its NIP-11 document tells, and so are the ones that
+    /// don't name their group.
+    async fn forward_group(&mut self, relay: &str, event: NostrEvent) {
+        if !group::is_group_event(&event) {
+            return;
+        }
+        if group::is_relay_kind(event.kind) {
+            let relay_pubkey = self.group_relay_pubkey(relay).await;
+            if !group::is_trusted(&event, relay_pubkey.as_deref()) {
+                log::debug!("dropping group event not signed by {}", relay);
+                return;
+            }
+        } else {
+            self.queue_profiles(&[event.pubkey()]);
+            self.track_note(&event);
+        }
+
+        self.send(Event::Group {
+            relay: relay.to_string(),
+            event,
+        });
+    }
+
+    /// The pubkey the relay at `relay` signs the events describing its
+    /// groups with, if its NIP-11 document gives one.
+    async fn group_relay_pubkey(&mut self, relay: &str) -> Option<String> {
+        let conn = self.pool.connection(relay).await.ok()?;
+        conn.relay_info()?.pubkey.clone()
+    }
+
+    /// Has `event` signed and publishes it to the relay hosting `group`,
+    /// which decides whether the user may.
+    async fn publish_to_group(&mut self, mut event: NostrEvent, group: &GroupId) {
+        if let Err(err) = self.signer.lock().await.sign(&mut event).await {
+            self.send(Event::Status(format!("Could not sign the event: {}", err)));
+            return;
+        }
+
+        let results = self
+            .pool
+            .publish_to(std::slice::from_ref(&group.relay), &event)
+            .await;
+        let refusal = results.into_iter().find_map(|(_, result)| match result {
+            Ok(Response::Ok { accepted: true, .. }) => None,
+            Ok(Response::Ok { message, .. }) => Some(message),
+            Ok(_) => Some(String::new()),
+            Err(err) => Some(err.to_string()),
+        });
+        match refusal {
+            Some(reason) if reason.is_empty() => self.send(Event::Status(
+                "The group's relay refused the event".to_string(),
+            )),
+            Some(reason) => self.send(Event::Status(format!(
+                "The group's relay refused the event: {}",
+                reason
+            ))),
+            None => {
+                self.send(Event::Status("Published".to_string()));
+                self.forward_group(&group.relay, event).await;
+            }
+        }
+    }
+
+    /// Has `event` signed and publishes it, showing it right away once a
+    /// relay accepted it. Returns whether one did.
+    async fn publish(&mut self, event: NostrEvent) -> bool {
+        self.publish_to(event, &[]).await
+    }
+
+    /// Same as `publish`, also sending `event` to `relays`.
+    async fn publish_to(&mut self, mut event: NostrEvent, relays: &[String]) -> bool {
+        if let Err(err) = self.signer.lock().await.sign(&mut event).await {
+            self.send(Event::Status(format!("Could not sign the event: {}", err)));
+            return false;
+        }
+
+        let mut results = self.pool.publish(&event).await;
+        if !relays.is_empty() {
+            results.extend(self.pool.publish_to(relays, &event).await);
+        }
+        if !any_accepted(&results) {
+            let unusable: Vec<&str> = results
+                .iter()
+                .filter_map(|(_, result)| result.as_ref().err()?.downcast_ref::<AuthRequired>())
+                .map(|AuthRequired(url)| url.as_str())
+                .collect();
+            let status = match unusable.is_empty() {
+                true => "No relay accepted the event".to_string(),
+                false => format!(
+                    "No relay accepted the event, {} require authentication, which isn't supported",
+                    unusable.join(", ")
+                ),
+            };
+            self.send(Event::Status(status));
+            return false;
+        }
+
+        self.send(Event::Status("Published".to_string()));
+        self.forward(event);
+        true
+    }
+
+    async fn handle_event(&mut self, event: NostrEvent) {
+        if list::is_list(&event) {
+            // The user's own lists have private items to decrypt
+            if event.pubkey() == self.pubkey {
+                self.forward_own_list(&event).await;
+                return;
+            }
+            if event.kind == PIN_LIST_KIND {
+                for id in event.tag_values("e") {
+                    self.pending_targets.push((id.to_string(), None, None));
+                }
+            }
+        }
+        if matches!(event.kind, TEXT_NOTE_KIND | FILE_METADATA_KIND) {
+            self.queue_profiles(&[event.pubkey()]);
+            self.track_note(&event);
+            self.fetch_references(&event).await;
+        }
+        if let Ok(repost) = Repost::from_event(&event, &self.secp) {
+            // The feed reads as notes, so reposts of other kinds of events,
+            // like articles, are left out
+            if repost
+                .target_kind
+                .is_some_and(|kind| !matches!(kind, TEXT_NOTE_KIND | FILE_METADATA_KIND))
+            {
+                return;
+            }
+            self.queue_profiles(&[event.pubkey()]);
+            match repost.embedded {
+                Some(original) => {
+                    self.queue_profiles(&[original.pubkey()]);
+                    self.track_note(&original);
+                }
+                None => self.pending_targets.push((
+                    repost.target_id,
+                    repost.relay,
+                    repost.target_author,
+                )),
+            }
+        }
+        if event.kind == CHANNEL_MESSAGE_KIND {
+            self.queue_profiles(&[event.pubkey()]);
+            self.track_note(&event);
+        }
+        if event.kind == METADATA_KIND {
+            self.verify_identifier(&event);
+        }
+
+        self.forward(event);
+    }
+
+    /// Fetches the profile and latest notes of `pubkey` from their write
+    /// relays and from `relays`.
+    async fn fetch_author(&mut self, pubkey: &str, relays: &[String]) {
+        let authors = [pubkey.to_string()];
+        let filter = Filter::new()
+            .kinds(vec![
+                TEXT_NOTE_KIND,
+                REPOST_KIND,
+                GENERIC_REPOST_KIND,
+                FILE_METADATA_KIND,
+            ])
+            .limit(AUTHOR_NOTES_LIMIT);
+
+        let mut events = self.pool.fetch_from_authors(filter.clone(), &authors).await;
+        if !relays.is_empty() {
+            let filters = vec![
+                filter.authors(authors.to_vec()),
+                Filter::new()
+                    .kinds(vec![METADATA_KIND])
+                    .authors(authors.to_vec()),
+            ];
+            events.extend(self.pool.fetch_from(relays, filters).await);
+        }
+        // Their pinned notes are shown first
+        let pins = Filter::new().kinds(vec![PIN_LIST_KIND]);
+        events.extend(self.pool.fetch_from_authors(pins, &authors).await);
+        // Zaps are published where they read mentions
+        let mut zap_relays = self.pool.bootstrap_relays().to_vec();
+        zap_relays.extend(self.pool.inbox_relays(pubkey));
+        let zaps = Filter::new()
+            .kinds(vec![ZAP_RECEIPT_KIND])
+            .tag('p', authors.to_vec())
+            .limit(PROFILE_ZAPS_LIMIT);
+        events.extend(self.pool.fetch_from(&zap_relays, vec![zaps]).await);
+
+        self.fetch_profiles(&authors).await;
+        for event in events {
+            self.handle_event(event).await;
+        }
+        self.fetch_badges(pubkey).await;
+    }
+
+    /// Fetches the badges `pubkey` shows on their profile and the latest
+    /// ones they were awarded, then the definitions of those badges from
+    /// their issuers.
+    async fn fetch_badges(&mut self, pubkey: &str) {
+        let authors = [pubkey.to_string()];
+        let shown = Filter::new()
+            .kinds(vec![PROFILE_BADGES_KIND])
+            .tag('d', vec![PROFILE_BADGES_IDENTIFIER.to_string()]);
+        let mut events = self.pool.fetch_from_authors(shown, &authors).await;
+
+        // Awards are published where the users awarded read mentions
+        let mut relays = self.pool.bootstrap_relays().to_vec();
+        relays.extend(self.pool.inbox_relays(pubkey));
+        let shown_ids: Vec<String> = events
+            .iter()
+            .flat_map(badge::profile_badges)
+            .map(|(_, award_id)| award_id)
+            .collect();
+        let mut filters = vec![Filter::new()
+            .kinds(vec![BADGE_AWARD_KIND])
+            .tag('p', authors.to_vec())
+            .limit(BADGE_AWARDS_LIMIT)];
+        if !shown_ids.is_empty() {
+            filters.push(Filter::new().ids(shown_ids));
+        }
+        events.extend(self.pool.fetch_from(&relays, filters).await);
+
+        let mut badges: HashMap<String, Vec<String>> = HashMap::new();
+        for award in events
+            .iter()
+            .filter_map(|event| BadgeAward::from_event(event).ok())
+        {
+            if let Some(identifier) = award.address.splitn(3, ':').nth(2) {
+                let identifiers = badges.entry(award.issuer).or_default();
+                if !identifiers.iter().any(|known| known == identifier) {
+                    identifiers.push(identifier.to_string());
+                }
+            }
+        }
+        let issuers: Vec<String> = badges.keys().cloned().collect();
+        for (issuer, identifiers) in badges {
+            let definitions = Filter::new()
+                .kinds(vec![BADGE_DEFINITION_KIND])
+                .tag('d', identifiers);
+            events.extend(self.pool.fetch_from_authors(definitions, &[issuer]).await);
+        }
+
+        self.fetch_profiles(&issuers).await;
+        for event in events {
+            self.handle_event(event).await;
+        }
+    }
+
+    /// Fetches the profiles of the users `event` mentions in its content,
+    /// and remembers to fetch the notes it quotes.
+    async fn fetch_references(&mut self, event: &NostrEvent) {
+        let mut mentioned = Vec::new();
+        for found in reference::references(&event.content) {
+            let entity = found.entity;
+            match entity.event_id() {
+                Some(id) => {
+                    let relay = entity.relays().first().cloned();
+                    let author = entity.pubkey().map(str::to_string);
+                    self.pending_targets.push((id.to_string(), relay, author));
+                }
+                None => mentioned.extend(entity.pubkey().map(str::to_string)),
+            }
+        }
+        // Quotes of addressable events carry a coordinate instead of an id
+        for id in repost::quoted_ids(event).filter(|id| !id.contains(':')) {
+            self.pending_targets.push((id.to_string(), None, None));
+        }
+
+        self.queue_profiles(&mentioned);
+    }
+
+    /// Remembers to fetch the reactions to `event`.
+    fn track_note(&mut self, event: &NostrEvent) {
+        if let Some(id) = event.id() {
+            if self.known_notes.insert(id.to_string()) {
+                self.pending_notes.push((id.to_string(), event.pubkey()));
+            }
+        }
+    }
+
+    /// Fetches the notes reposted or quoted since the last call, then the
+    /// profiles of the authors received in the meantime and the reactions
+    /// and zaps to their notes, from the relays they read mentions from,
+    /// and the deletions of those notes, from the relays they write to.
+    async fn fetch_activity(&mut self) {
+        self.fetch_targets().await;
+        let authors = std::mem::take(&mut self.pending_authors);
+        self.fetch_profiles(&authors).await;
+        if self.pending_notes.is_empty() {
+            return;
+        }
+
+        let (ids, authors): (Vec<String>, Vec<String>) =
+            std::mem::take(&mut self.pending_notes).into_iter().unzip();
+        let mut relays: Vec<String> = self.pool.bootstrap_relays().to_vec();
+        for author in &authors {
+            let mut author_relays = self.pool.inbox_relays(author);
+            author_relays.extend(self.pool.outbox_relays(author));
+            for relay in author_relays {
+                if !relays.contains(&relay) {
+                    relays.push(relay);
+                }
+            }
+        }
+
+        let mut deleting = authors;
+        deleting.sort();
+        deleting.dedup();
+        let filters = vec![
+            Filter::new()
+                .kinds(vec![REACTION_KIND, ZAP_RECEIPT_KIND])
+                .tag('e', ids.clone()),
+            Filter::new()
+                .kinds(vec![DELETION_KIND])
+                .authors(deleting)
+                .tag('e', ids),
+        ];
+        let mut events = self.pool.fetch_from(&relays, filters).await;
+
+        // Reactions taken back are deleted by their own authors
+        let (reactions, mut reacting): (Vec<String>, Vec<String>) = events
+            .iter()
+            .filter(|event| event.kind == REACTION_KIND)
+            .filter_map(|event| Some((event.id()?.to_string(), event.pubkey())))
+            .unzip();
+        if !reactions.is_empty() {
+            reacting.sort();
+            reacting.dedup();
+            let retracted = Filter::new()
+                .kinds(vec![DELETION_KIND])
+                .authors(reacting)
+                .tag('e', reactions);
+            events.extend(self.pool.fetch_from(&relays, vec![retracted]).await);
+        }
+
+        for event in events {
+            self.forward(event);
+        }
+    }
+
+    /// Fetches the reposted and quoted notes that weren't received along
+    /// with their repost, asking the relays hinted at and the ones their
+    /// authors write to as well.
+    async fn fetch_targets(&mut self) {
+        let targets: Vec<(String, Option<String>, Option<String>)> =
+            std::mem::take(&mut self.pending_targets)
+                .into_iter()
+                .filter(|(id, _, _)| !self.known_notes.contains(id))
+                .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        let authors: Vec<String> = targets
+            .iter()
+            .filter_map(|(_, _, author)| author.clone())
+            .collect();
+        self.pool.load_relay_lists(&authors).await;
+
+        let mut relays: Vec<String> = self.pool.bootstrap_relays().to_vec();
+        let mut ids = Vec::new();
+        for (id, relay, author) in targets {
+            let mut hinted: Vec<String> = relay.into_iter().collect();
+            if let Some(author) = author {
+                hinted.extend(self.pool.outbox_relays(&author));
+            }
+            for relay in hinted {
+                if !relays.contains(&relay) {
+                    relays.push(relay);
+                }
+            }
+            ids.push(id);
+        }
+
+        let filter = Filter::new().ids(ids);
+        for event in self.pool.fetch_from(&relays, vec![filter]).await {
+            self.queue_profiles(&[event.pubkey()]);
+            self.track_note(&event);
+            self.forward(event);
+        }
+    }
+
+    /// Opens the home feed, made of the notes of the users followed, and
+    /// gets the reports and labels they publish. Subscriptions for an
+    /// earlier contact list are closed first.
+    async fn subscribe_home(&mut self) {
+        let subscriptions = std::mem::take(&mut self.home_subscriptions);
+        self.pool.close_subscriptions(&subscriptions).await;
+
+        let contacts = self.contacts.clone().unwrap_or_default();
+        let (filter, authors) = contacts.home_feed(&self.pubkey);
+        self.fetch_profiles(&authors).await;
+        self.home_subscriptions = self.pool.subscribe_authors(filter, &authors).await;
+        // The user trusts the reports and labels of the users they follow
+        let moderation = Filter::new()
+            .kinds(vec![REPORT_KIND, LABEL_KIND])
+            .limit(MODERATION_LIMIT);
+        let moderation = self.pool.subscribe_authors(moderation, &authors).await;
+        self.home_subscriptions.extend(moderation);
+    }
+
+    /// Fetches the latest notes mentioning the user from the relays they
+    /// read from, which the home feed shows along with the ones followed.
+    async fn fetch_mentions(&mut self) {
+        let filter = Filter::new()
+            .kinds(vec![TEXT_NOTE_KIND])
+            .limit(MENTIONS_LIMIT);
+        let pubkey = self.pubkey.clone();
+        for event in self.pool.fetch_mentions(filter, &pubkey).await {
+            self.handle_event(event).await;
+        }
+    }
+
+    /// Takes in the contact list the user just published, following the
+    /// users now in it from the home feed.
+    async fn update_contacts(&mut self, published: anyhow::Result<NostrEvent>) {
+        let event = match published {
+            Ok(event) => event,
+            Err(err) => {
+                self.send(Event::Status(format!(
+                    "Could not update your contact list: {}",
+                    err
+                )));
+                return;
+            }
+        };
+
+        self.contacts = ContactList::from_event(&event).ok();
+        self.forward(event);
+        self.subscribe_home().await;
+        self.send(Event::Status("Contact list updated".to_string()));
+    }
+
+    /// Remembers to fetch the profiles of the given authors, along with the
+    /// ones of the other authors received in the meantime.
+    fn queue_profiles(&mut self, pubkeys: &[String]) {
+        for pubkey in pubkeys {
+            if !self.known_authors.contains(pubkey) && !self.pending_authors.contains(pubkey) {
+                self.pending_authors.push(pubkey.clone());
+            }
+        }
+    }
+
+    /// Fetches the profiles of the given authors that weren't requested yet.
+    async fn fetch_profiles(&mut self, pubkeys: &[String]) {
+        let missing: Vec<String> = pubkeys
+            .iter()
+            .filter(|pubkey| self.known_authors.insert(pubkey.to_string()))
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        for event in Profile::fetch_events(&mut self.pool, &missing).await {
+            self.verify_identifier(&event);
+            self.forward(event);
+        }
+    }
+
+    /// Checks the NIP-05 identifier of a profile in the background, letting
+    /// the main loop know once it's verified.
+    fn verify_identifier(&self, event: &NostrEvent) {
+        let Some(identifier) = Profile::from_event(event)
+            .ok()
+            .and_then(|profile| profile.nip05)
+            .and_then(|nip05| nip05.parse::<Identifier>().ok())
+        else {
+            return;
+        };
+
+        let pubkey = event.pubkey();
+        let verifier = self.verifier.clone();
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            if verifier.verify(&identifier, &pubkey).await {
+                let _ = sender.send(Event::Verified(pubkey, identifier));
+            }
+        });
+    }
+
+    fn forward(&self, event: NostrEvent) {
+        self.send(Event::Nostr(event));
+    }
+
+    fn send(&self, event: Event) {
+        // The main loop only goes away when the application quits
+        let _ = self.sender.send(event);
+    }
+}
+
+/// The wallet of the user, connected to the first time it's needed.
+struct LazyWallet {
+    /// Where the wallet is reached, until it's connected.
+    uri: Option<WalletConnectUri>,
+    wallet: Option<Wallet>,
+}
+
+impl LazyWallet {
+    /// The wallet, connecting to it if it wasn't yet. Users who didn't set
+    /// one up have none.
+    async fn get(&mut self) -> anyhow::Result<Option<&mut Wallet>> {
+        // The URI is kept until connecting works, so that it's tried again
+        if self.wallet.is_none() {
+            if let Some(uri) = &self.uri {
+                self.wallet = Some(Wallet::connect(uri.clone()).await?);
+                self.uri = None;
+            }
+        }
+
+        Ok(self.wallet.as_mut())
+    }
+}
+
+/// What the commands that may take long, waiting for the wallet or a
+/// remote signer, run with in their own task, so that the network task
+/// keeps forwarding what the relays send.
+#[derive(Clone)]
+struct Background {
+    signer: Arc<Mutex<Signer>>,
+    wallet: Arc<Mutex<LazyWallet>>,
+    sender: mpsc::UnboundedSender<Event>,
+}
+
+impl Background {
+    /// Has the zap `request` signed and gets the invoice paying it from the
+    /// lightning service behind `pay_url`, paying it with the wallet of the
+    /// user if they have one.
+    async fn zap(
+        &self,
+        mut request: NostrEvent,
+        pay_url: &str,
+        amount_msats: u64,
+    ) -> anyhow::Result<()> {
+        let pay = LnurlPay::fetch(pay_url).await?;
+        self.signer.lock().await.sign(&mut request).await?;
+        let invoice = pay.invoice(&request).await?;
+
+        let mut wallet = self.wallet.lock().await;
+        let Some(wallet) = wallet.get().await? else {
+            self.status(format!(
+                "Pay {} sats to zap: {}",
+                amount_msats / 1000,
+                invoice.invoice
+            ));
+            return Ok(());
+        };
+
+        self.status("Paying…".to_string());
+        match wallet.pay_invoice(&invoice.invoice).await {
+            Ok(_) => self.status(format!("Zapped {} sats", amount_msats / 1000)),
+            Err(err) if err.is::<NoAnswer>() => self.status(format!(
+                "The wallet didn't answer in time, so the zap of {} sats may or may not be paid: check the wallet before zapping again",
+                amount_msats / 1000
+            )),
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+
+    /// Lets the main loop know about the balance and latest payments of the
+    /// wallet of the user.
+    async fn fetch_wallet(&self) -> anyhow::Result<()> {
+        let mut wallet = self.wallet.lock().await;
+        let Some(wallet) = wallet.get().await? else {
+            return Err(anyhow::anyhow!("No wallet is set up"));
+        };
+
+        let balance = wallet.get_balance().await?;
+        let filter = TransactionFilter {
+            limit: Some(TRANSACTIONS_LIMIT),
+            ..TransactionFilter::default()
+        };
+        let transactions = wallet.list_transactions(&filter).await?;
+
+        self.send(Event::Wallet {
+            lud16: wallet.lud16().map(str::to_string),
+            balance,
+            transactions,
+        });
+        Ok(())
+    }
+
+    /// Has the wallet of the user make an invoice, and shows it.
+    async fn make_invoice(&self, amount_msats: u64, description: &str) -> anyhow::Result<()> {
+        let made = {
+            let mut wallet = self.wallet.lock().await;
+            let Some(wallet) = wallet.get().await? else {
+                return Err(anyhow::anyhow!("No wallet is set up"));
+            };
+            wallet.make_invoice(amount_msats, description, None).await?
+        };
+
+        self.fetch_wallet().await?;
+        let invoice = made.invoice.unwrap_or_default();
+        self.status(format!("Get paid with: {}", invoice));
+        Ok(())
+    }
+
+    fn status(&self, status: String) {
+        self.send(Event::Status(status));
+    }
+
+    fn send(&self, event: Event) {
+        // The main loop only goes away when the application quits
+        let _ = self.sender.send(event);
+    }
+}
@@ -1,34 +1,1373 @@
 use ratatui::{
-    layout::Alignment,
-    style::{Color, Style},
-    widgets::{Block, BorderType, Paragraph},
+    layout::{Alignment, Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, BorderType, List, ListItem, Paragraph, Wrap},
     Frame,
 };
 
-use super::app::App;
+use super::app::{App, InputPurpose, View};
+use super::markdown;
+use crate::nostr_client::article::Article;
+use crate::nostr_client::calendar::RsvpStatus;
+use crate::nostr_client::channel;
+use crate::nostr_client::content_warning;
+use crate::nostr_client::entity::Entity;
+use crate::nostr_client::event::{unix_time, Event as NostrEvent};
+use crate::nostr_client::expiration;
+use crate::nostr_client::file_metadata::{self, FILE_METADATA_KIND};
+use crate::nostr_client::group::{
+    GroupId, GROUP_EDIT_METADATA_KIND, GROUP_JOIN_REQUEST_KIND, GROUP_LEAVE_REQUEST_KIND,
+    GROUP_PUT_USER_KIND, GROUP_REMOVE_USER_KIND,
+};
+use crate::nostr_client::label::Label;
+use crate::nostr_client::list;
+use crate::nostr_client::reference;
+use crate::nostr_client::report::ReportType;
+use crate::nostr_client::repost;
+
+/// Deepest level replies are indented to, so long threads stay readable.
+const MAX_INDENT: usize = 6;
+
+/// Width of the sidebar listing the groups next to the one shown.
+const GROUPS_SIDEBAR_WIDTH: u16 = 28;
+
+/// How many lines of a quoted note are shown inside the note quoting it.
+const MAX_QUOTE_LINES: usize = 4;
 
 /// Renders the user interface widgets.
 pub fn render(app: &mut App, frame: &mut Frame) {
-    // This is where you add new widgets.
-    // See the following resources:
-    // - https://docs.rs/ratatui/latest/ratatui/widgets/index.html
-    // - https://github.com/ratatui-org/ratatui/tree/master/examples
+    let input_height = if app.input_box.is_some() { 3 } else { 0 };
+    let [main_area, input_area, help_area] = Layout::vertical([
+        Constraint::Min(0),
+        Constraint::Length(input_height),
+        Constraint::Length(1),
+    ])
+    .areas(frame.size());
+
+    match app.view().clone() {
+        View::Home | View::Thread(_) | View::List(_) => render_feed(app, frame, main_area),
+        View::Profile(pubkey) => {
+            let [profile_area, feed_area] =
+                Layout::vertical([Constraint::Length(8), Constraint::Min(0)]).areas(main_area);
+            render_profile(app, &pubkey, frame, profile_area);
+            render_feed(app, frame, feed_area);
+        }
+        View::Address(address) => render_address(app, &address, frame, main_area),
+        View::Bunker => render_bunker(app, frame, main_area),
+        View::Wallet => render_wallet(app, frame, main_area),
+        View::Channels => render_channels(app, frame, main_area),
+        View::Channel(id) => render_channel(app, &id, frame, main_area),
+        View::Group(id) => render_group(app, &id, frame, main_area),
+        View::Badges => render_badges(app, frame, main_area),
+        View::Agenda => render_agenda(app, frame, main_area),
+    }
+
+    if let Some(input) = &app.input_box {
+        let mut title = match &input.purpose {
+            InputPurpose::Lookup => "Look up (name@domain)".to_string(),
+            InputPurpose::Note => {
+                "New note (Tab completes @names, Ctrl-U uploads the file path typed last)"
+                    .to_string()
+            }
+            InputPurpose::Open => "Open (nostr:npub…, note…, nevent…, naddr…)".to_string(),
+            InputPurpose::Reply(parent_id) => match app.find_note(parent_id) {
+                Some(parent) => format!("Reply to {}", app.profiles.display_name(&parent.pubkey())),
+                None => "Reply".to_string(),
+            },
+            InputPurpose::Quote(target_id) => match app.find_note(target_id) {
+                Some(target) => format!("Quote {}", app.profiles.display_name(&target.pubkey())),
+                None => "Quote".to_string(),
+            },
+            InputPurpose::Delete(_) => {
+                "Delete this note? Enter a reason and press Enter, or Esc to keep it".to_string()
+            }
+            InputPurpose::React(_) => "React with (emoji, + or -)".to_string(),
+            InputPurpose::Article { draft: false } => {
+                "Publish the article in (path to a Markdown file)".to_string()
+            }
+            InputPurpose::Article { draft: true } => {
+                "Save a draft of the article in (path to a Markdown file)".to_string()
+            }
+            InputPurpose::Expiration => {
+                "Expire new notes after (30m, 12h, 7d…), or never if empty".to_string()
+            }
+            InputPurpose::MuteWord => "Mute or unmute (a word, or a #hashtag)".to_string(),
+            InputPurpose::EditProfile => {
+                "Edit your profile (a field like name or about, then its value)".to_string()
+            }
+            InputPurpose::Relay => {
+                "Add a relay (wss://… [read|write]), or remove it (-wss://…)".to_string()
+            }
+            InputPurpose::ConnectApp => "Connect to an app (nostrconnect://…)".to_string(),
+            InputPurpose::Receive => "Receive (sats, then a description if any)".to_string(),
+            InputPurpose::CreateChannel => "Name of the new channel".to_string(),
+            InputPurpose::EditChannel(_) => {
+                "Edit the channel (name, about or picture, then its value)".to_string()
+            }
+            InputPurpose::ChannelMessage {
+                channel_id,
+                reply_to,
+            } => {
+                let channel = app
+                    .channels
+                    .get(channel_id)
+                    .map_or("the channel".to_string(), |channel| channel.display_name());
+                match reply_to.as_deref().and_then(|id| app.find_note(id)) {
+                    Some(parent) => format!(
+                        "Reply to {} in {}",
+                        app.profiles.display_name(&parent.pubkey()),
+                        channel
+                    ),
+                    None => format!("Message {} (Tab completes @names)", channel),
+                }
+            }
+            InputPurpose::HideMessage(_) => {
+                "Hide this message? Enter a reason and press Enter, or Esc to keep it".to_string()
+            }
+            InputPurpose::OpenGroup => "Open a group (host'id)".to_string(),
+            InputPurpose::GroupMessage { group, reply_to } => {
+                let name = app
+                    .groups
+                    .get(group)
+                    .map_or(group.to_string(), |group| group.display_name());
+                match reply_to.as_deref().and_then(|id| app.find_note(id)) {
+                    Some(parent) => format!(
+                        "Reply to {} in {}",
+                        app.profiles.display_name(&parent.pubkey()),
+                        name
+                    ),
+                    None => format!("Message {} (Tab completes @names)", name),
+                }
+            }
+            InputPurpose::JoinGroup(_) => {
+                "Ask to join (an invite code if you have one, or just Enter)".to_string()
+            }
+            InputPurpose::LeaveGroup(_) => {
+                "Leave this group? Enter a reason and press Enter, or Esc to stay".to_string()
+            }
+            InputPurpose::Report { id, pubkey } => format!(
+                "Report {} as ({}) and why",
+                match id {
+                    Some(_) => "this note".to_string(),
+                    None => app.profiles.display_name(pubkey),
+                },
+                ReportType::ALL
+                    .map(|report_type| report_type.as_str())
+                    .join(", ")
+            ),
+            InputPurpose::Label { id, pubkey } => format!(
+                "Label {} with (a value, or a namespace then a value)",
+                match id {
+                    Some(_) => "this note".to_string(),
+                    None => app.profiles.display_name(pubkey),
+                }
+            ),
+            InputPurpose::Rsvp(_) => {
+                "RSVP (accepted, tentative or declined), then a note if any".to_string()
+            }
+            InputPurpose::NewContactList(pubkey) => format!(
+                "No contact list of yours was found. Type yes to start one following {}",
+                app.profiles.display_name(pubkey)
+            ),
+            InputPurpose::Zap { recipient, .. } => format!(
+                "Zap {} (sats, then a comment if any)",
+                app.profiles.display_name(recipient)
+            ),
+        };
+        let composing = input.purpose.is_composing();
+        if let (true, Some(after)) = (composing, app.note_expiration) {
+            title.push_str(&format!(" · expires after {}", short_duration(after)));
+        }
+        if !input.attachments.is_empty() {
+            title.push_str(&format!(" · 📎 {}", input.attachments.len()));
+        }
+        frame.render_widget(
+            Paragraph::new(format!("{}▏", input.text)).block(
+                Block::bordered()
+                    .title(title)
+                    .border_type(BorderType::Rounded),
+            ),
+            input_area,
+        );
+    }
+
+    let help = match &app.status {
+        Some(status) => Paragraph::new(status.as_str()).style(Style::default().fg(Color::Yellow)),
+        None if matches!(app.view(), View::Wallet) => {
+            Paragraph::new("j/k scroll · i receive · Esc back · q quit")
+                .style(Style::default().fg(Color::DarkGray))
+        }
+        None if matches!(app.view(), View::Agenda) => {
+            Paragraph::new("j/k scroll · v RSVP · Esc back · q quit")
+                .style(Style::default().fg(Color::DarkGray))
+        }
+        None if matches!(app.view(), View::Badges) => {
+            Paragraph::new("j/k scroll · a accept · h hide · Esc back · q quit")
+                .style(Style::default().fg(Color::DarkGray))
+        }
+        None if matches!(app.view(), View::Channels) => {
+            Paragraph::new("j/k scroll · Enter open · n new channel · Esc back · q quit")
+                .style(Style::default().fg(Color::DarkGray))
+        }
+        None if matches!(app.view(), View::Channel(_)) => Paragraph::new(
+            "j/k scroll · n message · r reply · +/R react · z zap · h hide · H mute in channels · \
+             t edit channel · p profile · Esc back · q quit",
+        )
+        .style(Style::default().fg(Color::DarkGray)),
+        None if matches!(app.view(), View::Group(_)) => Paragraph::new(
+            "j/k scroll · Tab/S-Tab switch group · o open group · n message · r reply · J join · \
+             L leave · a/K add/remove user · d delete · p profile · Esc back · q quit",
+        )
+        .style(Style::default().fg(Color::DarkGray)),
+        None if matches!(app.view(), View::Bunker) => {
+            Paragraph::new("j/k scroll · y/a/n answer · c connect app · d forget app · q quit")
+                .style(Style::default().fg(Color::DarkGray))
+        }
+        None => Paragraph::new(
+            "j/k scroll · Enter thread · n note · o open · r reply · +/R react · b repost · \
+             Q quote · f follow · z/Z zap · $ wallet · c channels · G groups · A badges · C agenda · d delete · x warning · e expiry · w/W article/draft · m/M/u mute · !/X report note/user · l/L label note/user · B/P bookmark/pin · \
+             g bookmarks · E edit profile · S relays · p profile · / look up · Esc back · q quit",
+        )
+        .style(Style::default().fg(Color::DarkGray)),
+    };
+    frame.render_widget(help.style(Style::default().bg(Color::Black)), help_area);
+}
+
+/// Renders the notes of the current view.
+fn render_feed(app: &mut App, frame: &mut Frame, area: Rect) {
+    let title = match app.view() {
+        View::Home => "Home".to_string(),
+        View::Profile(pubkey) => format!("Notes by {}", app.profiles.display_name(pubkey)),
+        View::Thread(_) => "Thread".to_string(),
+        View::Address(_)
+        | View::Bunker
+        | View::Wallet
+        | View::Channels
+        | View::Channel(_)
+        | View::Group(_)
+        | View::Badges
+        | View::Agenda => String::new(),
+        View::List(address) => list_title(app, address),
+    };
+
+    // Replies in a thread are indented under the note they answer
+    let notes: Vec<(usize, &NostrEvent)> = match (app.view(), &app.thread) {
+        (View::Thread(_), Some(thread)) => thread
+            .flatten()
+            .into_iter()
+            .filter(|(_, event)| !app.is_muted(event))
+            .collect(),
+        _ => app
+            .visible_notes()
+            .into_iter()
+            .map(|event| (0, event))
+            .collect(),
+    };
+
+    // Two columns go to the borders and two to the highlight symbol
+    let width = area.width.saturating_sub(4) as usize;
+    let items: Vec<ListItem> = notes
+        .into_iter()
+        .map(|(depth, event)| note_item(app, event, depth, width))
+        .collect();
+
+    let feed = List::new(items)
+        .block(
+            Block::bordered()
+                .title(title)
+                .title_alignment(Alignment::Center)
+                .border_type(BorderType::Rounded),
+        )
+        .style(Style::default().fg(Color::Cyan).bg(Color::Black))
+        .highlight_symbol("▌ ");
+
+    let mut state = app.feed_state.clone();
+    frame.render_stateful_widget(feed, area, &mut state);
+    app.feed_state = state;
+}
+
+/// Renders what the bunker does: the URI apps connect with, the request
+/// waiting for the user, the apps connected and the requests answered.
+fn render_bunker(app: &mut App, frame: &mut Frame, area: Rect) {
+    let Some(bunker) = &app.bunker else {
+        return;
+    };
+    let block = |title: String| {
+        Block::bordered()
+            .title(title)
+            .title_alignment(Alignment::Center)
+            .border_type(BorderType::Rounded)
+    };
+    let style = Style::default().fg(Color::Cyan).bg(Color::Black);
+
+    let [uri_area, approval_area, apps_area, log_area] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Length(4),
+        Constraint::Percentage(50),
+        Constraint::Min(0),
+    ])
+    .areas(area);
+
+    frame.render_widget(
+        Paragraph::new(bunker.uri.as_str())
+            .wrap(Wrap { trim: false })
+            .block(block("Connect apps with".to_string()))
+            .style(style),
+        uri_area,
+    );
+
+    let approval = match bunker.approvals.front() {
+        Some(approval) => Paragraph::new(vec![
+            Line::from(vec![
+                Span::styled(
+                    approval.app.clone(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(format!(" wants to {}", approval.description)),
+            ]),
+            Line::styled(
+                format!("y allow once · a always ({}) · n deny", approval.permission),
+                Style::default().fg(Color::Yellow),
+            ),
+        ])
+        .style(style),
+        None => Paragraph::new("No request waiting").style(style.fg(Color::DarkGray)),
+    };
+    let waiting = match bunker.approvals.len() {
+        0 | 1 => "Request".to_string(),
+        waiting => format!("Request (1 of {})", waiting),
+    };
+    frame.render_widget(approval.block(block(waiting)), approval_area);
+
+    let apps: Vec<ListItem> = bunker
+        .apps
+        .iter()
+        .map(|bunker_app| {
+            let permissions = match bunker_app.permissions.is_empty() {
+                true => "asks every time".to_string(),
+                false => bunker_app
+                    .permissions
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            };
+            ListItem::new(vec![
+                Line::styled(
+                    bunker_app.display_name(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Line::styled(permissions, Style::default().fg(Color::DarkGray)),
+            ])
+        })
+        .collect();
+    let apps = List::new(apps)
+        .block(block("Apps".to_string()))
+        .style(style)
+        .highlight_symbol("▌ ");
+
+    let log: Vec<Line> = bunker
+        .log
+        .iter()
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    frame.render_widget(
+        Paragraph::new(log)
+            .block(block("Answered".to_string()))
+            .style(style),
+        log_area,
+    );
+
+    let mut state = app.feed_state.clone();
+    frame.render_stateful_widget(apps, apps_area, &mut state);
+    app.feed_state = state;
+}
+
+/// Renders the balance of the wallet of the user, and its latest payments.
+fn render_wallet(app: &mut App, frame: &mut Frame, area: Rect) {
+    let mut title = "Wallet".to_string();
+    if let Some(address) = &app.wallet_address {
+        title.push_str(&format!(" · {}", address));
+    }
+    if let Some(balance) = app.wallet_balance {
+        title.push_str(&format!(" · {} sats", balance / 1000));
+    }
+
+    let items: Vec<ListItem> = app
+        .transactions
+        .iter()
+        .map(|transaction| {
+            let (arrow, color) = match transaction.is_incoming() {
+                true => ("↓", Color::Green),
+                false => ("↑", Color::Yellow),
+            };
+            let mut header = vec![Span::styled(
+                format!("{} {} sats", arrow, transaction.amount / 1000),
+                Style::default().fg(color).add_modifier(Modifier::BOLD),
+            )];
+            if transaction.fees_paid > 0 {
+                header.push(Span::styled(
+                    format!(" · {} msats in fees", transaction.fees_paid),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            let when = match transaction.settled_at {
+                Some(settled_at) => time_ago(settled_at),
+                None => "unpaid".to_string(),
+            };
+            header.push(Span::styled(
+                format!(" · {}", when),
+                Style::default().fg(Color::DarkGray),
+            ));
+
+            let mut lines = vec![Line::from(header)];
+            if let Some(description) = transaction
+                .description
+                .as_ref()
+                .filter(|description| !description.is_empty())
+            {
+                lines.push(Line::from(format!("  {}", description)));
+            }
+            lines.push(Line::default());
+            ListItem::new(Text::from(lines))
+        })
+        .collect();
+
+    let transactions = List::new(items)
+        .block(
+            Block::bordered()
+                .title(title)
+                .title_alignment(Alignment::Center)
+                .border_type(BorderType::Rounded),
+        )
+        .style(Style::default().fg(Color::Cyan).bg(Color::Black))
+        .highlight_symbol("▌ ");
+
+    let mut state = app.feed_state.clone();
+    frame.render_stateful_widget(transactions, area, &mut state);
+    app.feed_state = state;
+}
+
+/// Renders the upcoming calendar events of the user and the users they
+/// follow, soonest first, with who is coming.
+fn render_agenda(app: &mut App, frame: &mut Frame, area: Rect) {
+    let dimmed = Style::default().fg(Color::DarkGray);
+    let width = area.width.saturating_sub(4) as usize;
+
+    let items: Vec<ListItem> = app
+        .agenda()
+        .into_iter()
+        .map(|calendar_event| {
+            let title = match calendar_event.title.trim() {
+                "" => calendar_event.identifier.clone(),
+                title => title.to_string(),
+            };
+            let mut lines = vec![
+                Line::styled(
+                    format!("📅 {}", calendar_event.when()),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Line::styled(title, Style::default().add_modifier(Modifier::BOLD)),
+            ];
+            if let Some(location) = calendar_event.locations.first() {
+                lines.push(Line::from(format!("📍 {}", location)));
+            }
+            let about = calendar_event
+                .summary
+                .as_deref()
+                .unwrap_or(&calendar_event.description);
+            lines.extend(
+                wrap(about.trim(), width)
+                    .into_iter()
+                    .take(MAX_QUOTE_LINES)
+                    .map(Line::from),
+            );
+
+            let rsvps = app.rsvps(&calendar_event.address());
+            let count =
+                |status: RsvpStatus| rsvps.iter().filter(|rsvp| rsvp.status == status).count();
+            let mut footer = vec![Span::styled(
+                format!(
+                    "by {} · {} going · {} maybe",
+                    app.profiles.display_name(&calendar_event.author),
+                    count(RsvpStatus::Accepted),
+                    count(RsvpStatus::Tentative)
+                ),
+                dimmed,
+            )];
+            if let Some(own) = rsvps.iter().find(|rsvp| rsvp.author == app.pubkey) {
+                footer.push(Span::styled(
+                    format!(" · you: {}", own.status),
+                    Style::default().fg(Color::Green),
+                ));
+            }
+            lines.push(Line::from(footer));
+            lines.push(Line::default());
+            ListItem::new(Text::from(lines))
+        })
+        .collect();
+
+    let agenda = List::new(items)
+        .block(
+            Block::bordered()
+                .title("Agenda")
+                .title_alignment(Alignment::Center)
+                .border_type(BorderType::Rounded),
+        )
+        .style(Style::default().fg(Color::Cyan).bg(Color::Black))
+        .highlight_symbol("▌ ");
+
+    let mut state = app.feed_state.clone();
+    frame.render_stateful_widget(agenda, area, &mut state);
+    app.feed_state = state;
+}
+
+/// Renders the badges awarded to the user, newest first, with whether
+/// they show them on their profile.
+fn render_badges(app: &mut App, frame: &mut Frame, area: Rect) {
+    let dimmed = Style::default().fg(Color::DarkGray);
+    let width = area.width.saturating_sub(4) as usize;
+
+    let items: Vec<ListItem> = app
+        .awarded_badges()
+        .into_iter()
+        .map(|award| {
+            let definition = app.badge_definition(&award.address);
+            let name = definition.as_ref().map_or_else(
+                || {
+                    award
+                        .address
+                        .splitn(3, ':')
+                        .nth(2)
+                        .unwrap_or_default()
+                        .to_string()
+                },
+                |definition| definition.display_name(),
+            );
+            let status = match app.is_badge_shown(award) {
+                true => Span::styled(" · on your profile", Style::default().fg(Color::Green)),
+                false => Span::styled(" · not shown", dimmed),
+            };
+
+            let mut lines = vec![Line::from(vec![
+                Span::styled(
+                    format!("🏅 {}", name),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                status,
+            ])];
+            if let Some(description) = definition.and_then(|definition| definition.description) {
+                lines.extend(
+                    wrap(description.trim(), width)
+                        .into_iter()
+                        .take(MAX_QUOTE_LINES)
+                        .map(Line::from),
+                );
+            }
+            lines.push(Line::styled(
+                format!(
+                    "from {} · {}",
+                    app.profiles.display_name(&award.issuer),
+                    time_ago(award.created_at)
+                ),
+                dimmed,
+            ));
+            lines.push(Line::default());
+            ListItem::new(Text::from(lines))
+        })
+        .collect();
+
+    let badges = List::new(items)
+        .block(
+            Block::bordered()
+                .title("Badges")
+                .title_alignment(Alignment::Center)
+                .border_type(BorderType::Rounded),
+        )
+        .style(Style::default().fg(Color::Cyan).bg(Color::Black))
+        .highlight_symbol("▌ ");
+
+    let mut state = app.feed_state.clone();
+    frame.render_stateful_widget(badges, area, &mut state);
+    app.feed_state = state;
+}
+
+/// Renders the public chat channels found so far, the most active first.
+fn render_channels(app: &mut App, frame: &mut Frame, area: Rect) {
+    let dimmed = Style::default().fg(Color::DarkGray);
+    let width = area.width.saturating_sub(4) as usize;
+
+    let items: Vec<ListItem> = app
+        .channels
+        .list()
+        .into_iter()
+        .map(|channel| {
+            let mut header = vec![Span::styled(
+                channel.display_name(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )];
+            let messages = app.channels.messages(&channel.id).len();
+            if messages > 0 {
+                header.push(Span::styled(
+                    format!(
+                        " · {} message{}",
+                        messages,
+                        if messages == 1 { "" } else { "s" }
+                    ),
+                    dimmed,
+                ));
+            }
+            header.push(Span::styled(
+                format!(" · {}", time_ago(app.channels.last_active(&channel.id))),
+                dimmed,
+            ));
+
+            let mut lines = vec![Line::from(header)];
+            if let Some(about) = channel.metadata.about.as_deref() {
+                lines.extend(
+                    wrap(about.trim(), width)
+                        .into_iter()
+                        .take(MAX_QUOTE_LINES)
+                        .map(Line::from),
+                );
+            }
+            lines.push(Line::styled(
+                format!("by {}", app.profiles.display_name(&channel.creator)),
+                dimmed,
+            ));
+            lines.push(Line::default());
+            ListItem::new(Text::from(lines))
+        })
+        .collect();
+
+    let channels = List::new(items)
+        .block(
+            Block::bordered()
+                .title("Channels")
+                .title_alignment(Alignment::Center)
+                .border_type(BorderType::Rounded),
+        )
+        .style(Style::default().fg(Color::Cyan).bg(Color::Black))
+        .highlight_symbol("▌ ");
+
+    let mut state = app.feed_state.clone();
+    frame.render_stateful_widget(channels, area, &mut state);
+    app.feed_state = state;
+}
+
+/// Renders the messages of the channel with id `id`, oldest first like a
+/// chat, each reply under the start of the message it answers.
+fn render_channel(app: &mut App, id: &str, frame: &mut Frame, area: Rect) {
+    let dimmed = Style::default().fg(Color::DarkGray);
+    let title = match app.channels.get(id) {
+        Some(channel) => match channel.metadata.about.as_deref() {
+            Some(about) if !about.trim().is_empty() => {
+                format!("{} · {}", channel.display_name(), about.trim())
+            }
+            _ => channel.display_name(),
+        },
+        None => "Channel".to_string(),
+    };
+
+    let width = area.width.saturating_sub(4) as usize;
+    let items: Vec<ListItem> = app
+        .visible_notes()
+        .into_iter()
+        .map(|message| {
+            let Some(parent) = channel::reply_to(message).and_then(|id| app.find_note(id)) else {
+                return note_item(app, message, 0, width);
+            };
+
+            let (content, _) = readable_content(app, parent);
+            let first_line = wrap(&content, width).into_iter().next().unwrap_or_default();
+            let reply = Line::styled(
+                format!(
+                    "↪ {}: {}",
+                    app.profiles.display_name(&parent.pubkey()),
+                    first_line
+                ),
+                dimmed,
+            );
+            let mut lines = vec![reply];
+            lines.extend(note_lines(app, message, 0, width));
+            ListItem::new(Text::from(lines))
+        })
+        .collect();
+
+    let messages = List::new(items)
+        .block(
+            Block::bordered()
+                .title(title)
+                .title_alignment(Alignment::Center)
+                .border_type(BorderType::Rounded),
+        )
+        .style(Style::default().fg(Color::Cyan).bg(Color::Black))
+        .highlight_symbol("▌ ");
+
+    let mut state = app.feed_state.clone();
+    frame.render_stateful_widget(messages, area, &mut state);
+    app.feed_state = state;
+}
+
+/// Renders the events of the group `id` like a chat, next to a sidebar
+/// listing the groups of the user.
+fn render_group(app: &mut App, id: &GroupId, frame: &mut Frame, area: Rect) {
+    let dimmed = Style::default().fg(Color::DarkGray);
+    let [sidebar_area, chat_area] =
+        Layout::horizontal([Constraint::Length(GROUPS_SIDEBAR_WIDTH), Constraint::Min(0)])
+            .areas(area);
+
+    let joined = app.joined_groups();
+    let sidebar: Vec<ListItem> = app
+        .sidebar_groups()
+        .into_iter()
+        .map(|group_id| {
+            let name = app
+                .groups
+                .get(&group_id)
+                .map_or(group_id.id.clone(), |group| group.display_name());
+            let marker = if joined.contains(&group_id) {
+                "● "
+            } else {
+                "  "
+            };
+            let style = match group_id == *id {
+                true => Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED),
+                false => Style::default(),
+            };
+            ListItem::new(Line::styled(format!("{}{}", marker, name), style))
+        })
+        .collect();
     frame.render_widget(
-        Paragraph::new(format!(
-            "This is a tui template.\n\
-                Press `Esc`, `Ctrl-C` or `q` to stop running.\n\
-                Press left and right to increment and decrement the counter respectively.\n\
-                Counter: {}",
-                0
-        ))
+        List::new(sidebar)
+            .block(
+                Block::bordered()
+                    .title("Groups")
+                    .title_alignment(Alignment::Center)
+                    .border_type(BorderType::Rounded),
+            )
+            .style(Style::default().fg(Color::Cyan).bg(Color::Black)),
+        sidebar_area,
+    );
+
+    let title = match app.groups.get(id) {
+        Some(group) => {
+            let mut title = vec![group.display_name()];
+            if group.private {
+                title.push("private".to_string());
+            }
+            if group.closed {
+                title.push("closed".to_string());
+            }
+            let members = group.members.len();
+            if members > 0 {
+                title.push(format!(
+                    "{} member{}",
+                    members,
+                    if members == 1 { "" } else { "s" }
+                ));
+            }
+            match group.admins.iter().find(|(admin, _)| *admin == app.pubkey) {
+                Some((_, roles)) if !roles.is_empty() => title.push(roles.join(", ")),
+                Some(_) => title.push("admin".to_string()),
+                None if group.is_member(&app.pubkey) => title.push("member".to_string()),
+                None => {}
+            }
+            title.join(" · ")
+        }
+        None => id.to_string(),
+    };
+
+    let width = chat_area.width.saturating_sub(4) as usize;
+    let name = |pubkey: &str| app.profiles.display_name(pubkey);
+    let items: Vec<ListItem> = app
+        .visible_notes()
+        .into_iter()
+        .map(|event| {
+            let target = event.tag_values("p").next().unwrap_or_default();
+            let notice = match event.kind {
+                GROUP_JOIN_REQUEST_KIND => {
+                    format!("→ {} asked to join", name(&event.pubkey()))
+                }
+                GROUP_LEAVE_REQUEST_KIND => format!("← {} left", name(&event.pubkey())),
+                GROUP_PUT_USER_KIND => {
+                    format!("{} added {}", name(&event.pubkey()), name(target))
+                }
+                GROUP_REMOVE_USER_KIND => {
+                    format!("{} removed {}", name(&event.pubkey()), name(target))
+                }
+                GROUP_EDIT_METADATA_KIND => format!("{} edited the group", name(&event.pubkey())),
+                // Replies quote the message they answer with a `q` tag
+                _ => return note_item(app, event, 0, width),
+            };
+
+            let mut lines = vec![Line::styled(
+                format!("{} · {}", notice, time_ago(event.created_at)),
+                dimmed,
+            )];
+            lines.extend(
+                wrap(event.content.trim(), width)
+                    .into_iter()
+                    .take(MAX_QUOTE_LINES)
+                    .map(|line| Line::styled(line, dimmed)),
+            );
+            lines.push(Line::default());
+            ListItem::new(Text::from(lines))
+        })
+        .collect();
+
+    let messages = List::new(items)
         .block(
             Block::bordered()
-                .title("Template")
+                .title(title)
                 .title_alignment(Alignment::Center)
                 .border_type(BorderType::Rounded),
         )
         .style(Style::default().fg(Color::Cyan).bg(Color::Black))
-        .centered(),
-        frame.size(),
-    )
+        .highlight_symbol("▌ ");
+
+    let mut state = app.feed_state.clone();
+    frame.render_stateful_widget(messages, chat_area, &mut state);
+    app.feed_state = state;
+}
+
+/// Renders the profile of `pubkey` at the top of their view.
+fn render_profile(app: &App, pubkey: &str, frame: &mut Frame, area: Rect) {
+    let mut lines = vec![author_line(app, pubkey)];
+    lines.push(Line::styled(
+        pubkey.to_string(),
+        Style::default().fg(Color::DarkGray),
+    ));
+
+    if app.contacts.follows(pubkey) {
+        lines.push(Line::styled(
+            "✓ Following",
+            Style::default().fg(Color::Green),
+        ));
+    }
+    if let Some(profile) = app.profiles.get(pubkey) {
+        let links = [&profile.website, &profile.lud16]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("  ");
+        if !links.is_empty() {
+            lines.push(Line::styled(links, Style::default().fg(Color::Blue)));
+        }
+    }
+    let (msats, count) = app.zaps.of_user(pubkey);
+    if count > 0 {
+        lines.push(Line::styled(
+            format!(
+                "⚡ {} sats from {} zap{}",
+                short_sats(msats),
+                count,
+                if count == 1 { "" } else { "s" }
+            ),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    let badges: Vec<String> = app
+        .profile_badges(pubkey)
+        .iter()
+        .map(|badge| format!("🏅 {}", badge.display_name()))
+        .collect();
+    if !badges.is_empty() {
+        lines.push(Line::styled(
+            badges.join("  "),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    if let Some(profile) = app.profiles.get(pubkey) {
+        if let Some(about) = &profile.about {
+            lines.push(Line::default());
+            lines.extend(about.lines().map(|line| Line::from(line.to_string())));
+        }
+    }
+
+    frame.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().border_type(BorderType::Rounded))
+            .style(Style::default().fg(Color::Cyan).bg(Color::Black)),
+        area,
+    );
+}
+
+/// Renders the addressable event with coordinate `address`, once found.
+fn render_address(app: &mut App, address: &str, frame: &mut Frame, area: Rect) {
+    let Some(event) = app.addressable.get(address) else {
+        frame.render_widget(
+            Paragraph::new("Looking for the event…")
+                .block(Block::bordered().border_type(BorderType::Rounded))
+                .style(Style::default().fg(Color::DarkGray).bg(Color::Black)),
+            area,
+        );
+        return;
+    };
+
+    // Two columns go to the borders
+    let width = area.width.saturating_sub(2) as usize;
+    let (title, lines) = match Article::from_event(event) {
+        Ok(article) => {
+            let lines = article_lines(app, event, &article, width);
+            (article.title.unwrap_or(article.identifier), lines)
+        }
+        Err(_) => {
+            let title = event
+                .tag_values("title")
+                .next()
+                .or(event.tag_values("d").next())
+                .unwrap_or_default()
+                .to_string();
+            let mut header = author_line(app, &event.pubkey());
+            header.spans.push(Span::styled(
+                format!(" · {}", time_ago(event.created_at)),
+                Style::default().fg(Color::DarkGray),
+            ));
+
+            let mut lines = vec![header, Line::default()];
+            lines.extend(
+                event
+                    .content
+                    .lines()
+                    .map(|line| Line::from(line.to_string())),
+            );
+            (title, lines)
+        }
+    };
+
+    // Scrolling stops once the last line is on screen. Wrapped lines aren't
+    // counted, so the end may stay a bit out of reach.
+    let height = area.height.saturating_sub(2) as usize;
+    let max_scroll = lines.len().saturating_sub(height).min(u16::MAX as usize) as u16;
+    app.reader_scroll = app.reader_scroll.min(max_scroll);
+
+    frame.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .scroll((app.reader_scroll, 0))
+            .block(
+                Block::bordered()
+                    .title(title)
+                    .title_alignment(Alignment::Center)
+                    .border_type(BorderType::Rounded),
+            )
+            .style(Style::default().fg(Color::Cyan).bg(Color::Black)),
+        area,
+    );
+}
+
+/// An article: its author, publication date, summary, image and hashtags,
+/// followed by its Markdown body.
+fn article_lines(
+    app: &App,
+    event: &NostrEvent,
+    article: &Article,
+    width: usize,
+) -> Vec<Line<'static>> {
+    let dimmed = Style::default().fg(Color::DarkGray);
+
+    let mut header = author_line(app, &event.pubkey());
+    let date = match (article.draft, article.published_at) {
+        (true, _) => format!(" · draft, edited {} ago", time_ago(event.created_at)),
+        (false, Some(published_at)) if published_at + 60 < event.created_at => format!(
+            " · published {} ago, edited {} ago",
+            time_ago(published_at),
+            time_ago(event.created_at)
+        ),
+        (false, published_at) => format!(
+            " · published {} ago",
+            time_ago(published_at.unwrap_or(event.created_at))
+        ),
+    };
+    header.spans.push(Span::styled(date, dimmed));
+
+    let mut lines = vec![header];
+    if let Some(summary) = &article.summary {
+        lines.push(Line::styled(
+            summary.clone(),
+            Style::default().add_modifier(Modifier::ITALIC),
+        ));
+    }
+    if let Some(image) = &article.image {
+        lines.push(Line::styled(format!("🖼 {}", image), dimmed));
+    }
+    if !article.hashtags.is_empty() {
+        let hashtags = article
+            .hashtags
+            .iter()
+            .map(|hashtag| format!("#{}", hashtag))
+            .collect::<Vec<_>>()
+            .join(" ");
+        lines.push(Line::styled(hashtags, Style::default().fg(Color::Blue)));
+    }
+    lines.push(Line::styled("─".repeat(width.min(80)), dimmed));
+    lines.push(Line::default());
+    lines.extend(markdown::render(&article.content, width));
+
+    lines
+}
+
+/// The name of `pubkey`, followed by their verified identifier if any.
+fn author_line(app: &App, pubkey: &str) -> Line<'static> {
+    let mut spans = vec![Span::styled(
+        app.profiles.display_name(pubkey),
+        Style::default().add_modifier(Modifier::BOLD),
+    )];
+
+    if let Some(identifier) = app.verified_identifier(pubkey) {
+        spans.push(Span::styled(
+            format!(" ✓ {}", identifier),
+            Style::default().fg(Color::Green),
+        ));
+    }
+
+    Line::from(spans)
+}
+
+/// The title of the list with coordinate `address`, or what kind of list
+/// it is if it has none.
+fn list_title(app: &App, address: &str) -> String {
+    let Some(list) = app.lists.get(address) else {
+        return "List".to_string();
+    };
+    if let Some(title) = &list.title {
+        return title.clone();
+    }
+
+    let name = match list.kind {
+        list::MUTE_LIST_KIND => "Mute list",
+        list::PIN_LIST_KIND => "Pinned notes",
+        list::BOOKMARKS_KIND => "Bookmarks",
+        list::FOLLOW_SET_KIND => "Follow set",
+        list::BOOKMARK_SET_KIND => "Bookmark set",
+        list::CURATION_SET_KIND => "Curated notes",
+        _ => "List",
+    };
+    match list.author == app.pubkey {
+        true => name.to_string(),
+        false => format!("{} of {}", name, app.profiles.display_name(&list.author)),
+    }
+}
+
+/// A note of the feed: a header with the author and its age, followed by
+/// the content wrapped to `width`, indented `depth` levels. Reposts show
+/// who reposted above the original note.
+fn note_item(app: &App, event: &NostrEvent, depth: usize, width: usize) -> ListItem<'static> {
+    ListItem::new(Text::from(note_lines(app, event, depth, width)))
+}
+
+/// The lines of [`note_item`], for views that add some of their own.
+fn note_lines(app: &App, event: &NostrEvent, depth: usize, width: usize) -> Vec<Line<'static>> {
+    let indent = "  ".repeat(depth.min(MAX_INDENT));
+    let width = width.saturating_sub(indent.len());
+    let dimmed = Style::default().fg(Color::DarkGray);
+
+    let mut lines = Vec::new();
+    let note = match repost::is_repost(event) {
+        true => {
+            lines.push(Line::styled(
+                format!(
+                    "{}🔁 {} reposted · {}",
+                    indent,
+                    app.profiles.display_name(&event.pubkey()),
+                    time_ago(event.created_at)
+                ),
+                dimmed,
+            ));
+            match app.original(event) {
+                Some(original) => original,
+                None => {
+                    lines.push(Line::styled(
+                        format!("{}The reposted note wasn't found yet", indent),
+                        dimmed,
+                    ));
+                    lines.push(Line::default());
+                    return lines;
+                }
+            }
+        }
+        false => event,
+    };
+
+    // Profiles list the notes their user pinned first
+    if matches!(app.view(), View::Profile(_)) && app.is_pinned(note) {
+        lines.push(Line::styled(format!("{}📌 Pinned", indent), dimmed));
+    }
+
+    let mut header = author_line(app, &note.pubkey());
+    header.spans.insert(0, Span::raw(indent.clone()));
+    header.spans.push(Span::styled(
+        format!(" · {}", time_ago(note.created_at)),
+        dimmed,
+    ));
+    if app.is_bookmarked(note) {
+        header.spans.push(Span::raw(" · 🔖"));
+    }
+    let labels = app.trusted_labels(note);
+    if !labels.is_empty() {
+        let labels: Vec<String> = labels.iter().map(Label::to_string).collect();
+        header.spans.push(Span::styled(
+            format!(" · 🏷 {}", labels.join(", ")),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    if let Some(at) = expiration::expiration(note) {
+        header.spans.push(Span::styled(
+            format!(
+                " · expires in {}",
+                short_duration(at.saturating_sub(unix_time()))
+            ),
+            dimmed,
+        ));
+    }
+    lines.push(header);
+
+    if let Some(warning) = hidden_warning(app, note) {
+        lines.push(Line::from(vec![
+            Span::raw(indent.clone()),
+            warning,
+            Span::styled(" · x to show", dimmed),
+        ]));
+        lines.push(Line::default());
+        return lines;
+    }
+
+    let (content, quoted_ids) = readable_content(app, note);
+    lines.extend(
+        wrap(&content, width)
+            .into_iter()
+            .map(|line| Line::from(format!("{}{}", indent, line))),
+    );
+    // Kind 1063 events only link to their file in their tags
+    for file in file_metadata::files(note) {
+        lines.push(Line::styled(
+            format!("{}📎 {}", indent, file.describe()),
+            dimmed,
+        ));
+        if note.kind == FILE_METADATA_KIND {
+            lines.push(Line::from(format!("{}{}", indent, file.url)));
+        }
+    }
+    for quoted in quoted_ids
+        .iter()
+        .filter_map(|id| app.find_note(id))
+        .filter(|quoted| !app.is_muted(quoted))
+    {
+        lines.extend(quote_lines(app, quoted, width).into_iter().map(|mut line| {
+            line.spans.insert(0, Span::raw(indent.clone()));
+            line
+        }));
+    }
+    let activity: Vec<Span> = [zaps_span(app, note), reactions_line(app, note)]
+        .into_iter()
+        .flatten()
+        .collect();
+    if !activity.is_empty() {
+        let mut spans = vec![Span::raw(indent)];
+        for (index, span) in activity.into_iter().enumerate() {
+            if index > 0 {
+                spans.push(Span::raw("  "));
+            }
+            spans.push(span);
+        }
+        lines.push(Line::from(spans));
+    }
+    lines.push(Line::default());
+
+    lines
+}
+
+/// A note quoted by another one, shown inside it as a block.
+fn quote_lines(app: &App, quoted: &NostrEvent, width: usize) -> Vec<Line<'static>> {
+    let border = Span::styled("│ ", Style::default().fg(Color::DarkGray));
+
+    let mut header = author_line(app, &quoted.pubkey());
+    header.spans.insert(0, border.clone());
+    let mut lines = vec![header];
+
+    if let Some(warning) = hidden_warning(app, quoted) {
+        lines.push(Line::from(vec![border, warning]));
+        return lines;
+    }
+
+    let (content, _) = readable_content(app, quoted);
+    let content = wrap(&content, width.saturating_sub(2));
+    let truncated = content.len() > MAX_QUOTE_LINES;
+    for (i, line) in content.into_iter().take(MAX_QUOTE_LINES).enumerate() {
+        let ellipsis = if truncated && i + 1 == MAX_QUOTE_LINES {
+            "…"
+        } else {
+            ""
+        };
+        lines.push(Line::from(vec![
+            border.clone(),
+            Span::styled(
+                format!("{}{}", line, ellipsis),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]));
+    }
+
+    lines
+}
+
+/// What a note with a content warning, or reported by someone the user
+/// trusts, shows instead of its content, unless the user chose to read it.
+fn hidden_warning(app: &App, note: &NostrEvent) -> Option<Span<'static>> {
+    if note.id().is_some_and(|id| app.expanded.contains(id)) {
+        return None;
+    }
+
+    let text = match (
+        content_warning::content_warning(note),
+        app.report_flag(note),
+    ) {
+        (Some(""), _) => "⚠ Content warning".to_string(),
+        (Some(reason), _) => format!("⚠ Content warning: {}", reason),
+        (None, Some(report)) => format!(
+            "⚑ Reported as {} by {}",
+            report.report_type,
+            app.profiles.display_name(&report.reporter)
+        ),
+        (None, None) => return None,
+    };
+    Some(Span::styled(text, Style::default().fg(Color::Yellow)))
+}
+
+/// The content of `note` with the users it mentions replaced by their
+/// names, and the notes it quotes that were received replaced by nothing,
+/// since they are shown below it. Also returns the ids of those quoted
+/// notes, including the ones only found in `q` tags.
+fn readable_content(app: &App, note: &NostrEvent) -> (String, Vec<String>) {
+    let mut content = String::new();
+    let mut quoted: Vec<String> = Vec::new();
+    let mut position = 0;
+
+    for found in reference::references(&note.content) {
+        content.push_str(&note.content[position..found.start]);
+        position = found.end;
+
+        let original = &note.content[found.start..found.end];
+        match &found.entity {
+            Entity::Pubkey(pubkey) | Entity::Profile { pubkey, .. } => {
+                content.push_str(&format!("@{}", app.profiles.display_name(pubkey)));
+            }
+            Entity::Note(id) | Entity::Event { id, .. } if app.find_note(id).is_some() => {
+                quoted.push(id.clone());
+            }
+            // Notes not received yet, and articles, stay as shortened links
+            _ => content.push_str(&shorten(original)),
+        }
+    }
+    content.push_str(&note.content[position..]);
+
+    for id in repost::quoted_ids(note) {
+        if !quoted.iter().any(|known| known == id) {
+            quoted.push(id.to_string());
+        }
+    }
+
+    (content.trim_end().to_string(), quoted)
+}
+
+/// Shortens a long `nostr:` reference to fit in a line of the feed.
+fn shorten(reference: &str) -> String {
+    match reference.char_indices().nth(24) {
+        Some((cut, _)) => format!("{}…", &reference[..cut]),
+        None => reference.to_string(),
+    }
+}
+
+/// The reactions a note got, with the user's own one highlighted.
+fn reactions_line(app: &App, event: &NostrEvent) -> Option<Span<'static>> {
+    let id = event.id()?;
+    let summary = app.reactions.summary(id);
+    if summary.is_empty() {
+        return None;
+    }
+
+    let text = summary
+        .into_iter()
+        .map(|(content, count)| format!("{} {}", content.label(), count))
+        .collect::<Vec<_>>()
+        .join("  ");
+    let style = match app.reactions.reaction_of(&app.pubkey, id) {
+        Some(_) => Style::default().fg(Color::Magenta),
+        None => Style::default().fg(Color::DarkGray),
+    };
+
+    Some(Span::styled(text, style))
+}
+
+/// How much a note was zapped, if it was.
+fn zaps_span(app: &App, event: &NostrEvent) -> Option<Span<'static>> {
+    let (msats, count) = app.zaps.of_event(event.id()?);
+    if count == 0 {
+        return None;
+    }
+
+    Some(Span::styled(
+        format!("⚡ {} ({})", short_sats(msats), count),
+        Style::default().fg(Color::Yellow),
+    ))
+}
+
+/// Formats an amount in millisatoshis as sats, shortened when large.
+fn short_sats(msats: u64) -> String {
+    let sats = msats / 1000;
+    match sats {
+        0..=999 => sats.to_string(),
+        1_000..=999_999 => format!("{:.1}k", sats as f64 / 1_000.0),
+        _ => format!("{:.1}M", sats as f64 / 1_000_000.0),
+    }
+}
+
+/// Splits `text` into lines no longer than `width` characters, breaking
+/// between words whenever possible.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let line_len = line.chars().count();
+            let word_len = word.chars().count();
+
+            if line_len > 0 && line_len + 1 + word_len > width {
+                lines.push(std::mem::take(&mut line));
+            } else if line_len > 0 {
+                line.push(' ');
+            }
+
+            // Words longer than a whole line are cut wherever they overflow
+            let mut chars: Vec<char> = word.chars().collect();
+            while chars.len() > width {
+                lines.push(chars.drain(..width).collect());
+            }
+            line.extend(chars);
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Formats how long ago `timestamp` was, the way feeds usually do.
+pub fn time_ago(timestamp: u64) -> String {
+    short_duration(unix_time().saturating_sub(timestamp))
+}
+
+/// Formats a number of seconds in the largest unit that fits.
+fn short_duration(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
 }